@@ -6,9 +6,10 @@ use std::{
 
 use log::debug;
 use rmpv::Value;
+use serde::{Deserialize, Serialize};
 use skia_safe::Color4f;
 
-use crate::editor::{Colors, CursorMode, CursorShape, Style, UnderlineStyle};
+use crate::editor::{Colors, CursorMode, CursorShape, PopupMenuItem, Style, UnderlineStyle};
 
 #[derive(Clone, Debug)]
 pub enum ParseError {
@@ -111,7 +112,7 @@ pub enum GuiOption {
     Unknown(String, Value),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum WindowAnchor {
     NorthWest,
     NorthEast,
@@ -171,6 +172,8 @@ pub enum RedrawEvent {
         row: u64,
         column_start: u64,
         cells: Vec<GridLineCell>,
+        // Whether the line continues on the next row. Only sent by Neovim 0.10 and later.
+        wrap: bool,
     },
     Clear {
         grid: u64,
@@ -194,6 +197,7 @@ pub enum RedrawEvent {
     },
     WindowPosition {
         grid: u64,
+        window: u64,
         start_row: u64,
         start_column: u64,
         width: u64,
@@ -201,6 +205,7 @@ pub enum RedrawEvent {
     },
     WindowFloatPosition {
         grid: u64,
+        window: u64,
         anchor: WindowAnchor,
         anchor_grid: u64,
         anchor_row: f64,
@@ -278,6 +283,17 @@ pub enum RedrawEvent {
     ShowIntro {
         message: Vec<String>,
     },
+    PopupMenuShow {
+        items: Vec<PopupMenuItem>,
+        selected: Option<u64>,
+        row: u64,
+        column: u64,
+        grid: i64,
+    },
+    PopupMenuSelect {
+        selected: Option<u64>,
+    },
+    PopupMenuHide,
 }
 
 fn unpack_color(packed_color: u64) -> Color4f {
@@ -352,6 +368,17 @@ fn parse_u64(u64_value: Value) -> Result<u64> {
     u64_value.try_into().map_err(ParseError::U64)
 }
 
+// Window handles are sent as msgpack ext values wrapping the window id.
+fn parse_window_handle(window_handle_value: Value) -> Result<u64> {
+    match window_handle_value {
+        Value::Ext(_, ref data) => rmpv::decode::read_value(&mut data.as_slice())
+            .ok()
+            .and_then(|window_id| window_id.as_u64())
+            .ok_or(ParseError::U64(window_handle_value)),
+        window_handle_value => parse_u64(window_handle_value),
+    }
+}
+
 fn parse_i64(i64_value: Value) -> Result<i64> {
     i64_value.try_into().map_err(ParseError::I64)
 }
@@ -384,6 +411,9 @@ fn parse_mode_info_set(mode_info_set_arguments: Vec<Value>) -> Result<RedrawEven
 
         for (name, value) in info_map {
             match parse_string(name)?.as_str() {
+                "name" => {
+                    mode_info.name = Some(parse_string(value)?);
+                }
                 "cursor_shape" => {
                     mode_info.shape = CursorShape::from_type_name(&parse_string(value)?);
                 }
@@ -497,7 +527,9 @@ fn parse_style(style_map: Value) -> Result<Style> {
                 ("strikethrough", Value::Boolean(strikethrough)) => {
                     style.strikethrough = strikethrough
                 }
-                ("blend", Value::Integer(blend)) => style.blend = blend.as_u64().unwrap() as u8,
+                ("blend", Value::Integer(blend)) => {
+                    style.blend = blend.as_u64().unwrap_or(0).min(100) as u8
+                }
 
                 ("underline", Value::Boolean(true)) => {
                     style.underline = Some(UnderlineStyle::Underline)
@@ -566,7 +598,8 @@ fn parse_grid_line_cell(grid_line_cell: Value) -> Result<GridLineCell> {
 }
 
 fn parse_grid_line(grid_line_arguments: Vec<Value>) -> Result<RedrawEvent> {
-    let [grid_id, row, column_start, cells] = extract_values(grid_line_arguments)?;
+    let ([grid_id, row, column_start, cells], [wrap]) =
+        extract_values_with_optional(grid_line_arguments)?;
 
     Ok(RedrawEvent::GridLine {
         grid: parse_u64(grid_id)?,
@@ -576,6 +609,7 @@ fn parse_grid_line(grid_line_arguments: Vec<Value>) -> Result<RedrawEvent> {
             .into_iter()
             .map(parse_grid_line_cell)
             .collect::<Result<Vec<GridLineCell>>>()?,
+        wrap: wrap.map(parse_bool).transpose()?.unwrap_or(false),
     })
 }
 
@@ -619,11 +653,11 @@ fn parse_grid_scroll(grid_scroll_arguments: Vec<Value>) -> Result<RedrawEvent> {
 }
 
 fn parse_win_pos(win_pos_arguments: Vec<Value>) -> Result<RedrawEvent> {
-    let [grid, _window, start_row, start_column, width, height] =
-        extract_values(win_pos_arguments)?;
+    let [grid, window, start_row, start_column, width, height] = extract_values(win_pos_arguments)?;
 
     Ok(RedrawEvent::WindowPosition {
         grid: parse_u64(grid)?,
+        window: parse_window_handle(window)?,
         start_row: parse_u64(start_row)?,
         start_column: parse_u64(start_column)?,
         width: parse_u64(width)?,
@@ -643,11 +677,12 @@ fn parse_window_anchor(value: Value) -> Result<WindowAnchor> {
 }
 
 fn parse_win_float_pos(win_float_pos_arguments: Vec<Value>) -> Result<RedrawEvent> {
-    let ([grid, _window, anchor, anchor_grid, anchor_row, anchor_column, focusable], [sort_order]) =
+    let ([grid, window, anchor, anchor_grid, anchor_row, anchor_column, focusable], [sort_order]) =
         extract_values_with_optional(win_float_pos_arguments)?;
 
     Ok(RedrawEvent::WindowFloatPosition {
         grid: parse_u64(grid)?,
+        window: parse_window_handle(window)?,
         anchor: parse_window_anchor(anchor)?,
         anchor_grid: parse_u64(anchor_grid)?,
         anchor_row: parse_f64(anchor_row)?,
@@ -837,6 +872,46 @@ fn parse_msg_intro(msg_intro_arguments: Vec<Value>) -> Result<RedrawEvent> {
     })
 }
 
+fn parse_popupmenu_selected(selected: Value) -> Result<Option<u64>> {
+    // Neovim uses -1 to signal that no item is selected.
+    let selected = parse_i64(selected)?;
+    Ok((selected >= 0).then_some(selected as u64))
+}
+
+fn parse_popupmenu_item(item: Value) -> Result<PopupMenuItem> {
+    let [word, kind, menu, info] = extract_values(parse_array(item)?)?;
+
+    Ok(PopupMenuItem {
+        word: parse_string(word)?,
+        kind: parse_string(kind)?,
+        menu: parse_string(menu)?,
+        info: parse_string(info)?,
+    })
+}
+
+fn parse_popupmenu_show(popupmenu_show_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [items, selected, row, column, grid] = extract_values(popupmenu_show_arguments)?;
+
+    Ok(RedrawEvent::PopupMenuShow {
+        items: parse_array(items)?
+            .into_iter()
+            .map(parse_popupmenu_item)
+            .collect::<Result<_>>()?,
+        selected: parse_popupmenu_selected(selected)?,
+        row: parse_u64(row)?,
+        column: parse_u64(column)?,
+        grid: parse_i64(grid)?,
+    })
+}
+
+fn parse_popupmenu_select(popupmenu_select_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [selected] = extract_values(popupmenu_select_arguments)?;
+
+    Ok(RedrawEvent::PopupMenuSelect {
+        selected: parse_popupmenu_selected(selected)?,
+    })
+}
+
 pub fn parse_redraw_event(event_value: Value) -> Result<Vec<RedrawEvent>> {
     let mut event_contents = parse_array(event_value)?.into_iter();
     let event_name = event_contents
@@ -890,6 +965,9 @@ pub fn parse_redraw_event(event_value: Value) -> Result<Vec<RedrawEvent>> {
             "msg_ruler" => Some(parse_msg_ruler(event_parameters)),
             "msg_history_show" => Some(parse_msg_history_show(event_parameters)),
             "msg_intro" => Some(parse_msg_intro(event_parameters)),
+            "popupmenu_show" => Some(parse_popupmenu_show(event_parameters)),
+            "popupmenu_select" => Some(parse_popupmenu_select(event_parameters)),
+            "popupmenu_hide" => Some(Ok(RedrawEvent::PopupMenuHide)),
             _ => None,
         };
 
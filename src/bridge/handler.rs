@@ -11,13 +11,198 @@ use crate::{
 };
 use crate::{
     bridge::{events::parse_redraw_event, NeovimWriter},
-    editor::EditorCommand,
+    editor::{
+        parse_color, BackgroundImage, BackgroundImageFit, BackgroundKind, BlameLine, Diagnostic,
+        EditorCommand, LineNumbers, SpotlightArea, VisualBlock,
+    },
     error_handling::ResultPanicExplanation,
     event_aggregator::EVENT_AGGREGATOR,
     running_tracker::*,
     settings::SETTINGS,
 };
 
+// Arguments are the window handle, the first column and width of the number column, the cursor
+// line and an array of [row, line, number] triples.
+fn parse_line_numbers(arguments: Vec<Value>) -> Option<(u64, LineNumbers)> {
+    let [window, start, width, current_line, rows]: [Value; 5] = arguments.try_into().ok()?;
+    let rows = match rows {
+        Value::Array(rows) => rows
+            .iter()
+            .filter_map(|row| match row.as_array()?.as_slice() {
+                [row, line, number] => Some((row.as_u64()?, line.as_u64()?, number.as_u64()?)),
+                _ => None,
+            })
+            .collect(),
+        _ => return None,
+    };
+
+    Some((
+        window.as_u64()?,
+        LineNumbers {
+            start: start.as_u64()?,
+            width: width.as_u64()?,
+            current_line: current_line.as_u64()?,
+            rows,
+        },
+    ))
+}
+
+// Arguments of the w:neovide_* watchers are the window handle and the value, which `parse` turns
+// into what gets sent.
+fn parse_window_argument<T>(
+    arguments: Vec<Value>,
+    parse: impl FnOnce(Value) -> Option<T>,
+) -> Option<(u64, T)> {
+    let [window, value]: [Value; 2] = arguments.try_into().ok()?;
+    Some((window.as_u64()?, parse(value)?))
+}
+
+// Booleans set from vimscript are often 0 and 1 instead.
+fn value_as_bool(value: &Value) -> Option<bool> {
+    value
+        .as_bool()
+        .or_else(|| value.as_u64().map(|value| value != 0))
+}
+
+// The value is an array of [line, text] pairs, with the buffer lines starting at 1. Used for both
+// the diagnostics and the blame.
+fn parse_line_texts(arguments: Vec<Value>) -> Option<(u64, Vec<(u64, String)>)> {
+    parse_window_argument(arguments, |line_texts| match line_texts {
+        Value::Array(line_texts) => Some(
+            line_texts
+                .iter()
+                .filter_map(|line_text| match line_text.as_array()?.as_slice() {
+                    [line, text] => Some((line.as_u64()?, text.as_str()?.to_owned())),
+                    _ => None,
+                })
+                .collect(),
+        ),
+        _ => None,
+    })
+}
+
+// The fonts are either a list of font names or one string of comma separated names, like the
+// guifont.
+fn parse_font_list(fonts: &Value) -> Option<Vec<String>> {
+    let fonts: Vec<String> = match fonts {
+        Value::String(fonts) => fonts.as_str()?.split(',').map(str::to_owned).collect(),
+        Value::Array(fonts) => fonts
+            .iter()
+            .filter_map(|font| font.as_str().map(str::to_owned))
+            .collect(),
+        _ => return None,
+    };
+
+    Some(
+        fonts
+            .iter()
+            .map(|font| font.trim().to_owned())
+            .filter(|font| !font.is_empty())
+            .collect(),
+    )
+}
+
+// The image is either just a path or a dictionary with the path, opacity and fit. An empty path
+// removes the image again.
+fn parse_background_image(image: &Value) -> Option<Option<BackgroundImage>> {
+    let (path, opacity, fit) = match image {
+        Value::String(path) => (path.as_str()?, None, None),
+        Value::Map(entries) => {
+            let entry = |name: &str| {
+                entries
+                    .iter()
+                    .find(|(key, _)| key.as_str() == Some(name))
+                    .map(|(_, value)| value)
+            };
+            (
+                entry("path")?.as_str()?,
+                entry("opacity").and_then(|opacity| opacity.as_f64()),
+                entry("fit").and_then(|fit| fit.as_str()),
+            )
+        }
+        _ => return None,
+    };
+
+    if path.is_empty() {
+        return Some(None);
+    }
+
+    Some(Some(BackgroundImage::new(
+        path,
+        opacity.unwrap_or(1.0) as f32,
+        fit.and_then(BackgroundImageFit::from_name)
+            .unwrap_or(BackgroundImageFit::Cover),
+    )))
+}
+
+// The background is a dictionary with either a color, a gradient between two colors or an image
+// like w:neovide_background_image. Anything else removes the background again.
+fn parse_background(background: &Value) -> Option<BackgroundKind> {
+    let entries = match background {
+        Value::Map(entries) => entries,
+        _ => return None,
+    };
+    let entry = |name: &str| {
+        entries
+            .iter()
+            .find(|(key, _)| key.as_str() == Some(name))
+            .map(|(_, value)| value)
+    };
+
+    if let Some(color) = entry("color") {
+        return parse_color(color.as_str()?).map(BackgroundKind::Color);
+    }
+    if let Some(gradient) = entry("gradient") {
+        let colors: Vec<_> = gradient
+            .as_array()?
+            .iter()
+            .filter_map(|color| color.as_str().and_then(parse_color))
+            .collect();
+        return match colors.as_slice() {
+            [from, to] => Some(BackgroundKind::Gradient {
+                from: *from,
+                to: *to,
+                horizontal: entry("direction").and_then(|direction| direction.as_str())
+                    == Some("horizontal"),
+            }),
+            _ => None,
+        };
+    }
+    parse_background_image(entry("image")?)?.map(BackgroundKind::Image)
+}
+
+// Arguments are the window handle followed by the top row, left column, bottom row and right
+// column of the block. Only the window handle is sent when the block selection ended.
+// Arguments are the window handle and the columns and rows to move the float by, which are
+// integers unless a fraction of a cell is asked for.
+fn parse_float_move(arguments: &[Value]) -> Option<(u64, (f64, f64))> {
+    let as_cells = |cells: &Value| {
+        cells
+            .as_f64()
+            .or_else(|| cells.as_i64().map(|cells| cells as f64))
+    };
+    match arguments {
+        [window, columns, rows] => Some((window.as_u64()?, (as_cells(columns)?, as_cells(rows)?))),
+        _ => None,
+    }
+}
+
+fn parse_visual_block(arguments: &[Value]) -> Option<(u64, Option<VisualBlock>)> {
+    match arguments {
+        [window] => Some((window.as_u64()?, None)),
+        [window, top, left, bottom, right] => Some((
+            window.as_u64()?,
+            Some(VisualBlock {
+                top: top.as_u64()?,
+                left: left.as_u64()?,
+                bottom: bottom.as_u64()?,
+                right: right.as_u64()?,
+            }),
+        )),
+        _ => None,
+    }
+}
+
 #[derive(Clone)]
 pub struct NeovimHandler {}
 
@@ -96,9 +281,211 @@ impl Handler for NeovimHandler {
             "neovide.unregister_right_click" => {
                 EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::UnregisterRightClick));
             }
+            "neovide.window_timings" => {
+                EVENT_AGGREGATOR.send(WindowCommand::ListWindowTimings);
+            }
             "neovide.focus_window" => {
                 EVENT_AGGREGATOR.send(WindowCommand::FocusWindow);
             }
+            "neovide.line_numbers" => {
+                if let Some((window, line_numbers)) = parse_line_numbers(arguments) {
+                    EVENT_AGGREGATOR.send(EditorCommand::SetLineNumbers {
+                        window,
+                        line_numbers,
+                    });
+                }
+            }
+            "neovide.window_background_image" => {
+                if let Some((window, image)) =
+                    parse_window_argument(arguments, |image| parse_background_image(&image))
+                {
+                    EVENT_AGGREGATOR.send(EditorCommand::SetWindowBackground {
+                        window,
+                        background: image.map(BackgroundKind::Image),
+                    });
+                }
+            }
+            "neovide.window_background" => {
+                if let Some((window, background)) = parse_window_argument(arguments, |background| {
+                    Some(parse_background(&background))
+                }) {
+                    EVENT_AGGREGATOR
+                        .send(EditorCommand::SetWindowBackground { window, background });
+                }
+            }
+            "neovide.visual_block" => {
+                if let Some((window, block)) = parse_visual_block(&arguments) {
+                    EVENT_AGGREGATOR.send(EditorCommand::SetVisualBlock { window, block });
+                }
+            }
+            "neovide.pending_keys" => {
+                if let Some(keys) = arguments.first().and_then(|keys| keys.as_str()) {
+                    EVENT_AGGREGATOR.send(EditorCommand::SetPendingKeys(keys.to_owned()));
+                }
+            }
+            "neovide.recording" => {
+                if let Some(register) = arguments.first().and_then(|register| register.as_str()) {
+                    EVENT_AGGREGATOR.send(EditorCommand::SetRecording(register.to_owned()));
+                }
+            }
+            "neovide.dim_overlay" => {
+                if let Some(enabled) = arguments.first().and_then(|enabled| enabled.as_bool()) {
+                    EVENT_AGGREGATOR.send(EditorCommand::SetDimOverlay(enabled));
+                }
+            }
+            "neovide.peek_preview" => {
+                // A window handle opens the preview, anything else like nil dismisses it.
+                let window = arguments.first().and_then(|window| window.as_u64());
+                EVENT_AGGREGATOR.send(EditorCommand::SetPeekPreview(window));
+            }
+            "neovide.move_float" => {
+                if let Some((window, delta)) = parse_float_move(&arguments) {
+                    EVENT_AGGREGATOR.send(EditorCommand::MoveFloat { window, delta });
+                }
+            }
+            "neovide.spotlight" => {
+                // Anything but the window and the four edges of the area, like false, turns it off.
+                let edges: Vec<u64> = arguments.iter().filter_map(|edge| edge.as_u64()).collect();
+                let area = match edges.as_slice() {
+                    [window, top, left, bottom, right] => Some(SpotlightArea {
+                        window: *window,
+                        top: *top,
+                        left: *left,
+                        bottom: *bottom,
+                        right: *right,
+                    }),
+                    _ => None,
+                };
+                EVENT_AGGREGATOR.send(EditorCommand::SetSpotlight(area));
+            }
+            "neovide.window_brightness" => {
+                if let Some((window, brightness)) =
+                    parse_window_argument(arguments, |brightness| brightness.as_f64())
+                {
+                    EVENT_AGGREGATOR.send(EditorCommand::SetWindowBrightness {
+                        window,
+                        brightness: brightness as f32,
+                    });
+                }
+            }
+            "neovide.theme_changed" => {
+                EVENT_AGGREGATOR.send(EditorCommand::ThemeChanged);
+            }
+            "neovide.export_grid" => {
+                EVENT_AGGREGATOR.send(EditorCommand::ExportGrid);
+            }
+            "neovide.copy_visual_block" => {
+                EVENT_AGGREGATOR.send(EditorCommand::CopyVisualBlock);
+            }
+            "neovide.snapshot_grid" => {
+                if let Some(path) = arguments.first().and_then(|path| path.as_str()) {
+                    EVENT_AGGREGATOR.send(EditorCommand::SnapshotGrid(path.to_owned()));
+                }
+            }
+            "neovide.message_log_scroll" => {
+                if let Some(rows) = arguments.first().and_then(|rows| rows.as_i64()) {
+                    EVENT_AGGREGATOR.send(EditorCommand::ScrollMessageLog(rows));
+                }
+            }
+            "neovide.window_mirrored" => {
+                if let Some((window, mirrored)) =
+                    parse_window_argument(arguments, |mirrored| value_as_bool(&mirrored))
+                {
+                    EVENT_AGGREGATOR.send(EditorCommand::SetWindowMirrored { window, mirrored });
+                }
+            }
+            "neovide.window_pinned" => {
+                if let Some((window, pinned)) =
+                    parse_window_argument(arguments, |pinned| value_as_bool(&pinned))
+                {
+                    EVENT_AGGREGATOR.send(EditorCommand::SetWindowPinned { window, pinned });
+                }
+            }
+            "neovide.window_snap_to_pixels" => {
+                if let Some((window, snap_to_pixels)) =
+                    parse_window_argument(arguments, |snap_to_pixels| {
+                        value_as_bool(&snap_to_pixels)
+                    })
+                {
+                    EVENT_AGGREGATOR.send(EditorCommand::SetWindowSnapToPixels {
+                        window,
+                        snap_to_pixels,
+                    });
+                }
+            }
+            "neovide.window_corner_radius" => {
+                if let Some((window, corner_radius)) =
+                    parse_window_argument(arguments, |corner_radius| {
+                        corner_radius
+                            .as_f64()
+                            .or_else(|| corner_radius.as_u64().map(|radius| radius as f64))
+                    })
+                {
+                    EVENT_AGGREGATOR.send(EditorCommand::SetWindowCornerRadius {
+                        window,
+                        corner_radius: corner_radius as f32,
+                    });
+                }
+            }
+            "neovide.window_breadcrumb" => {
+                if let Some((window, breadcrumb)) = parse_window_argument(arguments, |breadcrumb| {
+                    breadcrumb.as_str().map(str::to_owned)
+                }) {
+                    EVENT_AGGREGATOR
+                        .send(EditorCommand::SetWindowBreadcrumb { window, breadcrumb });
+                }
+            }
+            "neovide.window_diagnostics" => {
+                if let Some((window, diagnostics)) = parse_line_texts(arguments) {
+                    let diagnostics = diagnostics
+                        .into_iter()
+                        .map(|(line, message)| Diagnostic { line, message })
+                        .collect();
+                    EVENT_AGGREGATOR.send(EditorCommand::SetWindowDiagnostics {
+                        window,
+                        diagnostics,
+                    });
+                }
+            }
+            "neovide.window_blame" => {
+                if let Some((window, blame)) = parse_line_texts(arguments) {
+                    let blame = blame
+                        .into_iter()
+                        .map(|(line, text)| BlameLine { line, text })
+                        .collect();
+                    EVENT_AGGREGATOR.send(EditorCommand::SetWindowBlame { window, blame });
+                }
+            }
+            "neovide.window_font_fallback" => {
+                if let Some((window, fonts)) =
+                    parse_window_argument(arguments, |fonts| parse_font_list(&fonts))
+                {
+                    EVENT_AGGREGATOR.send(EditorCommand::SetWindowFontFallback { window, fonts });
+                }
+            }
+            "neovide.window_smooth_scroll" => {
+                // Anything but a boolean, like the variable being removed, goes back to the global
+                // setting.
+                if let Some((window, smooth_scroll)) =
+                    parse_window_argument(arguments, |smooth_scroll| {
+                        Some(value_as_bool(&smooth_scroll))
+                    })
+                {
+                    EVENT_AGGREGATOR.send(EditorCommand::SetWindowSmoothScroll {
+                        window,
+                        smooth_scroll,
+                    });
+                }
+            }
+            "neovide.window_zoom" => {
+                if let Some((window, zoom)) = parse_window_argument(arguments, |zoom| zoom.as_f64())
+                {
+                    EVENT_AGGREGATOR.send(EditorCommand::SetWindowZoom {
+                        window,
+                        zoom: zoom as f32,
+                    });
+                }
+            }
             _ => {}
         }
     }
@@ -12,7 +12,13 @@ use log::{error, info};
 use nvim_rs::{error::CallError, Neovim, UiAttachOptions, Value};
 
 use crate::{
-    cmd_line::CmdLineSettings, error_handling::ResultPanicExplanation, running_tracker::*,
+    cmd_line::CmdLineSettings,
+    editor::{
+        CommandLineSettings, LineNumberSettings, MessageLogSettings, PendingKeysSettings,
+        PopupMenuSettings, RecordingIndicatorSettings, VisualBlockSettings,
+    },
+    error_handling::ResultPanicExplanation,
+    running_tracker::*,
     settings::*,
 };
 
@@ -21,7 +27,10 @@ pub use events::*;
 use handler::NeovimHandler;
 pub use session::NeovimWriter;
 use session::{NeovimInstance, NeovimSession};
-use setup::setup_neovide_specific_state;
+use setup::{
+    setup_line_numbers, setup_neovide_specific_state, setup_pending_keys,
+    setup_recording_indicator, setup_visual_block,
+};
 pub use ui_commands::{start_ui_command_handler, ParallelCommand, SerialCommand, UiCommand};
 
 const INTRO_MESSAGE_LUA: &str = include_str!("../../lua/intro.lua");
@@ -100,6 +109,42 @@ async fn start_neovim_runtime(instance: NeovimInstance) {
     SETTINGS.read_initial_values(&nvim).await;
     SETTINGS.setup_changed_listeners(&nvim).await;
 
+    if SETTINGS.get::<LineNumberSettings>().enabled {
+        setup_line_numbers(&nvim).await;
+    }
+
+    if SETTINGS.get::<VisualBlockSettings>().enabled {
+        setup_visual_block(&nvim).await;
+    }
+
+    if SETTINGS.get::<PendingKeysSettings>().enabled {
+        setup_pending_keys(&nvim).await;
+    }
+
+    if SETTINGS.get::<RecordingIndicatorSettings>().enabled {
+        setup_recording_indicator(&nvim).await;
+    }
+
+    if SETTINGS.get::<PopupMenuSettings>().external {
+        nvim.ui_set_option("ext_popupmenu", Value::from(true))
+            .await
+            .ok();
+    }
+
+    if SETTINGS.get::<CommandLineSettings>().centered {
+        nvim.ui_set_option("ext_cmdline", Value::from(true))
+            .await
+            .ok();
+    }
+
+    // Neovim turns on ext_cmdline along with it, so the command line is drawn by the editor then
+    // too, in the last row unless it's centered.
+    if SETTINGS.get::<MessageLogSettings>().enabled {
+        nvim.ui_set_option("ext_messages", Value::from(true))
+            .await
+            .ok();
+    }
+
     match session.io_handle.await {
         Err(join_error) => error!("Error joining IO loop: '{}'", join_error),
         Ok(Err(error)) => {
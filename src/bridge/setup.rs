@@ -31,6 +31,154 @@ const REGISTER_CLIPBOARD_PROVIDER_LUA: &str = r"
         cache_enabled = 0
     }";
 
+const SEND_LINE_NUMBERS_LUA: &str = r"
+    local function send_line_numbers(window)
+        local number = vim.wo[window].number
+        local relative_number = vim.wo[window].relativenumber
+        -- An empty column clears the numbers drawn before the options were turned off.
+        if not number and not relative_number then
+            vim.rpcnotify(1, 'neovide.line_numbers', window, 0, 0, 0, {})
+            return
+        end
+
+        local info = vim.fn.getwininfo(window)[1]
+        local buffer = vim.api.nvim_win_get_buf(window)
+        local largest_number = number and vim.api.nvim_buf_line_count(buffer) or info.height
+        local digits = #tostring(largest_number)
+        local width = math.min(math.max(vim.wo[window].numberwidth, digits + 1), info.textoff)
+
+        -- screenpos accounts for folds and wrapped lines, folded lines report the fold's row.
+        local rows = {}
+        local last_row = -1
+        for line = info.topline, info.botline do
+            local row = vim.fn.screenpos(window, line, 1).row - info.winrow
+            if row > last_row then
+                table.insert(rows, { row, line, line })
+                last_row = row
+            end
+        end
+
+        -- Relative numbers count the lines shown in between, a closed fold counts as one. The
+        -- cursor line shows its own number, or 0 without 'number'.
+        local cursor_line = vim.api.nvim_win_get_cursor(window)[1]
+        if relative_number then
+            local cursor_index = 1
+            for index, row in ipairs(rows) do
+                if row[2] <= cursor_line then
+                    cursor_index = index
+                end
+            end
+            for index, row in ipairs(rows) do
+                if index ~= cursor_index then
+                    row[3] = math.abs(index - cursor_index)
+                elseif not number then
+                    row[3] = 0
+                end
+            end
+        end
+
+        vim.rpcnotify(1, 'neovide.line_numbers', window, info.textoff - width, width, cursor_line, rows)
+    end
+
+    vim.api.nvim_create_autocmd(
+        { 'WinScrolled', 'WinResized', 'BufWinEnter', 'CursorMoved', 'CursorMovedI', 'TextChanged', 'TextChangedI' },
+        {
+            callback = function()
+                for _, window in ipairs(vim.api.nvim_tabpage_list_wins(0)) do
+                    send_line_numbers(window)
+                end
+            end,
+        }
+    )";
+
+// Makes neovim report the buffer line shown on each row of every window, so that the number
+// column can be drawn by neovide.
+pub async fn setup_line_numbers(nvim: &Neovim<NeovimWriter>) {
+    nvim.execute_lua(SEND_LINE_NUMBERS_LUA, vec![]).await.ok();
+}
+
+const SEND_VISUAL_BLOCK_LUA: &str = r"
+    local function send_visual_block()
+        local window = vim.api.nvim_get_current_win()
+        if vim.api.nvim_get_mode().mode ~= '\22' then
+            vim.rpcnotify(1, 'neovide.visual_block', window)
+            return
+        end
+
+        local info = vim.fn.getwininfo(window)[1]
+        local view = vim.fn.winsaveview()
+        local start = vim.fn.getpos('v')
+        local cursor = vim.fn.getpos('.')
+
+        -- Virtual columns count the screen cells, so the block stays rectangular over tabs and
+        -- short lines. A block extended with $ reaches the end of every line.
+        local start_column = vim.fn.virtcol({ start[2], start[3] })
+        local cursor_column = vim.fn.virtcol({ cursor[2], cursor[3] })
+        local left = math.min(start_column, cursor_column) - 1 - view.leftcol + info.textoff
+        local right = math.max(start_column, cursor_column) - view.leftcol + info.textoff
+        if view.curswant == vim.v.maxcol then
+            right = info.width
+        end
+
+        local top = math.max(math.min(start[2], cursor[2]), info.topline)
+        local bottom = math.min(math.max(start[2], cursor[2]), info.botline)
+        local top_row = vim.fn.screenpos(window, top, 1).row - info.winrow
+        local bottom_row = vim.fn.screenpos(window, bottom, 1).row - info.winrow + 1
+        vim.rpcnotify(1, 'neovide.visual_block', window, top_row, math.max(left, info.textoff), bottom_row, right)
+    end
+
+    vim.api.nvim_create_autocmd(
+        { 'ModeChanged', 'CursorMoved', 'WinScrolled' },
+        { callback = send_visual_block }
+    )";
+
+// Makes neovim report the screen rectangle of visual block selections, so that they can be drawn
+// as one block instead of per line highlights.
+pub async fn setup_visual_block(nvim: &Neovim<NeovimWriter>) {
+    nvim.execute_lua(SEND_VISUAL_BLOCK_LUA, vec![]).await.ok();
+}
+
+const SEND_PENDING_KEYS_LUA: &str = r"
+    local pending_keys = ''
+
+    -- on_key runs before the key is handled, so the state is only checked once it has been. The
+    -- state has an m while halfway a mapping and an o while an operator is pending.
+    vim.on_key(function(key)
+        vim.schedule(function()
+            local keys = ''
+            if vim.fn.state():find('[mo]') then
+                keys = pending_keys .. vim.fn.keytrans(key)
+            end
+            if keys ~= pending_keys then
+                pending_keys = keys
+                vim.rpcnotify(1, 'neovide.pending_keys', pending_keys)
+            end
+        end)
+    end, vim.api.nvim_create_namespace('neovide_pending_keys'))";
+
+// Makes neovim report the keys of operators and mappings which are still waiting for more input.
+pub async fn setup_pending_keys(nvim: &Neovim<NeovimWriter>) {
+    nvim.execute_lua(SEND_PENDING_KEYS_LUA, vec![]).await.ok();
+}
+
+const SEND_RECORDING_LUA: &str = r"
+    vim.api.nvim_create_autocmd('RecordingEnter', {
+        callback = function()
+            vim.rpcnotify(1, 'neovide.recording', vim.fn.reg_recording())
+        end,
+    })
+    -- reg_recording still returns the register while the recording is left.
+    vim.api.nvim_create_autocmd('RecordingLeave', {
+        callback = function()
+            vim.rpcnotify(1, 'neovide.recording', '')
+        end,
+    })";
+
+// Makes neovim report the register a macro is being recorded into, and when it stops.
+pub async fn setup_recording_indicator(nvim: &Neovim<NeovimWriter>) {
+    nvim.execute_lua(SEND_RECORDING_LUA, vec![]).await.ok();
+}
+
 pub async fn setup_neovide_remote_clipboard(nvim: &Neovim<NeovimWriter>, neovide_channel: u64) {
     // Users can opt-out with
     // vim: `let g:neovide_no_custom_clipboard = v:true`
@@ -137,6 +285,58 @@ pub async fn setup_neovide_specific_state(
         .await
         .ok();
 
+        // Create a command for reporting how long each window takes to draw.
+        nvim.command(&build_neovide_command(
+            neovide_channel,
+            0,
+            "NeovideWindowTimings",
+            "window_timings",
+        ))
+        .await
+        .ok();
+
+        // Create a command for copying the grid under the cursor, for bug reports.
+        nvim.command(&build_neovide_command(
+            neovide_channel,
+            0,
+            "NeovideExportGrid",
+            "export_grid",
+        ))
+        .await
+        .ok();
+
+        // Create a command for copying the visual block as the rectangle drawn, which keeps the
+        // columns of double width characters lined up.
+        nvim.command(&build_neovide_command(
+            neovide_channel,
+            0,
+            "NeovideCopyVisualBlock",
+            "copy_visual_block",
+        ))
+        .await
+        .ok();
+
+        // Create a command for writing the grid under the cursor to a file, to draw it again
+        // without neovim.
+        nvim.command(&build_neovide_command(
+            neovide_channel,
+            1,
+            "NeovideSnapshotGrid",
+            "snapshot_grid",
+        ))
+        .await
+        .ok();
+
+        // Create a command for scrolling the message log, up for positive counts.
+        nvim.command(&build_neovide_command(
+            neovide_channel,
+            1,
+            "NeovideMessageLogScroll",
+            "message_log_scroll",
+        ))
+        .await
+        .ok();
+
         if should_handle_clipboard {
             setup_neovide_remote_clipboard(nvim, neovide_channel).await;
         }
@@ -152,6 +352,24 @@ pub async fn setup_neovide_specific_state(
         .await
         .ok();
 
+    // Forward changes of the window variables for every window, including the first one.
+    for &(name, default, reset_with_buffer) in WINDOW_VARIABLES {
+        nvim.command(&build_window_variable_watcher(name, default))
+            .await
+            .ok();
+        if reset_with_buffer {
+            nvim.command(&build_window_variable_reset(name, default))
+                .await
+                .ok();
+        }
+    }
+
+    // Signal a theme change before the colorscheme defines its highlights, so that they're all
+    // applied at once.
+    nvim.command("autocmd ColorSchemePre * call rpcnotify(1, 'neovide.theme_changed')")
+        .await
+        .ok();
+
     // Create auto command for retrieving exit code from neovim on quit.
     nvim.command("autocmd VimLeave * call rpcnotify(1, 'neovide.quit', v:exiting)")
         .await
@@ -160,7 +378,48 @@ pub async fn setup_neovide_specific_state(
     setup_intro_message_autocommand(nvim).await.ok();
 }
 
-#[cfg(windows)]
+// The w:neovide_* variables forwarded to 'neovide.window_*' notifications, with the value sent
+// when one gets removed, and whether it's reset when another buffer is shown in the window.
+const WINDOW_VARIABLES: &[(&str, &str, bool)] = &[
+    ("brightness", "1.0", false),
+    ("zoom", "1.0", false),
+    ("mirrored", "v:false", false),
+    ("pinned", "v:false", false),
+    ("snap_to_pixels", "v:false", false),
+    ("corner_radius", "0.0", false),
+    ("breadcrumb", "''", false),
+    ("diagnostics", "[]", true),
+    ("blame", "[]", true),
+    ("font_fallback", "[]", false),
+    ("smooth_scroll", "v:null", false),
+    ("background_image", "''", false),
+    ("background", "v:null", false),
+];
+
+// The window id is baked into each watcher since the changed window isn't necessarily current.
+// Values set before the watcher was added, like the ones of the first window set in init.lua, are
+// sent right away.
+fn build_window_variable_watcher(name: &str, default: &str) -> String {
+    format!(
+        concat!(
+            "autocmd VimEnter,WinNew * execute \"call dictwatcheradd(w:, 'neovide_{name}', ",
+            "{{d, k, z -> rpcnotify(1, 'neovide.window_{name}', \" . win_getid() . \", ",
+            "get(z, 'new', {default}))}})\"",
+            " | if exists('w:neovide_{name}')",
+            " | call rpcnotify(1, 'neovide.window_{name}', win_getid(), w:neovide_{name})",
+            " | endif"
+        ),
+        name = name,
+        default = default
+    )
+}
+
+// Variables about the rows of the buffer, like the diagnostics, are stale once another buffer is
+// shown in the window.
+fn build_window_variable_reset(name: &str, default: &str) -> String {
+    format!("autocmd BufWinEnter * let w:neovide_{name} = {default}")
+}
+
 pub fn build_neovide_command(channel: u64, num_args: u64, command: &str, event: &str) -> String {
     let nargs: String = if num_args > 1 {
         "+".to_string()
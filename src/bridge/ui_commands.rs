@@ -4,7 +4,7 @@ use std::sync::Arc;
 use log::error;
 use log::trace;
 
-use nvim_rs::{call_args, rpc::model::IntoVal, Neovim};
+use nvim_rs::{call_args, rpc::model::IntoVal, Neovim, Value};
 use tokio::sync::mpsc::unbounded_channel;
 
 #[cfg(windows)]
@@ -14,7 +14,8 @@ use crate::windows_utils::{
 
 use super::show_intro_message;
 use crate::{
-    bridge::NeovimWriter, event_aggregator::EVENT_AGGREGATOR, running_tracker::RUNNING_TRACKER,
+    bridge::NeovimWriter, editor::RenderMetrics, event_aggregator::EVENT_AGGREGATOR,
+    running_tracker::RUNNING_TRACKER,
 };
 
 // Serial commands are any commands which must complete before the next value is sent. This
@@ -115,10 +116,16 @@ pub enum ParallelCommand {
         width: u64,
         height: u64,
     },
+    ResizeGrid {
+        grid_id: u64,
+        width: u64,
+        height: u64,
+    },
     FileDrop(String),
     FocusLost,
     FocusGained,
     DisplayAvailableFonts(Vec<String>),
+    DisplayWindowTimings(Vec<String>),
     SetBackground(String),
     #[cfg(windows)]
     RegisterRightClick,
@@ -127,6 +134,13 @@ pub enum ParallelCommand {
     ShowIntro {
         message: Vec<String>,
     },
+    ReportRenderMetrics(RenderMetrics),
+    // A grid that kept getting lines past its last row, along with the size it's resynced to.
+    ReportGridDesync {
+        grid_id: u64,
+        width: u64,
+        height: u64,
+    },
 }
 
 impl ParallelCommand {
@@ -143,6 +157,15 @@ impl ParallelCommand {
                 .ui_try_resize(width.max(10) as i64, height.max(3) as i64)
                 .await
                 .expect("Resize failed"),
+            ParallelCommand::ResizeGrid {
+                grid_id,
+                width,
+                height,
+            } => {
+                nvim.ui_try_resize_grid(grid_id as i64, width as i64, height as i64)
+                    .await
+                    .ok();
+            }
             ParallelCommand::FocusLost => {
                 nvim.ui_set_focus(false).await.expect("Focus Lost Failed")
             }
@@ -200,6 +223,14 @@ impl ParallelCommand {
                 .await
                 .ok();
             }
+            ParallelCommand::DisplayWindowTimings(report) => {
+                let message = if report.is_empty() {
+                    "No windows have been drawn yet".to_owned()
+                } else {
+                    report.join("\n")
+                };
+                nvim.out_write(&format!("{message}\n")).await.ok();
+            }
             #[cfg(windows)]
             ParallelCommand::RegisterRightClick => {
                 if unregister_rightclick() {
@@ -231,6 +262,39 @@ impl ParallelCommand {
             ParallelCommand::ShowIntro { message } => {
                 show_intro_message(nvim, &message).await.ok();
             }
+            ParallelCommand::ReportRenderMetrics(metrics) => {
+                nvim.exec_autocmds(
+                    Value::from("User"),
+                    vec![
+                        (Value::from("pattern"), Value::from("NeovideRenderMetrics")),
+                        (Value::from("data"), Value::from(metrics)),
+                        (Value::from("modeline"), Value::from(false)),
+                    ],
+                )
+                .await
+                .ok();
+            }
+            ParallelCommand::ReportGridDesync {
+                grid_id,
+                width,
+                height,
+            } => {
+                let data = Value::Map(vec![
+                    (Value::from("grid"), Value::from(grid_id)),
+                    (Value::from("width"), Value::from(width)),
+                    (Value::from("height"), Value::from(height)),
+                ]);
+                nvim.exec_autocmds(
+                    Value::from("User"),
+                    vec![
+                        (Value::from("pattern"), Value::from("NeovideGridDesync")),
+                        (Value::from("data"), data),
+                        (Value::from("modeline"), Value::from(false)),
+                    ],
+                )
+                .await
+                .ok();
+            }
         }
     }
 }
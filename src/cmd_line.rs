@@ -93,6 +93,32 @@ pub struct CmdLineSettings {
     #[arg(long = "novsync", action = ArgAction::SetTrue, value_parser = FalseyValueParser::new())]
     _novsync: bool,
 
+    /// The largest width and height in cells a grid can have, larger sizes sent by NeoVim are
+    /// clamped to it
+    #[arg(
+        long = "max-grid-size",
+        env = "NEOVIDE_MAX_GRID_SIZE",
+        default_value_t = 2048
+    )]
+    pub max_grid_size: u64,
+
+    /// The most combining marks kept in a grid cell, the ones past it are dropped
+    #[arg(
+        long = "max-combining-marks",
+        env = "NEOVIDE_MAX_COMBINING_MARKS",
+        default_value_t = 16
+    )]
+    pub max_combining_marks: u64,
+
+    /// The widest run of text sent to be drawn at once, longer ones are cut into chunks. 0 doesn't
+    /// cut them
+    #[arg(
+        long = "max-fragment-width",
+        env = "NEOVIDE_MAX_FRAGMENT_WIDTH",
+        default_value_t = 0
+    )]
+    pub max_fragment_width: u64,
+
     /// Which NeoVim binary to invoke headlessly instead of `nvim` found on $PATH
     #[arg(long = "neovim-bin", env = "NEOVIM_BIN")]
     pub neovim_bin: Option<String>,
@@ -0,0 +1,79 @@
+use std::path::PathBuf;
+
+use skia_safe::Color4f;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BackgroundImageFit {
+    // Fills the whole window, cropping the image where the aspect ratios differ.
+    Cover,
+    // Shows the whole image, leaving the uncovered parts of the window empty.
+    Contain,
+    Stretch,
+}
+
+impl BackgroundImageFit {
+    pub fn from_name(name: &str) -> Option<BackgroundImageFit> {
+        match name {
+            "cover" => Some(BackgroundImageFit::Cover),
+            "contain" => Some(BackgroundImageFit::Contain),
+            "stretch" => Some(BackgroundImageFit::Stretch),
+            _ => None,
+        }
+    }
+}
+
+/// An image drawn behind the grid of a single window, as set by `w:neovide_background_image`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackgroundImage {
+    pub path: PathBuf,
+    pub opacity: f32,
+    pub fit: BackgroundImageFit,
+}
+
+impl BackgroundImage {
+    pub fn new(path: &str, opacity: f32, fit: BackgroundImageFit) -> BackgroundImage {
+        let path = match (path.strip_prefix("~/"), dirs::home_dir()) {
+            (Some(relative_path), Some(home_dir)) => home_dir.join(relative_path),
+            _ => PathBuf::from(path),
+        };
+
+        BackgroundImage {
+            path,
+            opacity: opacity.clamp(0.0, 1.0),
+            fit,
+        }
+    }
+}
+
+/// What's drawn beneath the cells of a single window that have the default background, as set by
+/// `w:neovide_background`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackgroundKind {
+    Color(Color4f),
+    // Goes from the first color at the top, or at the left when horizontal, to the second one.
+    Gradient {
+        from: Color4f,
+        to: Color4f,
+        horizontal: bool,
+    },
+    Image(BackgroundImage),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_expands_the_home_directory_and_clamps_the_opacity() {
+        let image = BackgroundImage::new("~/wallpaper.png", 1.5, BackgroundImageFit::Cover);
+
+        if let Some(home_dir) = dirs::home_dir() {
+            assert_eq!(image.path, home_dir.join("wallpaper.png"));
+        }
+        assert_eq!(image.opacity, 1.0);
+        assert_eq!(
+            BackgroundImage::new("/tmp/a.png", -1.0, BackgroundImageFit::Stretch).path,
+            PathBuf::from("/tmp/a.png")
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use crate::{
+    editor::{build_virtual_text, parse_color, Colors, Style},
+    renderer::LineFragment,
+    settings::*,
+};
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "blame"]
+pub struct BlameSettings {
+    pub color: String,
+}
+
+impl Default for BlameSettings {
+    fn default() -> Self {
+        Self {
+            color: "#80808060".to_string(),
+        }
+    }
+}
+
+impl BlameSettings {
+    fn style(&self) -> Option<Arc<Style>> {
+        let style = Style::new(Colors::new(parse_color(&self.color), None, None));
+        Some(Arc::new(style))
+    }
+}
+
+/// The annotation of a buffer line of a window, like who last changed the line and when, given as
+/// the line number starting at 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlameLine {
+    pub line: u64,
+    pub text: String,
+}
+
+/// Builds the blame for the row its line is shown on, aligned to the right edge of the window. It
+/// takes the end of the row over diagnostics, which are drawn between the text and the blame.
+pub fn build_blame(
+    blame: &BlameLine,
+    row: u64,
+    content_end: u64,
+    grid_width: u64,
+    settings: &BlameSettings,
+) -> Option<LineFragment> {
+    build_virtual_text(row, &blame.text, content_end, grid_width, settings.style())
+}
@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    editor::{cell_width, parse_color, Colors, Style},
+    renderer::LineFragment,
+    settings::*,
+};
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "breadcrumb"]
+pub struct BreadcrumbSettings {
+    pub color: String,
+    pub background: String,
+}
+
+impl Default for BreadcrumbSettings {
+    fn default() -> Self {
+        Self {
+            color: "".to_string(),
+            background: "".to_string(),
+        }
+    }
+}
+
+impl BreadcrumbSettings {
+    fn style(&self) -> Option<Arc<Style>> {
+        let mut style = Style::new(Colors::new(
+            parse_color(&self.color),
+            parse_color(&self.background),
+            None,
+        ));
+        style.italic = true;
+        Some(Arc::new(style))
+    }
+}
+
+/// Builds the bar drawn over the top row of a window, holding as much of the context as fits into
+/// the width of the window. Nothing is drawn for an empty context.
+pub fn build_breadcrumb(
+    text: &str,
+    grid_width: u64,
+    settings: &BreadcrumbSettings,
+) -> Option<LineFragment> {
+    let mut width = 0;
+    let mut shown = String::new();
+    for grapheme in text.graphemes(true) {
+        let grapheme_width = cell_width(grapheme);
+        if width + grapheme_width > grid_width {
+            break;
+        }
+        width += grapheme_width;
+        shown.push_str(grapheme);
+    }
+    if width == 0 {
+        return None;
+    }
+
+    Some(LineFragment {
+        text: shown,
+        window_left: 0,
+        window_top: 0,
+        width,
+        style: settings.style(),
+        foreground_styles: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn breadcrumb_is_cut_to_the_window_width() {
+        let settings = BreadcrumbSettings::default();
+        let breadcrumb = build_breadcrumb("main.rs > 日本 > run", 12, &settings).unwrap();
+        assert_eq!(breadcrumb.text, "main.rs > 日");
+        assert_eq!(breadcrumb.width, 12);
+
+        // A wide character which doesn't fit completely is left out.
+        let breadcrumb = build_breadcrumb("main.rs > 日本", 11, &settings).unwrap();
+        assert_eq!(breadcrumb.text, "main.rs > ");
+        assert_eq!(breadcrumb.width, 10);
+
+        assert!(build_breadcrumb("", 12, &settings).is_none());
+    }
+}
@@ -0,0 +1,60 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+const EMOJI_PRESENTATION_SELECTOR: char = '\u{FE0F}';
+
+// Wide and fullwidth characters along with emoji shown as such by default are double width, as
+// are symbols which only show as emoji when followed by the presentation selector.
+fn is_double_width(grapheme: &str) -> bool {
+    grapheme.contains(EMOJI_PRESENTATION_SELECTOR) || grapheme.width() > 1
+}
+
+/// The number of grid cells the text takes up once it's drawn, counting double width graphemes
+/// twice. Used for text which isn't in the grid yet, like the IME preedit.
+pub fn cell_width(text: &str) -> u64 {
+    text.graphemes(true)
+        .map(|grapheme| if is_double_width(grapheme) { 2 } else { 1 })
+        .sum()
+}
+
+/// Whether the text of a grid cell is a single double width character, of which the cell holds the
+/// left half.
+pub fn is_double_width_cell(text: &str) -> bool {
+    let mut graphemes = text.graphemes(true);
+    matches!(
+        (graphemes.next(), graphemes.next()),
+        (Some(grapheme), None) if is_double_width(grapheme)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cell_width_counts_double_width_graphemes_twice() {
+        assert_eq!(cell_width("日本a語"), 7);
+        assert_eq!(cell_width("かな漢字 abc"), 12);
+        // Combining marks and variation selectors belong to the cell of their base character.
+        assert_eq!(cell_width("e\u{301}\u{2764}\u{FE0F}"), 3);
+        assert_eq!(cell_width(""), 0);
+    }
+
+    #[test]
+    fn emoji_sequences_are_one_double_width_grapheme() {
+        // A family joined by zero width joiners, a flag and a skin tone modifier.
+        assert_eq!(cell_width("\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}"), 2);
+        assert_eq!(cell_width("\u{1F1EF}\u{1F1F5}"), 2);
+        assert_eq!(cell_width("\u{1F44B}\u{1F3FD}"), 2);
+    }
+
+    #[test]
+    fn emoji_are_double_width_unless_shown_as_text() {
+        assert_eq!(cell_width("\u{1F680}"), 2);
+        assert_eq!(cell_width("\u{26A1}"), 2);
+        // The thermometer is a symbol shown as text unless asked for the emoji.
+        assert_eq!(cell_width("\u{1F321}"), 1);
+        assert_eq!(cell_width("\u{1F321}\u{FE0F}"), 2);
+        assert!(!is_double_width_cell("\u{1F321}"));
+    }
+}
@@ -0,0 +1,25 @@
+use crate::settings::*;
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "collapsed_floats"]
+pub struct CollapsedFloatSettings {
+    pub enabled: bool,
+    // Rows kept of a collapsed float, enough for the top border with its title.
+    pub header_height: u64,
+}
+
+impl Default for CollapsedFloatSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            header_height: 1,
+        }
+    }
+}
+
+impl CollapsedFloatSettings {
+    /// The rows to show of a float, all of them for the focused one.
+    pub fn collapsed_height(&self, focused: bool) -> Option<u64> {
+        (self.enabled && !focused).then_some(self.header_height.max(1))
+    }
+}
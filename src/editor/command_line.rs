@@ -0,0 +1,159 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    bridge::{GridLineCell, StyledContent},
+    editor::cell_width,
+    settings::*,
+};
+
+/// The grid of the window the command line box is drawn in. Neovim numbers its grids from 1
+/// upwards, so this never clashes with one of them.
+pub const COMMAND_LINE_GRID: u64 = u64::MAX;
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "cmdline"]
+pub struct CommandLineSettings {
+    // Draws the command line as a box in the middle of the screen instead of letting Neovim draw
+    // it at the bottom. This is applied when the UI attaches, so it has to be set from the init
+    // file.
+    pub centered: bool,
+    pub width: u64,
+    pub border: bool,
+}
+
+impl CommandLineSettings {
+    /// The border is only drawn around the box in the middle of the screen. In the last row the
+    /// command line looks like the one of Neovim.
+    pub fn has_border(&self) -> bool {
+        self.centered && self.border
+    }
+}
+
+impl Default for CommandLineSettings {
+    fn default() -> Self {
+        Self {
+            centered: false,
+            width: 60,
+            border: true,
+        }
+    }
+}
+
+pub fn push_text(cells: &mut Vec<GridLineCell>, text: &str, highlight_id: u64) {
+    for grapheme in text.graphemes(true) {
+        cells.push(GridLineCell {
+            text: grapheme.to_owned(),
+            highlight_id: Some(highlight_id),
+            repeat: None,
+        });
+        // The second half of a double width character is an empty cell, like in Neovim's grids.
+        if cell_width(grapheme) > 1 {
+            cells.push(GridLineCell {
+                text: "".to_owned(),
+                highlight_id: Some(highlight_id),
+                repeat: None,
+            });
+        }
+    }
+}
+
+/// The command line being typed, as sent by `cmdline_show`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommandLine {
+    pub content: StyledContent,
+    // Byte offset of the cursor in the content.
+    pub position: u64,
+    pub first_character: String,
+    pub prompt: String,
+    pub indent: u64,
+    // Nesting level, higher when a command line is opened from another one like with <C-r>=.
+    pub level: u64,
+}
+
+impl CommandLine {
+    /// Lays the command line out into one cell per grid column, returning the cells along with
+    /// the column of the cursor.
+    pub fn cells(&self) -> (Vec<GridLineCell>, u64) {
+        let mut cells = Vec::new();
+        push_text(&mut cells, &self.first_character, 0);
+        push_text(&mut cells, &self.prompt, 0);
+        push_text(&mut cells, &" ".repeat(self.indent as usize), 0);
+
+        let mut cursor_column = None;
+        let mut offset = 0;
+        for (highlight_id, text) in &self.content {
+            let position = self.position as usize;
+            if cursor_column.is_none() && position <= offset + text.len() {
+                let before = text.get(..position - offset).unwrap_or(text);
+                cursor_column = Some(cells.len() as u64 + cell_width(before));
+            }
+            push_text(&mut cells, text, *highlight_id);
+            offset += text.len();
+        }
+
+        let cursor_column = cursor_column.unwrap_or(cells.len() as u64);
+        (cells, cursor_column)
+    }
+}
+
+/// The position and size of the command line box on a screen of the given size. It's centered
+/// when asked to, otherwise it takes the place of Neovim's command line in the last row, which is
+/// where it goes when Neovim only hands it over along with the messages.
+pub fn geometry(
+    (screen_width, screen_height): (u64, u64),
+    settings: &CommandLineSettings,
+) -> ((f64, f64), (u64, u64)) {
+    if !settings.centered {
+        let top = screen_height.saturating_sub(1);
+        return ((0.0, top as f64), (screen_width.max(1), 1));
+    }
+
+    let border = if settings.border { 2 } else { 0 };
+    let width = settings.width.min(screen_width).max(border + 1);
+    let height = 1 + border;
+
+    let left = screen_width.saturating_sub(width) / 2;
+    let top = screen_height.saturating_sub(height) / 2;
+    ((left as f64, top as f64), (width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cells_put_the_cursor_after_the_prefix_and_wide_characters() {
+        let command_line = CommandLine {
+            content: vec![(0, "e 日".to_owned()), (3, "x".to_owned())],
+            position: "e 日".len() as u64,
+            first_character: ":".to_owned(),
+            prompt: "".to_owned(),
+            indent: 1,
+            level: 1,
+        };
+
+        let (cells, cursor_column) = command_line.cells();
+        let texts: Vec<_> = cells.iter().map(|cell| cell.text.as_str()).collect();
+        assert_eq!(texts, vec![":", " ", "e", " ", "日", "", "x"]);
+        assert_eq!(cells[6].highlight_id, Some(3));
+        assert_eq!(cursor_column, 6);
+    }
+
+    #[test]
+    fn geometry_is_the_last_row_unless_centered() {
+        let settings = CommandLineSettings {
+            centered: false,
+            ..CommandLineSettings::default()
+        };
+        assert_eq!(geometry((80, 24), &settings), ((0.0, 23.0), (80, 1)));
+        assert!(!settings.has_border());
+
+        let settings = CommandLineSettings {
+            centered: true,
+            width: 40,
+            border: true,
+        };
+        assert_eq!(geometry((80, 24), &settings), ((20.0, 10.0), (40, 3)));
+        assert!(settings.has_border());
+    }
+}
@@ -0,0 +1,66 @@
+use skia_safe::Color4f;
+
+use crate::{editor::parse_color, settings::*};
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "crosshair"]
+pub struct CrosshairSettings {
+    pub enabled: bool,
+    // Width of the lines in pixels.
+    pub thickness: f32,
+    pub color: String,
+}
+
+impl Default for CrosshairSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            thickness: 1.0,
+            color: "#80808080".to_string(),
+        }
+    }
+}
+
+impl CrosshairSettings {
+    pub fn color(&self) -> Option<Color4f> {
+        parse_color(&self.color)
+    }
+}
+
+/// Two thin lines through the center of the cursor cell, across the window the cursor is in. The
+/// window is given in cells of the screen, the cursor cell in cells of the window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Crosshair {
+    pub left: f64,
+    pub top: f64,
+    pub width: u64,
+    pub height: u64,
+    pub column: u64,
+    pub row: u64,
+    pub thickness: f32,
+    pub color: Color4f,
+}
+
+/// Places the crosshair on the cursor cell of a window at the given position. Nothing is drawn
+/// for a cursor outside of the window.
+pub fn build_crosshair(
+    (column, row): (u64, u64),
+    (window_left, window_top): (f64, f64),
+    (window_width, window_height): (u64, u64),
+    settings: &CrosshairSettings,
+) -> Option<Crosshair> {
+    if column >= window_width || row >= window_height {
+        return None;
+    }
+
+    Some(Crosshair {
+        left: window_left,
+        top: window_top,
+        width: window_width,
+        height: window_height,
+        column,
+        row,
+        thickness: settings.thickness,
+        color: settings.color()?,
+    })
+}
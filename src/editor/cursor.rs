@@ -1,11 +1,301 @@
 use std::{collections::HashMap, sync::Arc};
 
-use skia_safe::Color4f;
+use log::error;
+use nvim_rs::Value;
+use skia_safe::{Color4f, Point};
 
-use crate::editor::style::{Colors, Style};
+use crate::{
+    dimensions::Dimensions,
+    editor::style::{parse_color, Colors, Style},
+    settings::*,
+};
 
 use super::grid::GridCell;
 
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "cursor_shadow"]
+pub struct CursorShadowSettings {
+    // A radius of zero disables the shadow.
+    pub radius: f32,
+    pub color: String,
+}
+
+impl Default for CursorShadowSettings {
+    fn default() -> Self {
+        Self {
+            radius: 0.0,
+            color: "#00000080".to_string(),
+        }
+    }
+}
+
+impl CursorShadowSettings {
+    pub fn shadow(&self) -> Option<CursorShadow> {
+        if self.radius <= 0.0 {
+            return None;
+        }
+
+        Some(CursorShadow {
+            color: parse_color(&self.color)?,
+            radius: self.radius,
+        })
+    }
+}
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "cursor_contrast"]
+pub struct CursorContrastSettings {
+    // Picks the cursor color from the background under it instead of the cursor highlight.
+    pub enabled: bool,
+    // Backgrounds with a luminance below this get the light cursor, the others the dark one.
+    pub threshold: f32,
+    pub light_color: String,
+    pub dark_color: String,
+}
+
+impl Default for CursorContrastSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 0.5,
+            light_color: "#ffffff".to_string(),
+            dark_color: "#000000".to_string(),
+        }
+    }
+}
+
+// The relative luminance of a color, from 0 for black to 1 for white.
+fn luminance(color: Color4f) -> f32 {
+    0.2126 * color.r + 0.7152 * color.g + 0.0722 * color.b
+}
+
+impl CursorContrastSettings {
+    /// The cursor color which stands out on the given background, if the contrast is enabled.
+    pub fn cursor_color(&self, background: Color4f) -> Option<Color4f> {
+        if !self.enabled {
+            return None;
+        }
+
+        if luminance(background) < self.threshold {
+            parse_color(&self.light_color)
+        } else {
+            parse_color(&self.dark_color)
+        }
+    }
+
+    /// The style of the cursor with its color picked for the background of the cell under it, the
+    /// text under a block cursor takes the background of the cell.
+    pub fn cursor_style(
+        &self,
+        cursor_style: &Option<Arc<Style>>,
+        background: Color4f,
+    ) -> Option<Arc<Style>> {
+        let color = self.cursor_color(background)?;
+        let mut style = cursor_style
+            .as_deref()
+            .cloned()
+            .unwrap_or_else(|| Style::new(Colors::new(None, None, None)));
+        style.colors.foreground = Some(background);
+        style.colors.background = Some(color);
+        style.reverse = false;
+        Some(Arc::new(style))
+    }
+}
+
+/// What happens to the cursor when it ends up outside of what its window shows, for example
+/// while the window is scrolled away from it or after the grid shrank.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CursorOutOfView {
+    // Drawn where neovim put it anyway.
+    Show,
+    Hide,
+    Clamp,
+}
+
+impl ParseFromValue for CursorOutOfView {
+    fn parse_from_value(&mut self, value: Value) {
+        if value.is_str() {
+            *self = match value.as_str().unwrap() {
+                "show" => CursorOutOfView::Show,
+                "hide" => CursorOutOfView::Hide,
+                "clamp" => CursorOutOfView::Clamp,
+                value => {
+                    error!("Expected show, hide or clamp, but received {:?}", value);
+                    return;
+                }
+            };
+        } else {
+            error!(
+                "Expected a cursor out of view string, but received {:?}",
+                value
+            );
+        }
+    }
+}
+
+impl From<CursorOutOfView> for Value {
+    fn from(out_of_view: CursorOutOfView) -> Self {
+        match out_of_view {
+            CursorOutOfView::Show => Value::from("show"),
+            CursorOutOfView::Hide => Value::from("hide"),
+            CursorOutOfView::Clamp => Value::from("clamp"),
+        }
+    }
+}
+
+/// How much of a tab the block cursor covers when Neovim drew the tab as a run of blank cells.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CursorTabWidth {
+    Cell,
+    Full,
+}
+
+impl ParseFromValue for CursorTabWidth {
+    fn parse_from_value(&mut self, value: Value) {
+        if value.is_str() {
+            *self = match value.as_str().unwrap() {
+                "cell" => CursorTabWidth::Cell,
+                "full" => CursorTabWidth::Full,
+                value => {
+                    error!("Expected cell or full, but received {:?}", value);
+                    return;
+                }
+            };
+        } else {
+            error!(
+                "Expected a cursor tab width string, but received {:?}",
+                value
+            );
+        }
+    }
+}
+
+impl From<CursorTabWidth> for Value {
+    fn from(tab_width: CursorTabWidth) -> Self {
+        match tab_width {
+            CursorTabWidth::Cell => Value::from("cell"),
+            CursorTabWidth::Full => Value::from("full"),
+        }
+    }
+}
+
+/// Shapes forced onto the cursor in some modes, keyed by the mode names of `mode_info_set` like
+/// `insert` or `cmdline_normal`. They win over the shapes Neovim reports from 'guicursor'.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct CursorShapeOverrides(HashMap<String, CursorShape>);
+
+impl CursorShapeOverrides {
+    /// Forces the shape of the mode the cursor just changed to. The cell percentage is dropped as
+    /// well when the shape differs, as it's only meant for the reported one.
+    pub fn apply(&self, cursor_mode: &CursorMode, cursor: &mut Cursor) {
+        let shape = cursor_mode
+            .name
+            .as_deref()
+            .and_then(|mode_name| self.0.get(mode_name));
+        if let Some(shape) = shape {
+            if cursor_mode.shape.as_ref() != Some(shape) {
+                cursor.shape = shape.clone();
+                cursor.cell_percentage = None;
+            }
+        }
+    }
+}
+
+impl ParseFromValue for CursorShapeOverrides {
+    fn parse_from_value(&mut self, value: Value) {
+        // Neovim sends an empty table as an array.
+        if value.as_array().map(|array| array.is_empty()) == Some(true) {
+            *self = CursorShapeOverrides::default();
+            return;
+        }
+        let entries = match value {
+            Value::Map(entries) => entries,
+            value => {
+                error!(
+                    "Expected a table of cursor shapes, but received {:?}",
+                    value
+                );
+                return;
+            }
+        };
+
+        let mut overrides = HashMap::new();
+        for (mode_name, shape) in entries {
+            match (
+                mode_name.as_str(),
+                shape.as_str().and_then(CursorShape::from_type_name),
+            ) {
+                (Some(mode_name), Some(shape)) => {
+                    overrides.insert(mode_name.to_owned(), shape);
+                }
+                _ => error!(
+                    "Expected a mode name mapped to block, horizontal or vertical, but received {:?} = {:?}",
+                    mode_name, shape
+                ),
+            }
+        }
+        *self = CursorShapeOverrides(overrides);
+    }
+}
+
+impl From<CursorShapeOverrides> for Value {
+    fn from(overrides: CursorShapeOverrides) -> Self {
+        let mut entries: Vec<_> = overrides.0.into_iter().collect();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Value::Map(
+            entries
+                .into_iter()
+                .map(|(mode_name, shape)| (Value::from(mode_name), Value::from(shape.type_name())))
+                .collect(),
+        )
+    }
+}
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "cursor"]
+pub struct CursorViewSettings {
+    pub out_of_view: CursorOutOfView,
+    pub shape_overrides: CursorShapeOverrides,
+    pub tab_width: CursorTabWidth,
+}
+
+impl Default for CursorViewSettings {
+    fn default() -> Self {
+        Self {
+            out_of_view: CursorOutOfView::Show,
+            shape_overrides: CursorShapeOverrides::default(),
+            tab_width: CursorTabWidth::Cell,
+        }
+    }
+}
+
+/// Where a cursor moving to another grid goes from and to, in cells of the screen, so it can
+/// animate across the gap between the windows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CursorTravel {
+    pub start: (f64, f64),
+    pub end: (f64, f64),
+}
+
+impl CursorTravel {
+    /// The top left corners of the start and end cells on the screen.
+    pub fn to_pixels(self, font_dimensions: Dimensions) -> (Point, Point) {
+        let to_pixels = |(left, top): (f64, f64)| {
+            Point::new(
+                (left * font_dimensions.width as f64) as f32,
+                (top * font_dimensions.height as f64) as f32,
+            )
+        };
+        (to_pixels(self.start), to_pixels(self.end))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CursorShadow {
+    pub color: Color4f,
+    pub radius: f32,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum CursorShape {
     Block,
@@ -22,10 +312,19 @@ impl CursorShape {
             _ => None,
         }
     }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            CursorShape::Block => "block",
+            CursorShape::Horizontal => "horizontal",
+            CursorShape::Vertical => "vertical",
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq)]
 pub struct CursorMode {
+    pub name: Option<String>,
     pub shape: Option<CursorShape>,
     pub style_id: Option<u64>,
     pub cell_percentage: Option<f32>,
@@ -46,7 +345,12 @@ pub struct Cursor {
     pub style: Option<Arc<Style>>,
     pub enabled: bool,
     pub double_width: bool,
+    // Cells covered by a block cursor sitting on a tab, when it covers the whole tab.
+    pub tab_width: Option<u64>,
     pub grid_cell: GridCell,
+    pub shadow: Option<CursorShadow>,
+    // Set when the cursor just moved to another grid.
+    pub travel: Option<CursorTravel>,
 }
 
 impl Cursor {
@@ -62,7 +366,10 @@ impl Cursor {
             blinkoff: None,
             enabled: true,
             double_width: false,
+            tab_width: None,
             grid_cell: (" ".to_string(), None),
+            shadow: None,
+            travel: None,
         }
     }
 
@@ -84,12 +391,13 @@ impl Cursor {
         return self
             .style
             .as_ref()
-            .map(|s| (255_f32 * ((100 - s.blend) as f32 / 100.0_f32)) as u8)
+            .map(|s| (255_f32 * s.background_opacity()) as u8)
             .unwrap_or(255);
     }
 
     pub fn change_mode(&mut self, cursor_mode: &CursorMode, styles: &HashMap<u64, Arc<Style>>) {
         let CursorMode {
+            name: _,
             shape,
             style_id,
             cell_percentage,
@@ -198,6 +506,7 @@ mod tests {
     #[test]
     fn test_change_mode() {
         let cursor_mode = CursorMode {
+            name: Some("normal".to_owned()),
             shape: Some(CursorShape::Horizontal),
             style_id: Some(1),
             cell_percentage: Some(100.0),
@@ -219,6 +528,7 @@ mod tests {
         assert_eq!(cursor.blinkoff, Some(1));
 
         let cursor_mode_with_none = CursorMode {
+            name: None,
             shape: None,
             style_id: None,
             cell_percentage: None,
@@ -234,4 +544,67 @@ mod tests {
         assert_eq!(cursor.blinkon, None);
         assert_eq!(cursor.blinkoff, None);
     }
+
+    #[test]
+    fn shape_override_wins_over_the_reported_shape() {
+        let mut overrides = CursorShapeOverrides::default();
+        overrides.parse_from_value(Value::Map(vec![(
+            Value::from("insert"),
+            Value::from("block"),
+        )]));
+        let insert = CursorMode {
+            name: Some("insert".to_owned()),
+            shape: Some(CursorShape::Vertical),
+            cell_percentage: Some(0.25),
+            ..CursorMode::default()
+        };
+        let normal = CursorMode {
+            name: Some("normal".to_owned()),
+            shape: Some(CursorShape::Horizontal),
+            ..CursorMode::default()
+        };
+
+        let mut cursor = Cursor::new();
+        cursor.change_mode(&insert, &HashMap::new());
+        overrides.apply(&insert, &mut cursor);
+        assert_eq!(cursor.shape, CursorShape::Block);
+        assert_eq!(cursor.cell_percentage, None);
+
+        cursor.change_mode(&normal, &HashMap::new());
+        overrides.apply(&normal, &mut cursor);
+        assert_eq!(cursor.shape, CursorShape::Horizontal);
+    }
+
+    #[test]
+    fn test_cursor_contrast_picks_the_color_by_the_background_luminance() {
+        let settings = CursorContrastSettings {
+            enabled: true,
+            threshold: 0.4,
+            ..CursorContrastSettings::default()
+        };
+        let white = Color4f::new(1.0, 1.0, 1.0, 1.0);
+        let black = Color4f::new(0.0, 0.0, 0.0, 1.0);
+
+        // A mid gray is light enough for this threshold, a slightly darker one isn't.
+        assert_eq!(
+            settings.cursor_color(Color4f::new(0.45, 0.45, 0.45, 1.0)),
+            Some(black)
+        );
+        assert_eq!(
+            settings.cursor_color(Color4f::new(0.35, 0.35, 0.35, 1.0)),
+            Some(white)
+        );
+
+        let style = settings.cursor_style(&None, black).unwrap();
+        assert_eq!(style.colors.background, Some(white));
+        assert_eq!(style.colors.foreground, Some(black));
+
+        assert_eq!(CursorContrastSettings::default().cursor_color(black), None);
+    }
+
+    #[test]
+    fn test_cursor_shadow_is_disabled_without_radius() {
+        let settings = CursorShadowSettings::default();
+        assert_eq!(settings.shadow(), None);
+    }
 }
@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    editor::{cell_width, parse_color, Colors, Style},
+    renderer::LineFragment,
+    settings::*,
+};
+
+// Blank cells kept between the text of a row and its diagnostic.
+const DIAGNOSTIC_GAP: u64 = 2;
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "diagnostics"]
+pub struct DiagnosticSettings {
+    pub color: String,
+}
+
+impl Default for DiagnosticSettings {
+    fn default() -> Self {
+        Self {
+            color: "#80808080".to_string(),
+        }
+    }
+}
+
+impl DiagnosticSettings {
+    fn style(&self) -> Option<Arc<Style>> {
+        let mut style = Style::new(Colors::new(parse_color(&self.color), None, None));
+        style.italic = true;
+        Some(Arc::new(style))
+    }
+}
+
+/// A message shown at the end of a buffer line of a window, given as the line number starting at
+/// 1, so that it stays on its line when the window scrolls.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub line: u64,
+    pub message: String,
+}
+
+/// Builds the right aligned text of a diagnostic for the row its line is shown on, whose text ends
+/// at `content_end`. See [`build_virtual_text`].
+pub fn build_diagnostic(
+    diagnostic: &Diagnostic,
+    row: u64,
+    content_end: u64,
+    row_end: u64,
+    settings: &DiagnosticSettings,
+) -> Option<LineFragment> {
+    build_virtual_text(
+        row,
+        &diagnostic.message,
+        content_end,
+        row_end,
+        settings.style(),
+    )
+}
+
+/// Builds text drawn over the blank end of a row, whose text ends at `content_end`, aligned to
+/// `row_end`. The text is cut with an ellipsis when it doesn't fit, and left out when not even that
+/// fits.
+pub fn build_virtual_text(
+    row: u64,
+    text: &str,
+    content_end: u64,
+    row_end: u64,
+    style: Option<Arc<Style>>,
+) -> Option<LineFragment> {
+    let available = row_end.saturating_sub(content_end + DIAGNOSTIC_GAP);
+    // Only the first line of the text is shown.
+    let message = text.lines().next().unwrap_or("").trim();
+    if available == 0 || message.is_empty() {
+        return None;
+    }
+
+    let mut text = String::new();
+    let mut width = 0;
+    if cell_width(message) <= available {
+        text.push_str(message);
+        width = cell_width(message);
+    } else {
+        for grapheme in message.graphemes(true) {
+            let grapheme_width = cell_width(grapheme);
+            if width + grapheme_width + 1 > available {
+                break;
+            }
+            text.push_str(grapheme);
+            width += grapheme_width;
+        }
+        text.push('…');
+        width += 1;
+    }
+
+    Some(LineFragment {
+        text,
+        window_left: row_end - width,
+        window_top: row,
+        width,
+        style,
+        foreground_styles: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostics_are_right_aligned_and_cut_to_the_free_cells() {
+        let settings = DiagnosticSettings::default();
+        let diagnostic = Diagnostic {
+            line: 12,
+            message: "unused variable\nsecond line".to_owned(),
+        };
+        let placement = |content_end| {
+            build_diagnostic(&diagnostic, 3, content_end, 30, &settings)
+                .map(|fragment| (fragment.text, fragment.window_left, fragment.window_top))
+        };
+
+        assert_eq!(placement(4), Some(("unused variable".to_owned(), 15, 3)));
+        assert_eq!(placement(20), Some(("unused …".to_owned(), 22, 3)));
+        assert_eq!(placement(28), None);
+    }
+}
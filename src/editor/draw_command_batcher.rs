@@ -1,30 +1,370 @@
-use std::sync::mpsc::{channel, Receiver, SendError, Sender};
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeSet,
+    sync::mpsc::{channel, Receiver, SendError, Sender},
+    time::{Duration, Instant},
+};
 
-use crate::{editor::DrawCommand, event_aggregator::EVENT_AGGREGATOR};
+use log::warn;
+
+use crate::{
+    editor::{DrawCommand, RenderCounters, RenderMetrics},
+    event_aggregator::EVENT_AGGREGATOR,
+    renderer::WindowDrawCommand,
+};
+
+// Scrolls in the same direction compose, `a` followed by `b` moves the content as far as `a + b`.
+fn same_direction(a: i64, b: i64) -> bool {
+    a.signum() * b.signum() >= 0
+}
+
+/// Merges consecutive scrolls of the same region of a window into one, so the renderer moves the
+/// texture only once. The grid itself has already been scrolled once per event at this point, and
+/// the rows uncovered by the scrolls are redrawn by the lines following them.
+fn coalesce_scrolls(draw_commands: Vec<DrawCommand>) -> Vec<DrawCommand> {
+    let mut coalesced: Vec<DrawCommand> = Vec::with_capacity(draw_commands.len());
+    for draw_command in draw_commands {
+        if let (
+            Some(DrawCommand::Window {
+                grid_id: previous_grid_id,
+                command:
+                    WindowDrawCommand::Scroll {
+                        top: previous_top,
+                        bottom: previous_bottom,
+                        left: previous_left,
+                        right: previous_right,
+                        rows: previous_rows,
+                        cols: previous_cols,
+                    },
+            }),
+            DrawCommand::Window {
+                grid_id,
+                command:
+                    WindowDrawCommand::Scroll {
+                        top,
+                        bottom,
+                        left,
+                        right,
+                        rows,
+                        cols,
+                    },
+            },
+        ) = (coalesced.last_mut(), &draw_command)
+        {
+            if *previous_grid_id == *grid_id
+                && (
+                    *previous_top,
+                    *previous_bottom,
+                    *previous_left,
+                    *previous_right,
+                ) == (*top, *bottom, *left, *right)
+                && same_direction(*previous_rows, *rows)
+                && same_direction(*previous_cols, *cols)
+            {
+                *previous_rows += rows;
+                *previous_cols += cols;
+                continue;
+            }
+        }
+        coalesced.push(draw_command);
+    }
+    coalesced
+}
+
+// Far more than a flush of a full screen queues, which is about one command per row of every
+// window. Lines past it are dropped rather than growing without bounds, see `queue`.
+const MAX_QUEUED_LINES: usize = 1 << 14;
 
 pub struct DrawCommandBatcher {
     window_draw_command_sender: Sender<DrawCommand>,
     window_draw_command_receiver: Receiver<DrawCommand>,
+    counters: RenderCounters,
+    max_queued_lines: usize,
+    queued: Cell<usize>,
+    dropped: Cell<u64>,
+    // Dropped since the last batch was taken, which is logged once along with it.
+    dropped_in_batch: Cell<u64>,
+    // The grids that lost lines, which have to be drawn again from scratch.
+    dropped_grids: RefCell<BTreeSet<u64>>,
+    // A copy of every batch sent, which the tests look at instead of the renderer.
+    #[cfg(test)]
+    sent_batches: RefCell<Vec<Vec<DrawCommand>>>,
 }
 
 impl DrawCommandBatcher {
     pub fn new() -> DrawCommandBatcher {
+        DrawCommandBatcher::with_max_queued_lines(MAX_QUEUED_LINES)
+    }
+
+    pub fn with_max_queued_lines(max_queued_lines: usize) -> DrawCommandBatcher {
         let (sender, receiver) = channel();
 
         DrawCommandBatcher {
             window_draw_command_sender: sender,
             window_draw_command_receiver: receiver,
+            counters: RenderCounters::new(),
+            max_queued_lines,
+            queued: Cell::new(0),
+            dropped: Cell::new(0),
+            dropped_in_batch: Cell::new(0),
+            dropped_grids: RefCell::new(BTreeSet::new()),
+            #[cfg(test)]
+            sent_batches: RefCell::new(Vec::new()),
         }
     }
 
+    /// Queues the command for the next batch. Once more commands than the maximum are waiting,
+    /// the lines drawn are dropped and their windows remembered to be drawn again whole, see
+    /// `take_dropped_grids`. Everything else carries state the renderer can't get back, so it's
+    /// always queued.
     pub fn queue(&self, draw_command: DrawCommand) -> Result<(), Box<SendError<DrawCommand>>> {
+        if self.queued.get() >= self.max_queued_lines {
+            if let DrawCommand::Window {
+                grid_id,
+                command: WindowDrawCommand::DrawLine(_),
+            } = &draw_command
+            {
+                self.dropped.set(self.dropped.get() + 1);
+                self.dropped_in_batch.set(self.dropped_in_batch.get() + 1);
+                self.dropped_grids.borrow_mut().insert(*grid_id);
+                return Ok(());
+            }
+        }
+
         self.window_draw_command_sender
             .send(draw_command)
-            .map_err(Box::new)
+            .map_err(Box::new)?;
+        self.queued.set(self.queued.get() + 1);
+        Ok(())
+    }
+
+    /// The grids that lost lines since the last call. What the renderer shows of them is wrong
+    /// until they're drawn again whole.
+    pub fn take_dropped_grids(&self) -> BTreeSet<u64> {
+        std::mem::take(&mut *self.dropped_grids.borrow_mut())
+    }
+
+    fn take_batch(&self) -> Vec<DrawCommand> {
+        let batch = coalesce_scrolls(self.window_draw_command_receiver.try_iter().collect());
+        let dropped = self.dropped_in_batch.replace(0);
+        if dropped > 0 {
+            warn!(
+                "Dropped {} lines that didn't fit next to the {} queued draw commands, {} dropped so far",
+                dropped,
+                self.queued.get(),
+                self.dropped.get()
+            );
+        }
+        self.queued.set(0);
+        self.counters.count_batch(&batch);
+        batch
     }
 
-    pub fn send_batch(&self) {
-        let batch: Vec<DrawCommand> = self.window_draw_command_receiver.try_iter().collect();
+    /// Sends the queued commands to the renderer, returning how many were sent.
+    pub fn send_batch(&self) -> usize {
+        let batch = self.take_batch();
+        let sent = batch.len();
+        #[cfg(test)]
+        self.sent_batches.borrow_mut().push(batch.clone());
         EVENT_AGGREGATOR.send(batch);
+        sent
+    }
+
+    #[cfg(test)]
+    pub fn drain(&self) -> Vec<DrawCommand> {
+        self.take_batch()
+    }
+
+    #[cfg(test)]
+    pub fn take_sent_batches(&self) -> Vec<Vec<DrawCommand>> {
+        std::mem::take(&mut *self.sent_batches.borrow_mut())
+    }
+
+    /// The metrics of the batches sent since the last report, once every `interval`.
+    pub fn take_metrics(&self, now: Instant, interval: Duration) -> Option<RenderMetrics> {
+        self.counters.take_metrics(now, interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nvim_rs::Value;
+
+    use super::*;
+    use crate::{editor::WindowType, renderer::LineFragment};
+
+    fn scroll(grid_id: u64, rows: i64) -> DrawCommand {
+        DrawCommand::Window {
+            grid_id,
+            command: WindowDrawCommand::Scroll {
+                top: 0,
+                bottom: 10,
+                left: 0,
+                right: 20,
+                rows,
+                cols: 0,
+            },
+        }
+    }
+
+    fn scrolled_rows(draw_commands: &[DrawCommand]) -> Vec<(u64, i64)> {
+        draw_commands
+            .iter()
+            .filter_map(|draw_command| match draw_command {
+                DrawCommand::Window {
+                    grid_id,
+                    command: WindowDrawCommand::Scroll { rows, .. },
+                } => Some((*grid_id, *rows)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn consecutive_scrolls_of_a_region_are_coalesced() {
+        let batcher = DrawCommandBatcher::new();
+        batcher.queue(scroll(1, 2)).unwrap();
+        batcher.queue(scroll(1, 3)).unwrap();
+
+        assert_eq!(scrolled_rows(&batcher.drain()), vec![(1, 5)]);
+    }
+
+    #[test]
+    fn scrolls_are_not_coalesced_across_grids_directions_or_other_commands() {
+        let batcher = DrawCommandBatcher::new();
+        batcher.queue(scroll(1, 2)).unwrap();
+        batcher.queue(scroll(2, 2)).unwrap();
+        batcher.queue(scroll(2, -1)).unwrap();
+        batcher
+            .queue(DrawCommand::Window {
+                grid_id: 2,
+                command: WindowDrawCommand::Clear,
+            })
+            .unwrap();
+        batcher.queue(scroll(2, -1)).unwrap();
+
+        assert_eq!(
+            scrolled_rows(&batcher.drain()),
+            vec![(1, 2), (2, 2), (2, -1), (2, -1)]
+        );
+    }
+
+    fn line(grid_id: u64) -> DrawCommand {
+        DrawCommand::Window {
+            grid_id,
+            command: WindowDrawCommand::DrawLine(Vec::new()),
+        }
+    }
+
+    #[test]
+    fn only_lines_past_the_maximum_are_dropped() {
+        let batcher = DrawCommandBatcher::with_max_queued_lines(2);
+        batcher.queue(line(1)).unwrap();
+        batcher.queue(line(2)).unwrap();
+
+        batcher.queue(line(3)).unwrap();
+        batcher
+            .queue(DrawCommand::Window {
+                grid_id: 3,
+                command: WindowDrawCommand::Position {
+                    grid_position: (1.0, 2.0),
+                    grid_size: (10, 5),
+                    floating_order: None,
+                    zoom: 1.0,
+                    window_type: WindowType::Editor,
+                    snap_to_pixels: false,
+                },
+            })
+            .unwrap();
+        batcher.queue(line(4)).unwrap();
+        batcher
+            .queue(DrawCommand::Window {
+                grid_id: 4,
+                command: WindowDrawCommand::Close,
+            })
+            .unwrap();
+
+        // The position and the close still reach the renderer.
+        let batch = batcher.drain();
+        assert!(matches!(
+            batch.as_slice(),
+            [
+                DrawCommand::Window { grid_id: 1, .. },
+                DrawCommand::Window { grid_id: 2, .. },
+                DrawCommand::Window {
+                    grid_id: 3,
+                    command: WindowDrawCommand::Position { .. },
+                },
+                DrawCommand::Window {
+                    grid_id: 4,
+                    command: WindowDrawCommand::Close,
+                },
+            ]
+        ));
+
+        // The grids that lost lines are reported once.
+        assert_eq!(
+            batcher.take_dropped_grids().into_iter().collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+        assert!(batcher.take_dropped_grids().is_empty());
+
+        // Taking the batch makes room again.
+        batcher.queue(line(5)).unwrap();
+        assert_eq!(batcher.drain().len(), 1);
+        assert!(batcher.take_dropped_grids().is_empty());
+    }
+
+    #[test]
+    fn metrics_count_the_batches_since_the_last_report() {
+        let batcher = DrawCommandBatcher::new();
+        let fragment = |window_left| LineFragment {
+            text: "a".to_owned(),
+            window_left,
+            window_top: 0,
+            width: 1,
+            style: None,
+            foreground_styles: Vec::new(),
+        };
+        batcher
+            .queue(DrawCommand::Window {
+                grid_id: 1,
+                command: WindowDrawCommand::DrawLine(vec![fragment(0), fragment(1)]),
+            })
+            .unwrap();
+        batcher.queue(scroll(1, 2)).unwrap();
+        batcher.drain();
+        batcher.queue(scroll(1, 1)).unwrap();
+        batcher.drain();
+
+        let interval = Duration::from_secs(60);
+        assert_eq!(batcher.take_metrics(Instant::now(), interval), None);
+
+        let metrics = batcher
+            .take_metrics(Instant::now() + interval, interval)
+            .unwrap();
+        let counters = match Value::from(metrics) {
+            Value::Map(entries) => entries
+                .into_iter()
+                .map(|(key, value)| (key.as_str().unwrap().to_owned(), value.as_f64().unwrap()))
+                .collect::<Vec<_>>(),
+            value => panic!("Expected a map, got {value:?}"),
+        };
+        let counter = |name: &str| {
+            counters
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| *value)
+                .unwrap()
+        };
+        assert!((counter("commands_per_second") - 3.0 / 60.0).abs() < 0.001);
+        assert!((counter("redraws_per_second") - 2.0 / 60.0).abs() < 0.001);
+        assert_eq!(counter("fragments"), 2.0);
+
+        // The counters start again after a report.
+        let metrics = batcher
+            .take_metrics(Instant::now() + interval * 2, interval)
+            .unwrap();
+        assert_eq!(metrics.fragments, 0);
     }
 }
@@ -1,9 +1,153 @@
 use std::sync::Arc;
 
-use crate::editor::style::Style;
+use log::warn;
+use skia_safe::Color4f;
+
+use crate::{
+    cmd_line::CmdLineSettings,
+    editor::{cell_width, style::Style, VisualBlock},
+};
+
+/// Bounds on what neovim can make the grids hold and how it's sent to the renderer, set from the
+/// command line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GridLimits {
+    // The largest width and height a grid is allocated with, so that a bogus size sent by neovim
+    // can't exhaust the memory.
+    pub max_grid_size: u64,
+    // The most combining marks kept in a cell, so that text stacking hundreds of them can't make
+    // the shaping and drawing of a single cell arbitrarily slow.
+    pub max_combining_marks: u64,
+    // The widest line fragment sent to the renderer, with longer runs of the same style cut into
+    // chunks. Zero keeps whole runs.
+    pub max_fragment_width: u64,
+}
+
+impl GridLimits {
+    pub fn from_cmd_line(settings: &CmdLineSettings) -> GridLimits {
+        GridLimits {
+            max_grid_size: settings.max_grid_size.max(1),
+            max_combining_marks: settings.max_combining_marks,
+            max_fragment_width: settings.max_fragment_width,
+        }
+    }
+}
+
+// The same as the defaults of the command line arguments.
+impl Default for GridLimits {
+    fn default() -> GridLimits {
+        GridLimits {
+            max_grid_size: 2048,
+            max_combining_marks: 16,
+            max_fragment_width: 0,
+        }
+    }
+}
+
+// The blocks of combining diacritical marks, which stacked text is made of.
+const COMBINING_MARK_RANGES: &[(u32, u32)] = &[
+    (0x0300, 0x036F),
+    (0x1AB0, 0x1AFF),
+    (0x1DC0, 0x1DFF),
+    (0x20D0, 0x20FF),
+    (0xFE20, 0xFE2F),
+];
+
+fn is_combining_mark(character: char) -> bool {
+    COMBINING_MARK_RANGES
+        .iter()
+        .any(|(start, end)| (*start..=*end).contains(&(character as u32)))
+}
+
+/// Drops the combining marks of a cell past the maximum, or returns `None` when it has few enough
+/// of them to be kept as it is.
+pub fn limit_combining_marks(text: &str, max_combining_marks: u64) -> Option<String> {
+    let max_combining_marks = max_combining_marks as usize;
+    if text
+        .chars()
+        .filter(|character| is_combining_mark(*character))
+        .count()
+        <= max_combining_marks
+    {
+        return None;
+    }
+
+    let mut combining_marks = 0;
+    Some(
+        text.chars()
+            .filter(|character| {
+                if is_combining_mark(*character) {
+                    combining_marks += 1;
+                    combining_marks <= max_combining_marks
+                } else {
+                    true
+                }
+            })
+            .collect(),
+    )
+}
+
+fn clamp_grid_size((width, height): (u64, u64), max_grid_size: u64) -> (u64, u64) {
+    if width > max_grid_size || height > max_grid_size {
+        warn!(
+            "Grid size {}x{} exceeds the maximum of {}, clamping it",
+            width, height, max_grid_size
+        );
+    }
+    (width.min(max_grid_size), height.min(max_grid_size))
+}
 
 pub type GridCell = (String, Option<Arc<Style>>);
 
+/// Describes the style for exported grids, as its colors in hex and the attributes it has set,
+/// separated by commas. A style without any is written as `plain`.
+pub fn describe_style(style: &Style) -> String {
+    let hex = |color: Color4f| {
+        let [red, green, blue, alpha] =
+            [color.r, color.g, color.b, color.a].map(|component| (component * 255.0).round() as u8);
+        if alpha == 255 {
+            format!("#{red:02x}{green:02x}{blue:02x}")
+        } else {
+            format!("#{red:02x}{green:02x}{blue:02x}{alpha:02x}")
+        }
+    };
+
+    let mut attributes = Vec::new();
+    let colors = [
+        ("fg", style.colors.foreground),
+        ("bg", style.colors.background),
+        ("sp", style.colors.special),
+    ];
+    for (name, color) in colors {
+        if let Some(color) = color {
+            attributes.push(format!("{name}={}", hex(color)));
+        }
+    }
+    let flags = [
+        ("reverse", style.reverse),
+        ("italic", style.italic),
+        ("bold", style.bold),
+        ("strikethrough", style.strikethrough),
+    ];
+    for (name, set) in flags {
+        if set {
+            attributes.push(name.to_owned());
+        }
+    }
+    if let Some(underline) = style.underline {
+        attributes.push(format!("{underline:?}").to_lowercase());
+    }
+    if style.blend > 0 {
+        attributes.push(format!("blend={}", style.blend));
+    }
+
+    if attributes.is_empty() {
+        "plain".to_owned()
+    } else {
+        attributes.join(",")
+    }
+}
+
 #[macro_export]
 macro_rules! default_cell {
     () => {
@@ -11,25 +155,43 @@ macro_rules! default_cell {
     };
 }
 
+// Rows tracked by each word of the dirty row bitset.
+const DIRTY_WORD_BITS: u64 = u64::BITS as u64;
+
+fn dirty_words(height: u64) -> Vec<u64> {
+    vec![0; height.div_ceil(DIRTY_WORD_BITS) as usize]
+}
+
 pub struct CharacterGrid {
     pub width: u64,
     pub height: u64,
 
     characters: Vec<GridCell>,
+
+    // One bit per row changed since the renderer was last sent the rows, see `flush_dirty`.
+    dirty_rows: Vec<u64>,
+    // Whether the renderer has to start over from a blank window, not just redraw the dirty rows.
+    invalidated: bool,
+    // The size is clamped to it in both directions.
+    max_size: u64,
 }
 
 impl CharacterGrid {
-    pub fn new(size: (u64, u64)) -> CharacterGrid {
-        let (width, height) = size;
+    pub fn new(size: (u64, u64), max_size: u64) -> CharacterGrid {
+        let (width, height) = clamp_grid_size(size, max_size);
         let cell_count = (width * height) as usize;
         CharacterGrid {
             characters: vec![default_cell!(); cell_count],
             width,
             height,
+            dirty_rows: dirty_words(height),
+            invalidated: false,
+            max_size,
         }
     }
 
-    pub fn resize(&mut self, (width, height): (u64, u64)) {
+    pub fn resize(&mut self, size: (u64, u64)) {
+        let (width, height) = clamp_grid_size(size, self.max_size);
         let new_cell_count = (width * height) as usize;
         let mut new_characters = vec![default_cell!(); new_cell_count];
 
@@ -41,9 +203,79 @@ impl CharacterGrid {
             }
         }
 
+        // The rows keep their content, so only the new ones have to be drawn. The window draws
+        // the columns that wider rows gained.
+        let old_height = self.height;
+        self.dirty_rows.resize(dirty_words(height).len(), 0);
         self.width = width;
         self.height = height;
         self.characters = new_characters;
+        for row in old_height..height {
+            self.mark_row_dirty(row);
+        }
+        self.clear_dirty_bits_past_height();
+    }
+
+    fn clear_dirty_bits_past_height(&mut self) {
+        let used_bits = self.height % DIRTY_WORD_BITS;
+        if used_bits != 0 {
+            if let Some(last_word) = self.dirty_rows.last_mut() {
+                *last_word &= (1 << used_bits) - 1;
+            }
+        }
+    }
+
+    pub fn mark_row_dirty(&mut self, row: u64) {
+        if row < self.height {
+            self.dirty_rows[(row / DIRTY_WORD_BITS) as usize] |= 1 << (row % DIRTY_WORD_BITS);
+        }
+    }
+
+    pub fn is_row_dirty(&self, row: u64) -> bool {
+        row < self.height
+            && self.dirty_rows[(row / DIRTY_WORD_BITS) as usize] & (1 << (row % DIRTY_WORD_BITS))
+                != 0
+    }
+
+    fn set_row_dirty(&mut self, row: u64, dirty: bool) {
+        if dirty {
+            self.mark_row_dirty(row);
+        } else if row < self.height {
+            self.dirty_rows[(row / DIRTY_WORD_BITS) as usize] &= !(1 << (row % DIRTY_WORD_BITS));
+        }
+    }
+
+    pub fn mark_all_rows_dirty(&mut self) {
+        self.dirty_rows.fill(u64::MAX);
+        self.clear_dirty_bits_past_height();
+    }
+
+    /// Marks every row dirty, and the window to be cleared before they are drawn again.
+    pub fn invalidate(&mut self) {
+        self.invalidated = true;
+        self.mark_all_rows_dirty();
+    }
+
+    pub fn is_invalidated(&self) -> bool {
+        self.invalidated
+    }
+
+    /// The rows changed since the last `flush_dirty`, from the top.
+    pub fn dirty_rows(&self) -> impl DoubleEndedIterator<Item = u64> + '_ {
+        (0..self.height).filter(|row| self.is_row_dirty(*row))
+    }
+
+    /// Forgets the dirty rows once they were sent to the renderer.
+    pub fn flush_dirty(&mut self) {
+        self.dirty_rows.fill(0);
+        self.invalidated = false;
+    }
+
+    /// Moves the dirty state of a row along with its content when it's scrolled over another one.
+    /// A row only partly covered stays dirty if it already was.
+    pub fn move_dirty_row(&mut self, from_row: u64, to_row: u64, whole_row: bool) {
+        let dirty = self.is_row_dirty(from_row) || (!whole_row && self.is_row_dirty(to_row));
+        self.set_row_dirty(to_row, dirty);
     }
 
     pub fn clear(&mut self) {
@@ -83,14 +315,140 @@ impl CharacterGrid {
             None
         }
     }
+
+    /// Copies the columns `left..right` of one row over the same columns of another in one go.
+    /// The cells are cloned into the existing ones, which keeps their text allocations.
+    pub fn copy_row_span(&mut self, from_row: u64, to_row: u64, left: u64, right: u64) {
+        let right = right.min(self.width);
+        if from_row == to_row || from_row >= self.height || to_row >= self.height || left >= right {
+            return;
+        }
+
+        let width = self.width as usize;
+        let length = (right - left) as usize;
+        let from = from_row as usize * width + left as usize;
+        let to = to_row as usize * width + left as usize;
+        if from < to {
+            let (source, destination) = self.characters.split_at_mut(to);
+            destination[..length].clone_from_slice(&source[from..from + length]);
+        } else {
+            let (destination, source) = self.characters.split_at_mut(from);
+            destination[to..to + length].clone_from_slice(&source[..length]);
+        }
+    }
+
+    /// Moves the rows `top..bottom` up by `rows`, or down when it's negative, by rotating them in
+    /// place so no cell is cloned. The rows scrolled into view end up with what the rows moved
+    /// out of the region held, which doesn't matter as neovim draws them again right after.
+    pub fn scroll_rows(&mut self, top: u64, bottom: u64, rows: i64) {
+        let bottom = bottom.min(self.height);
+        if top >= bottom {
+            return;
+        }
+
+        let width = self.width as usize;
+        let region = &mut self.characters[top as usize * width..bottom as usize * width];
+        let shift = (rows.unsigned_abs()).min(bottom - top) as usize * width;
+        if rows > 0 {
+            region.rotate_left(shift);
+        } else {
+            region.rotate_right(shift);
+        }
+    }
+
+    /// Reconstructs the text of a block of cells for copying, one line per row with the trailing
+    /// whitespace trimmed. The empty second cell of a double width character is skipped, unless
+    /// the block starts on it, where it's kept as a space so the columns still line up.
+    pub fn block_text(&self, block: &VisualBlock) -> String {
+        let right = block.right.min(self.width);
+        let lines: Vec<_> = (block.top..block.bottom.min(self.height))
+            .map(|row| {
+                let mut line = String::new();
+                for column in block.left..right {
+                    match self.get_cell(column, row) {
+                        Some((text, _)) if text.is_empty() && column == block.left => {
+                            line.push(' ')
+                        }
+                        Some((text, _)) => line.push_str(text),
+                        None => {}
+                    }
+                }
+                line.trim_end().to_owned()
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    /// Exports the grid as text, one line per row with the row's text followed by its runs of
+    /// equally styled cells as `start-end:style`, see `describe_style`. Unstyled runs are written
+    /// as `-`.
+    #[cfg(test)]
+    pub fn export(&self) -> String {
+        let mut export = String::new();
+        for row_index in 0..self.height {
+            let row = self.row(row_index).unwrap();
+            let text: String = row.iter().map(|(text, _)| text.as_str()).collect();
+
+            let mut runs = Vec::new();
+            let mut run_start = 0;
+            for column in 1..=row.len() {
+                if column < row.len() && row[column].1 == row[run_start].1 {
+                    continue;
+                }
+
+                let style = match &row[run_start].1 {
+                    Some(style) => describe_style(style),
+                    None => "-".to_owned(),
+                };
+                runs.push(format!("{run_start}-{column}:{style}"));
+                run_start = column;
+            }
+
+            export.push_str(&format!("{text}|{}\n", runs.join(" ")));
+        }
+        export
+    }
+
+    /// Exports the text of the grid for bug reports, with the trailing whitespace of each row
+    /// trimmed. The cell under the cursor is wrapped in brackets, and a `^` on the line below the
+    /// cursor row points at it.
+    pub fn export_with_cursor(&self, (cursor_column, cursor_row): (u64, u64)) -> String {
+        let mut export = String::new();
+        for row_index in 0..self.height {
+            let row = self.row(row_index).unwrap();
+            let mut line = String::new();
+            let mut marker = None;
+            for (column, (text, _)) in row.iter().enumerate() {
+                if row_index == cursor_row && column as u64 == cursor_column {
+                    marker = Some(cell_width(&line) + 1);
+                    line.push('[');
+                    line.push_str(text);
+                    line.push(']');
+                } else {
+                    line.push_str(text);
+                }
+            }
+            export.push_str(line.trim_end());
+            export.push('\n');
+            if let Some(marker) = marker {
+                export.push_str(&" ".repeat(marker as usize));
+                export.push_str("^\n");
+            }
+        }
+        export
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::editor::style::Colors;
+    use crate::editor::style::{Colors, UnderlineStyle};
     use rand::*;
 
+    fn build_grid(size: (u64, u64)) -> CharacterGrid {
+        CharacterGrid::new(size, GridLimits::default().max_grid_size)
+    }
+
     #[derive(Debug)]
     struct Context {
         none_colors: Colors,
@@ -131,7 +489,7 @@ mod tests {
         let context = Context::new();
 
         // RUN FUNCTION
-        let character_grid = CharacterGrid::new(context.size);
+        let character_grid = build_grid(context.size);
         assert_eq!(character_grid.width, context.size.0);
         assert_eq!(character_grid.height, context.size.1);
         assert_eq!(
@@ -143,7 +501,7 @@ mod tests {
     #[test]
     fn get_cell_returns_expected_cell() {
         let context = Context::new();
-        let mut character_grid = CharacterGrid::new(context.size);
+        let mut character_grid = build_grid(context.size);
 
         character_grid.characters[context.index] = (
             "foo".to_string(),
@@ -164,7 +522,7 @@ mod tests {
     #[test]
     fn get_cell_mut_modifiers_grid_properly() {
         let context = Context::new();
-        let mut character_grid = CharacterGrid::new(context.size);
+        let mut character_grid = build_grid(context.size);
 
         character_grid.characters[context.index] = (
             "foo".to_string(),
@@ -195,17 +553,51 @@ mod tests {
             "foo".to_string(),
             Some(Arc::new(Style::new(context.none_colors))),
         );
-        let mut character_grid = CharacterGrid::new(context.size);
+        let mut character_grid = build_grid(context.size);
 
         // RUN FUNCTION
         character_grid.set_all_characters(grid_cell.clone());
         assert_eq!(character_grid.characters, vec![grid_cell; context.area]);
     }
 
+    #[test]
+    fn resize_marks_only_the_rows_it_changes_dirty() {
+        let mut character_grid = build_grid((10, 70));
+        assert_eq!(character_grid.dirty_rows().count(), 0);
+
+        // Rows spanning two words of the bitset work the same as the first ones.
+        character_grid.mark_row_dirty(65);
+        character_grid.resize((10, 72));
+        assert_eq!(
+            character_grid.dirty_rows().collect::<Vec<_>>(),
+            vec![65, 70, 71]
+        );
+
+        character_grid.flush_dirty();
+        character_grid.resize((10, 66));
+        assert_eq!(character_grid.dirty_rows().count(), 0);
+        // Getting taller again doesn't bring back the bits of the rows that went away.
+        character_grid.mark_row_dirty(65);
+        character_grid.resize((10, 64));
+        character_grid.resize((10, 65));
+        assert_eq!(character_grid.dirty_rows().collect::<Vec<_>>(), vec![64]);
+
+        // A new width leaves the rows it keeps alone, without the window having to be cleared.
+        character_grid.flush_dirty();
+        character_grid.resize((12, 65));
+        assert_eq!(character_grid.dirty_rows().count(), 0);
+        character_grid.resize((8, 67));
+        assert_eq!(
+            character_grid.dirty_rows().collect::<Vec<_>>(),
+            vec![65, 66]
+        );
+        assert!(!character_grid.is_invalidated());
+    }
+
     #[test]
     fn clear_empties_buffer() {
         let context = Context::new();
-        let mut character_grid = CharacterGrid::new(context.size);
+        let mut character_grid = build_grid(context.size);
 
         let grid_cell = (
             "foo".to_string(),
@@ -224,10 +616,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn oversized_grids_are_clamped_to_the_maximum_size() {
+        let max_grid_size = 100;
+        let mut character_grid = CharacterGrid::new((u64::MAX, 3), max_grid_size);
+        assert_eq!(
+            (character_grid.width, character_grid.height),
+            (max_grid_size, 3)
+        );
+
+        character_grid.resize((4, 10_000_000_000));
+        assert_eq!(
+            (character_grid.width, character_grid.height),
+            (4, max_grid_size)
+        );
+        assert_eq!(character_grid.characters.len() as u64, 4 * max_grid_size);
+    }
+
     #[test]
     fn resize_clears_and_resizes_grid() {
         let context = Context::new();
-        let mut character_grid = CharacterGrid::new(context.size);
+        let mut character_grid = build_grid(context.size);
         let (width, height) = (
             (thread_rng().gen::<u64>() % 500) + 1,
             (thread_rng().gen::<u64>() % 500) + 1,
@@ -258,4 +667,80 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn export_writes_the_text_and_style_runs_of_each_row() {
+        let context = Context::new();
+        let mut character_grid = build_grid((4, 2));
+        let style = Some(Arc::new(Style::new(context.none_colors.clone())));
+        for x in 1..3 {
+            *character_grid.get_cell_mut(x, 0).unwrap() = ("a".to_owned(), style.clone());
+        }
+
+        assert_eq!(
+            character_grid.export(),
+            " aa |0-1:- 1-3:plain 3-4:-\n    |0-4:-\n"
+        );
+    }
+
+    #[test]
+    fn styles_are_described_by_their_colors_and_attributes() {
+        let style = Style {
+            bold: true,
+            italic: true,
+            underline: Some(UnderlineStyle::UnderCurl),
+            blend: 30,
+            ..Style::new(Colors::new(
+                Some(Color4f::new(1.0, 0.0, 0.0, 1.0)),
+                None,
+                Some(Color4f::new(0.0, 0.0, 1.0, 0.5)),
+            ))
+        };
+        assert_eq!(
+            describe_style(&style),
+            "fg=#ff0000,sp=#0000ff80,italic,bold,undercurl,blend=30"
+        );
+    }
+
+    #[test]
+    fn export_with_cursor_marks_the_cursor_cell() {
+        let mut character_grid = build_grid((5, 3));
+        for (column, text) in ["日", "", "a", "b"].iter().enumerate() {
+            *character_grid.get_cell_mut(column as u64, 1).unwrap() = (text.to_string(), None);
+        }
+
+        assert_eq!(
+            character_grid.export_with_cursor((2, 1)),
+            "\n日[a]b\n   ^\n\n"
+        );
+        // A blank cell stays visible at the end of its row.
+        assert_eq!(
+            character_grid.export_with_cursor((4, 0)),
+            "    [ ]\n     ^\n日ab\n\n"
+        );
+    }
+
+    #[test]
+    fn block_text_skips_continuation_cells_and_trims_lines() {
+        let mut character_grid = build_grid((6, 3));
+        let mut write_row = |row, cells: &[&str]| {
+            for (column, text) in cells.iter().enumerate() {
+                *character_grid.get_cell_mut(column as u64, row).unwrap() =
+                    (text.to_string(), None);
+            }
+        };
+        write_row(0, &["a", "日", "", "b", " ", " "]);
+        write_row(1, &["c", "d", " ", " ", " ", " "]);
+        write_row(2, &["本", "", "e", "f", "g", "h"]);
+
+        let block = |left, right| VisualBlock {
+            top: 0,
+            left,
+            bottom: 3,
+            right,
+        };
+        assert_eq!(character_grid.block_text(&block(0, 5)), "a日b\ncd\n本efg");
+        // Starting on the second half of a wide character keeps its column as a space.
+        assert_eq!(character_grid.block_text(&block(2, 4)), " b\n\nef");
+    }
 }
@@ -0,0 +1,137 @@
+use crate::editor::{style::Style, WindowSnapshot};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CellChangeKind {
+    // Text was written into a blank cell.
+    Added,
+    // The text of the cell was cleared.
+    Removed,
+    // The cell has different text or a different style than before.
+    Changed,
+}
+
+/// A cell that differs between two snapshots, in cells of the window.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct CellChange {
+    pub column: u64,
+    pub row: u64,
+    pub kind: CellChangeKind,
+}
+
+fn snapshot_cell(snapshot: &WindowSnapshot, column: u64, row: u64) -> (&str, Option<&Style>) {
+    if column >= snapshot.width || row >= snapshot.height {
+        return ("", None);
+    }
+    match snapshot.cells.get((row * snapshot.width + column) as usize) {
+        Some((text, style_index)) => (
+            text.as_str(),
+            style_index.and_then(|style_index| snapshot.styles.get(style_index)),
+        ),
+        None => ("", None),
+    }
+}
+
+// Spaces and the right halves of double width characters have no text of their own.
+fn is_blank(text: &str) -> bool {
+    text.trim().is_empty()
+}
+
+/// Classifies every cell that differs between the grids of two snapshots of a window, row by row.
+/// Cells outside of the smaller grid count as blank, so a grid that grew has its new text added.
+/// Blank cells only differ by their style.
+#[allow(dead_code)]
+pub fn diff_snapshots(before: &WindowSnapshot, after: &WindowSnapshot) -> Vec<CellChange> {
+    let width = before.width.max(after.width);
+    let height = before.height.max(after.height);
+
+    let mut changes = Vec::new();
+    for row in 0..height {
+        for column in 0..width {
+            let (before_text, before_style) = snapshot_cell(before, column, row);
+            let (after_text, after_style) = snapshot_cell(after, column, row);
+            let (before_blank, after_blank) = (is_blank(before_text), is_blank(after_text));
+            let same_text = before_text == after_text || (before_blank && after_blank);
+            if same_text && before_style == after_style {
+                continue;
+            }
+
+            let kind = match (before_blank, after_blank) {
+                (true, false) => CellChangeKind::Added,
+                (false, true) => CellChangeKind::Removed,
+                _ => CellChangeKind::Changed,
+            };
+            changes.push(CellChange { column, row, kind });
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::{Colors, WindowType};
+
+    fn snapshot(rows: &[&str], styles: Vec<Style>, styled_cell: Option<usize>) -> WindowSnapshot {
+        let width = rows[0].chars().count() as u64;
+        let cells = rows
+            .iter()
+            .flat_map(|row| row.chars())
+            .enumerate()
+            .map(|(index, character)| {
+                let style_index = (Some(index) == styled_cell).then_some(0);
+                (character.to_string(), style_index)
+            })
+            .collect();
+        WindowSnapshot {
+            grid_id: 2,
+            window_type: WindowType::Editor,
+            anchor_info: None,
+            grid_position: (0.0, 0.0),
+            width,
+            height: rows.len() as u64,
+            styles,
+            cells,
+            row_display_widths: vec![width; rows.len()],
+        }
+    }
+
+    #[test]
+    fn changed_cells_are_classified() {
+        let mut bold = Style::new(Colors::new(None, None, None));
+        bold.bold = true;
+        let before = snapshot(&["ab c", "xy  "], Vec::new(), None);
+        let after = snapshot(&["a zc", "xq  ", "n   "], vec![bold], Some(0));
+
+        assert_eq!(
+            diff_snapshots(&before, &after),
+            vec![
+                CellChange {
+                    column: 0,
+                    row: 0,
+                    kind: CellChangeKind::Changed,
+                },
+                CellChange {
+                    column: 1,
+                    row: 0,
+                    kind: CellChangeKind::Removed,
+                },
+                CellChange {
+                    column: 2,
+                    row: 0,
+                    kind: CellChangeKind::Added,
+                },
+                CellChange {
+                    column: 1,
+                    row: 1,
+                    kind: CellChangeKind::Changed,
+                },
+                CellChange {
+                    column: 0,
+                    row: 2,
+                    kind: CellChangeKind::Added,
+                },
+            ]
+        );
+        assert!(diff_snapshots(&after, &after).is_empty());
+    }
+}
@@ -0,0 +1,133 @@
+//! Golden snapshots of window grids, to catch regressions in how redraw events end up in the grid.
+//!
+//! Snapshots are stored next to this file in `snapshots/<name>.snap`. Run the tests with
+//! `NEOVIDE_UPDATE_SNAPSHOTS=1` to write the current grids as the new goldens.
+
+use std::{env, fs, io, path::PathBuf};
+
+use crate::editor::Window;
+
+const UPDATE_VARIABLE: &str = "NEOVIDE_UPDATE_SNAPSHOTS";
+
+fn snapshot_path(name: &str) -> PathBuf {
+    // file!() is relative to the crate root, which is where the tests run from.
+    PathBuf::from(file!())
+        .with_file_name("snapshots")
+        .join(format!("{name}.snap"))
+}
+
+/// Compares the grid of the window with the stored golden, returning the differing rows.
+pub fn compare_grid_snapshot(name: &str, window: &Window) -> Result<(), String> {
+    let path = snapshot_path(name);
+    let golden = fs::read_to_string(&path)
+        .map_err(|error| format!("Could not read snapshot {}: {error}", path.display()))?;
+    let export = window.export_grid();
+    if export == golden {
+        return Ok(());
+    }
+
+    let golden_rows: Vec<_> = golden.lines().collect();
+    let export_rows: Vec<_> = export.lines().collect();
+    let differences: Vec<_> = (0..golden_rows.len().max(export_rows.len()))
+        .filter(|row| golden_rows.get(*row) != export_rows.get(*row))
+        .map(|row| {
+            format!(
+                "row {row}:\n  golden: {}\n  actual: {}",
+                golden_rows.get(row).unwrap_or(&"<missing>"),
+                export_rows.get(row).unwrap_or(&"<missing>"),
+            )
+        })
+        .collect();
+    Err(format!(
+        "Grid doesn't match snapshot {}\n{}",
+        path.display(),
+        differences.join("\n")
+    ))
+}
+
+/// Writes the grid of the window as the golden for the snapshot.
+pub fn update_grid_snapshot(name: &str, window: &Window) -> io::Result<()> {
+    let path = snapshot_path(name);
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    fs::write(path, window.export_grid())
+}
+
+/// Asserts that the grid of the window matches the golden, or updates the golden instead when
+/// `NEOVIDE_UPDATE_SNAPSHOTS` is set.
+pub fn assert_grid_snapshot(name: &str, window: &Window) {
+    if env::var_os(UPDATE_VARIABLE).is_some() {
+        update_grid_snapshot(name, window).expect("Could not update the snapshot");
+        return;
+    }
+
+    if let Err(message) = compare_grid_snapshot(name, window) {
+        panic!("{message}\nRun the tests with {UPDATE_VARIABLE}=1 to accept the new grid.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, rc::Rc, sync::Arc};
+
+    use super::*;
+    use crate::{
+        bridge::GridLineCell,
+        editor::{Colors, DrawCommandBatcher, GridLimits, Style, WindowType},
+    };
+
+    fn build_known_window() -> Window {
+        let mut window = Window::new(
+            1,
+            WindowType::Editor,
+            None,
+            (0.0, 0.0),
+            (12, 3),
+            Rc::new(DrawCommandBatcher::new()),
+            GridLimits::default(),
+        );
+
+        let mut bold = Style::new(Colors::new(None, None, None));
+        bold.bold = true;
+        let mut defined_styles = HashMap::new();
+        defined_styles.insert(1, Arc::new(bold));
+
+        let cells = |text: &str, highlight_id| {
+            text.chars()
+                .map(|character| GridLineCell {
+                    text: character.to_string(),
+                    highlight_id: Some(highlight_id),
+                    repeat: None,
+                })
+                .collect::<Vec<_>>()
+        };
+        window.draw_grid_line(0, 0, cells("fn main", 1), &defined_styles);
+        window.draw_grid_line(1, 2, cells("{}", 0), &defined_styles);
+        window
+    }
+
+    #[test]
+    fn known_grid_matches_its_golden() {
+        assert_grid_snapshot("known_grid", &build_known_window());
+    }
+
+    #[test]
+    fn changed_grid_does_not_match_the_golden() {
+        let mut window = build_known_window();
+        window.draw_grid_line(
+            2,
+            0,
+            vec![GridLineCell {
+                text: "x".to_owned(),
+                highlight_id: None,
+                repeat: None,
+            }],
+            &HashMap::new(),
+        );
+
+        let message = compare_grid_snapshot("known_grid", &window).unwrap_err();
+        assert!(message.contains("row 2:"));
+        assert!(!message.contains("row 0:"));
+    }
+}
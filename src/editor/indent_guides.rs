@@ -0,0 +1,115 @@
+use skia_safe::Color4f;
+
+use crate::{
+    editor::{grid::GridCell, parse_color},
+    settings::*,
+};
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "indent_guides"]
+pub struct IndentGuideSettings {
+    pub enabled: bool,
+    // Number of columns per indentation level.
+    pub width: u64,
+    pub color: String,
+}
+
+impl Default for IndentGuideSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            width: 4,
+            color: "#80808080".to_string(),
+        }
+    }
+}
+
+impl IndentGuideSettings {
+    pub fn color(&self) -> Option<Color4f> {
+        parse_color(&self.color)
+    }
+}
+
+/// A vertical guide along one indentation level, spanning the rows from top until bottom.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct IndentGuide {
+    pub column: u64,
+    pub top: u64,
+    pub bottom: u64,
+}
+
+/// The number of blank cells the row starts with. Styles are ignored, so indentation that is
+/// split over several fragments, like a tab under the cursorline, is measured as a whole. Rows
+/// without any text count as not indented.
+pub fn indent_width(row: &[GridCell]) -> u64 {
+    let indent = row.iter().take_while(|(text, _)| text == " ").count();
+    if indent == row.len() {
+        0
+    } else {
+        indent as u64
+    }
+}
+
+/// Builds the guides for rows with the given indentation, joining the guides of consecutive
+/// rows into a single one.
+pub fn indent_guides(indent_widths: &[u64], level_width: u64) -> Vec<IndentGuide> {
+    let level_width = level_width.max(1);
+    let deepest = indent_widths.iter().copied().max().unwrap_or(0);
+
+    let mut guides = Vec::new();
+    for column in (0..deepest).step_by(level_width as usize) {
+        let mut top = None;
+        for (row, indent_width) in indent_widths.iter().enumerate() {
+            let row = row as u64;
+            match (top, *indent_width > column) {
+                (None, true) => top = Some(row),
+                (Some(guide_top), false) => {
+                    guides.push(IndentGuide {
+                        column,
+                        top: guide_top,
+                        bottom: row,
+                    });
+                    top = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(guide_top) = top {
+            guides.push(IndentGuide {
+                column,
+                top: guide_top,
+                bottom: indent_widths.len() as u64,
+            });
+        }
+    }
+    guides
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guides_of_consecutive_rows_are_joined() {
+        assert_eq!(
+            indent_guides(&[0, 8, 4, 0, 2], 4),
+            vec![
+                IndentGuide {
+                    column: 0,
+                    top: 1,
+                    bottom: 3,
+                },
+                IndentGuide {
+                    column: 0,
+                    top: 4,
+                    bottom: 5,
+                },
+                IndentGuide {
+                    column: 4,
+                    top: 1,
+                    bottom: 2,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,29 @@
+use std::sync::Arc;
+
+use crate::{
+    editor::{Colors, Style},
+    settings::*,
+};
+
+#[derive(SettingGroup, Clone, Default)]
+pub struct LigatureSettings {
+    // Keeps text with highlights that only differ in their foreground together, so that
+    // ligatures can span them.
+    pub ligatures_across_highlights: bool,
+}
+
+/// Whether cells of the two styles can be shaped together, with only the color of their glyphs
+/// telling them apart. The foreground of a reversed style is its background, so those have to
+/// match exactly.
+pub fn differ_only_in_foreground(style: &Option<Arc<Style>>, other: &Option<Arc<Style>>) -> bool {
+    let default_style = Style::new(Colors::new(None, None, None));
+    let style = style.as_deref().unwrap_or(&default_style);
+    let other = other.as_deref().unwrap_or(&default_style);
+    if style.reverse || other.reverse {
+        return style == other;
+    }
+
+    let mut other = other.clone();
+    other.colors.foreground = style.colors.foreground;
+    *style == other
+}
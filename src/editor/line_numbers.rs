@@ -0,0 +1,128 @@
+use std::sync::Arc;
+
+use crate::{
+    editor::{parse_color, Colors, Style},
+    renderer::LineFragment,
+    settings::*,
+};
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "line_numbers"]
+pub struct LineNumberSettings {
+    // Draws the number column in the GUI on top of the one drawn by Neovim. This is applied when
+    // the UI attaches, so it has to be set from the init file.
+    pub enabled: bool,
+    pub color: String,
+    pub current_color: String,
+}
+
+impl Default for LineNumberSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            color: "".to_string(),
+            current_color: "".to_string(),
+        }
+    }
+}
+
+impl LineNumberSettings {
+    fn style(&self, current: bool) -> Option<Arc<Style>> {
+        let color = if current {
+            &self.current_color
+        } else {
+            &self.color
+        };
+
+        let mut style = Style::new(Colors::new(parse_color(color), None, None));
+        style.bold = current;
+        Some(Arc::new(style))
+    }
+}
+
+/// The buffer lines shown by a window, as reported by the neovide lua side.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineNumbers {
+    // First grid column and width of the number column.
+    pub start: u64,
+    pub width: u64,
+    pub current_line: u64,
+    // The grid row each visible buffer line starts on, the line and the number shown for it, which
+    // is relative to the cursor line with 'relativenumber'.
+    pub rows: Vec<(u64, u64, u64)>,
+}
+
+impl LineNumbers {
+    pub fn build_fragments(&self, settings: &LineNumberSettings) -> Vec<LineFragment> {
+        if self.width == 0 {
+            return Vec::new();
+        }
+
+        let number_width = self.width as usize - 1;
+        self.rows
+            .iter()
+            .map(|(row, line, number)| LineFragment {
+                text: format!("{number:>number_width$} "),
+                window_left: self.start,
+                window_top: *row,
+                width: self.width,
+                style: settings.style(*line == self.current_line),
+                foreground_styles: Vec::new(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_fragments_right_aligns_each_number() {
+        let line_numbers = LineNumbers {
+            start: 2,
+            width: 4,
+            current_line: 12,
+            rows: vec![(0, 9, 9), (1, 10, 10), (3, 12, 12)],
+        };
+
+        let fragments = line_numbers.build_fragments(&LineNumberSettings::default());
+        let cells: Vec<_> = fragments
+            .iter()
+            .map(|fragment| {
+                (
+                    fragment.text.as_str(),
+                    fragment.window_left,
+                    fragment.window_top,
+                    fragment.width,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            cells,
+            vec![("  9 ", 2, 0, 4), (" 10 ", 2, 1, 4), (" 12 ", 2, 3, 4)]
+        );
+        assert!(fragments[2].style.as_ref().unwrap().bold);
+        assert!(!fragments[0].style.as_ref().unwrap().bold);
+    }
+
+    #[test]
+    fn relative_numbers_keep_the_cursor_line_style() {
+        let line_numbers = LineNumbers {
+            start: 0,
+            width: 3,
+            current_line: 10,
+            rows: vec![(0, 9, 1), (1, 10, 0), (2, 11, 1)],
+        };
+
+        let fragments = line_numbers.build_fragments(&LineNumberSettings::default());
+        let texts: Vec<_> = fragments
+            .iter()
+            .map(|fragment| fragment.text.as_str())
+            .collect();
+
+        assert_eq!(texts, vec![" 1 ", " 0 ", " 1 "]);
+        assert!(fragments[1].style.as_ref().unwrap().bold);
+    }
+}
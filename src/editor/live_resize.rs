@@ -0,0 +1,54 @@
+/// Holds back the flushes neovim sends while the OS window is being resized. Each resize makes
+/// neovim resend the grids, so instead of drawing every flush, all the flushes of one step of the
+/// resize are sent to the renderer together when the next step starts or the resize ends.
+#[derive(Debug, Default)]
+pub struct LiveResize {
+    resizing: bool,
+    pending_flush: bool,
+}
+
+impl LiveResize {
+    /// The window layer asked neovim for a new size. Returns whether the held flushes have to be
+    /// sent now.
+    pub fn step(&mut self) -> bool {
+        self.resizing = true;
+        std::mem::take(&mut self.pending_flush)
+    }
+
+    /// The size settled. Returns whether the held flushes have to be sent now.
+    pub fn end(&mut self) -> bool {
+        self.resizing = false;
+        std::mem::take(&mut self.pending_flush)
+    }
+
+    /// Neovim flushed. Returns whether the flush should be sent right away.
+    pub fn flush(&mut self) -> bool {
+        if self.resizing {
+            self.pending_flush = true;
+        }
+        !self.resizing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flushes_within_a_step_are_sent_once() {
+        let mut live_resize = LiveResize::default();
+        assert!(live_resize.flush());
+
+        assert!(!live_resize.step());
+        assert!(!live_resize.flush());
+        assert!(!live_resize.flush());
+        assert!(!live_resize.flush());
+        assert!(live_resize.step());
+        // A step without flushes has nothing to send.
+        assert!(!live_resize.step());
+
+        assert!(!live_resize.flush());
+        assert!(live_resize.end());
+        assert!(live_resize.flush());
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::VecDeque;
+
+use crate::{
+    bridge::{GridLineCell, StyledContent},
+    editor::command_line::push_text,
+    settings::*,
+};
+
+/// The grid of the window the message log is drawn in, right below the command line box.
+pub const MESSAGE_LOG_GRID: u64 = u64::MAX - 1;
+/// The grid of the last row with the mode and the ruler, below the message log.
+pub const MESSAGE_STATUS_GRID: u64 = u64::MAX - 2;
+
+// Where Neovim puts the partial command and the ruler, counted from the end of the last row.
+const SHOW_COMMAND_COLUMNS: usize = 28;
+const RULER_COLUMNS: usize = 18;
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "message_log"]
+pub struct MessageLogSettings {
+    // Shows the messages in a log at the bottom of the screen instead of letting Neovim draw them.
+    // This is applied when the UI attaches, so it has to be set from the init file.
+    pub enabled: bool,
+    // Rows of the log shown at once.
+    pub height: u64,
+    // Lines kept for scrolling back.
+    pub history: u64,
+}
+
+impl Default for MessageLogSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            height: 8,
+            history: 1000,
+        }
+    }
+}
+
+/// Every message shown since starting, one entry per line, which the view follows as new ones
+/// come in unless it was scrolled up.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MessageLog {
+    lines: VecDeque<StyledContent>,
+    // Lines of the last message, which a message replacing it takes the place of.
+    last_message_lines: usize,
+    // Rows the view is scrolled up from the newest line, zero follows new messages.
+    scroll: u64,
+}
+
+// Splits the content of a message at its line breaks, keeping the highlight of each part.
+fn split_lines(content: StyledContent) -> Vec<StyledContent> {
+    let mut lines = vec![Vec::new()];
+    for (highlight_id, text) in content {
+        for (index, part) in text.split('\n').enumerate() {
+            if index > 0 {
+                lines.push(Vec::new());
+            }
+            if !part.is_empty() {
+                lines
+                    .last_mut()
+                    .unwrap()
+                    .push((highlight_id, part.to_owned()));
+            }
+        }
+    }
+    lines
+}
+
+impl MessageLog {
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn is_following(&self) -> bool {
+        self.scroll == 0
+    }
+
+    /// Adds a message to the end of the log, keeping at most `history` lines. A view scrolled up
+    /// stays on the lines it shows.
+    pub fn append(&mut self, content: StyledContent, replace_last: bool, history: u64) {
+        if replace_last {
+            for _ in 0..self.last_message_lines.min(self.lines.len()) {
+                self.lines.pop_back();
+            }
+        }
+
+        let lines = split_lines(content);
+        let added = lines.len() as i64
+            - if replace_last {
+                self.last_message_lines as i64
+            } else {
+                0
+            };
+        self.last_message_lines = lines.len();
+        self.lines.extend(lines);
+        while self.lines.len() as u64 > history.max(1) {
+            self.lines.pop_front();
+        }
+
+        if !self.is_following() {
+            self.scroll = (self.scroll as i64 + added).max(1) as u64;
+        }
+    }
+
+    /// Scrolls the view by the given rows, up towards older messages for positive ones. The view
+    /// can't go past the oldest line, or below the newest one.
+    pub fn scroll_by(&mut self, rows: i64, height: u64) {
+        let max_scroll = (self.lines.len() as u64).saturating_sub(height);
+        self.scroll = (self.scroll as i64 + rows).clamp(0, max_scroll as i64) as u64;
+    }
+
+    /// The lines in view for a log of the given height, the oldest first.
+    pub fn visible_lines(&self, height: u64) -> Vec<&StyledContent> {
+        let max_scroll = (self.lines.len() as u64).saturating_sub(height);
+        let end = self.lines.len() - self.scroll.min(max_scroll) as usize;
+        let start = end.saturating_sub(height as usize);
+        self.lines.range(start..end).collect()
+    }
+}
+
+/// What Neovim shows in the last row next to the messages, as sent by `msg_showmode`,
+/// `msg_showcmd` and `msg_ruler`. Each is empty while Neovim shows nothing there.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MessageStatus {
+    pub mode: StyledContent,
+    pub command: StyledContent,
+    pub ruler: StyledContent,
+}
+
+impl MessageStatus {
+    pub fn is_empty(&self) -> bool {
+        [&self.mode, &self.command, &self.ruler]
+            .iter()
+            .all(|content| content.iter().all(|(_, text)| text.is_empty()))
+    }
+
+    /// Lays the status out like Neovim does into a row of the given width, the mode at the start
+    /// and the partial command and the ruler in the columns left for them at the end.
+    pub fn cells(&self, width: u64) -> Vec<GridLineCell> {
+        let width = width as usize;
+        let mut row = vec![
+            GridLineCell {
+                text: " ".to_owned(),
+                highlight_id: Some(0),
+                repeat: None,
+            };
+            width
+        ];
+
+        for (column, content) in [
+            (0, &self.mode),
+            (width.saturating_sub(SHOW_COMMAND_COLUMNS), &self.command),
+            (width.saturating_sub(RULER_COLUMNS), &self.ruler),
+        ] {
+            let mut cells = Vec::new();
+            for (highlight_id, text) in content {
+                push_text(&mut cells, text, *highlight_id);
+            }
+            for (cell, target) in cells.into_iter().zip(row.iter_mut().skip(column)) {
+                *target = cell;
+            }
+        }
+        row
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(text: &str) -> StyledContent {
+        vec![(0, text.to_owned())]
+    }
+
+    fn visible_texts(log: &MessageLog, height: u64) -> Vec<String> {
+        log.visible_lines(height)
+            .into_iter()
+            .map(|line| line.iter().map(|(_, text)| text.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn appended_messages_scroll_the_view_to_the_bottom() {
+        let mut log = MessageLog::default();
+        log.append(message("first"), false, 100);
+        log.append(message("second\nthird"), false, 100);
+        log.append(message("fourth"), false, 100);
+
+        assert!(log.is_following());
+        assert_eq!(visible_texts(&log, 2), vec!["third", "fourth"]);
+
+        // A replacing message takes the place of the last one.
+        log.append(message("replaced"), true, 100);
+        assert_eq!(visible_texts(&log, 2), vec!["third", "replaced"]);
+    }
+
+    #[test]
+    fn scrolling_up_pins_the_view_until_scrolled_back_down() {
+        let mut log = MessageLog::default();
+        for text in ["a", "b", "c", "d"] {
+            log.append(message(text), false, 100);
+        }
+
+        log.scroll_by(1, 2);
+        assert_eq!(visible_texts(&log, 2), vec!["b", "c"]);
+        log.append(message("e"), false, 100);
+        assert!(!log.is_following());
+        assert_eq!(visible_texts(&log, 2), vec!["b", "c"]);
+
+        // The view stops at the oldest line and follows again at the newest.
+        log.scroll_by(10, 2);
+        assert_eq!(visible_texts(&log, 2), vec!["a", "b"]);
+        log.scroll_by(-10, 2);
+        assert!(log.is_following());
+        assert_eq!(visible_texts(&log, 2), vec!["d", "e"]);
+    }
+
+    #[test]
+    fn status_puts_the_ruler_and_the_partial_command_at_the_end() {
+        let mut status = MessageStatus::default();
+        assert!(status.is_empty());
+
+        status.mode = vec![(5, "-- INSERT --".to_owned())];
+        status.command = message("2d");
+        status.ruler = message("1,1           All");
+        assert!(!status.is_empty());
+
+        let cells = status.cells(40);
+        let text: String = cells.iter().map(|cell| cell.text.as_str()).collect();
+        assert_eq!(text.len(), 40);
+        assert!(text.starts_with("-- INSERT --2d"));
+        assert!(text.ends_with("1,1           All "));
+        assert_eq!(cells[0].highlight_id, Some(5));
+    }
+
+    #[test]
+    fn history_keeps_only_the_newest_lines() {
+        let mut log = MessageLog::default();
+        for text in ["a", "b", "c"] {
+            log.append(message(text), false, 2);
+        }
+        assert_eq!(visible_texts(&log, 5), vec!["b", "c"]);
+    }
+}
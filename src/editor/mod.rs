@@ -1,31 +1,113 @@
+mod background_image;
+mod blame;
+mod breadcrumb;
+mod cell_width;
+mod collapsed_floats;
+mod command_line;
+mod crosshair;
 mod cursor;
+mod diagnostics;
 mod draw_command_batcher;
 mod grid;
+mod grid_diff;
+#[cfg(test)]
+mod grid_snapshot;
+mod indent_guides;
+mod ligatures;
+mod line_numbers;
+mod live_resize;
+mod message_log;
+mod mode_ripple;
+mod peek_preview;
+mod pending_keys;
+mod popup_menu;
+mod recording_indicator;
+mod render_metrics;
+mod scroll_overview;
+mod scroll_percentage;
+mod spotlight;
 mod style;
+#[cfg(test)]
+mod tokenizer;
+mod url_detector;
+mod visual_block;
 mod window;
+mod window_separators;
 
-use std::{collections::HashMap, rc::Rc, sync::Arc, thread};
+use std::{
+    collections::HashMap,
+    rc::Rc,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
 
-use log::{error, trace};
+use log::{error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use skia_safe::{Color4f, Rect};
 
 use crate::{
-    bridge::{GuiOption, ParallelCommand, RedrawEvent, UiCommand, WindowAnchor},
+    bridge::{
+        GridLineCell, GuiOption, MessageKind, ParallelCommand, RedrawEvent, UiCommand, WindowAnchor,
+    },
+    clipboard,
+    cmd_line::CmdLineSettings,
+    dimensions::Dimensions,
     event_aggregator::EVENT_AGGREGATOR,
     profiling::tracy_zone,
     redraw_scheduler::REDRAW_SCHEDULER,
-    renderer::DrawCommand,
+    renderer::{DrawCommand, LineFragment},
+    settings::SETTINGS,
     window::WindowCommand,
 };
 
-pub use cursor::{Cursor, CursorMode, CursorShape};
+pub use background_image::{BackgroundImage, BackgroundImageFit, BackgroundKind};
+pub use blame::{build_blame, BlameLine, BlameSettings};
+pub use breadcrumb::{build_breadcrumb, BreadcrumbSettings};
+pub use cell_width::{cell_width, is_double_width_cell};
+pub use collapsed_floats::CollapsedFloatSettings;
+pub use command_line::{CommandLine, CommandLineSettings, COMMAND_LINE_GRID};
+pub use crosshair::{build_crosshair, Crosshair, CrosshairSettings};
+pub use cursor::{
+    Cursor, CursorContrastSettings, CursorMode, CursorOutOfView, CursorShadow,
+    CursorShadowSettings, CursorShape, CursorTabWidth, CursorTravel, CursorViewSettings,
+};
+pub use diagnostics::{build_diagnostic, build_virtual_text, Diagnostic, DiagnosticSettings};
 pub use draw_command_batcher::DrawCommandBatcher;
-pub use grid::CharacterGrid;
-pub use style::{Colors, Style, UnderlineStyle};
+pub use grid::{CharacterGrid, GridLimits};
+pub use grid_diff::{diff_snapshots, CellChange, CellChangeKind};
+pub use indent_guides::{IndentGuide, IndentGuideSettings};
+pub use ligatures::{differ_only_in_foreground, LigatureSettings};
+pub use line_numbers::{LineNumberSettings, LineNumbers};
+use live_resize::LiveResize;
+pub use message_log::{
+    MessageLog, MessageLogSettings, MessageStatus, MESSAGE_LOG_GRID, MESSAGE_STATUS_GRID,
+};
+pub use mode_ripple::{build_mode_ripple, ModeRipple, ModeRippleSettings};
+pub use peek_preview::{peek_preview_anchor, PeekPreviewSettings};
+pub use pending_keys::{PendingKeys, PendingKeysPosition, PendingKeysSettings};
+pub use popup_menu::{PopupMenu, PopupMenuItem, PopupMenuSettings};
+pub use recording_indicator::{RecordingIndicator, RecordingIndicatorSettings};
+pub use render_metrics::{RenderCounters, RenderMetrics, RenderMetricsSettings};
+pub use scroll_overview::{ScrollOverview, ScrollOverviewSettings, ScrollSegment, ViewportLines};
+pub use scroll_percentage::{
+    build_scroll_percentage, ScrollPercentagePosition, ScrollPercentageSettings,
+};
+pub use spotlight::{build_spotlight, Spotlight, SpotlightArea, SpotlightSettings};
+pub use style::{parse_color, Colors, Style, UnderlineStyle};
+#[cfg(test)]
+pub use tokenizer::{tokenize_row, TokenClassifier, TokenKind, TokenSpan};
+pub use url_detector::{detect_urls, UrlRange};
+pub use visual_block::{VisualBlock, VisualBlockSettings};
 pub use window::*;
+pub use window_separators::{
+    find_window_separators, SeparatorStyle, WindowSeparator, WindowSeparatorSettings,
+    WindowSeparators,
+};
 
 const MODE_CMDLINE: u64 = 4;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct AnchorInfo {
     pub anchor_grid_id: u64,
     pub anchor_type: WindowAnchor,
@@ -55,26 +137,164 @@ impl WindowAnchor {
 pub enum EditorCommand {
     NeovimRedrawEvent(RedrawEvent),
     RedrawScreen,
+    // The renderer lost its graphics context, so everything it drew before is gone.
+    GraphicsContextLost,
+    // The colorscheme is about to change, so the styles neovim defines until the next flush are
+    // part of one theme.
+    ThemeChanged,
+    SetWindowBrightness {
+        window: u64,
+        brightness: f32,
+    },
+    SetWindowZoom {
+        window: u64,
+        zoom: f32,
+    },
+    SetWindowMirrored {
+        window: u64,
+        mirrored: bool,
+    },
+    // Keeps a float above every other one, see `Editor::pin`.
+    SetWindowPinned {
+        window: u64,
+        pinned: bool,
+    },
+    SetWindowSnapToPixels {
+        window: u64,
+        snap_to_pixels: bool,
+    },
+    SetWindowCornerRadius {
+        window: u64,
+        corner_radius: f32,
+    },
+    SetWindowBreadcrumb {
+        window: u64,
+        breadcrumb: String,
+    },
+    SetWindowDiagnostics {
+        window: u64,
+        diagnostics: Vec<Diagnostic>,
+    },
+    SetWindowBlame {
+        window: u64,
+        blame: Vec<BlameLine>,
+    },
+    SetWindowFontFallback {
+        window: u64,
+        fonts: Vec<String>,
+    },
+    SetWindowSmoothScroll {
+        window: u64,
+        smooth_scroll: Option<bool>,
+    },
+    SetLineNumbers {
+        window: u64,
+        line_numbers: LineNumbers,
+    },
+    SetDimOverlay(bool),
+    // Lights the area and dims the rest of the screen, no area turns it off.
+    SetSpotlight(Option<SpotlightArea>),
+    // Shows a float as a translucent preview at the cursor, no window dismisses it.
+    SetPeekPreview(Option<u64>),
+    // Drags a float by the given columns and rows, until neovim places it again.
+    MoveFloat {
+        window: u64,
+        delta: (f64, f64),
+    },
+    // The keys of an incomplete sequence, empty once it's complete or was cancelled.
+    SetPendingKeys(String),
+    // The register a macro is recorded into, empty once the recording stops.
+    SetRecording(String),
+    SetWindowBackground {
+        window: u64,
+        background: Option<BackgroundKind>,
+    },
+    SetVisualBlock {
+        window: u64,
+        block: Option<VisualBlock>,
+    },
+    // Copies the grid the cursor is in to the clipboard, with the cursor marked.
+    ExportGrid,
+    // Writes a snapshot of the grid the cursor is in to the file, see `WindowSnapshot`.
+    SnapshotGrid(String),
+    // Copies the text of the last visual block selected in the window of the cursor to the
+    // clipboard.
+    CopyVisualBlock,
+    // Scrolls the message log by the given rows, up for positive ones.
+    ScrollMessageLog(i64),
+    // The OS window is being resized, and neovim was just asked for the next size.
+    LiveResizeStep,
+    LiveResizeEnded,
 }
 
 pub struct Editor {
     pub windows: HashMap<u64, Window>,
+    // Maps neovim window handles to the grids displaying them.
+    pub window_handles: HashMap<u64, u64>,
     pub cursor: Cursor,
     pub defined_styles: HashMap<u64, Arc<Style>>,
     pub mode_list: Vec<CursorMode>,
     pub draw_command_batcher: Rc<DrawCommandBatcher>,
+    // Handed to every window, see `GridLimits`.
+    grid_limits: GridLimits,
     pub current_mode_index: Option<u64>,
+    pub popup_menu: Option<PopupMenu>,
+    pub command_line: Option<CommandLine>,
+    // The grid of the window the cursor was last in, which gets the focus ring.
+    pub focused_grid: Option<u64>,
+    // Set by a theme change until the next flush, which repaints every window at once instead of
+    // each one as its styles are redefined.
+    restyling: bool,
+    // The old and the new style of each highlight redefined while restyling, swapped under the
+    // cells all at once.
+    redefined_styles: HashMap<u64, (Arc<Style>, Arc<Style>)>,
+    live_resize: LiveResize,
+    scroll_overview: Option<ScrollOverview>,
+    window_separators: Option<WindowSeparators>,
+    // Floats kept above every other one, in the order they were pinned.
+    pinned_grids: Vec<u64>,
+    // The float shown as a peek preview at the cursor.
+    peek_preview: Option<u64>,
+    // The area of a window lit by the spotlight, placed again whenever the window moves.
+    spotlight: Option<SpotlightArea>,
+    // The grid and the cell the cursor was last sent at.
+    last_cursor_cell: Option<(u64, (u64, u64))>,
+    crosshair: Option<Crosshair>,
+    // The mode neovim was last in, so that only actual changes make a ripple.
+    last_mode_index: Option<u64>,
+    default_colors: Option<Colors>,
+    message_log: MessageLog,
+    message_status: MessageStatus,
 }
 
 impl Editor {
-    pub fn new() -> Editor {
+    pub fn new(grid_limits: GridLimits) -> Editor {
         Editor {
+            grid_limits,
             windows: HashMap::new(),
+            window_handles: HashMap::new(),
             cursor: Cursor::new(),
             defined_styles: HashMap::new(),
             mode_list: Vec::new(),
             draw_command_batcher: Rc::new(DrawCommandBatcher::new()),
             current_mode_index: None,
+            popup_menu: None,
+            command_line: None,
+            focused_grid: None,
+            restyling: false,
+            redefined_styles: HashMap::new(),
+            live_resize: LiveResize::default(),
+            scroll_overview: None,
+            window_separators: None,
+            pinned_grids: Vec::new(),
+            peek_preview: None,
+            spotlight: None,
+            last_cursor_cell: None,
+            crosshair: None,
+            last_mode_index: None,
+            default_colors: None,
+            message_log: MessageLog::default(),
+            message_status: MessageStatus::default(),
         }
     }
 
@@ -89,9 +309,7 @@ impl Editor {
                     tracy_zone!("EditorModeInfoSet");
                     self.mode_list = cursor_modes;
                     if let Some(current_mode_i) = self.current_mode_index {
-                        if let Some(current_mode) = self.mode_list.get(current_mode_i as usize) {
-                            self.cursor.change_mode(current_mode, &self.defined_styles)
-                        }
+                        self.change_cursor_mode(current_mode_i);
                     }
                 }
                 RedrawEvent::OptionSet { gui_option } => {
@@ -100,8 +318,7 @@ impl Editor {
                 }
                 RedrawEvent::ModeChange { mode, mode_index } => {
                     tracy_zone!("ModeChange");
-                    if let Some(cursor_mode) = self.mode_list.get(mode_index as usize) {
-                        self.cursor.change_mode(cursor_mode, &self.defined_styles);
+                    if self.change_cursor_mode(mode_index) {
                         self.current_mode_index = Some(mode_index)
                     } else {
                         self.current_mode_index = None
@@ -109,6 +326,7 @@ impl Editor {
                     self.draw_command_batcher
                         .queue(DrawCommand::ModeChanged(mode))
                         .ok();
+                    self.ripple_mode_change(mode_index, &SETTINGS.get::<ModeRippleSettings>());
                 }
                 RedrawEvent::MouseOn => {
                     tracy_zone!("EditorMouseOn");
@@ -131,28 +349,43 @@ impl Editor {
                 RedrawEvent::Flush => {
                     tracy_zone!("EditorFlush");
                     trace!("Image flushed");
+                    self.finish_restyle();
                     self.send_cursor_info();
-                    {
-                        trace!("send_batch");
-                        self.draw_command_batcher.send_batch();
+                    let scroll_percentage_settings = SETTINGS.get::<ScrollPercentageSettings>();
+                    let ligatures_across_highlights = SETTINGS
+                        .get::<LigatureSettings>()
+                        .ligatures_across_highlights;
+                    for window in self.windows.values_mut() {
+                        window.set_ligatures_across_highlights(ligatures_across_highlights);
+                        window.redraw();
+                        window.update_scroll_percentage(&scroll_percentage_settings);
+                        window.update_urls();
+                        window.update_edge_indicators();
+                        window.update_virtual_text();
+                        window.update_indent_guides();
                     }
-                    {
-                        trace!("queue_next_frame");
-                        REDRAW_SCHEDULER.queue_next_frame();
+                    self.update_scroll_overview();
+                    self.update_window_separators(&SETTINGS.get::<WindowSeparatorSettings>());
+                    if self.live_resize.flush() {
+                        self.send_flush();
                     }
                 }
                 RedrawEvent::DefaultColorsSet { colors } => {
                     tracy_zone!("EditorDefaultColorsSet");
+                    self.default_colors = Some(colors.clone());
                     self.draw_command_batcher
                         .queue(DrawCommand::DefaultStyleChanged(Style::new(colors)))
                         .ok();
-                    self.redraw_screen();
-                    self.draw_command_batcher.send_batch();
-                    REDRAW_SCHEDULER.queue_next_frame();
+                    // The colors change along with the rest of the theme on the next flush.
+                    if !self.restyling {
+                        self.redraw_screen();
+                        self.draw_command_batcher.send_batch();
+                        REDRAW_SCHEDULER.queue_next_frame();
+                    }
                 }
                 RedrawEvent::HighlightAttributesDefine { id, style } => {
                     tracy_zone!("EditorHighlightAttributesDefine");
-                    self.defined_styles.insert(id, Arc::new(style));
+                    self.define_style(id, style);
                 }
                 RedrawEvent::CursorGoto {
                     grid,
@@ -169,18 +402,47 @@ impl Editor {
                 } => {
                     tracy_zone!("EditorResize");
                     self.resize_window(grid, width, height);
+                    if grid == 1 {
+                        self.lay_out_screen_boxes();
+                    }
                 }
                 RedrawEvent::GridLine {
                     grid,
                     row,
                     column_start,
                     cells,
+                    wrap,
                 } => {
                     tracy_zone!("EditorGridLine");
                     let defined_styles = &self.defined_styles;
                     let window = self.windows.get_mut(&grid);
                     if let Some(window) = window {
-                        window.draw_grid_line(row, column_start, cells, defined_styles);
+                        if window.clears_line_end(row, column_start, &cells) {
+                            window.clear_region(row, row + 1, column_start, window.get_width());
+                        } else {
+                            window.draw_grid_line(row, column_start, cells, defined_styles);
+                        }
+                        window.set_row_wraps(row, wrap);
+                        if let Some((width, height)) = window.take_resync_request() {
+                            warn!(
+                                "Grid {} appears to be out of sync with Neovim, requesting a {}x{} resize",
+                                grid, width, height
+                            );
+                            EVENT_AGGREGATOR.send(UiCommand::Parallel(
+                                ParallelCommand::ResizeGrid {
+                                    grid_id: grid,
+                                    width,
+                                    height,
+                                },
+                            ));
+                            EVENT_AGGREGATOR.send(UiCommand::Parallel(
+                                ParallelCommand::ReportGridDesync {
+                                    grid_id: grid,
+                                    width,
+                                    height,
+                                },
+                            ));
+                        }
                     }
                 }
                 RedrawEvent::Clear { grid } => {
@@ -211,16 +473,20 @@ impl Editor {
                 }
                 RedrawEvent::WindowPosition {
                     grid,
+                    window,
                     start_row,
                     start_column,
                     width,
                     height,
                 } => {
                     tracy_zone!("EditorWindowPosition");
-                    self.set_window_position(grid, start_column, start_row, width, height)
+                    self.window_handles.insert(window, grid);
+                    self.set_window_position(grid, start_column, start_row, width, height);
+                    self.update_spotlight(grid);
                 }
                 RedrawEvent::WindowFloatPosition {
                     grid,
+                    window,
                     anchor,
                     anchor_grid,
                     anchor_column: anchor_left,
@@ -229,18 +495,25 @@ impl Editor {
                     ..
                 } => {
                     tracy_zone!("EditorWindowFloatPosition");
-                    self.set_window_float_position(
-                        grid,
-                        anchor_grid,
-                        anchor,
-                        anchor_left,
-                        anchor_top,
-                        sort_order,
-                    )
+                    self.window_handles.insert(window, grid);
+                    if self.peek_preview == Some(grid) {
+                        // The peek preview stays at the cursor wherever neovim puts the float.
+                        self.place_peek_preview();
+                    } else {
+                        self.set_window_float_position(
+                            grid,
+                            anchor_grid,
+                            anchor,
+                            anchor_left,
+                            anchor_top,
+                            sort_order,
+                        )
+                    }
+                    self.update_spotlight(grid);
                 }
                 RedrawEvent::WindowHide { grid } => {
                     tracy_zone!("EditorWindowHide");
-                    let window = self.windows.get(&grid);
+                    let window = self.windows.get_mut(&grid);
                     if let Some(window) = window {
                         window.hide();
                     }
@@ -249,33 +522,336 @@ impl Editor {
                     tracy_zone!("EditorWindowClose");
                     self.close_window(grid)
                 }
+                RedrawEvent::MessageShow {
+                    kind,
+                    content,
+                    replace_last,
+                } => {
+                    tracy_zone!("EditorMessageShow");
+                    let settings = SETTINGS.get::<MessageLogSettings>();
+                    // The press enter prompt is pointless with every message staying in the log.
+                    if settings.enabled && !matches!(kind, MessageKind::ReturnPrompt) {
+                        self.message_log
+                            .append(content, replace_last, settings.history);
+                        self.draw_message_log();
+                    }
+                }
+                // The log keeps its messages, it only goes away until the next one unless it was
+                // scrolled back.
+                RedrawEvent::MessageClear if self.message_log.is_following() => {
+                    tracy_zone!("EditorMessageClear");
+                    if let Some(window) = self.windows.get_mut(&MESSAGE_LOG_GRID) {
+                        window.hide();
+                    }
+                }
+                RedrawEvent::MessageShowMode { content } => {
+                    tracy_zone!("EditorMessageShowMode");
+                    self.message_status.mode = content;
+                    self.draw_message_status();
+                }
+                RedrawEvent::MessageShowCommand { content } => {
+                    tracy_zone!("EditorMessageShowCommand");
+                    self.message_status.command = content;
+                    self.draw_message_status();
+                }
+                RedrawEvent::MessageRuler { content } => {
+                    tracy_zone!("EditorMessageRuler");
+                    self.message_status.ruler = content;
+                    self.draw_message_status();
+                }
                 RedrawEvent::MessageSetPosition { grid, row, .. } => {
                     tracy_zone!("EditorMessageSetPosition");
                     self.set_message_position(grid, row)
                 }
                 RedrawEvent::WindowViewport {
                     grid,
-                    // Don't send viewport events if they don't have a scroll delta
-                    scroll_delta: Some(scroll_delta),
+                    top_line,
+                    bottom_line,
+                    current_line,
+                    line_count,
+                    scroll_delta,
                     ..
                 } => {
                     tracy_zone!("EditorWindowViewport");
-                    self.send_updated_viewport(grid, scroll_delta)
+                    if let Some(window) = self.windows.get_mut(&grid) {
+                        window.set_viewport_lines(top_line, bottom_line, current_line, line_count);
+                    }
+                    self.send_updated_viewport(grid, scroll_delta);
+                    self.update_spotlight(grid);
                 }
                 RedrawEvent::ShowIntro { message } => {
                     EVENT_AGGREGATOR
                         .send(UiCommand::Parallel(ParallelCommand::ShowIntro { message }));
                 }
+                RedrawEvent::PopupMenuShow {
+                    items,
+                    selected,
+                    row,
+                    column,
+                    grid,
+                } => {
+                    tracy_zone!("EditorPopupMenuShow");
+                    self.show_popup_menu(items, selected, grid, row, column);
+                }
+                RedrawEvent::PopupMenuSelect { selected } => {
+                    tracy_zone!("EditorPopupMenuSelect");
+                    if let Some(popup_menu) = &mut self.popup_menu {
+                        popup_menu.select(selected.map(|selected| selected as usize));
+                        self.send_popup_menu();
+                    }
+                }
+                RedrawEvent::PopupMenuHide => {
+                    tracy_zone!("EditorPopupMenuHide");
+                    self.popup_menu = None;
+                    self.send_popup_menu();
+                }
+                RedrawEvent::CommandLineShow {
+                    content,
+                    position,
+                    first_character,
+                    prompt,
+                    indent,
+                    level,
+                } => {
+                    tracy_zone!("EditorCommandLineShow");
+                    self.command_line = Some(CommandLine {
+                        content,
+                        position,
+                        first_character,
+                        prompt,
+                        indent,
+                        level,
+                    });
+                    self.draw_command_line();
+                }
+                RedrawEvent::CommandLinePosition { position, level } => {
+                    tracy_zone!("EditorCommandLinePosition");
+                    if let Some(command_line) = self
+                        .command_line
+                        .as_mut()
+                        .filter(|command_line| command_line.level == level)
+                    {
+                        command_line.position = position;
+                        self.draw_command_line();
+                    }
+                }
+                RedrawEvent::CommandLineHide => {
+                    tracy_zone!("EditorCommandLineHide");
+                    self.command_line = None;
+                    if let Some(window) = self.windows.get_mut(&COMMAND_LINE_GRID) {
+                        window.hide();
+                    }
+                }
                 _ => {}
             },
             EditorCommand::RedrawScreen => {
                 tracy_zone!("EditorRedrawScreen");
                 self.redraw_screen();
             }
+            EditorCommand::GraphicsContextLost => {
+                tracy_zone!("EditorGraphicsContextLost");
+                warn!("Graphics context lost, repainting all windows");
+                self.redraw_screen();
+            }
+            EditorCommand::ThemeChanged => {
+                tracy_zone!("EditorThemeChanged");
+                self.restyling = true;
+            }
+            EditorCommand::SetWindowBrightness { window, brightness } => {
+                tracy_zone!("EditorSetWindowBrightness");
+                if let Some(window) = self.window_mut(window) {
+                    window.set_brightness(brightness);
+                }
+            }
+            EditorCommand::SetWindowZoom { window, zoom } => {
+                tracy_zone!("EditorSetWindowZoom");
+                if let Some(window) = self.window_mut(window) {
+                    window.set_zoom(zoom);
+                }
+            }
+            EditorCommand::SetWindowMirrored { window, mirrored } => {
+                tracy_zone!("EditorSetWindowMirrored");
+                if let Some(window) = self.window_mut(window) {
+                    window.set_mirrored(mirrored);
+                }
+            }
+            EditorCommand::SetWindowPinned { window, pinned } => {
+                tracy_zone!("EditorSetWindowPinned");
+                if let Some(&grid) = self.window_handles.get(&window) {
+                    if !pinned {
+                        self.unpin(grid);
+                    } else if let Err(error) = self.pin(grid) {
+                        warn!("Could not pin the window: {error}");
+                    }
+                }
+            }
+            EditorCommand::SetWindowSnapToPixels {
+                window,
+                snap_to_pixels,
+            } => {
+                tracy_zone!("EditorSetWindowSnapToPixels");
+                if let Some(window) = self.window_mut(window) {
+                    window.set_snap_to_pixels(snap_to_pixels);
+                }
+            }
+            EditorCommand::SetWindowCornerRadius {
+                window,
+                corner_radius,
+            } => {
+                tracy_zone!("EditorSetWindowCornerRadius");
+                if let Some(window) = self.window_mut(window) {
+                    window.set_corner_radius(corner_radius);
+                }
+            }
+            EditorCommand::SetWindowBreadcrumb { window, breadcrumb } => {
+                tracy_zone!("EditorSetWindowBreadcrumb");
+                if let Some(window) = self.window_mut(window) {
+                    window.set_breadcrumb(breadcrumb);
+                }
+            }
+            EditorCommand::SetWindowDiagnostics {
+                window,
+                diagnostics,
+            } => {
+                tracy_zone!("EditorSetWindowDiagnostics");
+                if let Some(window) = self.window_mut(window) {
+                    window.set_diagnostics(diagnostics);
+                }
+            }
+            EditorCommand::SetWindowBlame { window, blame } => {
+                tracy_zone!("EditorSetWindowBlame");
+                if let Some(window) = self.window_mut(window) {
+                    window.set_blame(blame);
+                }
+            }
+            EditorCommand::SetWindowFontFallback { window, fonts } => {
+                tracy_zone!("EditorSetWindowFontFallback");
+                if let Some(window) = self.window_mut(window) {
+                    window.set_font_fallback(fonts);
+                }
+            }
+            EditorCommand::SetWindowSmoothScroll {
+                window,
+                smooth_scroll,
+            } => {
+                tracy_zone!("EditorSetWindowSmoothScroll");
+                if let Some(window) = self.window_mut(window) {
+                    window.set_smooth_scroll(smooth_scroll);
+                }
+            }
+            EditorCommand::SetLineNumbers {
+                window,
+                line_numbers,
+            } => {
+                tracy_zone!("EditorSetLineNumbers");
+                if let Some(window) = self.window_mut(window) {
+                    window.set_line_numbers(&line_numbers);
+                }
+            }
+            EditorCommand::SetWindowBackground { window, background } => {
+                tracy_zone!("EditorSetWindowBackground");
+                if let Some(window) = self.window_mut(window) {
+                    window.set_background(background);
+                }
+            }
+            EditorCommand::SetVisualBlock { window, block } => {
+                tracy_zone!("EditorSetVisualBlock");
+                if let Some(window) = self.window_mut(window) {
+                    window.set_visual_block(block.as_ref());
+                }
+            }
+            EditorCommand::LiveResizeStep => {
+                if self.live_resize.step() {
+                    self.send_flush();
+                }
+            }
+            EditorCommand::LiveResizeEnded => {
+                if self.live_resize.end() {
+                    self.send_flush();
+                }
+            }
+            EditorCommand::ScrollMessageLog(rows) => {
+                tracy_zone!("EditorScrollMessageLog");
+                let height = SETTINGS.get::<MessageLogSettings>().height;
+                self.message_log.scroll_by(rows, height);
+                self.draw_message_log();
+            }
+            EditorCommand::ExportGrid => {
+                tracy_zone!("EditorExportGrid");
+                match self.export_cursor_grid() {
+                    Some(export) => match clipboard::set_contents(export) {
+                        Ok(()) => info!("Copied the grid of the cursor to the clipboard"),
+                        Err(error) => error!("Could not copy the grid to the clipboard: {}", error),
+                    },
+                    None => warn!("The cursor isn't in a known grid, nothing to export"),
+                }
+            }
+            EditorCommand::CopyVisualBlock => {
+                tracy_zone!("EditorCopyVisualBlock");
+                match self
+                    .windows
+                    .get(&self.cursor.parent_window_id)
+                    .and_then(|window| window.visual_block_text())
+                {
+                    Some(text) => {
+                        if let Err(error) = clipboard::set_contents(text) {
+                            error!(
+                                "Could not copy the visual block to the clipboard: {}",
+                                error
+                            );
+                        }
+                    }
+                    None => warn!("No visual block is selected, nothing to copy"),
+                }
+            }
+            EditorCommand::SnapshotGrid(path) => {
+                tracy_zone!("EditorSnapshotGrid");
+                match self.snapshot_cursor_grid(&path) {
+                    Ok(()) => info!("Wrote a snapshot of the grid of the cursor to {}", path),
+                    Err(error) => error!("Could not write the grid snapshot: {}", error),
+                }
+            }
+            EditorCommand::SetDimOverlay(enabled) => {
+                tracy_zone!("EditorSetDimOverlay");
+                self.set_dim_overlay(enabled);
+            }
+            EditorCommand::SetSpotlight(area) => {
+                tracy_zone!("EditorSetSpotlight");
+                self.set_spotlight(area);
+            }
+            EditorCommand::MoveFloat { window, delta } => {
+                tracy_zone!("EditorMoveFloat");
+                self.move_float(window, delta);
+            }
+            EditorCommand::SetPeekPreview(window) => {
+                tracy_zone!("EditorSetPeekPreview");
+                match window.and_then(|window| self.window_handles.get(&window).copied()) {
+                    Some(grid) => {
+                        if let Err(error) =
+                            self.open_peek_preview(grid, &SETTINGS.get::<PeekPreviewSettings>())
+                        {
+                            warn!("Could not open the peek preview: {error}");
+                        }
+                    }
+                    None => self.dismiss_peek_preview(),
+                }
+            }
+            EditorCommand::SetPendingKeys(keys) => {
+                tracy_zone!("EditorSetPendingKeys");
+                self.set_pending_keys(keys);
+            }
+            EditorCommand::SetRecording(register) => {
+                tracy_zone!("EditorSetRecording");
+                self.set_recording(register, &SETTINGS.get::<RecordingIndicatorSettings>());
+            }
         };
     }
 
     fn close_window(&mut self, grid: u64) {
+        self.pinned_grids.retain(|pinned_grid| *pinned_grid != grid);
+        if self.peek_preview == Some(grid) {
+            self.peek_preview = None;
+        }
         if let Some(window) = self.windows.remove(&grid) {
             window.close();
             self.draw_command_batcher
@@ -310,6 +886,7 @@ impl Editor {
                 (0.0, 0.0),
                 (width, height),
                 self.draw_command_batcher.clone(),
+                self.grid_limits,
             );
             self.windows.insert(grid, window);
         }
@@ -334,6 +911,7 @@ impl Editor {
                 (start_left as f64, start_top as f64),
                 (width, height),
                 self.draw_command_batcher.clone(),
+                self.grid_limits,
             );
             self.windows.insert(grid, new_window);
         }
@@ -360,23 +938,383 @@ impl Editor {
                 modified_top += parent_top;
             }
 
-            window.position(
-                Some(AnchorInfo {
-                    anchor_grid_id: anchor_grid,
-                    anchor_type,
-                    anchor_left,
-                    anchor_top,
-                    sort_order: sort_order.unwrap_or(grid),
-                }),
-                (width, height),
-                (modified_left, modified_top),
-            );
-            window.show();
+            let anchor_info = AnchorInfo {
+                anchor_grid_id: anchor_grid,
+                anchor_type,
+                anchor_left,
+                anchor_top,
+                sort_order: sort_order.unwrap_or(grid),
+            };
+            let old_sort_order = window.floating_order();
+            if !window.is_hidden() && old_sort_order == Some(anchor_info.sort_order) {
+                // A float keeps its size here, so one that stays in its place in the stack only
+                // moved and its grid can stay as it is.
+                window.anchor_info = Some(anchor_info);
+                window.move_to((modified_left, modified_top));
+            } else {
+                window.position(
+                    Some(anchor_info),
+                    (width, height),
+                    (modified_left, modified_top),
+                );
+                window.show();
+            }
+            self.raise_pinned();
         } else {
             error!("Attempted to float window that does not exist.");
         }
     }
 
+    /// Raises a floating window above every other float, e.g. to pop up a preview. Message
+    /// windows stay on top of everything regardless.
+    pub fn bring_to_front(&mut self, grid_id: u64) -> Result<(), String> {
+        match self.windows.get(&grid_id) {
+            Some(window) if window.is_floating() => {}
+            Some(_) => return Err(format!("Grid {grid_id} is not a floating window")),
+            None => return Err(format!("Grid {grid_id} does not exist")),
+        }
+
+        let top_sort_order = self
+            .windows
+            .iter()
+            .filter(|(id, window)| {
+                **id != grid_id && matches!(window.window_type, WindowType::Editor)
+            })
+            .filter_map(|(_, window)| window.floating_order())
+            .max()
+            .unwrap_or(0);
+
+        let window = self.windows.get_mut(&grid_id).unwrap();
+        window.set_sort_order(top_sort_order.saturating_add(1).min(u64::MAX - 1));
+        Ok(())
+    }
+
+    /// Keeps a floating window above every other float from now on, even above ones opened later.
+    /// Floats pinned later go above the ones pinned before.
+    pub fn pin(&mut self, grid_id: u64) -> Result<(), String> {
+        match self.windows.get(&grid_id) {
+            Some(window) if window.is_floating() => {}
+            Some(_) => return Err(format!("Grid {grid_id} is not a floating window")),
+            None => return Err(format!("Grid {grid_id} does not exist")),
+        }
+
+        if !self.pinned_grids.contains(&grid_id) {
+            self.pinned_grids.push(grid_id);
+        }
+        self.raise_pinned();
+        Ok(())
+    }
+
+    /// Lets the float be ordered by neovim again, which happens the next time it's positioned.
+    pub fn unpin(&mut self, grid_id: u64) {
+        self.pinned_grids
+            .retain(|pinned_grid| *pinned_grid != grid_id);
+    }
+
+    // Puts the pinned floats back on top when the floats were ordered anew. Each one only has to
+    // be above the floats that aren't pinned after it.
+    fn raise_pinned(&mut self) {
+        for (index, grid_id) in self.pinned_grids.clone().into_iter().enumerate() {
+            let later_pinned = &self.pinned_grids[index..];
+            let top_sort_order = self
+                .windows
+                .iter()
+                .filter(|(id, window)| {
+                    !later_pinned.contains(id) && matches!(window.window_type, WindowType::Editor)
+                })
+                .filter_map(|(_, window)| window.floating_order())
+                .max()
+                .unwrap_or(0);
+
+            if let Some(window) = self.windows.get_mut(&grid_id) {
+                let sort_order = window.floating_order();
+                if matches!(sort_order, Some(sort_order) if sort_order <= top_sort_order) {
+                    window.set_sort_order(top_sort_order.saturating_add(1).min(u64::MAX - 1));
+                }
+            }
+        }
+    }
+
+    /// Shows a floating window as a peek preview, placed at the cursor above every other float and
+    /// blended with the editor under it. An earlier preview is dismissed.
+    pub fn open_peek_preview(
+        &mut self,
+        grid_id: u64,
+        settings: &PeekPreviewSettings,
+    ) -> Result<(), String> {
+        match self.windows.get(&grid_id) {
+            Some(window) if window.is_floating() => {}
+            Some(_) => return Err(format!("Grid {grid_id} is not a floating window")),
+            None => return Err(format!("Grid {grid_id} does not exist")),
+        }
+
+        if self.peek_preview != Some(grid_id) {
+            self.dismiss_peek_preview();
+        }
+        self.peek_preview = Some(grid_id);
+        self.place_peek_preview();
+        self.bring_to_front(grid_id)?;
+        if let Some(window) = self.windows.get(&grid_id) {
+            window.set_blend(settings.blend.min(100) as u8);
+        }
+        Ok(())
+    }
+
+    /// Hides the peek preview until neovim shows its float again, which is then drawn like any
+    /// other float.
+    pub fn dismiss_peek_preview(&mut self) {
+        if let Some(window) = self
+            .peek_preview
+            .take()
+            .and_then(|grid| self.windows.get_mut(&grid))
+        {
+            window.set_blend(0);
+            window.hide();
+        }
+    }
+
+    fn place_peek_preview(&mut self) {
+        let grid = match self.peek_preview {
+            Some(grid) => grid,
+            None => return,
+        };
+        let (preview_height, sort_order) = match self.windows.get(&grid) {
+            Some(window) => (window.get_height(), window.floating_order()),
+            None => return,
+        };
+        let cursor_grid = self.cursor.parent_window_id;
+        let window_height = match self.windows.get(&cursor_grid) {
+            Some(window) => window.get_height(),
+            None => return,
+        };
+
+        let (anchor, anchor_left, anchor_top) =
+            peek_preview_anchor(self.cursor.grid_position, window_height, preview_height);
+        self.set_window_float_position(
+            grid,
+            cursor_grid,
+            anchor,
+            anchor_left,
+            anchor_top,
+            sort_order,
+        );
+    }
+
+    /// Dims everything behind the active float, e.g. while a picker is open. When the cursor isn't
+    /// in a float the overlay goes below every float instead; editor windows are always dimmed.
+    pub fn set_dim_overlay(&mut self, enabled: bool) {
+        let floating_order = enabled.then(|| {
+            self.windows
+                .get(&self.cursor.parent_window_id)
+                .and_then(|window| window.floating_order())
+                .unwrap_or(0)
+        });
+
+        self.draw_command_batcher
+            .queue(DrawCommand::DimOverlayChanged(floating_order))
+            .ok();
+    }
+
+    // The window neovim addresses by its handle rather than by its grid.
+    fn window_mut(&mut self, window: u64) -> Option<&mut Window> {
+        let grid = self.window_handles.get(&window)?;
+        self.windows.get_mut(grid)
+    }
+
+    // Only floats can be dragged, the other windows are laid out by neovim.
+    fn move_float(&mut self, window: u64, delta: (f64, f64)) {
+        let screen_size = self
+            .windows
+            .get(&1)
+            .map(|window| (window.get_width(), window.get_height()))
+            .unwrap_or_default();
+        match self.window_mut(window) {
+            Some(window) if window.is_floating() => window.move_by(delta, screen_size),
+            Some(_) => warn!("Window {} is not floating and can't be moved", window),
+            None => {}
+        }
+    }
+
+    /// Dims the whole screen but the area of a window, which follows the window wherever it's
+    /// shown. An area of an unknown window or outside of it turns the spotlight off.
+    pub fn set_spotlight(&mut self, area: Option<SpotlightArea>) {
+        self.spotlight = area;
+        self.send_spotlight();
+    }
+
+    // Places the spotlight again after its window moved or changed size.
+    fn update_spotlight(&mut self, grid: u64) {
+        let spotlight_grid = self
+            .spotlight
+            .and_then(|area| self.window_handles.get(&area.window));
+        if spotlight_grid == Some(&grid) {
+            self.send_spotlight();
+        }
+    }
+
+    fn send_spotlight(&mut self) {
+        let spotlight = self.spotlight.and_then(|area| {
+            let window = self
+                .window_handles
+                .get(&area.window)
+                .and_then(|grid| self.windows.get(grid))?;
+            build_spotlight(
+                &area,
+                window.get_grid_position(),
+                (window.get_width(), window.get_height()),
+                &SETTINGS.get::<SpotlightSettings>(),
+            )
+        });
+
+        self.draw_command_batcher
+            .queue(DrawCommand::SpotlightChanged(spotlight))
+            .ok();
+    }
+
+    /// Returns the grids of the windows drawn over any part of the rectangle, from the bottom
+    /// most one up, so that only those have to be redrawn for a dirty region. The editor doesn't
+    /// know the font, so its cell size has to be passed along.
+    #[allow(dead_code)]
+    pub fn windows_in_rect(&self, rect: Rect, font_dimensions: Dimensions) -> Vec<u64> {
+        let mut windows: Vec<_> = self
+            .windows
+            .iter()
+            .filter(|(_, window)| {
+                !window.is_hidden() && window.pixel_bounds(font_dimensions).intersects(&rect)
+            })
+            .map(|(grid, window)| {
+                let is_message = matches!(window.window_type, WindowType::Message);
+                let sort_order = window.floating_order();
+                ((is_message, sort_order, *grid), *grid)
+            })
+            .collect();
+        windows.sort_unstable_by_key(|(z_order, _)| *z_order);
+        windows.into_iter().map(|(_, grid)| grid).collect()
+    }
+
+    /// Returns the smallest rectangle covering every window which is shown, in pixels for cells
+    /// of the given size, or none when no window is. Useful for sizing the OS window to what's
+    /// drawn.
+    #[cfg(test)]
+    pub fn content_bounds(&self, font_dimensions: Dimensions) -> Option<Rect> {
+        self.windows
+            .values()
+            .filter(|window| !window.is_hidden())
+            .map(|window| window.pixel_bounds(font_dimensions))
+            .reduce(|mut bounds, window_bounds| {
+                bounds.join(&window_bounds);
+                bounds
+            })
+    }
+
+    /// Returns the background color most cells of the shown windows are drawn with, so that the
+    /// OS window decorations can be matched to the colorscheme. Nothing is returned before neovim
+    /// sent its default colors.
+    #[cfg(test)]
+    pub fn dominant_background(&self) -> Option<Color4f> {
+        let default_colors = self.default_colors.as_ref()?;
+        // Colorschemes only use a handful of backgrounds, so a list is plenty.
+        let mut counts: Vec<(Color4f, u64)> = Vec::new();
+        for window in self.windows.values().filter(|window| !window.is_hidden()) {
+            for background in window.cell_backgrounds(default_colors) {
+                match counts.iter_mut().find(|(color, _)| *color == background) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((background, 1)),
+                }
+            }
+        }
+        counts
+            .into_iter()
+            .max_by_key(|(_, count)| *count)
+            .map(|(color, _)| color)
+    }
+
+    pub fn set_pending_keys(&mut self, keys: String) {
+        let screen_size = self
+            .windows
+            .get(&1)
+            .map(|window| (window.get_width(), window.get_height()));
+        let pending_keys = match screen_size {
+            Some(screen_size) if !keys.is_empty() => {
+                let position = SETTINGS.get::<PendingKeysSettings>().position;
+                Some(PendingKeys::new(keys, screen_size, position))
+            }
+            _ => None,
+        };
+
+        self.draw_command_batcher
+            .queue(DrawCommand::PendingKeysChanged(pending_keys))
+            .ok();
+    }
+
+    /// Shows which register a macro is recorded into, or takes the indicator away again once the
+    /// recording stops.
+    pub fn set_recording(&mut self, register: String, settings: &RecordingIndicatorSettings) {
+        let screen_width = self.windows.get(&1).map(|window| window.get_width());
+        let indicator = match (screen_width, settings.color()) {
+            (Some(screen_width), Some(color)) if !register.is_empty() => {
+                Some(RecordingIndicator::new(register, screen_width, color))
+            }
+            _ => None,
+        };
+
+        self.draw_command_batcher
+            .queue(DrawCommand::RecordingIndicatorChanged(indicator))
+            .ok();
+    }
+
+    /// Returns the fragment under the cursor with its full text and style, e.g. to show which
+    /// highlight is used there.
+    pub fn cursor_fragment(&self) -> Option<LineFragment> {
+        let (column, row) = self.cursor.grid_position;
+        self.windows
+            .get(&self.cursor.parent_window_id)?
+            .fragment_at(column, row)
+    }
+
+    fn define_style(&mut self, id: u64, style: Style) {
+        // Unchanged definitions keep the existing style, which the cells already point to.
+        if matches!(self.defined_styles.get(&id), Some(old_style) if **old_style == style) {
+            return;
+        }
+
+        let style = Arc::new(style);
+        let old_style = match self.defined_styles.insert(id, style.clone()) {
+            Some(old_style) => old_style,
+            None => return,
+        };
+
+        // The grids hold on to the style the cells were drawn with, so cells using a redefined id,
+        // e.g. after a colorscheme change, would keep the stale style until neovim redraws them.
+        // During a theme change they're replaced all at once on the next flush.
+        let (_, new_style) = self
+            .redefined_styles
+            .entry(id)
+            .or_insert_with(|| (old_style, style.clone()));
+        *new_style = style;
+        if !self.restyling {
+            self.replace_redefined_styles();
+        }
+    }
+
+    fn replace_redefined_styles(&mut self) {
+        let redefined_styles = std::mem::take(&mut self.redefined_styles);
+        for window in self.windows.values_mut() {
+            if window.replace_styles(&redefined_styles) && !self.restyling {
+                window.redraw();
+            }
+        }
+    }
+
+    /// Repaints every window at once with the styles of a new theme.
+    fn finish_restyle(&mut self) {
+        if self.restyling {
+            self.replace_redefined_styles();
+            self.restyling = false;
+            self.redraw_screen();
+        }
+    }
+
     fn set_message_position(&mut self, grid: u64, grid_top: u64) {
         let parent_width = self
             .windows
@@ -408,6 +1346,7 @@ impl Editor {
                 (0.0, grid_top as f64),
                 (parent_width, 1),
                 self.draw_command_batcher.clone(),
+                self.grid_limits,
             );
             self.windows.insert(grid, new_window);
         }
@@ -475,64 +1414,693 @@ impl Editor {
 
     fn send_cursor_info(&mut self) {
         tracy_zone!("send_cursor_info");
-        let (grid_left, grid_top) = self.cursor.grid_position;
-        if let Some(window) = self.windows.get(&self.cursor.parent_window_id) {
-            let (character, style, double_width) = window.get_cursor_grid_cell(grid_left, grid_top);
+        let mut enabled = self.cursor.enabled;
+        // Neovim doesn't move the cursor into an external command line, so it's put there while
+        // the box is shown.
+        let (parent_window_id, grid_position) = self
+            .command_line_cursor()
+            .unwrap_or((self.cursor.parent_window_id, self.cursor.grid_position));
+        let clamped_position = self
+            .windows
+            .get(&parent_window_id)
+            .and_then(|window| window.clamp_out_of_view(grid_position));
+        let (mut grid_left, grid_top) = match clamped_position {
+            None => grid_position,
+            Some(clamped_position) => match SETTINGS.get::<CursorViewSettings>().out_of_view {
+                CursorOutOfView::Show => grid_position,
+                CursorOutOfView::Hide => {
+                    enabled = false;
+                    grid_position
+                }
+                CursorOutOfView::Clamp => clamped_position,
+            },
+        };
+
+        self.cursor.tab_width = None;
+        if let Some(window) = self.windows.get(&parent_window_id) {
+            let (character, style, double_width, right_half) =
+                window.get_cursor_grid_cell(grid_left, grid_top);
             self.cursor.grid_cell = (character, style);
             self.cursor.double_width = double_width;
+            // On the right half of a double width character the cursor covers the whole of it.
+            if right_half {
+                grid_left -= 1;
+            }
+
+            // A block cursor on a tab may be stretched over the whole tab instead of one of its
+            // cells.
+            if self.cursor.shape == CursorShape::Block {
+                let tab_width = SETTINGS.get::<CursorViewSettings>().tab_width;
+                let (tab_left, width) = window.cursor_span(grid_left, grid_top, tab_width);
+                if width > 1 {
+                    grid_left = tab_left;
+                    self.cursor.tab_width = Some(width);
+                }
+            }
         } else {
             self.cursor.double_width = false;
             self.cursor.grid_cell = (" ".to_string(), None);
         }
-        self.draw_command_batcher
-            .queue(DrawCommand::UpdateCursor(self.cursor.clone()))
-            .ok();
-    }
+        self.cursor.shadow = SETTINGS.get::<CursorShadowSettings>().shadow();
 
-    fn set_option(&mut self, gui_option: GuiOption) {
-        trace!("Option set {:?}", &gui_option);
+        self.update_focused_grid();
+        self.update_collapsed_floats(&SETTINGS.get::<CollapsedFloatSettings>());
 
-        match gui_option {
-            GuiOption::GuiFont(guifont) => {
-                if guifont == *"*" {
-                    EVENT_AGGREGATOR.send(WindowCommand::ListAvailableFonts);
+        // Only the sent copy is moved or hidden, so the cursor goes back to where neovim put it
+        // once that is visible again.
+        let mut cursor = self.cursor.clone();
+        cursor.parent_window_id = parent_window_id;
+        cursor.grid_position = (grid_left, grid_top);
+        cursor.enabled = enabled;
+        let cursor_cell = (parent_window_id, (grid_left, grid_top));
+        cursor.travel = self
+            .last_cursor_cell
+            .and_then(|last_cursor_cell| self.cursor_travel(last_cursor_cell, cursor_cell));
+        self.last_cursor_cell = Some(cursor_cell);
+        self.update_crosshair(&SETTINGS.get::<CrosshairSettings>());
+        if let Some(default_colors) = &self.default_colors {
+            let (_, cell_style) = &cursor.grid_cell;
+            let background = match cell_style {
+                Some(style) => Some(style.background(default_colors)),
+                None => default_colors.background,
+            };
+            if let Some(background) = background {
+                let contrast = SETTINGS.get::<CursorContrastSettings>();
+                if let Some(style) = contrast.cursor_style(&cursor.style, background) {
+                    cursor.style = Some(style);
                 }
-
-                self.draw_command_batcher
-                    .queue(DrawCommand::FontChanged(guifont))
-                    .ok();
-
-                self.redraw_screen();
             }
-            GuiOption::LineSpace(linespace) => {
-                self.draw_command_batcher
-                    .queue(DrawCommand::LineSpaceChanged(linespace))
-                    .ok();
+        }
+        self.draw_command_batcher
+            .queue(DrawCommand::UpdateCursor(cursor))
+            .ok();
+    }
 
-                self.redraw_screen();
-            }
-            _ => (),
+    /// The path of the cursor moving from a cell of one grid to a cell of another, across the
+    /// screen. Nothing is returned for moves inside a grid, or when either window is gone.
+    pub fn cursor_travel(
+        &self,
+        (from_grid, from_cell): (u64, (u64, u64)),
+        (to_grid, to_cell): (u64, (u64, u64)),
+    ) -> Option<CursorTravel> {
+        if from_grid == to_grid {
+            return None;
         }
+        let screen_cell = |grid, (column, row): (u64, u64)| {
+            self.windows.get(&grid).map(|window| {
+                let (left, top) = window.get_grid_position();
+                (left + column as f64, top + row as f64)
+            })
+        };
+        Some(CursorTravel {
+            start: screen_cell(from_grid, from_cell)?,
+            end: screen_cell(to_grid, to_cell)?,
+        })
     }
 
-    fn send_updated_viewport(&mut self, grid: u64, scroll_delta: f64) {
-        if let Some(window) = self.windows.get_mut(&grid) {
-            window.update_viewport(scroll_delta);
+    // Follows the cell the cursor was last sent at, within its window.
+    fn update_crosshair(&mut self, settings: &CrosshairSettings) {
+        let crosshair = if settings.enabled {
+            self.last_cursor_cell.and_then(|(grid, cell)| {
+                let window = self.windows.get(&grid)?;
+                build_crosshair(
+                    cell,
+                    window.get_grid_position(),
+                    (window.get_width(), window.get_height()),
+                    settings,
+                )
+            })
         } else {
-            trace!("viewport event received before window initialized");
+            None
+        };
+        if crosshair != self.crosshair {
+            self.crosshair = crosshair.clone();
+            self.draw_command_batcher
+                .queue(DrawCommand::CrosshairChanged(crosshair))
+                .ok();
         }
     }
 
-    fn redraw_screen(&mut self) {
-        for window in self.windows.values() {
-            window.redraw();
+    // The first mode neovim sends isn't a change, and neither is the same mode sent again.
+    fn ripple_mode_change(&mut self, mode_index: u64, settings: &ModeRippleSettings) {
+        match self.last_mode_index.replace(mode_index) {
+            Some(previous_mode_index) if settings.enabled && previous_mode_index != mode_index => {}
+            _ => return,
         }
-    }
+
+        let cursor_center = self.last_cursor_cell.and_then(|(grid, (column, row))| {
+            let (left, top) = self.windows.get(&grid)?.get_grid_position();
+            Some((left + column as f64 + 0.5, top + row as f64 + 0.5))
+        });
+        if let Some(ripple) = build_mode_ripple(cursor_center, settings) {
+            self.draw_command_batcher
+                .queue(DrawCommand::ModeRipple(ripple))
+                .ok();
+        }
+    }
+
+    /// Combines the viewports of the splits into one overview, with a segment above each split
+    /// showing where it's scrolled to. Floats and windows neovim didn't send a line count for are
+    /// left out.
+    pub fn scroll_overview(&self, settings: &ScrollOverviewSettings) -> Option<ScrollOverview> {
+        let mut segments: Vec<_> = self
+            .windows
+            .iter()
+            .filter(|(grid, window)| {
+                **grid != 1
+                    && !window.is_hidden()
+                    && !window.is_floating()
+                    && matches!(window.window_type, WindowType::Editor)
+            })
+            .filter_map(|(_, window)| {
+                let (left, _) = window.get_grid_position();
+                window
+                    .viewport_lines()
+                    .map(|viewport| ScrollSegment::new(left, window.get_width(), viewport))
+            })
+            .collect();
+        if segments.is_empty() {
+            return None;
+        }
+        segments.sort_by(|a, b| a.left.total_cmp(&b.left));
+
+        Some(ScrollOverview {
+            segments,
+            color: settings.color()?,
+            thumb_color: settings.thumb_color()?,
+            height: settings.height,
+        })
+    }
+
+    fn update_scroll_overview(&mut self) {
+        let settings = SETTINGS.get::<ScrollOverviewSettings>();
+        let scroll_overview = if settings.enabled {
+            self.scroll_overview(&settings)
+        } else {
+            None
+        };
+        if scroll_overview != self.scroll_overview {
+            self.scroll_overview = scroll_overview.clone();
+            self.draw_command_batcher
+                .queue(DrawCommand::ScrollOverviewChanged(scroll_overview))
+                .ok();
+        }
+    }
+
+    /// Finds the edges the splits share, for separators drawn over the ones neovim puts between
+    /// them. Floats are left out, as they aren't part of the layout.
+    pub fn window_separators(
+        &self,
+        settings: &WindowSeparatorSettings,
+    ) -> Option<WindowSeparators> {
+        let windows: Vec<_> = self
+            .windows
+            .iter()
+            .filter(|(grid, window)| {
+                **grid != 1
+                    && !window.is_hidden()
+                    && !window.is_floating()
+                    && matches!(window.window_type, WindowType::Editor)
+            })
+            .map(|(_, window)| {
+                let (left, top) = window.get_grid_position();
+                Rect::from_xywh(
+                    left as f32,
+                    top as f32,
+                    window.get_width() as f32,
+                    window.get_height() as f32,
+                )
+            })
+            .collect();
+        let mut separators = find_window_separators(&windows);
+        if separators.is_empty() {
+            return None;
+        }
+        separators.sort_by(|a, b| (a.left, a.top).partial_cmp(&(b.left, b.top)).unwrap());
+
+        let default_foreground = self
+            .default_colors
+            .as_ref()
+            .and_then(|colors| colors.foreground);
+        Some(WindowSeparators {
+            separators,
+            color: settings.color(default_foreground)?,
+            thickness: settings.thickness,
+            style: settings.style,
+        })
+    }
+
+    fn update_window_separators(&mut self, settings: &WindowSeparatorSettings) {
+        let window_separators = if settings.enabled {
+            self.window_separators(settings)
+        } else {
+            None
+        };
+        if window_separators != self.window_separators {
+            self.window_separators = window_separators.clone();
+            self.draw_command_batcher
+                .queue(DrawCommand::WindowSeparatorsChanged(window_separators))
+                .ok();
+        }
+    }
+
+    fn send_flush(&mut self) {
+        {
+            trace!("send_batch");
+            self.draw_command_batcher.send_batch();
+        }
+        // Windows which lost commands that didn't fit the queue are drawn again whole on the next
+        // flush.
+        for grid in self.draw_command_batcher.take_dropped_grids() {
+            if let Some(window) = self.windows.get_mut(&grid) {
+                window.invalidate();
+            }
+        }
+        {
+            let interval = SETTINGS.get::<RenderMetricsSettings>().interval;
+            let metrics = self
+                .draw_command_batcher
+                .take_metrics(Instant::now(), Duration::from_millis(interval));
+            if let Some(metrics) = metrics {
+                EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::ReportRenderMetrics(
+                    metrics,
+                )));
+            }
+        }
+        {
+            trace!("queue_next_frame");
+            REDRAW_SCHEDULER.queue_next_frame();
+        }
+    }
+
+    // The grid the cursor is in written out as text with the cursor marked, for bug reports.
+    fn export_cursor_grid(&self) -> Option<String> {
+        let grid = self.cursor.parent_window_id;
+        let (column, row) = self.cursor.grid_position;
+        // The run of text the cursor is on, to tell which highlight the cell is drawn with.
+        let fragment = match self.cursor_fragment() {
+            Some(fragment) => format!(
+                " on {:?} drawn as {}",
+                fragment.text,
+                fragment
+                    .style
+                    .as_ref()
+                    .map_or_else(|| "plain".to_owned(), |style| grid::describe_style(style))
+            ),
+            None => String::new(),
+        };
+        self.windows.get(&grid).map(|window| {
+            format!(
+                "grid {grid}, cursor at row {row}, column {column}{fragment}\n{}",
+                window.export_grid_with_cursor((column, row))
+            )
+        })
+    }
+
+    fn snapshot_cursor_grid(&self, path: &str) -> Result<(), String> {
+        let window = self
+            .windows
+            .get(&self.cursor.parent_window_id)
+            .ok_or_else(|| "The cursor isn't in a known grid".to_owned())?;
+        let json = serde_json::to_string(&window.snapshot()).map_err(|error| error.to_string())?;
+        std::fs::write(path, json).map_err(|error| error.to_string())
+    }
+
+    // Returns whether the mode is known.
+    fn change_cursor_mode(&mut self, mode_index: u64) -> bool {
+        let cursor_mode = match self.mode_list.get(mode_index as usize) {
+            Some(cursor_mode) => cursor_mode,
+            None => return false,
+        };
+        self.cursor.change_mode(cursor_mode, &self.defined_styles);
+        SETTINGS
+            .get::<CursorViewSettings>()
+            .shape_overrides
+            .apply(cursor_mode, &mut self.cursor);
+        true
+    }
+
+    // The focus stays on the last editor window while the cursor visits the message grid or the
+    // command line. The screen grid itself never gets focus, it only shows up without multigrid.
+    fn update_focused_grid(&mut self) {
+        let grid = self.cursor.parent_window_id;
+        let focusable = grid != 1
+            && matches!(
+                self.windows.get(&grid),
+                Some(Window {
+                    window_type: WindowType::Editor,
+                    ..
+                })
+            );
+        if focusable && self.focused_grid != Some(grid) {
+            self.focused_grid = Some(grid);
+            self.draw_command_batcher
+                .queue(DrawCommand::FocusChanged(self.focused_grid))
+                .ok();
+        }
+    }
+
+    /// Collapses the floats other than the focused one to their header, so that a stack of them
+    /// takes less space, and expands the focused one again.
+    fn update_collapsed_floats(&mut self, settings: &CollapsedFloatSettings) {
+        for (grid, window) in self.windows.iter_mut() {
+            if !window.is_floating() || window.window_type != WindowType::Editor {
+                continue;
+            }
+            window.set_collapsed(settings.collapsed_height(self.focused_grid == Some(*grid)));
+        }
+    }
+
+    // The part of the command line which fits the box, scrolled so that the cursor stays visible,
+    // along with the column of the cursor in it and how many columns were scrolled out.
+    fn command_line_layout(&self) -> Option<(Vec<GridLineCell>, u64, u64)> {
+        let command_line = self.command_line.as_ref()?;
+        let window = self.windows.get(&COMMAND_LINE_GRID)?;
+        let border = u64::from(SETTINGS.get::<CommandLineSettings>().has_border());
+        let inner_width = window.get_width().saturating_sub(2 * border).max(1);
+
+        let (cells, cursor_column) = command_line.cells();
+        let scroll = (cursor_column + 1).saturating_sub(inner_width) as usize;
+        let mut visible: Vec<_> = cells
+            .into_iter()
+            .skip(scroll)
+            .take(inner_width as usize)
+            .collect();
+        visible.resize(
+            inner_width as usize,
+            GridLineCell {
+                text: " ".to_owned(),
+                highlight_id: Some(0),
+                repeat: None,
+            },
+        );
+        Some((visible, cursor_column - scroll as u64, scroll as u64))
+    }
+
+    fn command_line_cursor(&self) -> Option<(u64, (u64, u64))> {
+        let (_, cursor_column, _) = self.command_line_layout()?;
+        let border = u64::from(SETTINGS.get::<CommandLineSettings>().has_border());
+        Some((COMMAND_LINE_GRID, (border + cursor_column, border)))
+    }
+
+    // Where the first column of the command line text is on the screen, left of the box once the
+    // text is scrolled.
+    fn command_line_origin(&self) -> Option<(f64, f64)> {
+        let (_, _, scroll) = self.command_line_layout()?;
+        let border = u64::from(SETTINGS.get::<CommandLineSettings>().has_border()) as f64;
+        let (left, top) = self.get_window_top_left(COMMAND_LINE_GRID)?;
+        Some((left + border - scroll as f64, top + border))
+    }
+
+    fn draw_command_line(&mut self) {
+        let screen_size = match self.windows.get(&1) {
+            Some(window) => (window.get_width(), window.get_height()),
+            None => return,
+        };
+        let settings = SETTINGS.get::<CommandLineSettings>();
+        let ((left, top), (width, height)) = command_line::geometry(screen_size, &settings);
+        let anchor_info = AnchorInfo {
+            anchor_grid_id: 1,
+            anchor_type: WindowAnchor::NorthWest,
+            anchor_left: left,
+            anchor_top: top,
+            // Above every float neovim creates.
+            sort_order: u64::MAX,
+        };
+
+        match self.windows.get_mut(&COMMAND_LINE_GRID) {
+            Some(window) => {
+                window.position(Some(anchor_info), (width, height), (left, top));
+                window.show();
+            }
+            None => {
+                let window = Window::new(
+                    COMMAND_LINE_GRID,
+                    WindowType::Editor,
+                    Some(anchor_info),
+                    (left, top),
+                    (width, height),
+                    self.draw_command_batcher.clone(),
+                    self.grid_limits,
+                );
+                self.windows.insert(COMMAND_LINE_GRID, window);
+            }
+        }
+
+        let (cells, _, _) = match self.command_line_layout() {
+            Some(layout) => layout,
+            None => return,
+        };
+        let defined_styles = &self.defined_styles;
+        let window = self.windows.get_mut(&COMMAND_LINE_GRID).unwrap();
+        if settings.has_border() {
+            let horizontal = "─".repeat(width.saturating_sub(2) as usize);
+            let border_row = |text: &str| {
+                text.chars()
+                    .map(|character| GridLineCell {
+                        text: character.to_string(),
+                        highlight_id: Some(0),
+                        repeat: None,
+                    })
+                    .collect::<Vec<_>>()
+            };
+            window.draw_grid_line(0, 0, border_row(&format!("╭{horizontal}╮")), defined_styles);
+            window.draw_grid_line(
+                height - 1,
+                0,
+                border_row(&format!("╰{horizontal}╯")),
+                defined_styles,
+            );
+            window.draw_grid_line(1, 0, border_row("│"), defined_styles);
+            window.draw_grid_line(1, width - 1, border_row("│"), defined_styles);
+            window.draw_grid_line(1, 1, cells, defined_styles);
+        } else {
+            window.draw_grid_line(0, 0, cells, defined_styles);
+        }
+    }
+
+    /// The boxes drawn over the screen are laid out from its size, so they're laid out again when
+    /// it changes. A hidden message log waits for the next message.
+    fn lay_out_screen_boxes(&mut self) {
+        if self.command_line.is_some() {
+            self.draw_command_line();
+        }
+        if matches!(self.windows.get(&MESSAGE_LOG_GRID), Some(window) if !window.is_hidden()) {
+            self.draw_message_log();
+        }
+        self.draw_message_status();
+    }
+
+    /// Draws the mode, the partial command and the ruler in the last row of the screen, which
+    /// Neovim leaves to the UI along with the messages.
+    fn draw_message_status(&mut self) {
+        let (width, screen_height) = match self.windows.get(&1) {
+            Some(window) => (window.get_width(), window.get_height()),
+            None => return,
+        };
+        if self.message_status.is_empty() {
+            if let Some(window) = self.windows.get_mut(&MESSAGE_STATUS_GRID) {
+                window.hide();
+            }
+            return;
+        }
+
+        let top = screen_height.saturating_sub(1) as f64;
+        let anchor_info = AnchorInfo {
+            anchor_grid_id: 1,
+            anchor_type: WindowAnchor::NorthWest,
+            anchor_left: 0.0,
+            anchor_top: top,
+            // Above the floats, but below the message log and the command line box.
+            sort_order: u64::MAX - 2,
+        };
+
+        match self.windows.get_mut(&MESSAGE_STATUS_GRID) {
+            Some(window) => {
+                window.position(Some(anchor_info), (width, 1), (0.0, top));
+                window.show();
+            }
+            None => {
+                let window = Window::new(
+                    MESSAGE_STATUS_GRID,
+                    WindowType::Message,
+                    Some(anchor_info),
+                    (0.0, top),
+                    (width, 1),
+                    self.draw_command_batcher.clone(),
+                    self.grid_limits,
+                );
+                self.windows.insert(MESSAGE_STATUS_GRID, window);
+            }
+        }
+
+        let cells = self.message_status.cells(width);
+        let window = self.windows.get_mut(&MESSAGE_STATUS_GRID).unwrap();
+        window.draw_grid_line(0, 0, cells, &self.defined_styles);
+    }
+
+    /// Draws the lines of the log in view over the bottom of the screen, right above the last
+    /// row with the mode and the ruler, see `draw_message_status`.
+    fn draw_message_log(&mut self) {
+        let screen_size = match self.windows.get(&1) {
+            Some(window) => (window.get_width(), window.get_height()),
+            None => return,
+        };
+        if self.message_log.is_empty() {
+            return;
+        }
+        let (width, screen_height) = screen_size;
+        let settings = SETTINGS.get::<MessageLogSettings>();
+        let lines = self.message_log.visible_lines(settings.height.max(1));
+        let height = (lines.len() as u64)
+            .min(screen_height.saturating_sub(1))
+            .max(1);
+        let top = screen_height.saturating_sub(height + 1) as f64;
+        let anchor_info = AnchorInfo {
+            anchor_grid_id: 1,
+            anchor_type: WindowAnchor::NorthWest,
+            anchor_left: 0.0,
+            anchor_top: top,
+            // Above the floats, but below the command line box.
+            sort_order: u64::MAX - 1,
+        };
+
+        match self.windows.get_mut(&MESSAGE_LOG_GRID) {
+            Some(window) => {
+                window.position(Some(anchor_info), (width, height), (0.0, top));
+                window.show();
+            }
+            None => {
+                let window = Window::new(
+                    MESSAGE_LOG_GRID,
+                    WindowType::Message,
+                    Some(anchor_info),
+                    (0.0, top),
+                    (width, height),
+                    self.draw_command_batcher.clone(),
+                    self.grid_limits,
+                );
+                self.windows.insert(MESSAGE_LOG_GRID, window);
+            }
+        }
+
+        let window = self.windows.get_mut(&MESSAGE_LOG_GRID).unwrap();
+        let skipped = lines.len() - height as usize;
+        for (row, line) in lines.into_iter().skip(skipped).enumerate() {
+            let mut cells = Vec::new();
+            for (highlight_id, text) in line {
+                command_line::push_text(&mut cells, text, *highlight_id);
+            }
+            cells.truncate(width as usize);
+            cells.resize(
+                width as usize,
+                GridLineCell {
+                    text: " ".to_owned(),
+                    highlight_id: Some(0),
+                    repeat: None,
+                },
+            );
+            window.draw_grid_line(row as u64, 0, cells, &self.defined_styles);
+        }
+    }
+
+    fn show_popup_menu(
+        &mut self,
+        items: Vec<PopupMenuItem>,
+        selected: Option<u64>,
+        grid: i64,
+        row: u64,
+        column: u64,
+    ) {
+        let max_height = SETTINGS.get::<PopupMenuSettings>().max_height as usize;
+
+        // A negative grid means the menu is anchored to the external cmdline. Without a box drawn
+        // for it, that's the last row of the screen, where neovim would draw it.
+        let screen_height = self
+            .windows
+            .get(&1)
+            .map(|window| window.get_height() as f64)
+            .unwrap_or(f64::MAX);
+        let (grid_left, grid_top) = match u64::try_from(grid) {
+            Ok(grid) => self.get_window_top_left(grid).unwrap_or((0.0, 0.0)),
+            // The column is one of the command line text, which scrolls in the box.
+            Err(_) => self
+                .command_line_origin()
+                .unwrap_or((0.0, (screen_height - 1.0).max(0.0))),
+        };
+        let anchor_left = (grid_left + column as f64).max(0.0);
+        let anchor_top = grid_top + row as f64;
+
+        // Prefer opening below the anchor, but flip above it when the menu wouldn't fit.
+        let height = items.len().min(max_height.max(1)) as f64;
+        let menu_top = if anchor_top + 1.0 + height > screen_height && anchor_top >= height {
+            anchor_top - height
+        } else {
+            anchor_top + 1.0
+        };
+
+        self.popup_menu = Some(PopupMenu::new(
+            items,
+            selected.map(|selected| selected as usize),
+            (anchor_left, menu_top),
+            max_height,
+        ));
+        self.send_popup_menu();
+    }
+
+    fn send_popup_menu(&self) {
+        self.draw_command_batcher
+            .queue(DrawCommand::PopupMenuChanged(self.popup_menu.clone()))
+            .ok();
+    }
+
+    fn set_option(&mut self, gui_option: GuiOption) {
+        trace!("Option set {:?}", &gui_option);
+
+        match gui_option {
+            GuiOption::GuiFont(guifont) => {
+                if guifont == *"*" {
+                    EVENT_AGGREGATOR.send(WindowCommand::ListAvailableFonts);
+                }
+
+                self.draw_command_batcher
+                    .queue(DrawCommand::FontChanged(guifont))
+                    .ok();
+
+                self.redraw_screen();
+            }
+            GuiOption::LineSpace(linespace) => {
+                self.draw_command_batcher
+                    .queue(DrawCommand::LineSpaceChanged(linespace))
+                    .ok();
+
+                self.redraw_screen();
+            }
+            _ => (),
+        }
+    }
+
+    fn send_updated_viewport(&mut self, grid: u64, scroll_delta: Option<f64>) {
+        if let Some(window) = self.windows.get_mut(&grid) {
+            window.update_viewport(scroll_delta);
+        } else {
+            trace!("viewport event received before window initialized");
+        }
+    }
+
+    fn redraw_screen(&mut self) {
+        for window in self.windows.values_mut() {
+            window.invalidate();
+            window.redraw();
+        }
+    }
 }
 
 pub fn start_editor() {
+    let cmd_line_settings = SETTINGS.get::<CmdLineSettings>();
+    let grid_limits = GridLimits::from_cmd_line(&cmd_line_settings);
     thread::spawn(move || {
-        let mut editor = Editor::new();
+        let mut editor = Editor::new(grid_limits);
 
         let mut editor_command_receiver = EVENT_AGGREGATOR.register_event::<EditorCommand>();
         while let Some(editor_command) = editor_command_receiver.blocking_recv() {
@@ -540,3 +2108,2200 @@ pub fn start_editor() {
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use serial_test::serial;
+    use skia_safe::Point;
+
+    use super::*;
+    use crate::{bridge::GridLineCell, renderer::WindowDrawCommand};
+
+    // The settings are global, so every test building an editor runs serially and starts from the
+    // defaults of every group the editor reads, whatever the tests before it set.
+    fn build_test_editor() -> Editor {
+        SETTINGS.set(&CursorShadowSettings::default());
+        SETTINGS.set(&CursorContrastSettings::default());
+        SETTINGS.set(&CursorViewSettings::default());
+        SETTINGS.set(&SpotlightSettings::default());
+        SETTINGS.set(&IndentGuideSettings::default());
+        SETTINGS.set(&ScrollOverviewSettings::default());
+        SETTINGS.set(&BreadcrumbSettings::default());
+        SETTINGS.set(&DiagnosticSettings::default());
+        SETTINGS.set(&BlameSettings::default());
+        SETTINGS.set(&RenderMetricsSettings::default());
+        SETTINGS.set(&ScrollPercentageSettings::default());
+        SETTINGS.set(&MessageLogSettings::default());
+        SETTINGS.set(&CommandLineSettings::default());
+        SETTINGS.set(&WindowSeparatorSettings::default());
+        SETTINGS.set(&CrosshairSettings::default());
+        SETTINGS.set(&PeekPreviewSettings::default());
+        SETTINGS.set(&ModeRippleSettings::default());
+        SETTINGS.set(&CollapsedFloatSettings::default());
+        SETTINGS.set(&LigatureSettings::default());
+        SETTINGS.set(&RecordingIndicatorSettings::default());
+        SETTINGS.set(&PendingKeysSettings::default());
+        SETTINGS.set(&LineNumberSettings::default());
+        SETTINGS.set(&VisualBlockSettings::default());
+        SETTINGS.set(&PopupMenuSettings::default());
+
+        let mut editor = Editor::new(GridLimits::default());
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::Resize {
+            grid: 1,
+            width: 80,
+            height: 24,
+        }));
+        editor.draw_command_batcher.drain();
+        editor
+    }
+
+    fn popup_menu_items(count: usize) -> Vec<PopupMenuItem> {
+        (0..count)
+            .map(|index| PopupMenuItem {
+                word: format!("item{index}"),
+                kind: "".to_owned(),
+                menu: "".to_owned(),
+                info: "".to_owned(),
+            })
+            .collect()
+    }
+
+    fn sent_popup_menus(editor: &Editor) -> Vec<Option<PopupMenu>> {
+        editor
+            .draw_command_batcher
+            .drain()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::PopupMenuChanged(popup_menu) => Some(popup_menu),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    #[serial]
+    fn popup_menu_show_and_select_send_the_menu() {
+        let mut editor = build_test_editor();
+
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::PopupMenuShow {
+                items: popup_menu_items(3),
+                selected: Some(1),
+                row: 5,
+                column: 3,
+                grid: 1,
+            },
+        ));
+
+        let popup_menus = sent_popup_menus(&editor);
+        assert_eq!(popup_menus.len(), 1);
+        let popup_menu = popup_menus[0].as_ref().expect("Popup menu should be shown");
+        assert_eq!(popup_menu.items, popup_menu_items(3));
+        assert_eq!(popup_menu.selected, Some(1));
+        assert_eq!(popup_menu.grid_position, (3.0, 6.0));
+
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::PopupMenuSelect { selected: Some(2) },
+        ));
+        let popup_menus = sent_popup_menus(&editor);
+        assert_eq!(popup_menus[0].as_ref().unwrap().selected, Some(2));
+
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::PopupMenuHide));
+        assert_eq!(sent_popup_menus(&editor), vec![None]);
+    }
+
+    #[test]
+    #[serial]
+    fn popup_menu_flips_above_the_anchor_at_the_bottom_of_the_screen() {
+        let mut editor = build_test_editor();
+
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::PopupMenuShow {
+                items: popup_menu_items(3),
+                selected: None,
+                row: 23,
+                column: 1,
+                grid: 1,
+            },
+        ));
+
+        let popup_menus = sent_popup_menus(&editor);
+        assert_eq!(popup_menus[0].as_ref().unwrap().grid_position, (1.0, 20.0));
+    }
+
+    #[test]
+    #[serial]
+    fn window_brightness_is_sent_without_touching_the_grid() {
+        let mut editor = build_test_editor();
+
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::WindowPosition {
+                grid: 2,
+                window: 1000,
+                start_row: 0,
+                start_column: 0,
+                width: 10,
+                height: 5,
+            },
+        ));
+        editor
+            .defined_styles
+            .insert(1, Arc::new(Style::new(Colors::new(None, None, None))));
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::GridLine {
+            grid: 2,
+            row: 2,
+            column_start: 3,
+            cells: vec![GridLineCell {
+                text: "a".to_owned(),
+                highlight_id: Some(1),
+                repeat: None,
+            }],
+            wrap: false,
+        }));
+        let cell_before = editor.windows[&2].get_cursor_grid_cell(3, 2);
+        editor.draw_command_batcher.drain();
+
+        editor.handle_editor_command(EditorCommand::SetWindowBrightness {
+            window: 1000,
+            brightness: 0.5,
+        });
+
+        let commands = editor.draw_command_batcher.drain();
+        assert!(commands.iter().any(|command| matches!(
+            command,
+            DrawCommand::Window {
+                grid_id: 2,
+                command: WindowDrawCommand::Brightness(brightness),
+            } if (brightness - 0.5).abs() < f32::EPSILON
+        )));
+
+        assert_eq!(editor.windows[&2].get_cursor_grid_cell(3, 2), cell_before);
+    }
+
+    #[test]
+    #[serial]
+    fn exported_grid_names_the_cursor_position_and_style() {
+        let mut editor = build_test_editor();
+        float_window(&mut editor, 2, 1);
+        let mut style = Style::new(Colors::new(None, None, None));
+        style.bold = true;
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::HighlightAttributesDefine { id: 1, style },
+        ));
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::GridLine {
+            grid: 2,
+            row: 1,
+            column_start: 3,
+            cells: vec![GridLineCell {
+                text: "a".to_owned(),
+                highlight_id: Some(1),
+                repeat: Some(2),
+            }],
+            wrap: false,
+        }));
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::CursorGoto {
+            grid: 2,
+            row: 1,
+            column: 3,
+        }));
+
+        let export = editor.export_cursor_grid().unwrap();
+        let lines: Vec<_> = export.lines().collect();
+        assert_eq!(
+            lines[0],
+            "grid 2, cursor at row 1, column 3 on \"aa\" drawn as bold"
+        );
+        assert_eq!(lines[2], "   [a]a");
+        assert_eq!(lines[3], "    ^");
+    }
+
+    #[test]
+    #[serial]
+    fn grid_snapshots_are_written_for_the_cursor_grid() {
+        let mut editor = build_test_editor();
+        float_window(&mut editor, 2, 1);
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::CursorGoto {
+            grid: 2,
+            row: 0,
+            column: 0,
+        }));
+
+        let path = std::env::temp_dir().join("neovide-grid-snapshot-test.json");
+        let path = path.to_str().unwrap();
+        assert_eq!(editor.snapshot_cursor_grid(path), Ok(()));
+        let snapshot: WindowSnapshot =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        std::fs::remove_file(path).ok();
+        assert_eq!(
+            (snapshot.grid_id, snapshot.width, snapshot.height),
+            (2, 10, 5)
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn window_mirroring_is_sent_to_the_window() {
+        let mut editor = build_test_editor();
+        float_window(&mut editor, 2, 1);
+        editor.draw_command_batcher.drain();
+
+        editor.handle_editor_command(EditorCommand::SetWindowMirrored {
+            window: 1002,
+            mirrored: true,
+        });
+
+        let commands = editor.draw_command_batcher.drain();
+        assert!(commands.iter().any(|command| matches!(
+            command,
+            DrawCommand::Window {
+                grid_id: 2,
+                command: WindowDrawCommand::Mirrored(true),
+            }
+        )));
+    }
+
+    #[test]
+    #[serial]
+    fn snapped_windows_are_placed_at_whole_pixels() {
+        let mut editor = build_test_editor();
+        float_window(&mut editor, 2, 1);
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::WindowFloatPosition {
+                grid: 2,
+                window: 1002,
+                anchor: WindowAnchor::NorthWest,
+                anchor_grid: 1,
+                anchor_row: 1.3,
+                anchor_column: 2.5,
+                focusable: true,
+                sort_order: Some(1),
+            },
+        ));
+        editor.draw_command_batcher.drain();
+
+        editor.handle_editor_command(EditorCommand::SetWindowSnapToPixels {
+            window: 1002,
+            snap_to_pixels: true,
+        });
+
+        let commands = editor.draw_command_batcher.drain();
+        assert!(commands.iter().any(|command| matches!(
+            command,
+            DrawCommand::Window {
+                grid_id: 2,
+                command: WindowDrawCommand::Position {
+                    grid_position: (left, top),
+                    snap_to_pixels: true,
+                    ..
+                },
+            } if (*left, *top) == (2.5, 1.3)
+        )));
+        let bounds = editor.windows[&2].pixel_bounds(Dimensions {
+            width: 7,
+            height: 15,
+        });
+        assert_eq!((bounds.left, bounds.top), (18.0, 20.0));
+    }
+
+    #[test]
+    #[serial]
+    fn window_breadcrumb_is_sent_to_the_window() {
+        let mut editor = build_test_editor();
+        float_window(&mut editor, 2, 1);
+        editor.draw_command_batcher.drain();
+
+        let set_breadcrumb = |breadcrumb: &str| EditorCommand::SetWindowBreadcrumb {
+            window: 1002,
+            breadcrumb: breadcrumb.to_owned(),
+        };
+        let sent_breadcrumbs = |editor: &Editor| -> Vec<_> {
+            editor
+                .draw_command_batcher
+                .drain()
+                .into_iter()
+                .filter_map(|command| match command {
+                    DrawCommand::Window {
+                        grid_id: 2,
+                        command: WindowDrawCommand::Breadcrumb(breadcrumb),
+                    } => Some(breadcrumb.map(|breadcrumb| breadcrumb.text)),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        editor.handle_editor_command(set_breadcrumb("init.lua"));
+        assert_eq!(sent_breadcrumbs(&editor), vec![Some("init.lua".to_owned())]);
+
+        // The float is 10 cells wide.
+        editor.handle_editor_command(set_breadcrumb("init.lua > setup"));
+        assert_eq!(
+            sent_breadcrumbs(&editor),
+            vec![Some("init.lua >".to_owned())]
+        );
+
+        editor.handle_editor_command(set_breadcrumb(""));
+        assert_eq!(sent_breadcrumbs(&editor), vec![None]);
+    }
+
+    #[test]
+    #[serial]
+    fn windows_without_smooth_scroll_jump_to_the_new_viewport() {
+        let mut editor = build_test_editor();
+        float_window(&mut editor, 2, 1);
+        float_window(&mut editor, 3, 1);
+        editor.handle_editor_command(EditorCommand::SetWindowSmoothScroll {
+            window: 1002,
+            smooth_scroll: Some(false),
+        });
+        editor.draw_command_batcher.drain();
+
+        for grid in [2, 3] {
+            editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+                RedrawEvent::WindowViewport {
+                    grid,
+                    top_line: 10.0,
+                    bottom_line: 20.0,
+                    current_line: 10.0,
+                    current_column: 0.0,
+                    line_count: Some(100.0),
+                    scroll_delta: Some(10.0),
+                },
+            ));
+        }
+
+        let viewports: Vec<_> = editor
+            .draw_command_batcher
+            .drain()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::Window {
+                    grid_id,
+                    command: WindowDrawCommand::Viewport { animated, .. },
+                } => Some((grid_id, animated)),
+                _ => None,
+            })
+            .collect();
+        // The other window keeps following the global setting.
+        assert_eq!(viewports, vec![(2, false), (3, true)]);
+    }
+
+    #[test]
+    #[serial]
+    fn grid_scrolls_move_the_viewport_when_neovim_sends_no_scroll_delta() {
+        let mut editor = build_test_editor();
+        float_window(&mut editor, 2, 1);
+        editor.draw_command_batcher.drain();
+
+        let scroll = |rows| {
+            EditorCommand::NeovimRedrawEvent(RedrawEvent::Scroll {
+                grid: 2,
+                top: 0,
+                bottom: 5,
+                left: 0,
+                right: 10,
+                rows,
+                columns: 0,
+            })
+        };
+        let viewport = |top_line, scroll_delta| {
+            EditorCommand::NeovimRedrawEvent(RedrawEvent::WindowViewport {
+                grid: 2,
+                top_line,
+                bottom_line: top_line + 5.0,
+                current_line: top_line,
+                current_column: 0.0,
+                line_count: None,
+                scroll_delta,
+            })
+        };
+        // Every step ends with the redraw of a flush.
+        let sent_viewports = |editor: &mut Editor, commands: Vec<EditorCommand>| {
+            for command in commands {
+                editor.handle_editor_command(command);
+            }
+            editor.windows.get_mut(&2).unwrap().redraw();
+            editor
+                .draw_command_batcher
+                .drain()
+                .into_iter()
+                .filter_map(|command| match command {
+                    DrawCommand::Window {
+                        grid_id: 2,
+                        command: WindowDrawCommand::Viewport { scroll_delta, .. },
+                    } => Some(scroll_delta),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            sent_viewports(&mut editor, vec![scroll(2), scroll(1), viewport(3.0, None)]),
+            vec![3.0]
+        );
+        // Deleting the top line scrolls the grid but leaves the viewport where it was.
+        assert!(sent_viewports(&mut editor, vec![scroll(1), viewport(3.0, None)]).is_empty());
+        // Scrolling past a closed fold moves the top line further than the rows scrolled.
+        assert_eq!(
+            sent_viewports(&mut editor, vec![scroll(1), viewport(8.0, None)]),
+            vec![1.0]
+        );
+
+        // The delta neovim sends is passed on for the renderer to follow.
+        assert_eq!(
+            sent_viewports(&mut editor, vec![scroll(2), viewport(10.0, Some(4.0))]),
+            vec![4.0]
+        );
+        assert!(sent_viewports(&mut editor, vec![viewport(10.0, Some(0.0))]).is_empty());
+    }
+
+    #[test]
+    #[serial]
+    fn cursor_moving_into_a_float_travels_between_the_grids() {
+        let mut editor = build_test_editor();
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::WindowPosition {
+                grid: 2,
+                window: 1002,
+                start_row: 1,
+                start_column: 0,
+                width: 40,
+                height: 22,
+            },
+        ));
+        // The float is 10x5 cells, anchored at the top left of cell (30, 10) of the screen.
+        float_window(&mut editor, 3, 1);
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::WindowFloatPosition {
+                grid: 3,
+                window: 1003,
+                anchor: WindowAnchor::NorthWest,
+                anchor_grid: 1,
+                anchor_row: 10.0,
+                anchor_column: 30.0,
+                focusable: true,
+                sort_order: Some(1),
+            },
+        ));
+        let font_dimensions = Dimensions {
+            width: 10,
+            height: 20,
+        };
+
+        let travel = editor.cursor_travel((2, (5, 3)), (3, (2, 1))).unwrap();
+        assert_eq!(
+            travel.to_pixels(font_dimensions),
+            (Point::new(50.0, 80.0), Point::new(320.0, 220.0))
+        );
+        assert_eq!(editor.cursor_travel((2, (5, 3)), (2, (6, 3))), None);
+
+        // Only the cursor sent right after the switch carries the travel.
+        let mut cursor_travel = |grid, (column, row)| {
+            editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+                RedrawEvent::CursorGoto { grid, column, row },
+            ));
+            editor.draw_command_batcher.drain();
+            editor.send_cursor_info();
+            sent_cursors(&editor).pop().unwrap().travel
+        };
+        cursor_travel(2, (5, 3));
+        assert_eq!(cursor_travel(3, (2, 1)), Some(travel));
+        assert_eq!(cursor_travel(3, (3, 1)), None);
+    }
+
+    #[test]
+    #[serial]
+    fn crosshair_follows_the_cursor_across_its_window() {
+        let mut editor = build_test_editor();
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::WindowPosition {
+                grid: 2,
+                window: 1002,
+                start_row: 1,
+                start_column: 41,
+                width: 39,
+                height: 22,
+            },
+        ));
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::CursorGoto {
+            grid: 2,
+            column: 7,
+            row: 3,
+        }));
+        editor.send_cursor_info();
+        editor.draw_command_batcher.drain();
+
+        let settings = CrosshairSettings {
+            enabled: true,
+            thickness: 2.0,
+            color: "#ff0000".to_string(),
+        };
+        let mut sent_crosshairs = |settings: &CrosshairSettings| {
+            editor.update_crosshair(settings);
+            editor
+                .draw_command_batcher
+                .drain()
+                .into_iter()
+                .filter_map(|command| match command {
+                    DrawCommand::CrosshairChanged(crosshair) => Some(crosshair),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            sent_crosshairs(&settings),
+            vec![Some(Crosshair {
+                left: 41.0,
+                top: 1.0,
+                width: 39,
+                height: 22,
+                column: 7,
+                row: 3,
+                thickness: 2.0,
+                color: Color4f::new(1.0, 0.0, 0.0, 1.0),
+            })]
+        );
+        // Nothing changed, so nothing is sent until it's turned off.
+        assert!(sent_crosshairs(&settings).is_empty());
+        assert_eq!(sent_crosshairs(&CrosshairSettings::default()), vec![None]);
+    }
+
+    #[test]
+    #[serial]
+    fn window_font_fallback_is_sent_in_order() {
+        let mut editor = build_test_editor();
+        float_window(&mut editor, 2, 1);
+        editor.draw_command_batcher.drain();
+
+        editor.handle_editor_command(EditorCommand::SetWindowFontFallback {
+            window: 1002,
+            fonts: vec![
+                "Noto Sans Mono CJK JP".to_owned(),
+                "Symbols Nerd Font".to_owned(),
+            ],
+        });
+
+        let commands = editor.draw_command_batcher.drain();
+        let font_fallbacks: Vec<_> = commands
+            .iter()
+            .filter_map(|command| match command {
+                DrawCommand::Window {
+                    grid_id: 2,
+                    command: WindowDrawCommand::FontFallback(fonts),
+                } => Some(fonts.as_slice()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            font_fallbacks,
+            vec![[
+                "Noto Sans Mono CJK JP".to_owned(),
+                "Symbols Nerd Font".to_owned()
+            ]
+            .as_slice()]
+        );
+        // The lines are drawn again with the new fonts.
+        assert!(commands.iter().any(|command| matches!(
+            command,
+            DrawCommand::Window {
+                grid_id: 2,
+                command: WindowDrawCommand::DrawLine(_),
+            }
+        )));
+    }
+
+    #[test]
+    #[serial]
+    fn window_diagnostics_are_sent_cut_to_the_end_of_their_rows() {
+        let mut editor = build_test_editor();
+        float_window(&mut editor, 2, 1);
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::GridLine {
+            grid: 2,
+            row: 1,
+            column_start: 0,
+            cells: vec![GridLineCell {
+                text: "a".to_owned(),
+                highlight_id: None,
+                repeat: Some(3),
+            }],
+            wrap: false,
+        }));
+        editor.draw_command_batcher.drain();
+
+        let set_diagnostics = |diagnostics: Vec<(u64, &str)>| EditorCommand::SetWindowDiagnostics {
+            window: 1002,
+            diagnostics: diagnostics
+                .into_iter()
+                .map(|(line, message)| Diagnostic {
+                    line,
+                    message: message.to_owned(),
+                })
+                .collect(),
+        };
+        let sent_diagnostics = |editor: &Editor| -> Vec<Vec<_>> {
+            editor
+                .draw_command_batcher
+                .drain()
+                .into_iter()
+                .filter_map(|command| match command {
+                    DrawCommand::Window {
+                        grid_id: 2,
+                        command: WindowDrawCommand::Diagnostics(diagnostics),
+                    } => Some(
+                        diagnostics
+                            .into_iter()
+                            .map(|fragment| {
+                                (fragment.text, fragment.window_left, fragment.window_top)
+                            })
+                            .collect(),
+                    ),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        // The float is 10 cells wide, with the text of the second row taking 3 of them.
+        editor.handle_editor_command(set_diagnostics(vec![(2, "unused variable"), (1, "E42")]));
+        assert_eq!(
+            sent_diagnostics(&editor),
+            vec![vec![("unus…".to_owned(), 5, 1), ("E42".to_owned(), 7, 0)]]
+        );
+
+        // Nothing is sent again when nothing moved.
+        editor.handle_editor_command(set_diagnostics(vec![(2, "unused variable"), (1, "E42")]));
+        assert!(sent_diagnostics(&editor).is_empty());
+
+        // Scrolling a line down moves the diagnostics up with their lines.
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::WindowViewport {
+                grid: 2,
+                top_line: 1.0,
+                bottom_line: 6.0,
+                current_line: 1.0,
+                current_column: 0.0,
+                line_count: Some(20.0),
+                scroll_delta: Some(1.0),
+            },
+        ));
+        assert_eq!(
+            sent_diagnostics(&editor),
+            vec![vec![("unused …".to_owned(), 2, 0)]]
+        );
+
+        editor.handle_editor_command(set_diagnostics(Vec::new()));
+        assert_eq!(sent_diagnostics(&editor), vec![Vec::new()]);
+    }
+
+    #[test]
+    #[serial]
+    fn window_blame_is_sent_right_aligned_after_the_diagnostics() {
+        let mut editor = build_test_editor();
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::WindowPosition {
+                grid: 2,
+                window: 1000,
+                start_row: 0,
+                start_column: 0,
+                width: 40,
+                height: 5,
+            },
+        ));
+        editor.draw_command_batcher.drain();
+        let placements = |fragments: Vec<LineFragment>| -> Vec<_> {
+            fragments
+                .into_iter()
+                .map(|fragment| (fragment.text, fragment.window_left, fragment.window_top))
+                .collect()
+        };
+
+        editor.handle_editor_command(EditorCommand::SetWindowDiagnostics {
+            window: 1000,
+            diagnostics: vec![Diagnostic {
+                line: 1,
+                message: "E42".to_owned(),
+            }],
+        });
+        editor.draw_command_batcher.drain();
+        editor.handle_editor_command(EditorCommand::SetWindowBlame {
+            window: 1000,
+            blame: vec![
+                BlameLine {
+                    line: 1,
+                    text: "alice 2d".to_owned(),
+                },
+                BlameLine {
+                    line: 4,
+                    text: "bob, 3 years ago, fix the parser for good".to_owned(),
+                },
+            ],
+        });
+
+        let mut blame = Vec::new();
+        let mut diagnostics = Vec::new();
+        for command in editor.draw_command_batcher.drain() {
+            match command {
+                DrawCommand::Window {
+                    grid_id: 2,
+                    command: WindowDrawCommand::Blame(fragments),
+                } => blame.push(placements(fragments)),
+                DrawCommand::Window {
+                    grid_id: 2,
+                    command: WindowDrawCommand::Diagnostics(fragments),
+                } => diagnostics.push(placements(fragments)),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            blame,
+            vec![vec![
+                ("alice 2d".to_owned(), 32, 0),
+                ("bob, 3 years ago, fix the parser for …".to_owned(), 2, 3),
+            ]]
+        );
+        // The diagnostic moved out of the way of the blame.
+        assert_eq!(diagnostics, vec![vec![("E42".to_owned(), 29, 0)]]);
+    }
+
+    #[test]
+    #[serial]
+    fn window_zoom_is_sent_with_the_position_and_keeps_the_grid_size() {
+        let mut editor = build_test_editor();
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::WindowPosition {
+                grid: 2,
+                window: 1000,
+                start_row: 0,
+                start_column: 0,
+                width: 10,
+                height: 5,
+            },
+        ));
+        editor.draw_command_batcher.drain();
+
+        editor.handle_editor_command(EditorCommand::SetWindowZoom {
+            window: 1000,
+            zoom: 2.0,
+        });
+
+        let positions: Vec<_> = editor
+            .draw_command_batcher
+            .drain()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::Window {
+                    grid_id: 2,
+                    command:
+                        WindowDrawCommand::Position {
+                            grid_size, zoom, ..
+                        },
+                } => Some((grid_size, zoom)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(positions, vec![((10, 5), 2.0)]);
+    }
+
+    #[test]
+    #[serial]
+    fn window_background_is_sent_again_when_the_window_is_redrawn() {
+        let mut editor = build_test_editor();
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::WindowPosition {
+                grid: 2,
+                window: 1000,
+                start_row: 0,
+                start_column: 0,
+                width: 10,
+                height: 5,
+            },
+        ));
+        editor.draw_command_batcher.drain();
+
+        let background = BackgroundKind::Gradient {
+            from: Color4f::new(0.0, 0.0, 0.0, 1.0),
+            to: Color4f::new(0.2, 0.2, 0.4, 1.0),
+            horizontal: false,
+        };
+        let sent_backgrounds = |editor: &Editor| -> Vec<_> {
+            editor
+                .draw_command_batcher
+                .drain()
+                .into_iter()
+                .filter_map(|command| match command {
+                    DrawCommand::Window {
+                        grid_id: 2,
+                        command: WindowDrawCommand::Background { kind },
+                    } => Some(kind),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        editor.handle_editor_command(EditorCommand::SetWindowBackground {
+            window: 1000,
+            background: Some(background.clone()),
+        });
+        assert_eq!(sent_backgrounds(&editor), vec![Some(background.clone())]);
+
+        editor.handle_editor_command(EditorCommand::GraphicsContextLost);
+        assert_eq!(sent_backgrounds(&editor), vec![Some(background)]);
+
+        editor.handle_editor_command(EditorCommand::SetWindowBackground {
+            window: 1000,
+            background: None,
+        });
+        assert_eq!(sent_backgrounds(&editor), vec![None]);
+        editor.handle_editor_command(EditorCommand::GraphicsContextLost);
+        assert!(sent_backgrounds(&editor).is_empty());
+    }
+
+    fn float_window(editor: &mut Editor, grid: u64, sort_order: u64) {
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::Resize {
+            grid,
+            width: 10,
+            height: 5,
+        }));
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::WindowFloatPosition {
+                grid,
+                window: 1000 + grid,
+                anchor: WindowAnchor::NorthWest,
+                anchor_grid: 1,
+                anchor_row: 0.0,
+                anchor_column: 0.0,
+                focusable: true,
+                sort_order: Some(sort_order),
+            },
+        ));
+    }
+
+    fn sort_order(editor: &Editor, grid: u64) -> u64 {
+        editor.windows[&grid]
+            .anchor_info
+            .as_ref()
+            .unwrap()
+            .sort_order
+    }
+
+    #[test]
+    #[serial]
+    fn only_floats_are_moved_by_dragging() {
+        let mut editor = build_test_editor();
+        float_window(&mut editor, 2, 1);
+        let move_float = |editor: &mut Editor, window, delta| {
+            editor.handle_editor_command(EditorCommand::MoveFloat { window, delta });
+        };
+
+        move_float(&mut editor, 1002, (3.0, 2.0));
+        assert_eq!(editor.windows[&2].get_grid_position(), (3.0, 2.0));
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::WindowPosition {
+                grid: 3,
+                window: 1003,
+                start_row: 0,
+                start_column: 0,
+                width: 10,
+                height: 5,
+            },
+        ));
+        move_float(&mut editor, 1003, (3.0, 2.0));
+        assert_eq!(editor.windows[&3].get_grid_position(), (0.0, 0.0));
+    }
+
+    #[test]
+    #[serial]
+    fn pinned_floats_stay_above_floats_opened_later() {
+        let mut editor = build_test_editor();
+        float_window(&mut editor, 2, 50);
+        float_window(&mut editor, 3, 60);
+        float_window(&mut editor, 4, 70);
+        let set_pinned = |editor: &mut Editor, grid: u64, pinned| {
+            editor.handle_editor_command(EditorCommand::SetWindowPinned {
+                window: 1000 + grid,
+                pinned,
+            });
+        };
+        set_pinned(&mut editor, 3, true);
+        set_pinned(&mut editor, 2, true);
+        assert_eq!(
+            editor.pin(1),
+            Err("Grid 1 is not a floating window".to_owned())
+        );
+
+        float_window(&mut editor, 5, 100);
+        // Neovim ordering a pinned float anew doesn't take it off the top either.
+        float_window(&mut editor, 3, 1);
+        let top_float = sort_order(&editor, 4).max(sort_order(&editor, 5));
+        assert!(sort_order(&editor, 3) > top_float);
+        assert!(sort_order(&editor, 2) > sort_order(&editor, 3));
+
+        set_pinned(&mut editor, 2, false);
+        set_pinned(&mut editor, 3, false);
+        float_window(&mut editor, 6, 500);
+        assert!(sort_order(&editor, 6) > sort_order(&editor, 2));
+    }
+
+    #[test]
+    #[serial]
+    fn moving_a_float_only_sends_its_new_position() {
+        let mut editor = build_test_editor();
+        float_window(&mut editor, 2, 1);
+        editor.draw_command_batcher.drain();
+
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::WindowFloatPosition {
+                grid: 2,
+                window: 1002,
+                anchor: WindowAnchor::NorthWest,
+                anchor_grid: 1,
+                anchor_row: 4.0,
+                anchor_column: 12.0,
+                focusable: true,
+                sort_order: Some(1),
+            },
+        ));
+
+        let commands: Vec<_> = editor
+            .draw_command_batcher
+            .drain()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::Window {
+                    grid_id: 2,
+                    command,
+                } => Some(command),
+                _ => None,
+            })
+            .collect();
+        assert!(matches!(
+            commands.as_slice(),
+            [WindowDrawCommand::Position {
+                grid_position: (12.0, 4.0),
+                grid_size: (10, 5),
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    #[serial]
+    fn bring_to_front_gives_the_float_the_highest_order() {
+        let mut editor = build_test_editor();
+        float_window(&mut editor, 2, 50);
+        float_window(&mut editor, 3, 60);
+        float_window(&mut editor, 4, 70);
+        editor.draw_command_batcher.drain();
+
+        assert_eq!(editor.bring_to_front(2), Ok(()));
+
+        assert!(sort_order(&editor, 2) > sort_order(&editor, 3));
+        assert!(sort_order(&editor, 2) > sort_order(&editor, 4));
+        assert!(editor
+            .draw_command_batcher
+            .drain()
+            .iter()
+            .any(|command| matches!(
+                command,
+                DrawCommand::Window {
+                    grid_id: 2,
+                    command: WindowDrawCommand::Reorder {
+                        floating_order: Some(_),
+                    },
+                }
+            )));
+    }
+
+    #[test]
+    #[serial]
+    fn peek_preview_opens_blended_at_the_cursor() {
+        let mut editor = build_test_editor();
+        float_window(&mut editor, 2, 50);
+        float_window(&mut editor, 3, 60);
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::CursorGoto {
+            grid: 1,
+            row: 3,
+            column: 7,
+        }));
+        editor.draw_command_batcher.drain();
+
+        editor.handle_editor_command(EditorCommand::SetPeekPreview(Some(1002)));
+        let commands = editor.draw_command_batcher.drain();
+        assert!(commands.iter().any(|command| matches!(
+            command,
+            DrawCommand::Window {
+                grid_id: 2,
+                command: WindowDrawCommand::Position { grid_position, .. },
+            } if *grid_position == (7.0, 4.0)
+        )));
+        assert!(commands.iter().any(|command| matches!(
+            command,
+            DrawCommand::Window {
+                grid_id: 2,
+                command: WindowDrawCommand::Blend(20),
+            }
+        )));
+        assert!(sort_order(&editor, 2) > sort_order(&editor, 3));
+
+        // Neovim moving the float doesn't take it away from the cursor.
+        float_window(&mut editor, 2, 50);
+        assert_eq!(editor.windows[&2].get_grid_position(), (7.0, 4.0));
+
+        editor.handle_editor_command(EditorCommand::SetPeekPreview(None));
+        let commands = editor.draw_command_batcher.drain();
+        assert!(commands.iter().any(|command| matches!(
+            command,
+            DrawCommand::Window {
+                grid_id: 2,
+                command: WindowDrawCommand::Blend(0),
+            }
+        )));
+        assert!(editor.windows[&2].is_hidden());
+    }
+
+    #[test]
+    #[serial]
+    fn unfocused_floats_collapse_to_their_header() {
+        let mut editor = build_test_editor();
+        float_window(&mut editor, 2, 50);
+        float_window(&mut editor, 3, 60);
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::GridLine {
+            grid: 2,
+            row: 4,
+            column_start: 0,
+            cells: vec![GridLineCell {
+                text: "a".to_owned(),
+                highlight_id: None,
+                repeat: None,
+            }],
+            wrap: false,
+        }));
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::Flush));
+        editor.draw_command_batcher.drain();
+        let settings = CollapsedFloatSettings {
+            enabled: true,
+            ..Default::default()
+        };
+        let sent_sizes = |editor: &Editor| -> Vec<_> {
+            editor
+                .draw_command_batcher
+                .drain()
+                .into_iter()
+                .filter_map(|command| match command {
+                    DrawCommand::Window {
+                        grid_id,
+                        command: WindowDrawCommand::Position { grid_size, .. },
+                    } => Some((grid_id, grid_size)),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        editor.focused_grid = Some(3);
+        editor.update_collapsed_floats(&settings);
+        assert_eq!(sent_sizes(&editor), vec![(2, (10, 1))]);
+        assert_eq!(editor.windows[&2].get_height(), 5);
+        assert_eq!(editor.windows[&2].get_cursor_grid_cell(0, 4).0, "a");
+
+        // Nothing changes until the focus moves to another float.
+        editor.update_collapsed_floats(&settings);
+        assert!(sent_sizes(&editor).is_empty());
+
+        editor.focused_grid = Some(2);
+        editor.update_collapsed_floats(&settings);
+        let mut sizes = sent_sizes(&editor);
+        sizes.sort();
+        assert_eq!(sizes, vec![(2, (10, 5)), (3, (10, 1))]);
+    }
+
+    #[test]
+    #[serial]
+    fn mode_change_starts_a_ripple_when_enabled() {
+        let mut editor = build_test_editor();
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::CursorGoto {
+            grid: 1,
+            row: 3,
+            column: 7,
+        }));
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::Flush));
+        let sent_ripples = |editor: &Editor| -> Vec<_> {
+            editor
+                .draw_command_batcher
+                .drain()
+                .into_iter()
+                .filter_map(|command| match command {
+                    DrawCommand::ModeRipple(ripple) => Some(ripple),
+                    _ => None,
+                })
+                .collect()
+        };
+        let settings = ModeRippleSettings {
+            enabled: true,
+            cursor_centered: true,
+            ..Default::default()
+        };
+
+        // Neovim sends the starting mode without anything changing.
+        editor.ripple_mode_change(0, &settings);
+        assert!(sent_ripples(&editor).is_empty());
+
+        editor.ripple_mode_change(1, &settings);
+        let ripples = sent_ripples(&editor);
+        assert_eq!(ripples.len(), 1);
+        assert_eq!(ripples[0].center, Some((7.5, 3.5)));
+        assert!((ripples[0].color.a - 0.15).abs() < f32::EPSILON);
+
+        editor.ripple_mode_change(1, &settings);
+        assert!(sent_ripples(&editor).is_empty());
+        editor.ripple_mode_change(
+            0,
+            &ModeRippleSettings {
+                enabled: false,
+                ..Default::default()
+            },
+        );
+        assert!(sent_ripples(&editor).is_empty());
+    }
+
+    fn sent_dim_overlays(editor: &Editor) -> Vec<Option<u64>> {
+        editor
+            .draw_command_batcher
+            .drain()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::DimOverlayChanged(floating_order) => Some(floating_order),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    #[serial]
+    fn dim_overlay_is_placed_behind_the_active_float() {
+        let mut editor = build_test_editor();
+        float_window(&mut editor, 2, 50);
+        float_window(&mut editor, 3, 60);
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::CursorGoto {
+            grid: 3,
+            row: 0,
+            column: 0,
+        }));
+        editor.draw_command_batcher.drain();
+
+        editor.handle_editor_command(EditorCommand::SetDimOverlay(true));
+        assert_eq!(sent_dim_overlays(&editor), vec![Some(60)]);
+
+        editor.handle_editor_command(EditorCommand::SetDimOverlay(false));
+        assert_eq!(sent_dim_overlays(&editor), vec![None]);
+    }
+
+    #[test]
+    #[serial]
+    fn dim_overlay_goes_below_every_float_outside_of_one() {
+        let mut editor = build_test_editor();
+        float_window(&mut editor, 2, 50);
+        editor.draw_command_batcher.drain();
+
+        editor.set_dim_overlay(true);
+        assert_eq!(sent_dim_overlays(&editor), vec![Some(0)]);
+    }
+
+    #[test]
+    #[serial]
+    fn spotlight_cuts_the_area_of_its_window_out_of_the_overlay() {
+        let mut editor = build_test_editor();
+        let window_position = |start_column, width| {
+            EditorCommand::NeovimRedrawEvent(RedrawEvent::WindowPosition {
+                grid: 2,
+                window: 1002,
+                start_row: 2,
+                start_column,
+                width,
+                height: 20,
+            })
+        };
+        editor.handle_editor_command(window_position(40, 40));
+        editor.draw_command_batcher.drain();
+
+        let sent_spotlights = |editor: &mut Editor, command| {
+            editor.handle_editor_command(command);
+            editor
+                .draw_command_batcher
+                .drain()
+                .into_iter()
+                .filter_map(|command| match command {
+                    DrawCommand::SpotlightChanged(spotlight) => Some(spotlight.map(|spotlight| {
+                        (
+                            spotlight.left,
+                            spotlight.top,
+                            spotlight.width,
+                            spotlight.height,
+                        )
+                    })),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // The columns past the window are left out.
+        let area = SpotlightArea {
+            window: 1002,
+            top: 5,
+            left: 0,
+            bottom: 10,
+            right: 100,
+        };
+        assert_eq!(
+            sent_spotlights(&mut editor, EditorCommand::SetSpotlight(Some(area))),
+            vec![Some((40.0, 7.0, 40, 5))]
+        );
+        // The cutout follows the window when it moves or shrinks.
+        assert_eq!(
+            sent_spotlights(&mut editor, window_position(10, 30)),
+            vec![Some((10.0, 7.0, 30, 5))]
+        );
+        assert_eq!(
+            sent_spotlights(
+                &mut editor,
+                EditorCommand::NeovimRedrawEvent(RedrawEvent::WindowPosition {
+                    grid: 3,
+                    window: 1003,
+                    start_row: 0,
+                    start_column: 0,
+                    width: 10,
+                    height: 2,
+                })
+            ),
+            vec![]
+        );
+        assert_eq!(
+            sent_spotlights(&mut editor, EditorCommand::SetSpotlight(None)),
+            vec![None]
+        );
+        assert_eq!(
+            sent_spotlights(&mut editor, window_position(20, 30)),
+            vec![]
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn windows_in_rect_returns_the_intersecting_windows_bottom_up() {
+        let mut editor = build_test_editor();
+        // Both floats are 10x5 cells at the top left of the screen, the second one is moved right.
+        float_window(&mut editor, 3, 2);
+        float_window(&mut editor, 2, 1);
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::WindowFloatPosition {
+                grid: 3,
+                window: 1003,
+                anchor: WindowAnchor::NorthWest,
+                anchor_grid: 1,
+                anchor_row: 0.0,
+                anchor_column: 40.0,
+                focusable: true,
+                sort_order: Some(2),
+            },
+        ));
+        let font_dimensions = Dimensions {
+            width: 10,
+            height: 20,
+        };
+
+        // Only the first float and the screen grid cover the top left cell.
+        let top_left = Rect::from_xywh(0.0, 0.0, 10.0, 20.0);
+        assert_eq!(
+            editor.windows_in_rect(top_left, font_dimensions),
+            vec![1, 2]
+        );
+        // A band across the top of the screen covers both floats, drawn in their sort order.
+        let top_band = Rect::from_xywh(0.0, 0.0, 800.0, 20.0);
+        assert_eq!(
+            editor.windows_in_rect(top_band, font_dimensions),
+            vec![1, 2, 3]
+        );
+        // Below the floats only the screen grid is left.
+        let bottom = Rect::from_xywh(0.0, 200.0, 800.0, 20.0);
+        assert_eq!(editor.windows_in_rect(bottom, font_dimensions), vec![1]);
+        // Past the screen nothing is drawn.
+        let outside = Rect::from_xywh(800.0, 0.0, 10.0, 10.0);
+        assert!(editor.windows_in_rect(outside, font_dimensions).is_empty());
+    }
+
+    #[test]
+    #[serial]
+    fn windows_in_rect_leaves_out_hidden_floats() {
+        let mut editor = build_test_editor();
+        float_window(&mut editor, 2, 1);
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::WindowHide {
+            grid: 2,
+        }));
+        let font_dimensions = Dimensions {
+            width: 10,
+            height: 20,
+        };
+
+        let top_left = Rect::from_xywh(0.0, 0.0, 10.0, 20.0);
+        assert_eq!(editor.windows_in_rect(top_left, font_dimensions), vec![1]);
+    }
+
+    #[test]
+    #[serial]
+    fn scroll_overview_combines_the_viewports_of_the_splits() {
+        let mut editor = build_test_editor();
+        for (grid, left) in [(3, 40), (2, 0)] {
+            editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+                RedrawEvent::WindowPosition {
+                    grid,
+                    window: 1000 + grid,
+                    start_row: 0,
+                    start_column: left,
+                    width: 40,
+                    height: 23,
+                },
+            ));
+        }
+        let viewport = |grid, top_line, bottom_line, line_count| {
+            EditorCommand::NeovimRedrawEvent(RedrawEvent::WindowViewport {
+                grid,
+                top_line,
+                bottom_line,
+                current_line: top_line,
+                current_column: 0.0,
+                line_count: Some(line_count),
+                scroll_delta: None,
+            })
+        };
+        editor.handle_editor_command(viewport(2, 0.0, 23.0, 92.0));
+        editor.handle_editor_command(viewport(3, 150.0, 173.0, 200.0));
+        // A float doesn't take part, as it covers the splits underneath.
+        float_window(&mut editor, 4, 1);
+        editor.handle_editor_command(viewport(4, 0.0, 5.0, 10.0));
+
+        let overview = editor
+            .scroll_overview(&ScrollOverviewSettings::default())
+            .unwrap();
+        assert_eq!(
+            overview.segments,
+            vec![
+                ScrollSegment {
+                    left: 0.0,
+                    width: 40,
+                    thumb_start: 0.0,
+                    thumb_end: 0.25,
+                },
+                ScrollSegment {
+                    left: 40.0,
+                    width: 40,
+                    thumb_start: 0.75,
+                    thumb_end: 0.865,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn window_separators_follow_the_edges_the_splits_share() {
+        let mut editor = build_test_editor();
+        for (grid, left, width) in [(2, 0, 40), (3, 41, 39)] {
+            editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+                RedrawEvent::WindowPosition {
+                    grid,
+                    window: 1000 + grid,
+                    start_row: 0,
+                    start_column: left,
+                    width,
+                    height: 23,
+                },
+            ));
+        }
+        // A float doesn't take part, as it isn't part of the layout.
+        float_window(&mut editor, 4, 1);
+        editor.draw_command_batcher.drain();
+
+        let settings = WindowSeparatorSettings {
+            enabled: true,
+            thickness: 2.0,
+            color: "#ff0000".to_string(),
+            style: SeparatorStyle::Double,
+        };
+        editor.update_window_separators(&settings);
+        let sent: Vec<_> = editor
+            .draw_command_batcher
+            .drain()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::WindowSeparatorsChanged(separators) => Some(separators),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            sent,
+            vec![Some(WindowSeparators {
+                separators: vec![WindowSeparator {
+                    left: 40.5,
+                    top: 0.0,
+                    length: 23.0,
+                    vertical: true,
+                }],
+                color: Color4f::new(1.0, 0.0, 0.0, 1.0),
+                thickness: 2.0,
+                style: SeparatorStyle::Double,
+            })]
+        );
+
+        // Nothing is sent again until the layout changes.
+        editor.update_window_separators(&settings);
+        assert!(editor.draw_command_batcher.drain().is_empty());
+    }
+
+    #[test]
+    #[serial]
+    fn content_bounds_cover_every_shown_window() {
+        let mut editor = build_test_editor();
+        let font_dimensions = Dimensions {
+            width: 10,
+            height: 20,
+        };
+        // The screen grid alone is 80x24 cells.
+        assert_eq!(
+            editor.content_bounds(font_dimensions),
+            Some(Rect::from_xywh(0.0, 0.0, 800.0, 480.0))
+        );
+
+        // A 10x5 float hanging off the bottom right corner of the screen widens the bounds.
+        float_window(&mut editor, 2, 1);
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::WindowFloatPosition {
+                grid: 2,
+                window: 1002,
+                anchor: WindowAnchor::NorthWest,
+                anchor_grid: 1,
+                anchor_row: 22.0,
+                anchor_column: 75.0,
+                focusable: true,
+                sort_order: Some(1),
+            },
+        ));
+        assert_eq!(
+            editor.content_bounds(font_dimensions),
+            Some(Rect::from_xywh(0.0, 0.0, 850.0, 540.0))
+        );
+
+        // Hidden windows aren't drawn, so they don't count.
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::WindowHide {
+            grid: 2,
+        }));
+        assert_eq!(
+            editor.content_bounds(font_dimensions),
+            Some(Rect::from_xywh(0.0, 0.0, 800.0, 480.0))
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn focus_ring_moves_with_the_cursor_to_the_new_window() {
+        let mut editor = build_test_editor();
+        float_window(&mut editor, 2, 1);
+        float_window(&mut editor, 3, 2);
+        let sent_focus = |editor: &mut Editor, grid| {
+            editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+                RedrawEvent::CursorGoto {
+                    grid,
+                    row: 0,
+                    column: 0,
+                },
+            ));
+            editor.send_cursor_info();
+            editor
+                .draw_command_batcher
+                .drain()
+                .into_iter()
+                .filter_map(|command| match command {
+                    DrawCommand::FocusChanged(grid) => Some(grid),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(sent_focus(&mut editor, 2), vec![Some(2)]);
+        // Staying in the window doesn't resend the ring.
+        assert!(sent_focus(&mut editor, 2).is_empty());
+        assert_eq!(sent_focus(&mut editor, 3), vec![Some(3)]);
+        // The screen grid isn't a window of its own, so the ring stays where it was.
+        assert!(sent_focus(&mut editor, 1).is_empty());
+        assert_eq!(editor.focused_grid, Some(3));
+    }
+
+    fn sent_pending_keys(editor: &Editor) -> Vec<Option<PendingKeys>> {
+        editor
+            .draw_command_batcher
+            .drain()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::PendingKeysChanged(pending_keys) => Some(pending_keys),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    #[serial]
+    fn pending_keys_show_the_indicator_and_clearing_removes_it() {
+        let mut editor = build_test_editor();
+        SETTINGS.set(&PendingKeysSettings {
+            enabled: true,
+            position: PendingKeysPosition::BottomRight,
+        });
+
+        editor.handle_editor_command(EditorCommand::SetPendingKeys("<Space>f".to_owned()));
+        // The 10 cell wide indicator sits one cell from the right edge, above the statusline.
+        assert_eq!(
+            sent_pending_keys(&editor),
+            vec![Some(PendingKeys {
+                keys: "<Space>f".to_owned(),
+                grid_position: (69.0, 21.0),
+            })]
+        );
+
+        editor.handle_editor_command(EditorCommand::SetPendingKeys("".to_owned()));
+        assert_eq!(sent_pending_keys(&editor), vec![None]);
+    }
+
+    #[test]
+    #[serial]
+    fn recording_a_macro_shows_its_register_until_it_stops() {
+        let mut editor = build_test_editor();
+        let settings = RecordingIndicatorSettings {
+            enabled: true,
+            color: "#ff0000".to_owned(),
+        };
+        let sent_indicators = |editor: &Editor| -> Vec<_> {
+            editor
+                .draw_command_batcher
+                .drain()
+                .into_iter()
+                .filter_map(|command| match command {
+                    DrawCommand::RecordingIndicatorChanged(indicator) => Some(indicator),
+                    _ => None,
+                })
+                .collect()
+        };
+        editor.draw_command_batcher.drain();
+
+        editor.set_recording("q".to_owned(), &settings);
+        // The 5 cell wide indicator sits one cell from the top right corner.
+        assert_eq!(
+            sent_indicators(&editor),
+            vec![Some(RecordingIndicator {
+                register: "q".to_owned(),
+                color: Color4f::new(1.0, 0.0, 0.0, 1.0),
+                grid_position: (74.0, 1.0),
+            })]
+        );
+
+        editor.set_recording("".to_owned(), &settings);
+        assert_eq!(sent_indicators(&editor), vec![None]);
+    }
+
+    #[test]
+    #[serial]
+    fn bring_to_front_rejects_non_floating_grids() {
+        let mut editor = build_test_editor();
+
+        assert!(editor.bring_to_front(1).is_err());
+        assert!(editor.bring_to_front(42).is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn cursor_shadow_settings_reach_the_cursor_command() {
+        let mut editor = build_test_editor();
+        SETTINGS.set(&CursorShadowSettings {
+            radius: 6.0,
+            color: "#ff000080".to_owned(),
+        });
+
+        editor.send_cursor_info();
+
+        let shadows: Vec<_> = editor
+            .draw_command_batcher
+            .drain()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::UpdateCursor(cursor) => Some(cursor.shadow),
+                _ => None,
+            })
+            .collect();
+        let shadow = shadows[0]
+            .as_ref()
+            .expect("Cursor shadow should be enabled");
+        assert_eq!(shadow.radius, 6.0);
+        assert_eq!(shadow.color.r, 1.0);
+        assert!((shadow.color.a - 128.0 / 255.0).abs() < 0.01);
+    }
+
+    fn sent_cursors(editor: &Editor) -> Vec<Cursor> {
+        editor
+            .draw_command_batcher
+            .drain()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::UpdateCursor(cursor) => Some(cursor),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    #[serial]
+    fn cursor_inside_a_ligature_covers_only_its_own_cell() {
+        let mut editor = build_test_editor();
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::GridLine {
+            grid: 1,
+            row: 0,
+            column_start: 0,
+            cells: ["a", " ", "=", ">", " ", "b"]
+                .into_iter()
+                .map(|text| GridLineCell {
+                    text: text.to_owned(),
+                    highlight_id: None,
+                    repeat: None,
+                })
+                .collect(),
+            wrap: false,
+        }));
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::CursorGoto {
+            grid: 1,
+            column: 3,
+            row: 0,
+        }));
+        editor.draw_command_batcher.drain();
+        editor.send_cursor_info();
+
+        // The renderer may draw `=>` as one glyph, but the cursor is the second cell of it.
+        let cursor = sent_cursors(&editor).pop().unwrap();
+        assert_eq!(cursor.grid_position, (3, 0));
+        assert_eq!(cursor.grid_cell.0, ">");
+        assert!(!cursor.double_width);
+        assert_eq!(cursor.tab_width, None);
+    }
+
+    #[test]
+    #[serial]
+    fn cursor_out_of_view_is_hidden_or_clamped() {
+        let mut editor = build_test_editor();
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::CursorGoto {
+            grid: 1,
+            row: 5,
+            column: 90,
+        }));
+
+        // By default it's drawn where neovim put it.
+        editor.send_cursor_info();
+        let cursors = sent_cursors(&editor);
+        assert!(cursors[0].enabled);
+        assert_eq!(cursors[0].grid_position, (90, 5));
+
+        SETTINGS.set(&CursorViewSettings {
+            out_of_view: CursorOutOfView::Hide,
+            ..CursorViewSettings::default()
+        });
+        editor.send_cursor_info();
+        let cursors = sent_cursors(&editor);
+        assert!(!cursors[0].enabled);
+        // The editor keeps the real position.
+        assert_eq!(editor.cursor.grid_position, (90, 5));
+
+        SETTINGS.set(&CursorViewSettings {
+            out_of_view: CursorOutOfView::Clamp,
+            ..CursorViewSettings::default()
+        });
+        editor.send_cursor_info();
+        let cursors = sent_cursors(&editor);
+        assert!(cursors[0].enabled);
+        assert_eq!(cursors[0].grid_position, (79, 5));
+
+        // The cursor line is scrolled below the viewport.
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::WindowViewport {
+                grid: 1,
+                top_line: 10.0,
+                bottom_line: 34.0,
+                current_line: 40.0,
+                current_column: 0.0,
+                line_count: None,
+                scroll_delta: None,
+            },
+        ));
+        editor.send_cursor_info();
+        assert_eq!(sent_cursors(&editor)[0].grid_position, (79, 23));
+    }
+
+    #[test]
+    #[serial]
+    fn dominant_background_is_the_most_common_cell_background() {
+        let mut editor = build_test_editor();
+        assert_eq!(editor.dominant_background(), None);
+
+        let black = Color4f::new(0.0, 0.0, 0.0, 1.0);
+        let red = Color4f::new(1.0, 0.0, 0.0, 1.0);
+        editor.default_colors = Some(Colors::new(None, Some(black), None));
+        assert_eq!(editor.dominant_background(), Some(black));
+
+        editor
+            .defined_styles
+            .insert(1, Arc::new(Style::new(Colors::new(None, Some(red), None))));
+        // 13 of the 24 rows get the red background.
+        for row in 0..13 {
+            editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::GridLine {
+                grid: 1,
+                row,
+                column_start: 0,
+                cells: vec![GridLineCell {
+                    text: " ".to_owned(),
+                    highlight_id: Some(1),
+                    repeat: Some(80),
+                }],
+                wrap: false,
+            }));
+        }
+        assert_eq!(editor.dominant_background(), Some(red));
+    }
+
+    #[test]
+    #[serial]
+    fn cursor_fragment_spans_the_style_run_under_the_cursor() {
+        let mut editor = build_test_editor();
+        for id in 1..=2 {
+            let mut style = Style::new(Colors::new(None, None, None));
+            style.bold = id == 2;
+            editor.defined_styles.insert(id, Arc::new(style));
+        }
+        let cells = [("ab", 1), ("cde", 2), ("f", 1)]
+            .into_iter()
+            .flat_map(|(text, highlight_id)| {
+                text.chars().map(move |character| GridLineCell {
+                    text: character.to_string(),
+                    highlight_id: Some(highlight_id),
+                    repeat: None,
+                })
+            })
+            .collect();
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::GridLine {
+            grid: 1,
+            row: 4,
+            column_start: 0,
+            cells,
+            wrap: false,
+        }));
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::CursorGoto {
+            grid: 1,
+            row: 4,
+            column: 3,
+        }));
+
+        let fragment = editor
+            .cursor_fragment()
+            .expect("The cursor is inside the grid");
+        assert_eq!(fragment.text, "cde");
+        assert_eq!((fragment.window_left, fragment.window_top), (2, 4));
+        assert_eq!(fragment.width, 3);
+        assert_eq!(fragment.style, editor.defined_styles.get(&2).cloned());
+    }
+
+    fn redrawn_grids(editor: &Editor) -> Vec<u64> {
+        let mut grids = editor
+            .draw_command_batcher
+            .drain()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::Window {
+                    grid_id,
+                    command: WindowDrawCommand::Clear | WindowDrawCommand::DrawLine(_),
+                } => Some(grid_id),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        grids.dedup();
+        grids
+    }
+
+    #[test]
+    #[serial]
+    fn centered_command_line_is_a_float_in_the_middle_of_the_screen() {
+        let mut editor = build_test_editor();
+        SETTINGS.set(&CommandLineSettings {
+            centered: true,
+            width: 40,
+            border: true,
+        });
+
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::CommandLineShow {
+                content: vec![(0, "echo".to_owned())],
+                position: 4,
+                first_character: ":".to_owned(),
+                prompt: "".to_owned(),
+                indent: 0,
+                level: 1,
+            },
+        ));
+
+        let positions: Vec<_> = editor
+            .draw_command_batcher
+            .drain()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::Window {
+                    grid_id: COMMAND_LINE_GRID,
+                    command:
+                        WindowDrawCommand::Position {
+                            grid_position,
+                            grid_size,
+                            floating_order,
+                            ..
+                        },
+                } => Some((grid_position, grid_size, floating_order)),
+                _ => None,
+            })
+            .collect();
+        // The 40x3 box is centered on the 80x24 screen.
+        assert_eq!(positions, vec![((20.0, 10.0), (40, 3), Some(u64::MAX))]);
+
+        let export = editor.windows[&COMMAND_LINE_GRID].export_grid();
+        assert!(export.lines().nth(1).unwrap().starts_with("│:echo "));
+        assert_eq!(
+            editor.command_line_cursor(),
+            Some((COMMAND_LINE_GRID, (6, 1)))
+        );
+
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::CommandLineHide,
+        ));
+        assert_eq!(editor.command_line_cursor(), None);
+    }
+
+    #[test]
+    #[serial]
+    fn command_line_popup_menu_without_a_box_opens_above_the_last_row() {
+        let mut editor = build_test_editor();
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::PopupMenuShow {
+                items: popup_menu_items(3),
+                selected: None,
+                row: 0,
+                column: 5,
+                grid: -1,
+            },
+        ));
+
+        let popup_menus = sent_popup_menus(&editor);
+        assert_eq!(popup_menus[0].as_ref().unwrap().grid_position, (5.0, 20.0));
+    }
+
+    #[test]
+    #[serial]
+    fn command_line_popup_menu_follows_the_scrolled_text() {
+        let mut editor = build_test_editor();
+        SETTINGS.set(&CommandLineSettings {
+            centered: true,
+            width: 20,
+            border: true,
+        });
+
+        // 31 cells with the cursor after them in 18 inner columns, so 14 are scrolled out.
+        let text = "e ".to_owned() + &"a".repeat(28);
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::CommandLineShow {
+                content: vec![(0, text.clone())],
+                position: text.len() as u64,
+                first_character: ":".to_owned(),
+                prompt: "".to_owned(),
+                indent: 0,
+                level: 1,
+            },
+        ));
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::PopupMenuShow {
+                items: popup_menu_items(3),
+                selected: None,
+                row: 0,
+                column: 25,
+                grid: -1,
+            },
+        ));
+
+        // The box is at column 30 and its text starts past the border.
+        let popup_menus = sent_popup_menus(&editor);
+        assert_eq!(popup_menus[0].as_ref().unwrap().grid_position.0, 42.0);
+    }
+
+    #[test]
+    #[serial]
+    fn message_boxes_follow_the_screen_size() {
+        let mut editor = build_test_editor();
+        SETTINGS.set(&MessageLogSettings {
+            enabled: true,
+            ..MessageLogSettings::default()
+        });
+
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::MessageShow {
+            kind: MessageKind::Echo,
+            content: vec![(0, "one\ntwo".to_owned())],
+            replace_last: false,
+        }));
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::MessageShowMode {
+                content: vec![(0, "-- INSERT --".to_owned())],
+            },
+        ));
+        editor.draw_command_batcher.drain();
+
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::Resize {
+            grid: 1,
+            width: 100,
+            height: 30,
+        }));
+        let mut positions: Vec<_> = editor
+            .draw_command_batcher
+            .drain()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::Window {
+                    grid_id,
+                    command:
+                        WindowDrawCommand::Position {
+                            grid_position,
+                            grid_size,
+                            ..
+                        },
+                } if grid_id != 1 => Some((grid_id, grid_position, grid_size)),
+                _ => None,
+            })
+            .collect();
+        positions.sort_by_key(|(grid_id, ..)| *grid_id);
+        // The log sits right above the mode in the new last row.
+        assert_eq!(
+            positions,
+            vec![
+                (MESSAGE_STATUS_GRID, (0.0, 29.0), (100, 1)),
+                (MESSAGE_LOG_GRID, (0.0, 27.0), (100, 2)),
+            ]
+        );
+        let export = editor.windows[&MESSAGE_STATUS_GRID].export_grid();
+        assert!(export.starts_with("-- INSERT --"));
+    }
+
+    #[test]
+    #[serial]
+    fn graphics_context_loss_repaints_every_window() {
+        let mut editor = build_test_editor();
+        float_window(&mut editor, 2, 1);
+        float_window(&mut editor, 3, 2);
+        editor.draw_command_batcher.drain();
+
+        editor.handle_editor_command(EditorCommand::GraphicsContextLost);
+
+        let mut grids = redrawn_grids(&editor);
+        grids.sort_unstable();
+        assert_eq!(grids, vec![1, 2, 3]);
+    }
+
+    #[test]
+    #[serial]
+    fn redefining_a_used_highlight_redraws_the_windows_using_it() {
+        let mut editor = build_test_editor();
+        let define = |id, bold| {
+            let mut style = Style::new(Colors::new(None, None, None));
+            style.bold = bold;
+            EditorCommand::NeovimRedrawEvent(RedrawEvent::HighlightAttributesDefine { id, style })
+        };
+        editor.handle_editor_command(define(1, false));
+        editor.handle_editor_command(define(2, false));
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::GridLine {
+            grid: 1,
+            row: 0,
+            column_start: 0,
+            cells: vec![GridLineCell {
+                text: "a".to_owned(),
+                highlight_id: Some(1),
+                repeat: None,
+            }],
+            wrap: false,
+        }));
+        editor.draw_command_batcher.drain();
+
+        // Neither an unused id nor an unchanged definition needs a redraw.
+        editor.handle_editor_command(define(2, true));
+        editor.handle_editor_command(define(1, false));
+        assert!(redrawn_grids(&editor).is_empty());
+
+        editor.handle_editor_command(define(1, true));
+        assert_eq!(redrawn_grids(&editor), vec![1]);
+        let (_, style, _, _) = editor.windows[&1].get_cursor_grid_cell(0, 0);
+        assert!(style.unwrap().bold);
+    }
+
+    #[test]
+    #[serial]
+    fn theme_change_repaints_every_window_once_on_flush() {
+        let mut editor = build_test_editor();
+        float_window(&mut editor, 2, 1);
+        let define = |id, bold| {
+            let mut style = Style::new(Colors::new(None, None, None));
+            style.bold = bold;
+            EditorCommand::NeovimRedrawEvent(RedrawEvent::HighlightAttributesDefine { id, style })
+        };
+        editor.handle_editor_command(define(1, false));
+        editor.handle_editor_command(define(2, false));
+        for (grid, id) in [(1, 1), (2, 2)] {
+            editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::GridLine {
+                grid,
+                row: 0,
+                column_start: 0,
+                cells: vec![GridLineCell {
+                    text: "a".to_owned(),
+                    highlight_id: Some(id),
+                    repeat: None,
+                }],
+                wrap: false,
+            }));
+        }
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::Flush));
+        editor.draw_command_batcher.drain();
+
+        editor.handle_editor_command(EditorCommand::ThemeChanged);
+        editor.handle_editor_command(define(1, true));
+        editor.handle_editor_command(define(2, true));
+        assert!(redrawn_grids(&editor).is_empty());
+
+        editor.draw_command_batcher.take_sent_batches();
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(RedrawEvent::Flush));
+        let clears: Vec<_> = editor
+            .draw_command_batcher
+            .take_sent_batches()
+            .into_iter()
+            .flatten()
+            .filter_map(|command| match command {
+                DrawCommand::Window {
+                    grid_id,
+                    command: WindowDrawCommand::Clear,
+                } => Some(grid_id),
+                _ => None,
+            })
+            .collect();
+        let mut grids = clears.clone();
+        grids.sort_unstable();
+        grids.dedup();
+        assert_eq!(grids.len(), clears.len());
+        assert!(grids.contains(&1) && grids.contains(&2));
+        assert!(
+            editor.windows[&2]
+                .get_cursor_grid_cell(0, 0)
+                .1
+                .unwrap()
+                .bold
+        );
+
+        // Styles defined after the theme change are drawn as they come in again.
+        editor.handle_editor_command(define(1, false));
+        assert_eq!(redrawn_grids(&editor), vec![1]);
+    }
+
+    #[test]
+    #[serial]
+    fn line_numbers_are_sent_to_the_window_grid() {
+        let mut editor = build_test_editor();
+        editor.handle_editor_command(EditorCommand::NeovimRedrawEvent(
+            RedrawEvent::WindowPosition {
+                grid: 2,
+                window: 1001,
+                start_row: 0,
+                start_column: 0,
+                width: 20,
+                height: 5,
+            },
+        ));
+        editor.draw_command_batcher.drain();
+
+        editor.handle_editor_command(EditorCommand::SetLineNumbers {
+            window: 1001,
+            line_numbers: LineNumbers {
+                start: 0,
+                width: 3,
+                current_line: 1,
+                rows: vec![(0, 1, 1), (2, 2, 2)],
+            },
+        });
+
+        let fragments: Vec<_> = editor
+            .draw_command_batcher
+            .drain()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::Window {
+                    grid_id: 2,
+                    command: WindowDrawCommand::LineNumbers(fragments),
+                } => Some(fragments),
+                _ => None,
+            })
+            .flatten()
+            .map(|fragment| (fragment.text, fragment.window_left, fragment.window_top))
+            .collect();
+        assert_eq!(
+            fragments,
+            vec![(" 1 ".to_owned(), 0, 0), (" 2 ".to_owned(), 0, 2)]
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn clearing_the_end_of_a_line_only_clears_that_region() {
+        let mut editor = build_test_editor();
+        let grid_line = |row, column_start, cells: Vec<(&str, u64)>| {
+            EditorCommand::NeovimRedrawEvent(RedrawEvent::GridLine {
+                grid: 1,
+                row,
+                column_start,
+                cells: cells
+                    .into_iter()
+                    .map(|(text, repeat)| GridLineCell {
+                        text: text.to_owned(),
+                        highlight_id: Some(0),
+                        repeat: Some(repeat),
+                    })
+                    .collect(),
+                wrap: false,
+            })
+        };
+        editor.handle_editor_command(grid_line(0, 0, vec![("a", 3), ("b", 3)]));
+        editor.handle_editor_command(grid_line(1, 0, vec![("c", 2)]));
+        editor.draw_command_batcher.drain();
+
+        editor.handle_editor_command(grid_line(0, 4, vec![(" ", 76)]));
+        let commands: Vec<_> = editor
+            .draw_command_batcher
+            .drain()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::Window { command, .. } => Some(command),
+                _ => None,
+            })
+            .collect();
+        assert!(matches!(
+            commands.as_slice(),
+            [WindowDrawCommand::ClearRegion {
+                top: 0,
+                bottom: 1,
+                left: 4,
+                right: 80,
+            }]
+        ));
+
+        let window = &editor.windows[&1];
+        assert_eq!(window.get_cursor_grid_cell(3, 0).0, "b");
+        assert_eq!(window.get_cursor_grid_cell(4, 0).0, " ");
+        assert_eq!(window.get_cursor_grid_cell(5, 0).0, " ");
+        assert_eq!(window.get_cursor_grid_cell(1, 1).0, "c");
+    }
+}
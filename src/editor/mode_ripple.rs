@@ -0,0 +1,48 @@
+use skia_safe::Color4f;
+
+use crate::{editor::parse_color, settings::*};
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "mode_ripple"]
+pub struct ModeRippleSettings {
+    pub enabled: bool,
+    pub color: String,
+    // How opaque the ripple starts out, from 0 to 1, before it fades away.
+    pub intensity: f32,
+    // Spreads a ring from the cursor instead of flashing the whole screen.
+    pub cursor_centered: bool,
+}
+
+impl Default for ModeRippleSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            color: "#ffffff".to_string(),
+            intensity: 0.15,
+            cursor_centered: false,
+        }
+    }
+}
+
+/// A short flash drawn over everything when the mode changes, around the center of the cursor
+/// cell in cells of the screen when it has one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModeRipple {
+    pub center: Option<(f64, f64)>,
+    pub color: Color4f,
+}
+
+/// Nothing is drawn for an invalid color. Without a cursor on the screen the ripple flashes the
+/// whole screen even when it should start at the cursor.
+pub fn build_mode_ripple(
+    cursor_center: Option<(f64, f64)>,
+    settings: &ModeRippleSettings,
+) -> Option<ModeRipple> {
+    let mut color = parse_color(&settings.color)?;
+    color.a *= settings.intensity.clamp(0.0, 1.0);
+
+    Some(ModeRipple {
+        center: cursor_center.filter(|_| settings.cursor_centered),
+        color,
+    })
+}
@@ -0,0 +1,36 @@
+use crate::{bridge::WindowAnchor, settings::*};
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "peek_preview"]
+pub struct PeekPreviewSettings {
+    // How much of the editor shows through the preview, from 0 to 100 like 'winblend'.
+    pub blend: u64,
+}
+
+impl Default for PeekPreviewSettings {
+    fn default() -> Self {
+        Self { blend: 20 }
+    }
+}
+
+/// Anchors a preview of the given height to the cursor cell, on the row below the cursor when it
+/// fits in the window there and on the row above it otherwise.
+pub fn peek_preview_anchor(
+    (cursor_column, cursor_row): (u64, u64),
+    window_height: u64,
+    preview_height: u64,
+) -> (WindowAnchor, f64, f64) {
+    if cursor_row + 1 + preview_height <= window_height {
+        (
+            WindowAnchor::NorthWest,
+            cursor_column as f64,
+            (cursor_row + 1) as f64,
+        )
+    } else {
+        (
+            WindowAnchor::SouthWest,
+            cursor_column as f64,
+            cursor_row as f64,
+        )
+    }
+}
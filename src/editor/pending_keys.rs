@@ -0,0 +1,111 @@
+use log::error;
+use nvim_rs::Value;
+
+use crate::{editor::cell_width, settings::*};
+
+/// The corner of the screen the pending keys are shown in.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PendingKeysPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl ParseFromValue for PendingKeysPosition {
+    fn parse_from_value(&mut self, value: Value) {
+        if value.is_str() {
+            *self = match value.as_str().unwrap() {
+                "top_left" => PendingKeysPosition::TopLeft,
+                "top_right" => PendingKeysPosition::TopRight,
+                "bottom_left" => PendingKeysPosition::BottomLeft,
+                "bottom_right" => PendingKeysPosition::BottomRight,
+                value => {
+                    error!(
+                        "Expected top_left, top_right, bottom_left or bottom_right, but received {:?}",
+                        value
+                    );
+                    return;
+                }
+            };
+        } else {
+            error!(
+                "Expected a pending keys position string, but received {:?}",
+                value
+            );
+        }
+    }
+}
+
+impl From<PendingKeysPosition> for Value {
+    fn from(position: PendingKeysPosition) -> Self {
+        match position {
+            PendingKeysPosition::TopLeft => Value::from("top_left"),
+            PendingKeysPosition::TopRight => Value::from("top_right"),
+            PendingKeysPosition::BottomLeft => Value::from("bottom_left"),
+            PendingKeysPosition::BottomRight => Value::from("bottom_right"),
+        }
+    }
+}
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "pending_keys"]
+pub struct PendingKeysSettings {
+    // Reports the keys of pending operators and mappings from Neovim. This is applied when the UI
+    // attaches, so it has to be set from the init file.
+    pub enabled: bool,
+    pub position: PendingKeysPosition,
+}
+
+impl Default for PendingKeysSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            position: PendingKeysPosition::BottomRight,
+        }
+    }
+}
+
+/// The keys typed so far of a sequence that isn't complete yet, like `d2` or a leader prefix.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingKeys {
+    pub keys: String,
+    // Top left of the indicator in screen grid coordinates.
+    pub grid_position: (f64, f64),
+}
+
+impl PendingKeys {
+    /// Places the indicator in the corner of a screen of the given size, one cell away from its
+    /// edges. The bottom corners stay clear of the last window's statusline and the cmdline.
+    pub fn new(
+        keys: String,
+        (screen_width, screen_height): (u64, u64),
+        position: PendingKeysPosition,
+    ) -> PendingKeys {
+        let mut pending_keys = PendingKeys {
+            keys,
+            grid_position: (0.0, 0.0),
+        };
+        let width = pending_keys.width();
+        let left = match position {
+            PendingKeysPosition::TopLeft | PendingKeysPosition::BottomLeft => 1,
+            PendingKeysPosition::TopRight | PendingKeysPosition::BottomRight => {
+                screen_width.saturating_sub(width + 1)
+            }
+        };
+        let top = match position {
+            PendingKeysPosition::TopLeft | PendingKeysPosition::TopRight => 1,
+            PendingKeysPosition::BottomLeft | PendingKeysPosition::BottomRight => {
+                screen_height.saturating_sub(3)
+            }
+        };
+
+        pending_keys.grid_position = (left as f64, top as f64);
+        pending_keys
+    }
+
+    pub fn width(&self) -> u64 {
+        // One column of padding on each side.
+        cell_width(&self.keys) + 2
+    }
+}
@@ -0,0 +1,156 @@
+use crate::{editor::cell_width, settings::*};
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "popupmenu"]
+pub struct PopupMenuSettings {
+    // Draws the completion menu in the GUI instead of letting Neovim draw it into the grid. This
+    // is applied when the UI attaches, so it has to be set from the init file.
+    pub external: bool,
+    pub max_height: u64,
+    pub background_color: String,
+    pub selected_background_color: String,
+}
+
+impl Default for PopupMenuSettings {
+    fn default() -> Self {
+        Self {
+            external: false,
+            max_height: 12,
+            background_color: "".to_string(),
+            selected_background_color: "".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PopupMenuItem {
+    pub word: String,
+    pub kind: String,
+    pub menu: String,
+    pub info: String,
+}
+
+impl PopupMenuItem {
+    pub fn label(&self) -> String {
+        [&self.word, &self.kind, &self.menu]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PopupMenu {
+    pub items: Vec<PopupMenuItem>,
+    pub selected: Option<usize>,
+    // Top left of the menu in screen grid coordinates.
+    pub grid_position: (f64, f64),
+    // Index of the first visible item and the maximum number of visible items.
+    pub scroll_offset: usize,
+    pub max_height: usize,
+}
+
+impl PopupMenu {
+    pub fn new(
+        items: Vec<PopupMenuItem>,
+        selected: Option<usize>,
+        grid_position: (f64, f64),
+        max_height: usize,
+    ) -> PopupMenu {
+        let mut popup_menu = PopupMenu {
+            items,
+            selected: None,
+            grid_position,
+            scroll_offset: 0,
+            max_height: max_height.max(1),
+        };
+        popup_menu.select(selected);
+        popup_menu
+    }
+
+    pub fn select(&mut self, selected: Option<usize>) {
+        self.selected = selected.filter(|index| *index < self.items.len());
+
+        // Scroll just enough to keep the selected item visible.
+        if let Some(selected) = self.selected {
+            if selected < self.scroll_offset {
+                self.scroll_offset = selected;
+            } else if selected >= self.scroll_offset + self.max_height {
+                self.scroll_offset = selected + 1 - self.max_height;
+            }
+        }
+    }
+
+    pub fn visible_items(&self) -> &[PopupMenuItem] {
+        let end = (self.scroll_offset + self.max_height).min(self.items.len());
+        &self.items[self.scroll_offset.min(end)..end]
+    }
+
+    pub fn width(&self) -> u64 {
+        self.items
+            .iter()
+            .map(|item| cell_width(&item.label()))
+            .max()
+            .unwrap_or(0)
+            // One column of padding on each side.
+            + 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(count: usize) -> Vec<PopupMenuItem> {
+        (0..count)
+            .map(|index| PopupMenuItem {
+                word: format!("item{index}"),
+                kind: "".to_owned(),
+                menu: "".to_owned(),
+                info: "".to_owned(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn selecting_past_the_visible_items_scrolls() {
+        let mut popup_menu = PopupMenu::new(items(10), None, (0.0, 0.0), 3);
+        assert_eq!(popup_menu.visible_items(), &items(10)[0..3]);
+
+        popup_menu.select(Some(5));
+        assert_eq!(popup_menu.scroll_offset, 3);
+        assert_eq!(popup_menu.visible_items(), &items(10)[3..6]);
+
+        popup_menu.select(Some(1));
+        assert_eq!(popup_menu.scroll_offset, 1);
+
+        popup_menu.select(None);
+        assert_eq!(popup_menu.selected, None);
+        assert_eq!(popup_menu.scroll_offset, 1);
+    }
+
+    #[test]
+    fn label_skips_empty_parts() {
+        let item = PopupMenuItem {
+            word: "foo".to_owned(),
+            kind: "".to_owned(),
+            menu: "[LSP]".to_owned(),
+            info: "".to_owned(),
+        };
+        assert_eq!(item.label(), "foo [LSP]");
+    }
+
+    #[test]
+    fn width_counts_wide_characters_as_two_cells() {
+        let item = |word: &str| PopupMenuItem {
+            word: word.to_owned(),
+            kind: "".to_owned(),
+            menu: "".to_owned(),
+            info: "".to_owned(),
+        };
+        let popup_menu = PopupMenu::new(vec![item("abcde"), item("日本語")], None, (0.0, 0.0), 3);
+        assert_eq!(popup_menu.width(), 8);
+    }
+}
@@ -0,0 +1,59 @@
+use skia_safe::Color4f;
+
+use crate::{
+    editor::{cell_width, parse_color},
+    settings::*,
+};
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "recording_indicator"]
+pub struct RecordingIndicatorSettings {
+    // Has Neovim report when a macro is being recorded. This is applied when the UI attaches, so
+    // it has to be set from the init file.
+    pub enabled: bool,
+    pub color: String,
+}
+
+impl Default for RecordingIndicatorSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            color: "#e06c75".to_string(),
+        }
+    }
+}
+
+impl RecordingIndicatorSettings {
+    pub fn color(&self) -> Option<Color4f> {
+        parse_color(&self.color)
+    }
+}
+
+/// A dot with the register a macro is recorded into, like `recording @q` in the mode message.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordingIndicator {
+    pub register: String,
+    pub color: Color4f,
+    // Top left of the indicator in screen grid coordinates.
+    pub grid_position: (f64, f64),
+}
+
+impl RecordingIndicator {
+    /// Places the indicator in the top right corner of a screen of the given width, one cell away
+    /// from its edges.
+    pub fn new(register: String, screen_width: u64, color: Color4f) -> RecordingIndicator {
+        let mut indicator = RecordingIndicator {
+            register,
+            color,
+            grid_position: (0.0, 0.0),
+        };
+        let left = screen_width.saturating_sub(indicator.width() + 1);
+        indicator.grid_position = (left as f64, 1.0);
+        indicator
+    }
+
+    pub fn width(&self) -> u64 {
+        // The dot with a space after it, and one column of padding on each side.
+        cell_width(&self.register) + 4
+    }
+}
@@ -0,0 +1,95 @@
+use std::{
+    cell::Cell,
+    time::{Duration, Instant},
+};
+
+use nvim_rs::Value;
+
+use crate::{editor::DrawCommand, renderer::WindowDrawCommand, settings::*};
+
+#[derive(SettingGroup, Clone, Default)]
+#[setting_prefix = "render_metrics"]
+pub struct RenderMetricsSettings {
+    // Milliseconds between two reports, zero disables them.
+    pub interval: u64,
+}
+
+/// Counts what the editor sends to the renderer between two reports.
+pub struct RenderCounters {
+    commands: Cell<u64>,
+    batches: Cell<u64>,
+    fragments: Cell<u64>,
+    since: Cell<Instant>,
+}
+
+impl RenderCounters {
+    pub fn new() -> RenderCounters {
+        RenderCounters {
+            commands: Cell::new(0),
+            batches: Cell::new(0),
+            fragments: Cell::new(0),
+            since: Cell::new(Instant::now()),
+        }
+    }
+
+    pub fn count_batch(&self, draw_commands: &[DrawCommand]) {
+        let fragments: usize = draw_commands
+            .iter()
+            .map(|draw_command| match draw_command {
+                DrawCommand::Window {
+                    command: WindowDrawCommand::DrawLine(line_fragments),
+                    ..
+                } => line_fragments.len(),
+                _ => 0,
+            })
+            .sum();
+        self.commands
+            .set(self.commands.get() + draw_commands.len() as u64);
+        self.fragments.set(self.fragments.get() + fragments as u64);
+        self.batches.set(self.batches.get() + 1);
+    }
+
+    /// Returns the metrics since the last report and starts counting again, once at least
+    /// `interval` passed since then.
+    pub fn take_metrics(&self, now: Instant, interval: Duration) -> Option<RenderMetrics> {
+        let elapsed = now.saturating_duration_since(self.since.get());
+        if interval.is_zero() || elapsed < interval {
+            return None;
+        }
+
+        let seconds = elapsed.as_secs_f64();
+        let metrics = RenderMetrics {
+            commands_per_second: self.commands.replace(0) as f64 / seconds,
+            redraws_per_second: self.batches.replace(0) as f64 / seconds,
+            fragments: self.fragments.replace(0),
+        };
+        self.since.set(now);
+        Some(metrics)
+    }
+}
+
+/// The load of the renderer over the last interval, reported to Neovim for statuslines.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderMetrics {
+    pub commands_per_second: f64,
+    // Batches of draw commands sent to the renderer, each one redraws the frame.
+    pub redraws_per_second: f64,
+    // Line fragments drawn over the whole interval.
+    pub fragments: u64,
+}
+
+impl From<RenderMetrics> for Value {
+    fn from(metrics: RenderMetrics) -> Self {
+        Value::Map(vec![
+            (
+                Value::from("commands_per_second"),
+                Value::from(metrics.commands_per_second),
+            ),
+            (
+                Value::from("redraws_per_second"),
+                Value::from(metrics.redraws_per_second),
+            ),
+            (Value::from("fragments"), Value::from(metrics.fragments)),
+        ])
+    }
+}
@@ -0,0 +1,75 @@
+use skia_safe::Color4f;
+
+use crate::{editor::parse_color, settings::*};
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "scroll_overview"]
+pub struct ScrollOverviewSettings {
+    pub enabled: bool,
+    // Height of the bar in pixels.
+    pub height: f32,
+    pub color: String,
+    pub thumb_color: String,
+}
+
+impl Default for ScrollOverviewSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            height: 3.0,
+            color: "#80808040".to_string(),
+            thumb_color: "#808080c0".to_string(),
+        }
+    }
+}
+
+impl ScrollOverviewSettings {
+    pub fn color(&self) -> Option<Color4f> {
+        parse_color(&self.color)
+    }
+
+    pub fn thumb_color(&self) -> Option<Color4f> {
+        parse_color(&self.thumb_color)
+    }
+}
+
+/// The lines a window shows out of its buffer, as reported by `win_viewport`. The bottom line is
+/// the first line past the viewport.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewportLines {
+    pub top_line: f64,
+    pub bottom_line: f64,
+    pub line_count: f64,
+}
+
+/// The part of the overview above one window, spanning the same screen columns as the window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScrollSegment {
+    pub left: f64,
+    pub width: u64,
+    // Where the viewport starts and ends in the buffer, as fractions of its length.
+    pub thumb_start: f64,
+    pub thumb_end: f64,
+}
+
+impl ScrollSegment {
+    pub fn new(left: f64, width: u64, viewport: &ViewportLines) -> ScrollSegment {
+        let line_count = viewport.line_count.max(1.0);
+        let ratio = |line: f64| (line / line_count).clamp(0.0, 1.0);
+        ScrollSegment {
+            left,
+            width,
+            thumb_start: ratio(viewport.top_line),
+            thumb_end: ratio(viewport.bottom_line),
+        }
+    }
+}
+
+/// One bar along the top of the screen showing where every split is scrolled to at once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScrollOverview {
+    pub segments: Vec<ScrollSegment>,
+    pub color: Color4f,
+    pub thumb_color: Color4f,
+    pub height: f32,
+}
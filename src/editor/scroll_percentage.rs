@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use log::error;
+use nvim_rs::Value;
+
+use crate::{
+    editor::{cell_width, parse_color, Colors, Style, ViewportLines},
+    renderer::LineFragment,
+    settings::*,
+};
+
+/// The corner of a window the scroll percentage is shown in.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ScrollPercentagePosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl ParseFromValue for ScrollPercentagePosition {
+    fn parse_from_value(&mut self, value: Value) {
+        if value.is_str() {
+            *self = match value.as_str().unwrap() {
+                "top_left" => ScrollPercentagePosition::TopLeft,
+                "top_right" => ScrollPercentagePosition::TopRight,
+                "bottom_left" => ScrollPercentagePosition::BottomLeft,
+                "bottom_right" => ScrollPercentagePosition::BottomRight,
+                value => {
+                    error!(
+                        "Expected top_left, top_right, bottom_left or bottom_right, but received {:?}",
+                        value
+                    );
+                    return;
+                }
+            };
+        } else {
+            error!(
+                "Expected a scroll percentage position string, but received {:?}",
+                value
+            );
+        }
+    }
+}
+
+impl From<ScrollPercentagePosition> for Value {
+    fn from(position: ScrollPercentagePosition) -> Self {
+        match position {
+            ScrollPercentagePosition::TopLeft => Value::from("top_left"),
+            ScrollPercentagePosition::TopRight => Value::from("top_right"),
+            ScrollPercentagePosition::BottomLeft => Value::from("bottom_left"),
+            ScrollPercentagePosition::BottomRight => Value::from("bottom_right"),
+        }
+    }
+}
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "scroll_percentage"]
+pub struct ScrollPercentageSettings {
+    pub enabled: bool,
+    pub position: ScrollPercentagePosition,
+    pub color: String,
+    pub background: String,
+}
+
+impl Default for ScrollPercentageSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            position: ScrollPercentagePosition::TopRight,
+            color: "".to_string(),
+            background: "".to_string(),
+        }
+    }
+}
+
+impl ScrollPercentageSettings {
+    fn style(&self) -> Option<Arc<Style>> {
+        Some(Arc::new(Style::new(Colors::new(
+            parse_color(&self.color),
+            parse_color(&self.background),
+            None,
+        ))))
+    }
+}
+
+/// How far a window is scrolled through its buffer, the way Vim's ruler shows it. `Top`, `Bot`
+/// and `All` stand for the start, the end and all of the buffer being shown.
+pub fn scroll_percentage(viewport: &ViewportLines) -> String {
+    let above = viewport.top_line.max(0.0) as u64;
+    let below = (viewport.line_count - viewport.bottom_line).max(0.0) as u64;
+    if above == 0 {
+        if below == 0 {
+            "All".to_owned()
+        } else {
+            "Top".to_owned()
+        }
+    } else if below == 0 {
+        "Bot".to_owned()
+    } else {
+        format!("{}%", above * 100 / (above + below))
+    }
+}
+
+/// Builds the indicator in the configured corner of a window of the given size. Nothing is
+/// drawn when it's disabled or doesn't fit.
+pub fn build_scroll_percentage(
+    viewport: &ViewportLines,
+    (grid_width, grid_height): (u64, u64),
+    settings: &ScrollPercentageSettings,
+) -> Option<LineFragment> {
+    let text = scroll_percentage(viewport);
+    let width = cell_width(&text);
+    if !settings.enabled || width > grid_width || grid_height == 0 {
+        return None;
+    }
+
+    let (window_left, window_top) = match settings.position {
+        ScrollPercentagePosition::TopLeft => (0, 0),
+        ScrollPercentagePosition::TopRight => (grid_width - width, 0),
+        ScrollPercentagePosition::BottomLeft => (0, grid_height - 1),
+        ScrollPercentagePosition::BottomRight => (grid_width - width, grid_height - 1),
+    };
+    Some(LineFragment {
+        text,
+        window_left,
+        window_top,
+        width,
+        style: settings.style(),
+        foreground_styles: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn viewport(top_line: f64, bottom_line: f64, line_count: f64) -> ViewportLines {
+        ViewportLines {
+            top_line,
+            bottom_line,
+            line_count,
+        }
+    }
+
+    #[test]
+    fn percentage_counts_the_lines_above_and_below_the_window() {
+        assert_eq!(scroll_percentage(&viewport(30.0, 50.0, 110.0)), "33%");
+        assert_eq!(scroll_percentage(&viewport(0.0, 50.0, 110.0)), "Top");
+        assert_eq!(scroll_percentage(&viewport(60.0, 110.0, 110.0)), "Bot");
+        assert_eq!(scroll_percentage(&viewport(0.0, 20.0, 12.0)), "All");
+    }
+}
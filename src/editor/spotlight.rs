@@ -0,0 +1,97 @@
+use skia_safe::Color4f;
+
+use crate::settings::*;
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "spotlight"]
+pub struct SpotlightSettings {
+    // How dark everything outside of the spotlight gets, from 0 to 1.
+    pub opacity: f32,
+}
+
+impl Default for SpotlightSettings {
+    fn default() -> Self {
+        Self { opacity: 0.6 }
+    }
+}
+
+/// The cells of a window to keep lit, the bottom row and the right column excluded.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SpotlightArea {
+    pub window: u64,
+    pub top: u64,
+    pub left: u64,
+    pub bottom: u64,
+    pub right: u64,
+}
+
+/// Everything but the cutout is dimmed, which is given in cells of the screen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spotlight {
+    pub left: f64,
+    pub top: f64,
+    pub width: u64,
+    pub height: u64,
+    pub color: Color4f,
+}
+
+/// Places the area on the screen for a window at the given position, cut down to the size of the
+/// window. Nothing is lit for an area outside of it.
+pub fn build_spotlight(
+    area: &SpotlightArea,
+    (window_left, window_top): (f64, f64),
+    (window_width, window_height): (u64, u64),
+    settings: &SpotlightSettings,
+) -> Option<Spotlight> {
+    let right = area.right.min(window_width);
+    let bottom = area.bottom.min(window_height);
+    if area.left >= right || area.top >= bottom {
+        return None;
+    }
+
+    Some(Spotlight {
+        left: window_left + area.left as f64,
+        top: window_top + area.top as f64,
+        width: right - area.left,
+        height: bottom - area.top,
+        color: Color4f::new(0.0, 0.0, 0.0, settings.opacity.clamp(0.0, 1.0)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn areas_are_cut_down_to_the_window() {
+        let area = SpotlightArea {
+            window: 1000,
+            top: 2,
+            left: 4,
+            bottom: 30,
+            right: 10,
+        };
+        let spotlight =
+            build_spotlight(&area, (40.0, 1.0), (20, 10), &SpotlightSettings::default()).unwrap();
+        assert_eq!(
+            (
+                spotlight.left,
+                spotlight.top,
+                spotlight.width,
+                spotlight.height
+            ),
+            (44.0, 3.0, 6, 8)
+        );
+
+        let outside = SpotlightArea { top: 10, ..area };
+        assert_eq!(
+            build_spotlight(
+                &outside,
+                (40.0, 1.0),
+                (20, 10),
+                &SpotlightSettings::default()
+            ),
+            None
+        );
+    }
+}
@@ -1,13 +1,56 @@
+use csscolorparser::Color;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use skia_safe::Color4f;
 
-#[derive(new, Debug, Clone, PartialEq)]
+/// Parses a css color string from a setting. Returns None for empty or invalid strings so that
+/// callers can fall back to their defaults.
+pub fn parse_color(color: &str) -> Option<Color4f> {
+    let [red, green, blue, alpha] = color.parse::<Color>().ok()?.to_array();
+    Some(Color4f::new(
+        red as f32,
+        green as f32,
+        blue as f32,
+        alpha as f32,
+    ))
+}
+
+// Skia colors aren't serializable, so they are stored as their rgba components.
+fn serialize_color<S: Serializer>(
+    color: &Option<Color4f>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    color
+        .map(|color| [color.r, color.g, color.b, color.a])
+        .serialize(serializer)
+}
+
+fn deserialize_color<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Color4f>, D::Error> {
+    let components = Option::<[f32; 4]>::deserialize(deserializer)?;
+    Ok(components.map(|[red, green, blue, alpha]| Color4f::new(red, green, blue, alpha)))
+}
+
+#[derive(new, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Colors {
+    #[serde(
+        serialize_with = "serialize_color",
+        deserialize_with = "deserialize_color"
+    )]
     pub foreground: Option<Color4f>,
+    #[serde(
+        serialize_with = "serialize_color",
+        deserialize_with = "deserialize_color"
+    )]
     pub background: Option<Color4f>,
+    #[serde(
+        serialize_with = "serialize_color",
+        deserialize_with = "deserialize_color"
+    )]
     pub special: Option<Color4f>,
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum UnderlineStyle {
     Underline,
     UnderDouble,
@@ -16,7 +59,7 @@ pub enum UnderlineStyle {
     UnderCurl,
 }
 
-#[derive(new, Debug, Clone, PartialEq)]
+#[derive(new, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Style {
     pub colors: Colors,
     #[new(default)]
@@ -58,6 +101,12 @@ impl Style {
         }
     }
 
+    /// How opaque the background is drawn, from the `blend` neovim sends for `winblend` and
+    /// `pumblend`. The text itself is always drawn opaque.
+    pub fn background_opacity(&self) -> f32 {
+        (100 - self.blend.min(100)) as f32 / 100.0
+    }
+
     pub fn special(&self, default_colors: &Colors) -> Color4f {
         self.colors
             .special
@@ -143,6 +192,12 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_no_underline_by_default() {
+        let style = Style::new(COLORS);
+        assert_eq!(style.underline, None);
+    }
+
     #[test]
     fn test_special() {
         let mut style = Style::new(COLORS);
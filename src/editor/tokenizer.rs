@@ -0,0 +1,165 @@
+use crate::editor::grid::GridCell;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TokenKind {
+    Identifier,
+    Number,
+    Punctuation,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TokenSpan {
+    pub kind: TokenKind,
+    // Grid columns covered by the token. The end column is exclusive.
+    pub start: u64,
+    pub end: u64,
+}
+
+// A deliberately simple classifier which splits grid text into words, numbers and punctuation.
+// It is not meant to replace Neovim's syntax highlighting, only to make cheap GUI side features
+// (bracket matching, number highlighting and such) possible without a round trip to Neovim.
+#[derive(Debug, Clone)]
+pub struct TokenClassifier {
+    // Characters other than alphanumerics which are allowed inside identifiers.
+    pub identifier_characters: String,
+}
+
+impl Default for TokenClassifier {
+    fn default() -> Self {
+        Self {
+            identifier_characters: "_".to_owned(),
+        }
+    }
+}
+
+impl TokenClassifier {
+    fn is_identifier_character(&self, character: char) -> bool {
+        character.is_alphanumeric() || self.identifier_characters.contains(character)
+    }
+
+    fn starts_token(&self, character: char) -> Option<TokenKind> {
+        if character.is_whitespace() {
+            None
+        } else if character.is_ascii_digit() {
+            Some(TokenKind::Number)
+        } else if self.is_identifier_character(character) {
+            Some(TokenKind::Identifier)
+        } else {
+            Some(TokenKind::Punctuation)
+        }
+    }
+
+    fn continues_token(&self, kind: TokenKind, character: char) -> bool {
+        match kind {
+            TokenKind::Identifier => self.is_identifier_character(character),
+            // Allow things like 0x1F, 1_000 and 3.14 to stay a single number.
+            TokenKind::Number => {
+                character.is_alphanumeric() || character == '.' || character == '_'
+            }
+            // Every punctuation character is its own token so brackets can be matched.
+            TokenKind::Punctuation => false,
+        }
+    }
+}
+
+// Splits a row of grid cells into token spans. The empty right halves of double width characters
+// are treated as part of the token on their left.
+pub fn tokenize_row(row: &[GridCell], classifier: &TokenClassifier) -> Vec<TokenSpan> {
+    let mut tokens: Vec<TokenSpan> = Vec::new();
+    let mut current: Option<TokenSpan> = None;
+
+    for (column, (text, _)) in row.iter().enumerate() {
+        let column = column as u64;
+
+        let character = match text.chars().next() {
+            Some(character) => character,
+            None => {
+                if let Some(token) = current.as_mut() {
+                    token.end = column + 1;
+                }
+                continue;
+            }
+        };
+
+        if let Some(token) = current.as_mut() {
+            if classifier.continues_token(token.kind, character) {
+                token.end = column + 1;
+                continue;
+            }
+        }
+
+        tokens.extend(current.take());
+        current = classifier.starts_token(character).map(|kind| TokenSpan {
+            kind,
+            start: column,
+            end: column + 1,
+        });
+    }
+
+    tokens.extend(current);
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cells(text: &str) -> Vec<GridCell> {
+        text.chars()
+            .map(|character| (character.to_string(), None))
+            .collect()
+    }
+
+    fn span(kind: TokenKind, start: u64, end: u64) -> TokenSpan {
+        TokenSpan { kind, start, end }
+    }
+
+    #[test]
+    fn tokenize_row_splits_identifiers_numbers_and_punctuation() {
+        let row = cells("let foo_1 = (42 + 0x1F);");
+        let tokens = tokenize_row(&row, &TokenClassifier::default());
+
+        assert_eq!(
+            tokens,
+            vec![
+                span(TokenKind::Identifier, 0, 3),
+                span(TokenKind::Identifier, 4, 9),
+                span(TokenKind::Punctuation, 10, 11),
+                span(TokenKind::Punctuation, 12, 13),
+                span(TokenKind::Number, 13, 15),
+                span(TokenKind::Punctuation, 16, 17),
+                span(TokenKind::Number, 18, 22),
+                span(TokenKind::Punctuation, 22, 23),
+                span(TokenKind::Punctuation, 23, 24),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_row_uses_configured_identifier_characters() {
+        let row = cells("foo-bar");
+        let classifier = TokenClassifier {
+            identifier_characters: "-".to_owned(),
+        };
+
+        assert_eq!(
+            tokenize_row(&row, &classifier),
+            vec![span(TokenKind::Identifier, 0, 7)]
+        );
+    }
+
+    #[test]
+    fn tokenize_row_includes_double_width_continuation_cells() {
+        let row = vec![
+            ("漢".to_owned(), None),
+            ("".to_owned(), None),
+            ("a".to_owned(), None),
+            (" ".to_owned(), None),
+        ];
+
+        assert_eq!(
+            tokenize_row(&row, &TokenClassifier::default()),
+            vec![span(TokenKind::Identifier, 0, 3)]
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use crate::editor::grid::GridCell;
+
+const URL_SCHEMES: &[&str] = &["https://", "http://", "ftp://", "file://"];
+
+// Characters which commonly end a sentence or enclose a url rather than being part of it.
+const TRAILING_PUNCTUATION: &[char] = &['.', ',', ';', ':', '!', '?', '\'', '"'];
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UrlRange {
+    pub row: u64,
+    // Grid columns covered by the url. The end column is exclusive.
+    pub start: u64,
+    pub end: u64,
+    pub url: String,
+}
+
+impl UrlRange {
+    pub fn contains(&self, column: u64, row: u64) -> bool {
+        self.row == row && column >= self.start && column < self.end
+    }
+}
+
+fn is_url_character(character: char) -> bool {
+    !character.is_whitespace() && !matches!(character, '<' | '>' | '"' | '`' | '{' | '}' | '|')
+}
+
+fn starts_with_scheme(characters: &[char]) -> Option<usize> {
+    URL_SCHEMES.iter().find_map(|scheme| {
+        let matches = characters.len() > scheme.len()
+            && scheme
+                .chars()
+                .zip(characters)
+                .all(|(expected, character)| expected == character.to_ascii_lowercase());
+        matches.then_some(scheme.len())
+    })
+}
+
+// Drops trailing punctuation and closing brackets without a matching opening bracket, so that
+// urls written in prose or inside parentheses don't swallow the surrounding characters.
+fn trim_url_end(characters: &[char]) -> usize {
+    let mut end = characters.len();
+    while end > 0 {
+        let last = characters[end - 1];
+        let unbalanced = |open, close| {
+            last == close
+                && characters[..end].iter().filter(|c| **c == close).count()
+                    > characters[..end].iter().filter(|c| **c == open).count()
+        };
+
+        if TRAILING_PUNCTUATION.contains(&last) || unbalanced('(', ')') || unbalanced('[', ']') {
+            end -= 1;
+        } else {
+            break;
+        }
+    }
+    end
+}
+
+// Finds the urls in a row of grid cells. Unlike Neovim's url highlight attribute this only looks
+// at the text, so it works for any buffer, terminal or message.
+pub fn detect_urls(row: u64, cells: &[GridCell]) -> Vec<UrlRange> {
+    // Each character along with the grid columns its cell covers. The empty right halves of
+    // double width characters extend the cell on their left.
+    let mut characters: Vec<char> = Vec::new();
+    let mut columns: Vec<(u64, u64)> = Vec::new();
+    for (column, (text, _)) in cells.iter().enumerate() {
+        let column = column as u64;
+        match text.chars().next() {
+            Some(character) => {
+                characters.push(character);
+                columns.push((column, column + 1));
+            }
+            None => {
+                if let Some((_, end)) = columns.last_mut() {
+                    *end = column + 1;
+                }
+            }
+        }
+    }
+
+    let mut urls = Vec::new();
+    let mut index = 0;
+    while index < characters.len() {
+        let at_word_start = index == 0 || !characters[index - 1].is_alphanumeric();
+        let scheme_length = match starts_with_scheme(&characters[index..]) {
+            Some(scheme_length) if at_word_start => scheme_length,
+            _ => {
+                index += 1;
+                continue;
+            }
+        };
+
+        let mut end = index + scheme_length;
+        while end < characters.len() && is_url_character(characters[end]) {
+            end += 1;
+        }
+        let end = index + trim_url_end(&characters[index..end]);
+
+        if end > index + scheme_length {
+            urls.push(UrlRange {
+                row,
+                start: columns[index].0,
+                end: columns[end - 1].1,
+                url: characters[index..end].iter().collect(),
+            });
+        }
+        index = end.max(index + 1);
+    }
+
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cells(text: &str) -> Vec<GridCell> {
+        text.chars()
+            .map(|character| (character.to_string(), None))
+            .collect()
+    }
+
+    #[test]
+    fn detect_urls_finds_the_url_columns() {
+        let urls = detect_urls(3, &cells("see https://neovide.dev/faq for more"));
+
+        assert_eq!(
+            urls,
+            vec![UrlRange {
+                row: 3,
+                start: 4,
+                end: 27,
+                url: "https://neovide.dev/faq".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn detect_urls_skips_surrounding_punctuation() {
+        let urls = detect_urls(0, &cells("(docs at http://example.com/a_(b)), or ftp://x."));
+
+        let found: Vec<_> = urls.iter().map(|url| url.url.as_str()).collect();
+        assert_eq!(found, vec!["http://example.com/a_(b)", "ftp://x"]);
+    }
+
+    #[test]
+    fn detect_urls_ignores_schemes_inside_words() {
+        assert!(detect_urls(0, &cells("xhttps://example.com https://")).is_empty());
+    }
+}
@@ -0,0 +1,36 @@
+use skia_safe::Color4f;
+
+use crate::{editor::parse_color, settings::*};
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "visual_block"]
+pub struct VisualBlockSettings {
+    // Draws visual block selections as one rectangle on top of the grid. This is applied when the
+    // UI attaches, so it has to be set from the init file.
+    pub enabled: bool,
+    pub color: String,
+}
+
+impl Default for VisualBlockSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            color: "#80808060".to_string(),
+        }
+    }
+}
+
+impl VisualBlockSettings {
+    pub fn color(&self) -> Option<Color4f> {
+        parse_color(&self.color)
+    }
+}
+
+/// A rectangle of grid cells. The bottom row and right column are exclusive.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct VisualBlock {
+    pub top: u64,
+    pub left: u64,
+    pub bottom: u64,
+    pub right: u64,
+}
@@ -1,26 +1,144 @@
-use std::{collections::HashMap, rc::Rc, sync::Arc};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap, HashSet},
+    rc::Rc,
+    sync::Arc,
+};
 
 use log::warn;
-use unicode_segmentation::UnicodeSegmentation;
+use serde::{Deserialize, Serialize};
+use skia_safe::{Color4f, Rect};
 
+#[cfg(test)]
+use crate::editor::{tokenize_row, TokenClassifier, TokenSpan};
 use crate::{
     bridge::GridLineCell,
-    editor::{grid::CharacterGrid, style::Style, AnchorInfo, DrawCommand, DrawCommandBatcher},
+    dimensions::Dimensions,
+    editor::{
+        build_blame, build_breadcrumb, build_diagnostic, build_scroll_percentage, cell_width,
+        detect_urls, differ_only_in_foreground,
+        grid::{limit_combining_marks, CharacterGrid, GridCell, GridLimits},
+        indent_guides, is_double_width_cell,
+        style::Style,
+        AnchorInfo, BackgroundKind, BlameLine, BlameSettings, BreadcrumbSettings, Colors,
+        CursorTabWidth, Diagnostic, DiagnosticSettings, DrawCommand, DrawCommandBatcher,
+        IndentGuide, IndentGuideSettings, LineNumberSettings, LineNumbers,
+        ScrollPercentageSettings, UrlRange, ViewportLines, VisualBlock, VisualBlockSettings,
+    },
     renderer::{LineFragment, WindowDrawCommand},
+    settings::SETTINGS,
 };
 
+// Number of consecutive out of bounds grid lines after which the grid is considered out of sync
+// with Neovim.
+const DESYNC_DRAW_THRESHOLD: u32 = 3;
+
+// The widest tab the cursor covers, as the tabstop of the buffer isn't known.
+const MAX_TAB_WIDTH: u64 = 8;
+
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 8.0;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum WindowType {
     Editor,
     Message,
 }
 
+/// Everything needed to draw a window again without neovim, for dumping to disk when reproducing
+/// rendering bugs. Each distinct style is stored once, and the cells, row by row, refer to them
+/// by their index.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowSnapshot {
+    pub grid_id: u64,
+    pub window_type: WindowType,
+    pub anchor_info: Option<AnchorInfo>,
+    pub grid_position: (f64, f64),
+    pub width: u64,
+    pub height: u64,
+    pub styles: Vec<Style>,
+    pub cells: Vec<(String, Option<usize>)>,
+    pub row_display_widths: Vec<u64>,
+}
+
+/// Marks a row whose content doesn't fit in the window, like Neovim's `extends` listchars.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum EdgeIndicator {
+    // The line continues on the next row.
+    Wrap,
+    // The content is wider than the window.
+    Extends,
+}
+
 pub struct Window {
     grid_id: u64,
     grid: CharacterGrid,
     pub window_type: WindowType,
 
     pub anchor_info: Option<AnchorInfo>,
+    // The floating order the renderer got with the last position or reorder.
+    sent_floating_order: Option<u64>,
     grid_position: (f64, f64),
+    // Scales the cells the window is drawn with, without changing its grid size.
+    zoom: f32,
+    // Whether scrolls are animated, following the global scroll animation when unset.
+    smooth_scroll: Option<bool>,
+    // Draws the window at whole pixels, even while it's moving, as fractional ones blur it.
+    snap_to_pixels: bool,
+    // The rows drawn of a collapsed float, which shows only its header while the rest of the grid
+    // is kept for when it expands again.
+    collapsed_height: Option<u64>,
+    // Kept to send it again when the renderer starts the window over.
+    background: Option<BackgroundKind>,
+    // See `GridLimits`.
+    max_fragment_width: u64,
+    max_combining_marks: u64,
+    // Keeps cells whose highlights only differ in the foreground in one fragment.
+    ligatures_across_highlights: bool,
+    // The highlights drawn since the grid was last cleared, some of which may have been drawn
+    // over since. Only these can be redefined under the cells.
+    highlight_ids: HashSet<u64>,
+    // The first line shown and how many lines fit, as of the last viewport event.
+    top_line: f64,
+    line_height: f64,
+    // The top line the renderer last got, so viewports that didn't move aren't sent again.
+    sent_top_line: Option<f64>,
+    // Rows the whole grid scrolled by since the last viewport, which is how far the viewport moved
+    // when neovim doesn't send the scroll delta. Lines hidden in folds or wrapped over several
+    // rows make the top line a poor measure of it.
+    pending_scroll: i64,
+    // The last visual block selected in the window, kept after the selection ends since entering
+    // the command line to copy it already ends it.
+    visual_block: Option<VisualBlock>,
+
+    out_of_bounds_draws: u32,
+
+    // Rows changed since the urls were last detected, so only those have to be scanned again.
+    // Scrolls move rows without redrawing them, so this is kept apart from the rows the grid
+    // marks for the next redraw.
+    url_dirty_rows: BTreeSet<u64>,
+    urls: Vec<UrlRange>,
+
+    // The column each row's content reaches, which can be past the grid width.
+    row_display_widths: Vec<u64>,
+    hidden: bool,
+    viewport_lines: Option<ViewportLines>,
+    // What was last sent for the scroll percentage.
+    scroll_percentage: Option<LineFragment>,
+    wrapped_rows: BTreeSet<u64>,
+    edge_indicators: Vec<(u64, EdgeIndicator)>,
+    indent_guides: Vec<IndentGuide>,
+    // The context shown in a bar over the top row, like the file name and the symbol path.
+    breadcrumb: Option<String>,
+    diagnostics: Vec<Diagnostic>,
+    blame: Vec<BlameLine>,
+    // What was last sent for the diagnostics and the blame, which depends on where the text of their
+    // rows ends.
+    diagnostic_fragments: Vec<LineFragment>,
+    blame_fragments: Vec<LineFragment>,
+
+    // Whether the buffer line the cursor is on is above, inside or below the viewport.
+    cursor_line_offset: Ordering,
 
     draw_command_batcher: Rc<DrawCommandBatcher>,
 }
@@ -33,15 +151,49 @@ impl Window {
         grid_position: (f64, f64),
         grid_size: (u64, u64),
         draw_command_batcher: Rc<DrawCommandBatcher>,
+        limits: GridLimits,
     ) -> Window {
-        let window = Window {
+        let mut window = Window {
             grid_id,
-            grid: CharacterGrid::new(grid_size),
+            grid: CharacterGrid::new(grid_size, limits.max_grid_size),
             window_type,
             anchor_info,
+            sent_floating_order: None,
             grid_position,
+            zoom: 1.0,
+            smooth_scroll: None,
+            snap_to_pixels: false,
+            collapsed_height: None,
+            background: None,
+            max_fragment_width: limits.max_fragment_width,
+            max_combining_marks: limits.max_combining_marks,
+            ligatures_across_highlights: false,
+            highlight_ids: HashSet::new(),
+            top_line: 0.0,
+            line_height: 0.0,
+            sent_top_line: None,
+            pending_scroll: 0,
+            visual_block: None,
+            out_of_bounds_draws: 0,
+            url_dirty_rows: BTreeSet::new(),
+            urls: Vec::new(),
+            row_display_widths: Vec::new(),
+            hidden: false,
+            viewport_lines: None,
+            scroll_percentage: None,
+            wrapped_rows: BTreeSet::new(),
+            edge_indicators: Vec::new(),
+            indent_guides: Vec::new(),
+            breadcrumb: None,
+            diagnostics: Vec::new(),
+            blame: Vec::new(),
+            diagnostic_fragments: Vec::new(),
+            blame_fragments: Vec::new(),
+            cursor_line_offset: Ordering::Equal,
             draw_command_batcher,
         };
+        // The grid may have been clamped to less than the requested size.
+        window.reset_row_edges();
         window.send_updated_position();
         window
     }
@@ -55,30 +207,267 @@ impl Window {
             .ok();
     }
 
-    fn send_updated_position(&self) {
+    fn send_updated_position(&mut self) {
+        self.sent_floating_order = self.floating_order();
+        let height = match self.collapsed_height {
+            Some(collapsed_height) => collapsed_height.min(self.grid.height),
+            None => self.grid.height,
+        };
         self.send_command(WindowDrawCommand::Position {
             grid_position: self.grid_position,
-            grid_size: (self.grid.width, self.grid.height),
-            floating_order: self.anchor_info.clone().map(|anchor| anchor.sort_order),
+            grid_size: (self.grid.width, height),
+            floating_order: self.sent_floating_order,
+            zoom: self.zoom,
+            window_type: self.window_type,
+            snap_to_pixels: self.snap_to_pixels,
         });
     }
 
+    /// Sends the floating order if it changed since the last position or reorder.
+    fn send_floating_order(&mut self) {
+        let floating_order = self.floating_order();
+        if floating_order != self.sent_floating_order {
+            self.sent_floating_order = floating_order;
+            self.send_command(WindowDrawCommand::Reorder { floating_order });
+        }
+    }
+
+    /// Returns the cell under the cursor along with whether it's a double width character and
+    /// whether the cursor sits on its right half. The text is the whole cluster neovim sent for
+    /// the cell, so combining marks stay with their base character for the cursor to draw. On the
+    /// empty right half of a double width character the cluster to its left is returned, and the
+    /// cursor should be drawn over that cell instead.
     pub fn get_cursor_grid_cell(
         &self,
         window_left: u64,
         window_top: u64,
-    ) -> (String, Option<Arc<Style>>, bool) {
+    ) -> (String, Option<Arc<Style>>, bool, bool) {
         let grid_cell = match self.grid.get_cell(window_left, window_top) {
             Some((character, style)) => (character.clone(), style.clone()),
             _ => (' '.to_string(), None),
         };
 
-        let double_width = match self.grid.get_cell(window_left + 1, window_top) {
-            Some((character, _)) => character.is_empty(),
+        if grid_cell.0.is_empty() && window_left > 0 {
+            if let Some((character, style)) = self.grid.get_cell(window_left - 1, window_top) {
+                if !character.is_empty() {
+                    return (character.clone(), style.clone(), true, true);
+                }
+            }
+        }
+
+        // Clusters like emoji sequences are measured as well, in case the continuation cell
+        // wasn't drawn yet, or lies past the last column.
+        let double_width = cell_width(&grid_cell.0) > 1
+            || match self.grid.get_cell(window_left + 1, window_top) {
+                Some((character, _)) => character.is_empty(),
+                _ => false,
+            };
+
+        (grid_cell.0, grid_cell.1, double_width, false)
+    }
+
+    /// Returns the first column and the width in cells the cursor covers. Neovim draws tabs as
+    /// blank cells, so with [`CursorTabWidth::Full`] a tab is taken to be the run of blank cells
+    /// with the same style around the cursor, at most eight cells wide. Blank cells past the text
+    /// of the row aren't a tab.
+    pub fn cursor_span(
+        &self,
+        window_left: u64,
+        window_top: u64,
+        tab_width: CursorTabWidth,
+    ) -> (u64, u64) {
+        let cells = match self.grid.row(window_top) {
+            Some(cells) if tab_width == CursorTabWidth::Full => cells,
+            _ => return (window_left, 1),
+        };
+        let column = window_left as usize;
+        let is_blank = |cell: &GridCell| cell.0 == " ";
+        let same_style = |cell: &GridCell| match (&cell.1, &cells[column].1) {
+            (Some(style), Some(cursor_style)) => Arc::ptr_eq(style, cursor_style),
+            (None, None) => true,
             _ => false,
         };
+        if column >= cells.len()
+            || !is_blank(&cells[column])
+            || window_left >= self.content_end(window_top)
+        {
+            return (window_left, 1);
+        }
+
+        let mut start = column;
+        while start > 0 && is_blank(&cells[start - 1]) && same_style(&cells[start - 1]) {
+            start -= 1;
+        }
+        let mut end = column + 1;
+        while end < cells.len() && is_blank(&cells[end]) && same_style(&cells[end]) {
+            end += 1;
+        }
+        // A longer run is several tabs, or spaces ahead of the tab. Only the tab stop holding the
+        // cursor is covered, counted from where the run ends as that's where the text continues.
+        let max_width = MAX_TAB_WIDTH as usize;
+        if end - start > max_width {
+            let stops_after = (end - column - 1) / max_width;
+            end -= stops_after * max_width;
+            start = start.max(end.saturating_sub(max_width));
+        }
+
+        (start as u64, (end - start) as u64)
+    }
+
+    /// Points the cells drawn with the old styles of the redefined highlights, keyed by their id,
+    /// to the new ones in one pass over the grid. Returns whether there were any.
+    pub fn replace_styles(&mut self, redefined: &HashMap<u64, (Arc<Style>, Arc<Style>)>) -> bool {
+        let replacements: HashMap<*const Style, &Arc<Style>> = redefined
+            .iter()
+            .filter(|(id, _)| self.highlight_ids.contains(id))
+            .map(|(_, (old_style, new_style))| (Arc::as_ptr(old_style), new_style))
+            .collect();
+        if replacements.is_empty() {
+            return false;
+        }
+
+        let mut replaced = false;
+        for row in 0..self.grid.height {
+            let mut row_replaced = false;
+            for column in 0..self.grid.width {
+                if let Some((_, Some(style))) = self.grid.get_cell_mut(column, row) {
+                    if let Some(new_style) = replacements.get(&Arc::as_ptr(style)) {
+                        *style = Arc::clone(new_style);
+                        row_replaced = true;
+                    }
+                }
+            }
+            if row_replaced {
+                self.grid.mark_row_dirty(row);
+                replaced = true;
+            }
+        }
+        replaced
+    }
+
+    /// The text of a row as it's drawn, with the empty right halves of double width characters
+    /// left out and the trailing spaces kept.
+    #[cfg(test)]
+    pub fn get_line_text(&self, row: u64) -> Option<String> {
+        let cells = self.grid.row(row)?;
+        Some(
+            cells
+                .iter()
+                .map(|(text, _)| text.as_str())
+                .filter(|text| !text.is_empty())
+                .collect(),
+        )
+    }
+
+    /// See [`CharacterGrid::export`].
+    #[cfg(test)]
+    pub fn export_grid(&self) -> String {
+        self.grid.export()
+    }
+
+    /// See [`CharacterGrid::export_with_cursor`].
+    pub fn export_grid_with_cursor(&self, cursor_position: (u64, u64)) -> String {
+        self.grid.export_with_cursor(cursor_position)
+    }
+
+    /// Captures the grid and the placement of the window, see [`WindowSnapshot`].
+    pub fn snapshot(&self) -> WindowSnapshot {
+        let mut styles = Vec::new();
+        let mut style_indices: HashMap<*const Style, usize> = HashMap::new();
+        let mut cells = Vec::with_capacity((self.grid.width * self.grid.height) as usize);
+        for row in 0..self.grid.height {
+            for (text, style) in self.grid.row(row).unwrap_or_default() {
+                let style_index = style.as_ref().map(|style| {
+                    *style_indices.entry(Arc::as_ptr(style)).or_insert_with(|| {
+                        styles.push(style.as_ref().clone());
+                        styles.len() - 1
+                    })
+                });
+                cells.push((text.clone(), style_index));
+            }
+        }
+
+        WindowSnapshot {
+            grid_id: self.grid_id,
+            window_type: self.window_type,
+            anchor_info: self.anchor_info.clone(),
+            grid_position: self.grid_position,
+            width: self.grid.width,
+            height: self.grid.height,
+            styles,
+            cells,
+            row_display_widths: self.row_display_widths.clone(),
+        }
+    }
+
+    /// Rebuilds a window from a [`WindowSnapshot`] and draws all of it. Cells missing from the
+    /// snapshot are left blank and ones referring to unknown styles are unstyled.
+    #[cfg(test)]
+    pub fn restore(
+        snapshot: WindowSnapshot,
+        draw_command_batcher: Rc<DrawCommandBatcher>,
+        limits: GridLimits,
+    ) -> Window {
+        let mut window = Window::new(
+            snapshot.grid_id,
+            snapshot.window_type,
+            snapshot.anchor_info,
+            snapshot.grid_position,
+            (snapshot.width, snapshot.height),
+            draw_command_batcher,
+            limits,
+        );
+
+        let styles: Vec<Arc<Style>> = snapshot.styles.into_iter().map(Arc::new).collect();
+        let width = snapshot.width.max(1);
+        for (index, (text, style_index)) in snapshot.cells.into_iter().enumerate() {
+            let index = index as u64;
+            if let Some(cell) = window.grid.get_cell_mut(index % width, index / width) {
+                *cell = (
+                    text,
+                    style_index.and_then(|style_index| styles.get(style_index).cloned()),
+                );
+            }
+        }
+        for (row, display_width) in snapshot.row_display_widths.into_iter().enumerate() {
+            if let Some(row_display_width) = window.row_display_widths.get_mut(row) {
+                *row_display_width = display_width;
+            }
+        }
+
+        // The restored rows are drawn, and scanned for urls, as if neovim had just sent them.
+        window.grid.mark_all_rows_dirty();
+        window.mark_urls_stale();
+        window.redraw();
+        window
+    }
+
+    /// Returns the columns and rows of the cells whose style matches, row by row. Cells without a
+    /// highlight are matched against the default style, which leaves all the colors unset.
+    #[cfg(test)]
+    pub fn cells_matching(&self, predicate: impl Fn(&Style) -> bool) -> Vec<(u64, u64)> {
+        let default_style = Style::new(Colors::new(None, None, None));
+        let default_matches = predicate(&default_style);
+        let mut cells = Vec::new();
+        for row in 0..self.grid.height {
+            for (column, (_, style)) in self.grid.row(row).unwrap_or_default().iter().enumerate() {
+                let matches = match style {
+                    Some(style) => predicate(style),
+                    None => default_matches,
+                };
+                if matches {
+                    cells.push((column as u64, row));
+                }
+            }
+        }
+        cells
+    }
 
-        (grid_cell.0, grid_cell.1, double_width)
+    /// The text of the last selected visual block, see [`CharacterGrid::block_text`].
+    pub fn visual_block_text(&self) -> Option<String> {
+        self.visual_block
+            .as_ref()
+            .map(|block| self.grid.block_text(block))
     }
 
     pub fn get_width(&self) -> u64 {
@@ -89,27 +478,204 @@ impl Window {
         self.grid.height
     }
 
+    pub fn is_floating(&self) -> bool {
+        self.anchor_info.is_some()
+    }
+
+    /// Where the window is stacked among the floats, which are drawn above the other windows.
+    pub fn floating_order(&self) -> Option<u64> {
+        self.anchor_info.as_ref().map(|anchor| anchor.sort_order)
+    }
+
+    /// The area the window covers on screen in pixels, for cells of the given size.
+    pub fn pixel_bounds(&self, font_dimensions: Dimensions) -> Rect {
+        let (left, top) = self.grid_position;
+        let mut left = left as f32 * font_dimensions.width as f32;
+        let mut top = top as f32 * font_dimensions.height as f32;
+        if self.snap_to_pixels {
+            left = left.round();
+            top = top.round();
+        }
+        Rect::from_xywh(
+            left,
+            top,
+            (self.grid.width * font_dimensions.width) as f32,
+            (self.grid.height * font_dimensions.height) as f32,
+        )
+    }
+
     pub fn get_grid_position(&self) -> (f64, f64) {
         self.grid_position
     }
 
+    /// The cell of the window under a pixel of the screen, for cells of the given size, or `None`
+    /// outside of the grid. The right half of a double width character maps onto its left half,
+    /// where Neovim expects clicks on it.
+    #[cfg(test)]
+    pub fn pixel_to_grid_cell(
+        &self,
+        pixel_x: f64,
+        pixel_y: f64,
+        cell_size: (f64, f64),
+    ) -> Option<(u64, u64)> {
+        let (cell_width, cell_height) = cell_size;
+        if cell_width <= 0.0 || cell_height <= 0.0 {
+            return None;
+        }
+
+        let column = (pixel_x / cell_width - self.grid_position.0).floor();
+        let row = (pixel_y / cell_height - self.grid_position.1).floor();
+        if column < 0.0
+            || row < 0.0
+            || column >= self.grid.width as f64
+            || row >= self.grid.height as f64
+        {
+            return None;
+        }
+
+        let (mut column, row) = (column as u64, row as u64);
+        if column > 0 {
+            if let Some((text, _)) = self.grid.get_cell(column, row) {
+                if text.is_empty() {
+                    column -= 1;
+                }
+            }
+        }
+        Some((column, row))
+    }
+
+    #[cfg(test)]
+    pub fn tokenize_line(&self, row: u64, classifier: &TokenClassifier) -> Option<Vec<TokenSpan>> {
+        self.grid
+            .row(row)
+            .map(|cells| tokenize_row(cells, classifier))
+    }
+
     pub fn position(
         &mut self,
         anchor_info: Option<AnchorInfo>,
         grid_size: (u64, u64),
         grid_position: (f64, f64),
     ) {
-        self.grid.resize(grid_size);
+        // Floats restacking in place only need to be drawn in another order, their grids stay.
+        let sort_orders = (
+            self.floating_order(),
+            anchor_info.as_ref().map(|anchor| anchor.sort_order),
+        );
+        if let (Some(old_sort_order), Some(sort_order)) = sort_orders {
+            if !self.hidden
+                && old_sort_order != sort_order
+                && grid_size == (self.grid.width, self.grid.height)
+                && grid_position == self.grid_position
+            {
+                self.anchor_info = anchor_info;
+                self.send_floating_order();
+                return;
+            }
+        }
+
+        let old_size = self.resize_grid(grid_size);
         self.anchor_info = anchor_info;
         self.grid_position = grid_position;
         self.send_updated_position();
+        self.draw_exposed_columns(old_size);
         self.redraw();
     }
 
+    /// Offsets the window by the given number of cells, for dragging floats around. The grid is
+    /// left as it is and at least one cell of the window stays on a screen of the given size, so
+    /// it can always be dragged back.
+    pub fn move_by(&mut self, (delta_left, delta_top): (f64, f64), screen_size: (u64, u64)) {
+        let (screen_width, screen_height) = screen_size;
+        let (left, top) = self.grid_position;
+        let clamp = |position: f64, size: u64, screen_size: u64| {
+            position.clamp(1.0 - size as f64, screen_size.saturating_sub(1) as f64)
+        };
+
+        let grid_position = (
+            clamp(left + delta_left, self.grid.width, screen_width),
+            clamp(top + delta_top, self.grid.height, screen_height),
+        );
+        self.move_to(grid_position);
+    }
+
+    /// Moves the window without touching its grid, for when only its position changed, so
+    /// nothing has to be drawn again.
+    pub fn move_to(&mut self, grid_position: (f64, f64)) {
+        if grid_position != self.grid_position {
+            self.grid_position = grid_position;
+            self.send_updated_position();
+        }
+    }
+
+    pub fn set_sort_order(&mut self, sort_order: u64) {
+        if let Some(anchor_info) = &mut self.anchor_info {
+            anchor_info.sort_order = sort_order;
+            self.send_floating_order();
+        }
+    }
+
     pub fn resize(&mut self, new_size: (u64, u64)) {
-        self.grid.resize(new_size);
+        let old_size = self.resize_grid(new_size);
         self.send_updated_position();
+        self.draw_exposed_columns(old_size);
         self.redraw();
+        // The bar holds as much of the context as fits into the new width.
+        if self.breadcrumb.is_some() {
+            self.send_breadcrumb();
+        }
+    }
+
+    /// Resizes the grid while keeping what the renderer already drew, which it carries over to
+    /// the resized surface. Only the removed cells are cleared, and only the new rows are marked
+    /// for the next redraw. Returns the previous size.
+    fn resize_grid(&mut self, new_size: (u64, u64)) -> (u64, u64) {
+        let (old_width, old_height) = (self.grid.width, self.grid.height);
+        self.grid.resize(new_size);
+        let (width, height) = (self.grid.width, self.grid.height);
+
+        // The removed cells are cleared while they're still part of the surface. There's no
+        // point when the whole window is cleared anyway.
+        if !self.grid.is_invalidated() {
+            if width < old_width {
+                self.send_command(WindowDrawCommand::ClearRegion {
+                    top: 0,
+                    bottom: old_height.min(height),
+                    left: width,
+                    right: old_width,
+                });
+            }
+            if height < old_height {
+                self.send_command(WindowDrawCommand::ClearRegion {
+                    top: height,
+                    bottom: old_height,
+                    left: 0,
+                    right: old_width,
+                });
+            }
+        }
+
+        self.out_of_bounds_draws = 0;
+        self.mark_urls_stale();
+        self.reset_row_edges();
+        (old_width, old_height)
+    }
+
+    /// Draws the columns a wider grid added to the rows it kept. The new rows and the rows that
+    /// changed since the last redraw are drawn whole by the next one.
+    fn draw_exposed_columns(&self, (old_width, old_height): (u64, u64)) {
+        if self.grid.is_invalidated() || self.grid.width <= old_width {
+            return;
+        }
+
+        for row in 0..old_height.min(self.grid.height) {
+            if self.grid.is_row_dirty(row) {
+                continue;
+            }
+            self.send_command(WindowDrawCommand::DrawLine(
+                self.line_fragments(row, old_width),
+            ));
+        }
     }
 
     fn modify_grid(
@@ -127,37 +693,37 @@ impl Window {
             None => previous_style.clone(),
         };
 
-        // Compute text.
-        let mut text = cell.text;
-        if let Some(times) = cell.repeat {
-            // Repeats of zero times should be ignored, they are mostly useful for terminal Neovim
-            // to distinguish between empty lines and lines ending with spaces.
-            if times == 0 {
-                return;
-            }
-            text = text.repeat(times as usize);
+        // Repeats of zero times should be ignored, they are mostly useful for terminal Neovim
+        // to distinguish between empty lines and lines ending with spaces.
+        let times = cell.repeat.unwrap_or(1);
+        if times == 0 {
+            return;
         }
 
-        // Insert the contents of the cell into the grid.
-        if text.is_empty() {
-            if let Some(cell) = self.grid.get_cell_mut(*column_pos, row_index) {
-                *cell = (text, style.clone());
+        // Insert the contents of the cell into the grid. The text is always the content of a
+        // single cell, so it's stored as is rather than split into graphemes again. That keeps
+        // zero width parts such as emoji variation selectors together with their base character
+        // even when the segmentation disagrees with Neovim's.
+        // Cells past the right edge are dropped, which happens when neovim draws a line for the
+        // grid size from before a resize.
+        let fitting = times.min(self.grid.width.saturating_sub(*column_pos));
+        if fitting > 0 {
+            self.grid.mark_row_dirty(row_index);
+        }
+        let text = limit_combining_marks(&cell.text, self.max_combining_marks).unwrap_or(cell.text);
+        // The right half of a double width character is the empty cell Neovim sends after it.
+        for _ in 0..fitting {
+            if let Some(grid_cell) = self.grid.get_cell_mut(*column_pos, row_index) {
+                *grid_cell = (text.clone(), style.clone());
             }
             *column_pos += 1;
-        } else {
-            for character in text.graphemes(true) {
-                if let Some(cell) = self.grid.get_cell_mut(*column_pos, row_index) {
-                    *cell = (character.to_string(), style.clone());
-                }
-                *column_pos += 1;
-            }
         }
 
         *previous_style = style;
     }
 
     // Build a line fragment for the given row starting from current_start up until the next style
-    // change or double width character.
+    // change or double width character, or the maximum fragment width.
     fn build_line_fragment(&self, row_index: u64, start: u64) -> (u64, LineFragment) {
         let row = self.grid.row(row_index).unwrap();
 
@@ -165,14 +731,44 @@ impl Window {
 
         let mut text = String::new();
         let mut width = 0;
+        // The width and the text up to the last space, where a long run is cut so that ligatures
+        // stay whole.
+        let mut last_break = None;
+        let mut foreground_styles: Vec<(u64, Option<Arc<Style>>)> = Vec::new();
         for possible_end_index in start..self.grid.width {
             let (character, possible_end_style) = &row[possible_end_index as usize];
 
-            // Style doesn't match. Draw what we've got.
-            if style != possible_end_style {
+            // Style doesn't match. Draw what we've got, unless only the foreground differs and
+            // the text is kept together for its ligatures.
+            if style != possible_end_style
+                && !(self.ligatures_across_highlights
+                    && differ_only_in_foreground(style, possible_end_style))
+            {
+                break;
+            }
+
+            // The halves of a double width character stay together past the maximum.
+            if self.max_fragment_width > 0
+                && width >= self.max_fragment_width
+                && !character.is_empty()
+            {
+                if let Some((break_width, break_length)) = last_break {
+                    width = break_width;
+                    text.truncate(break_length);
+                    foreground_styles.retain(|(column, _)| *column < width);
+                }
                 break;
             }
 
+            // A cell with another highlight than the one before it starts drawing in its color.
+            let current_style = match foreground_styles.last() {
+                Some((_, current_style)) => current_style,
+                None => style,
+            };
+            if possible_end_style != current_style {
+                foreground_styles.push((width, possible_end_style.clone()));
+            }
+
             width += 1;
             // The previous character is double width, so send this as its own draw command.
             if character.is_empty() {
@@ -181,6 +777,17 @@ impl Window {
 
             // Add the grid cell to the cells to render.
             text.push_str(character);
+            if character == " " {
+                last_break = Some((width, text.len()));
+            }
+
+            // The renderer counts characters like this as two columns, so one neovim draws in a
+            // single cell ends the fragment to keep the rest of it in place.
+            if cell_width(character) > 1
+                && !matches!(row.get(possible_end_index as usize + 1), Some((next, _)) if next.is_empty())
+            {
+                break;
+            }
         }
 
         let line_fragment = LineFragment {
@@ -189,23 +796,90 @@ impl Window {
             window_top: row_index,
             width,
             style: style.clone(),
+            foreground_styles,
         };
 
         (start + width, line_fragment)
     }
 
-    // Redraw line by calling build_line_fragment starting at 0
-    // until current_start is greater than the grid width and sending the resulting
-    // fragments as a batch.
-    fn redraw_line(&self, row: u64) {
-        let mut current_start = 0;
-        let mut line_fragments = Vec::new();
+    /// The background every cell of the grid is drawn with, row by row.
+    #[cfg(test)]
+    pub fn cell_backgrounds<'a>(
+        &'a self,
+        default_colors: &'a Colors,
+    ) -> impl Iterator<Item = Color4f> + 'a {
+        (0..self.grid.height)
+            .filter_map(|row| self.grid.row(row))
+            .flatten()
+            .map(|(_, style)| match style {
+                Some(style) => style.background(default_colors),
+                None => default_colors.background.unwrap(),
+            })
+    }
+
+    /// Returns the fragment containing the given cell, which spans the whole run of cells drawn
+    /// together with it.
+    pub fn fragment_at(&self, column: u64, row: u64) -> Option<LineFragment> {
+        if column >= self.grid.width || row >= self.grid.height {
+            return None;
+        }
+
+        self.line_fragments(row, 0)
+            .into_iter()
+            .find(|fragment| column < fragment.window_left + fragment.width)
+    }
+
+    // Build the fragments of a line by calling build_line_fragment starting at the first column
+    // until current_start is greater than the grid width, then merge the ones split apart only by
+    // a double width character.
+    fn line_fragments(&self, row: u64, first_column: u64) -> Vec<LineFragment> {
+        let mut current_start = first_column;
+        let mut line_fragments: Vec<LineFragment> = Vec::new();
         while current_start < self.grid.width {
             let (next_start, line_fragment) = self.build_line_fragment(row, current_start);
             current_start = next_start;
+
+            if let Some(previous) = line_fragments.last_mut() {
+                if self.can_merge_fragments(previous, &line_fragment) {
+                    // The highlights of the fragment continue from the one the previous ends with.
+                    let current_style = match previous.foreground_styles.last() {
+                        Some((_, current_style)) => current_style,
+                        None => &previous.style,
+                    };
+                    if *current_style != line_fragment.style {
+                        previous
+                            .foreground_styles
+                            .push((previous.width, line_fragment.style.clone()));
+                    }
+                    previous.foreground_styles.extend(
+                        line_fragment
+                            .foreground_styles
+                            .into_iter()
+                            .map(|(column, style)| (previous.width + column, style)),
+                    );
+                    previous.text.push_str(&line_fragment.text);
+                    previous.width += line_fragment.width;
+                    continue;
+                }
+            }
             line_fragments.push(line_fragment);
         }
-        self.send_command(WindowDrawCommand::DrawLine(line_fragments));
+        line_fragments
+    }
+
+    // The renderer places each character by the columns of the ones before it, so a fragment can
+    // only be continued when its text is exactly as wide as the cells it covers. Fragments cut at
+    // the maximum width stay apart.
+    fn can_merge_fragments(&self, previous: &LineFragment, next: &LineFragment) -> bool {
+        previous.style == next.style
+            && previous.window_left + previous.width == next.window_left
+            && cell_width(&previous.text) == previous.width
+            && (self.max_fragment_width == 0
+                || previous.width + next.width <= self.max_fragment_width)
+    }
+
+    fn redraw_line(&self, row: u64) {
+        self.send_command(WindowDrawCommand::DrawLine(self.line_fragments(row, 0)));
     }
 
     pub fn draw_grid_line(
@@ -215,8 +889,20 @@ impl Window {
         cells: Vec<GridLineCell>,
         defined_styles: &HashMap<u64, Arc<Style>>,
     ) {
+        // Nothing changes without cells, so the line and its neighbours don't need a redraw.
+        if cells.is_empty() {
+            return;
+        }
+
+        // Where the line ends, even when that's past the right edge of the grid.
+        let line_end = line_end(column_start, &cells);
+
         let mut previous_style = None;
         if row < self.grid.height {
+            self.out_of_bounds_draws = 0;
+            self.url_dirty_rows.insert(row);
+            self.highlight_ids
+                .extend(cells.iter().filter_map(|cell| cell.highlight_id));
             let mut column_pos = column_start;
             for cell in cells {
                 self.modify_grid(
@@ -228,19 +914,35 @@ impl Window {
                 );
             }
 
-            // Due to the limitations of the current rendering strategy, some underlines get
-            // clipped by the line below. To mitigate that, we redraw the adjacent lines whenever
-            // an individual line is redrawn. Unfortunately, some clipping still happens.
-            // TODO: figure out how to solve this
-            if row < self.grid.height - 1 {
-                self.redraw_line(row + 1);
-            }
-            self.redraw_line(row);
-            if row > 0 {
-                self.redraw_line(row - 1);
-            }
+            // A line starting at the first column replaces the whole row, otherwise only the
+            // drawn part of it changed.
+            let display_width = &mut self.row_display_widths[row as usize];
+            *display_width = if column_start == 0 {
+                line_end
+            } else {
+                (*display_width).max(line_end)
+            };
+
+            // The line is only drawn on the next redraw, so several changes of it are sent at once.
+            // The renderer keeps underlines inside the cells of their own line, so neither of the
+            // adjacent lines paints over them and only this line needs a redraw.
+        } else {
+            warn!(
+                "Draw command out of bounds: row {} of grid {} with {} columns and {} rows",
+                row, self.grid_id, self.grid.width, self.grid.height
+            );
+            self.out_of_bounds_draws += 1;
+        }
+    }
+
+    /// Returns the grid size Neovim should be asked to resync to when repeated out of bounds
+    /// draws indicate that it disagrees with the size of this grid. Only fires once per desync.
+    pub fn take_resync_request(&mut self) -> Option<(u64, u64)> {
+        if self.out_of_bounds_draws >= DESYNC_DRAW_THRESHOLD {
+            self.out_of_bounds_draws = 0;
+            Some((self.grid.width, self.grid.height))
         } else {
-            warn!("Draw command out of bounds");
+            None
         }
     }
 
@@ -272,6 +974,29 @@ impl Window {
             cols,
         });
 
+        self.url_dirty_rows.extend(top..bottom);
+        let whole_rows = left == 0 && right == self.grid.width;
+        if whole_rows {
+            self.scroll_row_edges(top, bottom, rows);
+        }
+        if whole_rows && cols == 0 && top == 0 && bottom == self.grid.height {
+            self.pending_scroll += rows;
+        }
+
+        // The columns the cells are moved to, a character crossing the left one is split by it.
+        let (moved_left, moved_right) = if cols > 0 {
+            (left, right.saturating_sub(cols as u64))
+        } else {
+            (left + cols.unsigned_abs(), right)
+        };
+        let straddled_left: Vec<_> = if whole_rows {
+            Vec::new()
+        } else {
+            (top..bottom.min(self.grid.height))
+                .map(|row| self.continues_double_width(moved_left, row))
+                .collect()
+        };
+
         // Scrolls must not only translate the rendered texture, but also must move the grid data
         // accordingly so that future renders work correctly.
         for y in y_iter {
@@ -279,6 +1004,19 @@ impl Window {
             let mut cols_left;
             let mut cols_right;
             if dest_y >= 0 && dest_y < self.grid.height as i64 {
+                // The renderer moves what it drew of the row, so a row still to be drawn has to be
+                // drawn where it ends up instead.
+                self.grid
+                    .move_dirty_row(y as u64, dest_y as u64, whole_rows && cols == 0);
+            }
+            if dest_y >= 0 && dest_y < self.grid.height as i64 && cols == 0 {
+                // Vertical scrolls of whole rows, the common case, move all of them at once below.
+                // Those of part of the rows move that part in one block.
+                if !whole_rows {
+                    self.grid
+                        .copy_row_span(y as u64, dest_y as u64, left, right);
+                }
+            } else if dest_y >= 0 && dest_y < self.grid.height as i64 {
                 let x_iter: &mut dyn Iterator<Item = i64> = if cols > 0 {
                     cols_left = (left as i64 + cols)..right as i64;
                     &mut cols_left
@@ -301,65 +1039,667 @@ impl Window {
                 }
             }
         }
+
+        if whole_rows && cols == 0 {
+            self.grid.scroll_rows(top, bottom, rows);
+        }
+        if !whole_rows {
+            for (row, straddled_left) in (top..bottom.min(self.grid.height)).zip(straddled_left) {
+                self.blank_split_double_width(row, moved_left, moved_right, straddled_left);
+            }
+        }
+    }
+
+    // Whether the cell continues a double width character. That's the empty cell Neovim sends
+    // after one, so it continues whatever isn't empty on its left.
+    fn continues_double_width(&self, column: u64, row: u64) -> bool {
+        let text = |column| {
+            self.grid
+                .get_cell(column, row)
+                .map(|(text, _)| text.as_str())
+        };
+        column > 0 && text(column) == Some("") && !matches!(text(column - 1), Some("") | None)
+    }
+
+    // Moving only some columns of a row can take one half of a double width character away from
+    // the other, which can only happen at the edges of the columns moved to. Neither half can be
+    // drawn alone, so they're blanked. `straddled_left` tells whether a character crossed the
+    // left edge before the move.
+    fn blank_split_double_width(&mut self, row: u64, left: u64, right: u64, straddled_left: bool) {
+        let mut orphans = Vec::new();
+        if straddled_left && !self.continues_double_width(left, row) {
+            orphans.push(left - 1);
+        }
+        for column in [left, right] {
+            let empty =
+                matches!(self.grid.get_cell(column, row), Some((text, _)) if text.is_empty());
+            if empty && !self.continues_double_width(column, row) {
+                orphans.push(column);
+            }
+        }
+
+        for column in &orphans {
+            if let Some((text, _)) = self.grid.get_cell_mut(*column, row) {
+                *text = " ".to_owned();
+            }
+        }
+        // The renderer only moved the pixels of the halves.
+        if !orphans.is_empty() {
+            self.grid.mark_row_dirty(row);
+        }
     }
 
     pub fn clear(&mut self) {
         self.grid.clear();
-        self.send_command(WindowDrawCommand::Clear);
+        self.highlight_ids.clear();
+        self.grid.invalidate();
+        self.mark_urls_stale();
+        self.reset_row_edges();
     }
 
-    pub fn redraw(&self) {
-        self.send_command(WindowDrawCommand::Clear);
-        // Draw the lines from the bottom up so that underlines don't get overwritten by the line
-        // below.
-        for row in (0..self.grid.height).rev() {
-            self.redraw_line(row);
+    /// Blanks the cells of `top..bottom` and `left..right` and has the renderer clear just their
+    /// pixels, the rest of the window is kept as it is.
+    pub fn clear_region(&mut self, top: u64, bottom: u64, left: u64, right: u64) {
+        let bottom = bottom.min(self.grid.height);
+        let right = right.min(self.grid.width);
+        if top >= bottom || left >= right {
+            return;
         }
-    }
 
-    pub fn hide(&self) {
-        self.send_command(WindowDrawCommand::Hide);
+        for row in top..bottom {
+            for column in left..right {
+                if let Some(cell) = self.grid.get_cell_mut(column, row) {
+                    *cell = crate::default_cell!();
+                }
+            }
+            // The same as drawing the blanks as a line.
+            let display_width = &mut self.row_display_widths[row as usize];
+            *display_width = if left == 0 {
+                right
+            } else {
+                (*display_width).max(right)
+            };
+        }
+        self.url_dirty_rows.extend(top..bottom);
+        self.send_command(WindowDrawCommand::ClearRegion {
+            top,
+            bottom,
+            left,
+            right,
+        });
     }
 
-    pub fn show(&self) {
-        self.send_command(WindowDrawCommand::Show);
+    /// Whether the cells of a line only blank the rest of its row with the default style, which
+    /// Neovim sends to clear to the end of a line.
+    pub fn clears_line_end(&self, row: u64, column_start: u64, cells: &[GridLineCell]) -> bool {
+        match cells {
+            [GridLineCell {
+                text,
+                highlight_id: None | Some(0),
+                repeat,
+            }] => {
+                row < self.grid.height
+                    && text == " "
+                    && column_start.saturating_add(repeat.unwrap_or(1)) == self.grid.width
+            }
+            _ => false,
+        }
     }
 
-    pub fn close(&self) {
-        self.send_command(WindowDrawCommand::Close);
+    fn reset_row_edges(&mut self) {
+        self.row_display_widths = vec![0; self.grid.height as usize];
+        self.wrapped_rows.clear();
     }
 
-    pub fn update_viewport(&self, scroll_delta: f64) {
-        self.send_command(WindowDrawCommand::Viewport { scroll_delta });
+    fn scroll_row_edges(&mut self, top: u64, bottom: u64, rows: i64) {
+        let bottom = bottom.min(self.grid.height);
+        let source_row = |row: u64| {
+            let source = row as i64 + rows;
+            (source >= top as i64 && source < bottom as i64).then_some(source as u64)
+        };
+
+        let widths = self.row_display_widths.clone();
+        let wrapped_rows = std::mem::take(&mut self.wrapped_rows);
+        for row in top..bottom {
+            let source = source_row(row);
+            self.row_display_widths[row as usize] =
+                source.map_or(0, |source| widths[source as usize]);
+            if matches!(source, Some(source) if wrapped_rows.contains(&source)) {
+                self.wrapped_rows.insert(row);
+            }
+        }
+        self.wrapped_rows.extend(
+            wrapped_rows
+                .into_iter()
+                .filter(|row| *row < top || *row >= bottom),
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::collections::HashMap;
+    pub fn set_row_wraps(&mut self, row: u64, wraps: bool) {
+        if row >= self.grid.height {
+            return;
+        }
 
-    use super::*;
-    use crate::event_aggregator::EVENT_AGGREGATOR;
+        if wraps {
+            self.wrapped_rows.insert(row);
+        } else {
+            self.wrapped_rows.remove(&row);
+        }
+    }
 
-    #[test]
-    fn window_separator_modifies_grid_and_sends_draw_command() {
-        let mut draw_command_receiver = EVENT_AGGREGATOR.register_event::<Vec<DrawCommand>>();
-        let draw_command_batcher = Rc::new(DrawCommandBatcher::new());
+    pub fn row_display_width(&self, row: u64) -> u64 {
+        self.row_display_widths
+            .get(row as usize)
+            .copied()
+            .unwrap_or(0)
+    }
 
-        let mut window = Window::new(
-            1,
-            WindowType::Editor,
-            None,
-            (0.0, 0.0),
-            (114, 64),
-            draw_command_batcher.clone(),
-        );
+    pub fn edge_indicator(&self, row: u64) -> Option<EdgeIndicator> {
+        if self.wrapped_rows.contains(&row) {
+            Some(EdgeIndicator::Wrap)
+        } else if self.row_display_width(row) > self.grid.width {
+            Some(EdgeIndicator::Extends)
+        } else {
+            None
+        }
+    }
 
-        draw_command_batcher.send_batch();
+    /// Sends the rows which need an edge indicator to the renderer if they changed.
+    pub fn update_edge_indicators(&mut self) {
+        let edge_indicators: Vec<_> = (0..self.grid.height)
+            .filter_map(|row| self.edge_indicator(row).map(|indicator| (row, indicator)))
+            .collect();
 
-        draw_command_receiver
-            .try_recv()
-            .expect("Could not receive commands");
+        if edge_indicators != self.edge_indicators {
+            self.edge_indicators = edge_indicators;
+            self.send_command(WindowDrawCommand::EdgeIndicators(
+                self.edge_indicators.clone(),
+            ));
+        }
+    }
+
+    fn indent_widths(&self) -> Vec<u64> {
+        (0..self.grid.height)
+            .map(|row| indent_guides::indent_width(self.grid.row(row).unwrap()))
+            .collect()
+    }
+
+    pub fn update_indent_guides(&mut self) {
+        let settings = SETTINGS.get::<IndentGuideSettings>();
+        let color = settings.color().filter(|_| settings.enabled);
+        let indent_guides = match color {
+            Some(_) => indent_guides::indent_guides(&self.indent_widths(), settings.width),
+            None => Vec::new(),
+        };
+
+        if indent_guides != self.indent_guides {
+            self.indent_guides = indent_guides;
+            self.send_command(WindowDrawCommand::IndentGuides(
+                self.indent_guides.clone(),
+                color.unwrap_or(Color4f::new(0.0, 0.0, 0.0, 0.0)),
+            ));
+        }
+    }
+
+    fn mark_urls_stale(&mut self) {
+        self.url_dirty_rows.extend(0..self.grid.height);
+    }
+
+    /// Rescans the rows changed since the last call for urls and sends the clickable ranges to
+    /// the renderer if they changed.
+    pub fn update_urls(&mut self) {
+        if self.url_dirty_rows.is_empty() {
+            return;
+        }
+
+        let dirty_rows = std::mem::take(&mut self.url_dirty_rows);
+        let mut urls: Vec<UrlRange> = self
+            .urls
+            .iter()
+            .filter(|url| url.row < self.grid.height && !dirty_rows.contains(&url.row))
+            .cloned()
+            .collect();
+        for row in dirty_rows {
+            if let Some(cells) = self.grid.row(row) {
+                urls.extend(detect_urls(row, cells));
+            }
+        }
+        urls.sort_by_key(|url| (url.row, url.start));
+
+        if urls != self.urls {
+            self.urls = urls;
+            self.send_command(WindowDrawCommand::Urls(self.urls.clone()));
+        }
+    }
+
+    /// Sends the rows changed since the last redraw, after clearing the window if the whole grid
+    /// was invalidated.
+    pub fn redraw(&mut self) {
+        // Floats tag what they draw with their place in the stack when the renderer doesn't have
+        // it yet, so it composites it the same way as the position sent with it.
+        if self.grid.is_invalidated() || self.grid.dirty_rows().next().is_some() {
+            self.send_floating_order();
+        }
+        if self.grid.is_invalidated() {
+            self.send_command(WindowDrawCommand::Clear);
+            if self.background.is_some() {
+                self.send_background();
+            }
+        }
+        for row in self.grid.dirty_rows() {
+            self.redraw_line(row);
+        }
+        self.grid.flush_dirty();
+    }
+
+    /// Has the next redraw clear the window and draw every row, for when the renderer lost what
+    /// it drew.
+    pub fn invalidate(&mut self) {
+        // The order may have been lost along with the rest.
+        self.sent_floating_order = None;
+        self.grid.invalidate();
+    }
+
+    pub fn hide(&mut self) {
+        self.hidden = true;
+        self.send_command(WindowDrawCommand::Hide);
+    }
+
+    pub fn show(&mut self) {
+        self.hidden = false;
+        self.send_command(WindowDrawCommand::Show);
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+
+    pub fn viewport_lines(&self) -> Option<&ViewportLines> {
+        self.viewport_lines.as_ref()
+    }
+
+    pub fn close(&self) {
+        self.send_command(WindowDrawCommand::Close);
+    }
+
+    pub fn set_viewport_lines(
+        &mut self,
+        top_line: f64,
+        bottom_line: f64,
+        current_line: f64,
+        line_count: Option<f64>,
+    ) {
+        let scrolled = top_line != self.top_line;
+        self.top_line = top_line;
+        self.line_height = bottom_line - top_line;
+        // Older versions of neovim don't send the line count.
+        self.viewport_lines = line_count.map(|line_count| ViewportLines {
+            top_line,
+            bottom_line,
+            line_count,
+        });
+        // The bottom line is the first line past the viewport.
+        self.cursor_line_offset = if current_line < top_line {
+            Ordering::Less
+        } else if current_line >= bottom_line && bottom_line > top_line {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        };
+        // The diagnostics and the blame move along with their lines.
+        if scrolled {
+            self.update_virtual_text();
+        }
+    }
+
+    /// Sends the scroll percentage to the renderer if it, or where it goes, changed.
+    pub fn update_scroll_percentage(&mut self, settings: &ScrollPercentageSettings) {
+        let scroll_percentage = self.viewport_lines.as_ref().and_then(|viewport| {
+            build_scroll_percentage(viewport, (self.grid.width, self.grid.height), settings)
+        });
+        if scroll_percentage != self.scroll_percentage {
+            self.scroll_percentage = scroll_percentage.clone();
+            self.send_command(WindowDrawCommand::ScrollPercentage(scroll_percentage));
+        }
+    }
+
+    /// Returns the closest visible position if the cursor at the given grid position isn't
+    /// visible, either because it's outside of the grid or its line is scrolled out of view.
+    pub fn clamp_out_of_view(&self, (column, row): (u64, u64)) -> Option<(u64, u64)> {
+        let last_column = self.grid.width.saturating_sub(1);
+        let last_row = self.grid.height.saturating_sub(1);
+        let clamped_row = match self.cursor_line_offset {
+            Ordering::Less => 0,
+            Ordering::Greater => last_row,
+            Ordering::Equal => row.min(last_row),
+        };
+        let clamped = (column.min(last_column), clamped_row);
+
+        (clamped != (column, row) || self.cursor_line_offset != Ordering::Equal).then_some(clamped)
+    }
+
+    /// Sends how far the viewport moved, going by the rows the grid scrolled since the last one
+    /// when neovim doesn't say. Edits like deleting the top line scroll the grid as well, but leave
+    /// the top line where it was, so they don't count. Nothing is sent when it didn't move, so the
+    /// renderer has nothing to animate.
+    pub fn update_viewport(&mut self, scroll_delta: Option<f64>) {
+        let pending_scroll = std::mem::take(&mut self.pending_scroll);
+        let scroll_delta = scroll_delta.unwrap_or_else(|| {
+            if self.sent_top_line != Some(self.top_line) {
+                pending_scroll as f64
+            } else {
+                0.0
+            }
+        });
+        self.sent_top_line = Some(self.top_line);
+        if scroll_delta.abs() > f64::EPSILON {
+            self.send_command(WindowDrawCommand::Viewport {
+                line_height: self.line_height,
+                scroll_delta,
+                animated: self.smooth_scroll.unwrap_or(true),
+            });
+        }
+    }
+
+    pub fn set_smooth_scroll(&mut self, smooth_scroll: Option<bool>) {
+        self.smooth_scroll = smooth_scroll;
+    }
+
+    pub fn set_line_numbers(&self, line_numbers: &LineNumbers) {
+        let settings = SETTINGS.get::<LineNumberSettings>();
+        self.send_command(WindowDrawCommand::LineNumbers(
+            line_numbers.build_fragments(&settings),
+        ));
+    }
+
+    pub fn set_breadcrumb(&mut self, breadcrumb: String) {
+        self.breadcrumb = (!breadcrumb.is_empty()).then_some(breadcrumb);
+        self.send_breadcrumb();
+    }
+
+    fn send_breadcrumb(&self) {
+        let settings = SETTINGS.get::<BreadcrumbSettings>();
+        let breadcrumb = self
+            .breadcrumb
+            .as_ref()
+            .and_then(|breadcrumb| build_breadcrumb(breadcrumb, self.grid.width, &settings));
+        self.send_command(WindowDrawCommand::Breadcrumb(breadcrumb));
+    }
+
+    pub fn set_diagnostics(&mut self, diagnostics: Vec<Diagnostic>) {
+        self.diagnostics = diagnostics;
+        self.update_virtual_text();
+    }
+
+    pub fn set_blame(&mut self, blame: Vec<BlameLine>) {
+        self.blame = blame;
+        self.update_virtual_text();
+    }
+
+    // The row a buffer line, starting at 1, is shown on, counted from the top line of the viewport.
+    fn line_row(&self, line: u64) -> Option<u64> {
+        let row = line as f64 - 1.0 - self.top_line;
+        (row >= 0.0 && row < self.grid.height as f64).then_some(row as u64)
+    }
+
+    // The column after the last non blank cell of the row.
+    fn content_end(&self, row: u64) -> u64 {
+        self.grid
+            .row(row)
+            .and_then(|cells| cells.iter().rposition(|(text, _)| !text.trim().is_empty()))
+            .map_or(0, |column| column as u64 + 1)
+    }
+
+    /// Sends the diagnostics and the blame to the renderer if they or the space left for them
+    /// changed.
+    pub fn update_virtual_text(&mut self) {
+        if !self.blame.is_empty() || !self.blame_fragments.is_empty() {
+            self.update_blame();
+        }
+        if !self.diagnostics.is_empty() || !self.diagnostic_fragments.is_empty() {
+            self.update_diagnostics();
+        }
+    }
+
+    fn update_blame(&mut self) {
+        let settings = SETTINGS.get::<BlameSettings>();
+        let blame_fragments: Vec<_> = self
+            .blame
+            .iter()
+            .filter_map(|blame| {
+                let row = self.line_row(blame.line)?;
+                let content_end = self.content_end(row);
+                build_blame(blame, row, content_end, self.grid.width, &settings)
+            })
+            .collect();
+
+        if blame_fragments != self.blame_fragments {
+            self.blame_fragments = blame_fragments;
+            self.send_command(WindowDrawCommand::Blame(self.blame_fragments.clone()));
+        }
+    }
+
+    fn update_diagnostics(&mut self) {
+        let settings = SETTINGS.get::<DiagnosticSettings>();
+        let diagnostic_fragments: Vec<_> = self
+            .diagnostics
+            .iter()
+            .filter_map(|diagnostic| {
+                let row = self.line_row(diagnostic.line)?;
+                let content_end = self.content_end(row);
+                // Diagnostics end right before the blame of their row, if it has one.
+                let row_end = self
+                    .blame_fragments
+                    .iter()
+                    .find(|blame| blame.window_top == row)
+                    .map_or(self.grid.width, |blame| blame.window_left);
+                build_diagnostic(diagnostic, row, content_end, row_end, &settings)
+            })
+            .collect();
+
+        if diagnostic_fragments != self.diagnostic_fragments {
+            self.diagnostic_fragments = diagnostic_fragments;
+            self.send_command(WindowDrawCommand::Diagnostics(
+                self.diagnostic_fragments.clone(),
+            ));
+        }
+    }
+
+    pub fn set_zoom(&mut self, zoom: f32) {
+        let zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        if (zoom - self.zoom).abs() > f32::EPSILON {
+            self.zoom = zoom;
+            self.send_updated_position();
+            // The renderer builds the surface again at the zoomed font size, so it's drawn anew.
+            self.invalidate();
+            self.redraw();
+        }
+    }
+
+    // Only affects how the window is composited, the grid and its styles are left untouched.
+    pub fn set_brightness(&self, brightness: f32) {
+        self.send_command(WindowDrawCommand::Brightness(brightness.max(0.0)));
+    }
+
+    /// Lets the editor show through the whole window, from 0 for opaque to 100 like 'winblend'.
+    pub fn set_blend(&self, blend: u8) {
+        self.send_command(WindowDrawCommand::Blend(blend.min(100)));
+    }
+
+    /// Keeps the window at whole pixels wherever its grid is placed, which the renderer also does
+    /// while animating it there.
+    pub fn set_snap_to_pixels(&mut self, snap_to_pixels: bool) {
+        if self.snap_to_pixels != snap_to_pixels {
+            self.snap_to_pixels = snap_to_pixels;
+            self.send_updated_position();
+        }
+    }
+
+    /// Has the fragments keep text together across highlights that only change its color, so
+    /// that the ligatures in it are shaped. Every row is drawn again when this changes.
+    pub fn set_ligatures_across_highlights(&mut self, ligatures_across_highlights: bool) {
+        if self.ligatures_across_highlights != ligatures_across_highlights {
+            self.ligatures_across_highlights = ligatures_across_highlights;
+            self.grid.mark_all_rows_dirty();
+        }
+    }
+
+    /// Shows only the given number of rows from the top of the window, or all of them again. The
+    /// grid keeps its size, so the rows below the header are drawn again on expanding.
+    pub fn set_collapsed(&mut self, collapsed_height: Option<u64>) {
+        if self.collapsed_height == collapsed_height {
+            return;
+        }
+
+        let drawn_height = self.collapsed_height.unwrap_or(self.grid.height);
+        self.collapsed_height = collapsed_height;
+        self.send_updated_position();
+        if collapsed_height.is_none() {
+            for row in drawn_height..self.grid.height {
+                self.grid.mark_row_dirty(row);
+            }
+            self.redraw();
+        }
+    }
+
+    pub fn set_mirrored(&self, mirrored: bool) {
+        self.send_command(WindowDrawCommand::Mirrored(mirrored));
+    }
+
+    pub fn set_corner_radius(&self, corner_radius: f32) {
+        self.send_command(WindowDrawCommand::CornerRadius(corner_radius.max(0.0)));
+    }
+
+    /// Sets the fonts the window's glyphs missing from the guifont are looked up in, in order,
+    /// before the system fallback. The lines are drawn again to pick them up.
+    pub fn set_font_fallback(&mut self, fonts: Vec<String>) {
+        self.send_command(WindowDrawCommand::FontFallback(Arc::new(fonts)));
+        self.grid.mark_all_rows_dirty();
+        self.redraw();
+    }
+
+    /// Draws the background beneath the cells with the default background. Those are drawn
+    /// transparent over one, so every line is drawn again.
+    pub fn set_background(&mut self, background: Option<BackgroundKind>) {
+        if self.background != background {
+            self.background = background;
+            self.send_background();
+            self.grid.mark_all_rows_dirty();
+            self.redraw();
+        }
+    }
+
+    fn send_background(&self) {
+        self.send_command(WindowDrawCommand::Background {
+            kind: self.background.clone(),
+        });
+    }
+
+    /// Fits the block to the grid and widens it so that it never cuts through a double width
+    /// character on any of its rows. Every row is covered by the same columns, including the
+    /// virtual space past the end of shorter lines.
+    pub fn visual_block_rect(&self, block: &VisualBlock) -> VisualBlock {
+        let bottom = block.bottom.min(self.grid.height);
+        let top = block.top.min(bottom);
+        let right = block.right.min(self.grid.width);
+        let left = block.left.min(right);
+        let (mut widened_left, mut widened_right) = (left, right);
+
+        // The second half of a double width character is an empty cell.
+        let is_continuation = |column, row| match self.grid.get_cell(column, row) {
+            Some((text, _)) => text.is_empty(),
+            None => false,
+        };
+        for row in top..bottom {
+            if left > 0 && is_continuation(left, row) {
+                widened_left = left - 1;
+            }
+            if right < self.grid.width && is_continuation(right, row) {
+                widened_right = right + 1;
+            }
+        }
+
+        VisualBlock {
+            top,
+            left: widened_left,
+            bottom,
+            right: widened_right,
+        }
+    }
+
+    pub fn set_visual_block(&mut self, block: Option<&VisualBlock>) {
+        if let Some(block) = block {
+            self.visual_block = Some(*block);
+        }
+        let color = SETTINGS.get::<VisualBlockSettings>().color();
+        self.send_command(WindowDrawCommand::VisualBlock(
+            block.and_then(|block| Some((self.visual_block_rect(block), color?))),
+        ));
+    }
+}
+
+// The column after the last cell of a line. Repeats come from neovim and can add up past what a
+// column holds, which only means the line ends far past the right edge.
+fn line_end(column_start: u64, cells: &[GridLineCell]) -> u64 {
+    cells.iter().fold(column_start, |end, cell| {
+        end.saturating_add(cell.repeat.unwrap_or(1))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use nvim_rs::Value;
+
+    use super::*;
+    use crate::{
+        bridge::{parse_redraw_event, RedrawEvent, WindowAnchor},
+        editor::{Colors, ScrollPercentagePosition, TokenKind, UnderlineStyle},
+        event_aggregator::EVENT_AGGREGATOR,
+    };
+
+    fn build_test_window(grid_size: (u64, u64)) -> Window {
+        Window::new(
+            1,
+            WindowType::Editor,
+            None,
+            (0.0, 0.0),
+            grid_size,
+            Rc::new(DrawCommandBatcher::new()),
+            GridLimits::default(),
+        )
+    }
+
+    fn underlined_styles() -> HashMap<u64, Arc<Style>> {
+        let mut style = Style::new(Colors::new(None, None, None));
+        style.underline = Some(UnderlineStyle::Underline);
+
+        let mut defined_styles = HashMap::new();
+        defined_styles.insert(1, Arc::new(style));
+        defined_styles
+    }
+
+    #[test]
+    fn window_separator_modifies_grid_and_sends_draw_command() {
+        let mut draw_command_receiver = EVENT_AGGREGATOR.register_event::<Vec<DrawCommand>>();
+        let draw_command_batcher = Rc::new(DrawCommandBatcher::new());
+
+        let mut window = Window::new(
+            1,
+            WindowType::Editor,
+            None,
+            (0.0, 0.0),
+            (114, 64),
+            draw_command_batcher.clone(),
+            GridLimits::default(),
+        );
+
+        draw_command_batcher.send_batch();
+
+        draw_command_receiver
+            .try_recv()
+            .expect("Could not receive commands");
 
         window.draw_grid_line(
             1,
@@ -374,6 +1714,7 @@ mod tests {
 
         assert_eq!(window.grid.get_cell(70, 1), Some(&("|".to_owned(), None)));
 
+        window.redraw();
         draw_command_batcher.send_batch();
 
         let sent_commands = draw_command_receiver
@@ -381,4 +1722,1739 @@ mod tests {
             .expect("Could not receive commands");
         assert!(!sent_commands.is_empty());
     }
+
+    fn parse_highlight(id: u64, attributes: Vec<(&str, bool)>) -> (u64, Style) {
+        parse_highlight_values(
+            id,
+            attributes
+                .into_iter()
+                .map(|(name, value)| (name, Value::from(value)))
+                .collect(),
+        )
+    }
+
+    fn parse_highlight_values(id: u64, attributes: Vec<(&str, Value)>) -> (u64, Style) {
+        let attributes = attributes
+            .into_iter()
+            .map(|(name, value)| (Value::from(name), value))
+            .collect();
+        let event = Value::Array(vec![
+            Value::from("hl_attr_define"),
+            Value::Array(vec![
+                Value::from(id),
+                Value::Map(attributes),
+                Value::Map(Vec::new()),
+                Value::Array(Vec::new()),
+            ]),
+        ]);
+        match parse_redraw_event(event).unwrap().pop() {
+            Some(RedrawEvent::HighlightAttributesDefine { id, style }) => (id, style),
+            event => panic!("Expected a highlight definition, got {event:?}"),
+        }
+    }
+
+    #[test]
+    fn every_underline_style_from_hl_attr_define_is_kept() {
+        for (attribute, underline) in [
+            ("underline", UnderlineStyle::Underline),
+            ("undercurl", UnderlineStyle::UnderCurl),
+            ("underdouble", UnderlineStyle::UnderDouble),
+            ("underdotted", UnderlineStyle::UnderDot),
+            ("underdashed", UnderlineStyle::UnderDash),
+        ] {
+            let (_, style) = parse_highlight(1, vec![(attribute, true)]);
+            assert_eq!(style.underline, Some(underline), "{attribute}");
+        }
+
+        let (_, style) = parse_highlight(1, vec![("bold", true), ("underline", false)]);
+        assert_eq!(style.underline, None);
+    }
+
+    #[test]
+    fn strikethrough_from_hl_attr_define_reaches_the_line_fragment() {
+        let (id, style) = parse_highlight(7, vec![("strikethrough", true), ("underline", true)]);
+        let mut defined_styles = HashMap::new();
+        defined_styles.insert(id, Arc::new(style));
+
+        let mut window = build_test_window((10, 1));
+        window.draw_grid_line(
+            0,
+            0,
+            vec![GridLineCell {
+                text: "a".to_owned(),
+                highlight_id: Some(7),
+                repeat: None,
+            }],
+            &defined_styles,
+        );
+
+        let style = window.fragment_at(0, 0).unwrap().style.unwrap();
+        assert!(style.strikethrough);
+        assert_eq!(style.underline, Some(UnderlineStyle::Underline));
+    }
+
+    #[test]
+    fn long_runs_are_cut_into_fragments_of_the_maximum_width() {
+        let fragments = |window: &Window| -> Vec<(u64, String)> {
+            let mut fragments = Vec::new();
+            let mut start = 0;
+            while start < window.grid.width {
+                let (next_start, fragment) = window.build_line_fragment(0, start);
+                fragments.push((fragment.width, fragment.text));
+                start = next_start;
+            }
+            fragments
+        };
+        let mut window = build_test_window((25, 1));
+        window.max_fragment_width = 10;
+        window.draw_grid_line(
+            0,
+            0,
+            vec![GridLineCell {
+                text: "a".to_owned(),
+                highlight_id: None,
+                repeat: Some(25),
+            }],
+            &HashMap::new(),
+        );
+        assert_eq!(
+            fragments(&window),
+            vec![
+                (10, "a".repeat(10)),
+                (10, "a".repeat(10)),
+                (5, "a".repeat(5))
+            ]
+        );
+
+        // Runs are cut after the last space before the maximum when there is one, and never
+        // between the halves of a double width character.
+        let mut window = build_test_window((12, 1));
+        window.max_fragment_width = 6;
+        window.draw_grid_line(
+            0,
+            0,
+            ["a", "=", ">", " ", "b", "=", "=", "c", "d", "e", "日", ""]
+                .into_iter()
+                .map(|text| GridLineCell {
+                    text: text.to_owned(),
+                    highlight_id: None,
+                    repeat: None,
+                })
+                .collect(),
+            &HashMap::new(),
+        );
+        assert_eq!(
+            fragments(&window),
+            vec![
+                (4, "a=> ".to_owned()),
+                (6, "b==cde".to_owned()),
+                (2, "日".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn fragments_split_by_a_double_width_character_are_merged() {
+        let mut window = build_test_window((8, 1));
+        window.draw_grid_line(
+            0,
+            0,
+            ["a", "b", "日", "", "c", "d", "e", "f"]
+                .into_iter()
+                .map(|text| GridLineCell {
+                    text: text.to_owned(),
+                    highlight_id: None,
+                    repeat: None,
+                })
+                .collect(),
+            &HashMap::new(),
+        );
+
+        let fragments = window.line_fragments(0, 0);
+        assert_eq!(fragments.len(), 1);
+        assert_eq!(fragments[0].text, "ab日cdef");
+        assert_eq!(fragments[0].width, 8);
+        assert_eq!(window.fragment_at(5, 0), Some(fragments[0].clone()));
+
+        // A character the renderer takes for double width but neovim draws in one cell still
+        // splits the line, so the text after it isn't drawn a column too far.
+        window.draw_grid_line(
+            0,
+            3,
+            vec![GridLineCell {
+                text: "x".to_owned(),
+                highlight_id: None,
+                repeat: None,
+            }],
+            &HashMap::new(),
+        );
+        assert_eq!(
+            window
+                .line_fragments(0, 0)
+                .into_iter()
+                .map(|fragment| fragment.text)
+                .collect::<Vec<_>>(),
+            vec!["ab日".to_owned(), "xcdef".to_owned()]
+        );
+    }
+
+    #[test]
+    fn ligature_stays_in_one_fragment_across_foreground_highlights() {
+        let style = |foreground, background| {
+            Arc::new(Style::new(Colors::new(Some(foreground), background, None)))
+        };
+        let red = Color4f::new(1.0, 0.0, 0.0, 1.0);
+        let blue = Color4f::new(0.0, 0.0, 1.0, 1.0);
+        let mut defined_styles = HashMap::new();
+        defined_styles.insert(1, style(red, None));
+        defined_styles.insert(2, style(blue, None));
+        defined_styles.insert(3, style(blue, Some(red)));
+        let draw = |window: &mut Window, highlights: [u64; 3]| {
+            window.draw_grid_line(
+                0,
+                0,
+                ["a", "=", ">"]
+                    .into_iter()
+                    .zip(highlights)
+                    .map(|(text, highlight_id)| GridLineCell {
+                        text: text.to_owned(),
+                        highlight_id: Some(highlight_id),
+                        repeat: None,
+                    })
+                    .collect(),
+                &defined_styles,
+            );
+        };
+        let texts = |window: &Window| -> Vec<_> {
+            window
+                .line_fragments(0, 0)
+                .into_iter()
+                .map(|fragment| fragment.text)
+                .collect()
+        };
+
+        let mut window = build_test_window((3, 1));
+        draw(&mut window, [1, 1, 2]);
+        assert_eq!(texts(&window), vec!["a=", ">"]);
+
+        window.set_ligatures_across_highlights(true);
+        let fragments = window.line_fragments(0, 0);
+        assert_eq!(fragments.len(), 1);
+        assert_eq!(fragments[0].text, "a=>");
+        assert_eq!(fragments[0].style, Some(defined_styles[&1].clone()));
+        assert_eq!(
+            fragments[0].foreground_styles,
+            vec![(2, Some(defined_styles[&2].clone()))]
+        );
+
+        // The color of the fragment changes back where the first highlight comes in again.
+        draw(&mut window, [1, 2, 1]);
+        assert_eq!(
+            window.line_fragments(0, 0)[0].foreground_styles,
+            vec![
+                (1, Some(defined_styles[&2].clone())),
+                (2, Some(defined_styles[&1].clone()))
+            ]
+        );
+
+        // A different background still splits the text.
+        draw(&mut window, [1, 1, 3]);
+        assert_eq!(texts(&window), vec!["a=", ">"]);
+    }
+
+    #[test]
+    fn blend_from_hl_attr_define_reaches_the_line_fragment() {
+        let (id, style) = parse_highlight_values(4, vec![("blend", Value::from(30))]);
+        let mut defined_styles = HashMap::new();
+        defined_styles.insert(id, Arc::new(style));
+
+        let mut window = build_test_window((10, 1));
+        window.draw_grid_line(
+            0,
+            0,
+            vec![GridLineCell {
+                text: "a".to_owned(),
+                highlight_id: Some(4),
+                repeat: None,
+            }],
+            &defined_styles,
+        );
+
+        let style = window.fragment_at(0, 0).unwrap().style.unwrap();
+        assert_eq!(style.blend, 30);
+        assert!((style.background_opacity() - 0.7).abs() < f32::EPSILON);
+
+        // Anything past fully transparent is taken as that.
+        let (_, style) = parse_highlight_values(5, vec![("blend", Value::from(250))]);
+        assert_eq!(style.blend, 100);
+        assert_eq!(style.background_opacity(), 0.0);
+    }
+
+    #[test]
+    fn reverse_from_hl_attr_define_swaps_the_colors_of_the_line_fragment() {
+        let red = Color4f::new(1.0, 0.0, 0.0, 1.0);
+        let default_colors = Colors {
+            foreground: Some(Color4f::new(1.0, 1.0, 1.0, 1.0)),
+            background: Some(Color4f::new(0.0, 0.0, 0.0, 1.0)),
+            special: None,
+        };
+        let (id, mut style) = parse_highlight(3, vec![("reverse", true)]);
+        style.colors.foreground = Some(red);
+        let mut defined_styles = HashMap::new();
+        defined_styles.insert(id, Arc::new(style));
+
+        let mut window = build_test_window((10, 1));
+        window.draw_grid_line(
+            0,
+            0,
+            vec![GridLineCell {
+                text: "a".to_owned(),
+                highlight_id: Some(3),
+                repeat: None,
+            }],
+            &defined_styles,
+        );
+
+        // The missing background falls back to the default one before being swapped.
+        let style = window.fragment_at(0, 0).unwrap().style.unwrap();
+        assert!(style.reverse);
+        assert_eq!(
+            style.foreground(&default_colors),
+            Color4f::new(0.0, 0.0, 0.0, 1.0)
+        );
+        assert_eq!(style.background(&default_colors), red);
+    }
+
+    #[test]
+    fn stacked_combining_marks_are_cut_off_at_the_maximum() {
+        let mut window = build_test_window((3, 1));
+        let zalgo = format!("a{}", "\u{301}\u{316}".repeat(100));
+        window.draw_grid_line(
+            0,
+            0,
+            [zalgo.as_str(), "e\u{301}", "\u{1F44D}\u{1F3FD}"]
+                .into_iter()
+                .map(|text| GridLineCell {
+                    text: text.to_owned(),
+                    highlight_id: None,
+                    repeat: None,
+                })
+                .collect(),
+            &HashMap::new(),
+        );
+
+        assert_eq!(
+            window.grid.get_cell(0, 0).unwrap().0,
+            format!("a{}", "\u{301}\u{316}".repeat(8))
+        );
+        assert_eq!(window.grid.get_cell(1, 0).unwrap().0, "e\u{301}");
+        assert_eq!(window.grid.get_cell(2, 0).unwrap().0, "\u{1F44D}\u{1F3FD}");
+    }
+
+    #[test]
+    fn line_text_skips_the_halves_of_double_width_characters() {
+        let mut window = build_test_window((6, 2));
+        window.draw_grid_line(
+            0,
+            0,
+            ["a", "日", "", "b", " ", " "]
+                .into_iter()
+                .map(|text| GridLineCell {
+                    text: text.to_owned(),
+                    highlight_id: None,
+                    repeat: None,
+                })
+                .collect(),
+            &HashMap::new(),
+        );
+
+        assert_eq!(window.get_line_text(0), Some("a日b  ".to_owned()));
+        assert_eq!(window.get_line_text(1), Some("      ".to_owned()));
+        assert_eq!(window.get_line_text(2), None);
+    }
+
+    #[test]
+    fn wide_emoji_sequences_keep_the_cells_neovim_sends_after_them() {
+        let mut window = build_test_window((10, 1));
+        let cells = |texts: &[&str]| {
+            texts
+                .iter()
+                .map(|text| GridLineCell {
+                    text: (*text).to_owned(),
+                    highlight_id: None,
+                    repeat: None,
+                })
+                .collect()
+        };
+        window.draw_grid_line(0, 0, cells(&["a"; 10]), &HashMap::new());
+
+        // The family and the flag come with their right half, the cells after that are left alone.
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        let flag = "\u{1F1EF}\u{1F1F5}";
+        window.draw_grid_line(0, 1, cells(&[family, ""]), &HashMap::new());
+        window.draw_grid_line(0, 5, cells(&[flag, ""]), &HashMap::new());
+        let texts: Vec<_> = window
+            .grid
+            .row(0)
+            .unwrap()
+            .iter()
+            .map(|(text, _)| text.as_str())
+            .collect();
+        assert_eq!(
+            texts,
+            vec!["a", family, "", "a", "a", flag, "", "a", "a", "a"]
+        );
+        assert_eq!(
+            window.get_cursor_grid_cell(1, 0),
+            (family.to_owned(), None, true, false)
+        );
+
+        // Redrawing just the flag doesn't touch the cell after it.
+        window.draw_grid_line(0, 5, cells(&[flag]), &HashMap::new());
+        assert_eq!(window.grid.get_cell(6, 0).unwrap().0, "");
+        window.draw_grid_line(0, 6, cells(&["b"]), &HashMap::new());
+        assert_eq!(window.grid.get_cell(6, 0).unwrap().0, "b");
+    }
+
+    #[test]
+    fn cursor_cell_keeps_combining_marks_with_their_base() {
+        let mut window = build_test_window((10, 1));
+        window.draw_grid_line(
+            0,
+            0,
+            ["e\u{301}", "a", "\u{1F44D}\u{1F3FD}", ""]
+                .into_iter()
+                .map(|text| GridLineCell {
+                    text: text.to_owned(),
+                    highlight_id: None,
+                    repeat: None,
+                })
+                .collect(),
+            &HashMap::new(),
+        );
+
+        assert_eq!(
+            window.get_cursor_grid_cell(0, 0),
+            ("e\u{301}".to_owned(), None, false, false)
+        );
+        assert_eq!(
+            window.get_cursor_grid_cell(2, 0),
+            ("\u{1F44D}\u{1F3FD}".to_owned(), None, true, false)
+        );
+    }
+
+    #[test]
+    fn cursor_on_either_half_of_a_double_width_character_covers_it() {
+        let mut window = build_test_window((5, 1));
+        window.draw_grid_line(
+            0,
+            0,
+            ["\u{3042}", "", "a", "\u{3044}", ""]
+                .into_iter()
+                .map(|text| GridLineCell {
+                    text: text.to_owned(),
+                    highlight_id: None,
+                    repeat: None,
+                })
+                .collect(),
+            &HashMap::new(),
+        );
+
+        assert_eq!(
+            window.get_cursor_grid_cell(0, 0),
+            ("\u{3042}".to_owned(), None, true, false)
+        );
+        assert_eq!(
+            window.get_cursor_grid_cell(1, 0),
+            ("\u{3042}".to_owned(), None, true, true)
+        );
+        assert_eq!(
+            window.get_cursor_grid_cell(2, 0),
+            ("a".to_owned(), None, false, false)
+        );
+        // The right half is the last column of the grid.
+        assert_eq!(
+            window.get_cursor_grid_cell(3, 0),
+            ("\u{3044}".to_owned(), None, true, false)
+        );
+        assert_eq!(
+            window.get_cursor_grid_cell(4, 0),
+            ("\u{3044}".to_owned(), None, true, true)
+        );
+    }
+
+    #[test]
+    fn full_width_cursor_covers_the_blank_cells_of_a_tab() {
+        let mut window = build_test_window((20, 1));
+        window.draw_grid_line(
+            0,
+            0,
+            vec![
+                GridLineCell {
+                    text: "a".to_owned(),
+                    highlight_id: None,
+                    repeat: None,
+                },
+                GridLineCell {
+                    text: " ".to_owned(),
+                    highlight_id: None,
+                    repeat: Some(4),
+                },
+                GridLineCell {
+                    text: "b".to_owned(),
+                    highlight_id: None,
+                    repeat: None,
+                },
+                GridLineCell {
+                    text: " ".to_owned(),
+                    highlight_id: None,
+                    repeat: Some(10),
+                },
+                GridLineCell {
+                    text: "c".to_owned(),
+                    highlight_id: None,
+                    repeat: None,
+                },
+            ],
+            &HashMap::new(),
+        );
+
+        assert_eq!(window.cursor_span(3, 0, CursorTabWidth::Full), (1, 4));
+        assert_eq!(window.cursor_span(3, 0, CursorTabWidth::Cell), (3, 1));
+        assert_eq!(window.cursor_span(0, 0, CursorTabWidth::Full), (0, 1));
+        // Only the tab stop under the cursor is covered out of a longer run.
+        assert_eq!(window.cursor_span(15, 0, CursorTabWidth::Full), (8, 8));
+        assert_eq!(window.cursor_span(7, 0, CursorTabWidth::Full), (6, 2));
+        // The blank end of the row isn't a tab.
+        assert_eq!(window.cursor_span(18, 0, CursorTabWidth::Full), (18, 1));
+    }
+
+    #[test]
+    fn pixels_map_to_the_cells_of_the_window() {
+        let mut window = build_test_window((10, 3));
+        window.position(None, (10, 3), (2.0, 1.0));
+        window.draw_grid_line(
+            0,
+            0,
+            ["a", "\u{1F44D}", ""]
+                .into_iter()
+                .map(|text| GridLineCell {
+                    text: text.to_owned(),
+                    highlight_id: None,
+                    repeat: None,
+                })
+                .collect(),
+            &HashMap::new(),
+        );
+        let cell_size = (8.0, 16.0);
+
+        assert_eq!(
+            window.pixel_to_grid_cell(16.0, 16.0, cell_size),
+            Some((0, 0))
+        );
+        assert_eq!(
+            window.pixel_to_grid_cell(33.0, 47.0, cell_size),
+            Some((2, 1))
+        );
+        assert_eq!(
+            window.pixel_to_grid_cell(95.9, 63.9, cell_size),
+            Some((9, 2))
+        );
+        // Both halves of the double width character map onto its first cell.
+        assert_eq!(
+            window.pixel_to_grid_cell(28.0, 24.0, cell_size),
+            Some((1, 0))
+        );
+        assert_eq!(
+            window.pixel_to_grid_cell(36.0, 24.0, cell_size),
+            Some((1, 0))
+        );
+
+        assert_eq!(window.pixel_to_grid_cell(15.9, 20.0, cell_size), None);
+        assert_eq!(window.pixel_to_grid_cell(20.0, 15.9, cell_size), None);
+        assert_eq!(window.pixel_to_grid_cell(96.0, 20.0, cell_size), None);
+        assert_eq!(window.pixel_to_grid_cell(20.0, 64.0, cell_size), None);
+    }
+
+    #[test]
+    fn moving_a_float_keeps_its_grid_and_part_of_it_on_screen() {
+        let mut window = build_test_window((10, 5));
+        window.draw_grid_line(
+            0,
+            0,
+            vec![GridLineCell {
+                text: "a".to_owned(),
+                highlight_id: None,
+                repeat: None,
+            }],
+            &HashMap::new(),
+        );
+        window.draw_command_batcher.drain();
+
+        window.move_by((3.0, 2.0), (80, 24));
+        assert_eq!(window.get_grid_position(), (3.0, 2.0));
+        let commands = window.draw_command_batcher.drain();
+        assert!(matches!(
+            commands.as_slice(),
+            [DrawCommand::Window {
+                command: WindowDrawCommand::Position {
+                    grid_position: (left, top),
+                    ..
+                },
+                ..
+            }] if (*left, *top) == (3.0, 2.0)
+        ));
+        assert_eq!(window.grid.get_cell(0, 0).unwrap().0, "a");
+
+        window.move_by((-100.0, 100.0), (80, 24));
+        assert_eq!(window.get_grid_position(), (-9.0, 23.0));
+    }
+
+    fn resized_window_commands(old_size: (u64, u64), new_size: (u64, u64)) -> Vec<String> {
+        let mut window = build_test_window(old_size);
+        for row in 0..old_size.1 {
+            window.draw_grid_line(
+                row,
+                0,
+                vec![GridLineCell {
+                    text: "a".to_owned(),
+                    highlight_id: None,
+                    repeat: Some(old_size.0),
+                }],
+                &HashMap::new(),
+            );
+        }
+        window.redraw();
+        window.draw_command_batcher.drain();
+
+        window.resize(new_size);
+        window
+            .draw_command_batcher
+            .drain()
+            .into_iter()
+            .map(|command| match command {
+                DrawCommand::Window {
+                    command: WindowDrawCommand::Position { grid_size, .. },
+                    ..
+                } => format!("position {}x{}", grid_size.0, grid_size.1),
+                DrawCommand::Window {
+                    command: WindowDrawCommand::DrawLine(fragments),
+                    ..
+                } => {
+                    let columns: Vec<_> = fragments
+                        .iter()
+                        .map(|fragment| {
+                            format!(
+                                "{}..{}",
+                                fragment.window_left,
+                                fragment.window_left + fragment.width
+                            )
+                        })
+                        .collect();
+                    format!("line {} {}", fragments[0].window_top, columns.join(" "))
+                }
+                DrawCommand::Window {
+                    command:
+                        WindowDrawCommand::ClearRegion {
+                            top,
+                            bottom,
+                            left,
+                            right,
+                        },
+                    ..
+                } => format!("clear {top}..{bottom} {left}..{right}"),
+                command => format!("{command:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn growing_window_draws_only_the_new_cells() {
+        assert_eq!(
+            resized_window_commands((4, 3), (6, 4)),
+            vec![
+                "position 6x4",
+                "line 0 4..6",
+                "line 1 4..6",
+                "line 2 4..6",
+                "line 3 0..6",
+            ]
+        );
+    }
+
+    #[test]
+    fn shrinking_window_only_clears_the_removed_cells() {
+        assert_eq!(
+            resized_window_commands((4, 3), (2, 2)),
+            vec!["clear 0..2 2..4", "clear 2..3 0..4", "position 2x2"]
+        );
+    }
+
+    #[test]
+    fn wider_and_shorter_window_clears_rows_and_draws_columns() {
+        assert_eq!(
+            resized_window_commands((4, 3), (6, 2)),
+            vec![
+                "clear 2..3 0..4",
+                "position 6x2",
+                "line 0 4..6",
+                "line 1 4..6",
+            ]
+        );
+    }
+
+    #[test]
+    fn collapsed_window_sends_its_header_and_redraws_the_rest_on_expanding() {
+        let mut window = build_test_window((4, 3));
+        window.redraw();
+        window.draw_command_batcher.drain();
+
+        window.set_collapsed(Some(1));
+        let commands = window.draw_command_batcher.drain();
+        assert!(matches!(
+            commands.as_slice(),
+            [DrawCommand::Window {
+                command: WindowDrawCommand::Position {
+                    grid_size: (4, 1),
+                    ..
+                },
+                ..
+            }]
+        ));
+        assert_eq!(window.get_height(), 3);
+
+        window.set_collapsed(None);
+        let rows: Vec<_> = window
+            .draw_command_batcher
+            .drain()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::Window {
+                    command: WindowDrawCommand::DrawLine(fragments),
+                    ..
+                } => Some(fragments[0].window_top),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(rows, vec![1, 2]);
+    }
+
+    #[test]
+    fn window_with_anchor_info_is_floating_and_tags_its_redraws() {
+        let window = build_test_window((4, 2));
+        assert!(!window.is_floating());
+        assert_eq!(window.floating_order(), None);
+
+        let mut window = Window::new(
+            2,
+            WindowType::Editor,
+            Some(AnchorInfo {
+                anchor_grid_id: 1,
+                anchor_type: WindowAnchor::NorthWest,
+                anchor_left: 0.0,
+                anchor_top: 0.0,
+                sort_order: 7,
+            }),
+            (0.0, 0.0),
+            (4, 2),
+            Rc::new(DrawCommandBatcher::new()),
+            GridLimits::default(),
+        );
+        assert!(window.is_floating());
+        assert_eq!(window.floating_order(), Some(7));
+
+        window.draw_command_batcher.drain();
+        window.invalidate();
+        window.redraw();
+        let commands = window.draw_command_batcher.drain();
+        assert!(matches!(
+            commands.as_slice(),
+            [
+                DrawCommand::Window {
+                    command: WindowDrawCommand::Reorder {
+                        floating_order: Some(7),
+                    },
+                    ..
+                },
+                DrawCommand::Window {
+                    command: WindowDrawCommand::Clear,
+                    ..
+                },
+                ..
+            ]
+        ));
+
+        // Nothing to draw, nothing to tag.
+        window.redraw();
+        assert!(window.draw_command_batcher.drain().is_empty());
+
+        // The renderer has the order already, so later redraws aren't tagged again.
+        window.draw_grid_line(
+            0,
+            0,
+            vec![GridLineCell {
+                text: "a".to_owned(),
+                highlight_id: None,
+                repeat: None,
+            }],
+            &HashMap::new(),
+        );
+        window.redraw();
+        assert!(!window
+            .draw_command_batcher
+            .drain()
+            .iter()
+            .any(|command| matches!(
+                command,
+                DrawCommand::Window {
+                    command: WindowDrawCommand::Reorder { .. },
+                    ..
+                }
+            )));
+    }
+
+    #[test]
+    fn position_commands_carry_the_window_type() {
+        let mut window = Window::new(
+            2,
+            WindowType::Message,
+            None,
+            (0.0, 20.0),
+            (80, 3),
+            Rc::new(DrawCommandBatcher::new()),
+            GridLimits::default(),
+        );
+        window.position(None, (80, 4), (0.0, 19.0));
+
+        let window_types: Vec<_> = window
+            .draw_command_batcher
+            .drain()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::Window {
+                    command: WindowDrawCommand::Position { window_type, .. },
+                    ..
+                } => Some(window_type),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(window_types, vec![WindowType::Message, WindowType::Message]);
+    }
+
+    #[test]
+    fn restacking_a_float_in_place_only_reorders_it() {
+        let mut window = build_test_window((10, 5));
+        let anchor = |sort_order| AnchorInfo {
+            anchor_grid_id: 1,
+            anchor_type: WindowAnchor::NorthWest,
+            anchor_left: 2.0,
+            anchor_top: 1.0,
+            sort_order,
+        };
+        window.position(Some(anchor(1)), (10, 5), (2.0, 1.0));
+        window.draw_command_batcher.drain();
+
+        window.position(Some(anchor(7)), (10, 5), (2.0, 1.0));
+        let commands = window.draw_command_batcher.drain();
+        assert!(matches!(
+            commands.as_slice(),
+            [DrawCommand::Window {
+                command: WindowDrawCommand::Reorder {
+                    floating_order: Some(7)
+                },
+                ..
+            }]
+        ));
+
+        // Moving it lays it out again, but the grid is the same so nothing is drawn.
+        window.position(Some(anchor(8)), (10, 5), (3.0, 1.0));
+        let commands = window.draw_command_batcher.drain();
+        assert!(matches!(
+            commands.as_slice(),
+            [DrawCommand::Window {
+                command: WindowDrawCommand::Position {
+                    grid_position: (3.0, 1.0),
+                    ..
+                },
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn empty_grid_line_sends_no_draw_commands() {
+        let mut window = build_test_window((10, 3));
+        let defined_styles = underlined_styles();
+        window.draw_grid_line(
+            1,
+            0,
+            vec![GridLineCell {
+                text: "a".to_owned(),
+                highlight_id: Some(1),
+                repeat: None,
+            }],
+            &defined_styles,
+        );
+        window.draw_command_batcher.drain();
+
+        window.draw_grid_line(1, 0, Vec::new(), &defined_styles);
+        assert!(window.draw_command_batcher.drain().is_empty());
+        assert_eq!(window.grid.get_cell(0, 1).unwrap().0, "a");
+    }
+
+    #[test]
+    fn underlined_trailing_blanks_are_part_of_the_fragment() {
+        let mut window = build_test_window((10, 1));
+        let defined_styles = underlined_styles();
+
+        window.draw_grid_line(
+            0,
+            0,
+            vec![
+                GridLineCell {
+                    text: "a".to_owned(),
+                    highlight_id: Some(1),
+                    repeat: None,
+                },
+                GridLineCell {
+                    text: " ".to_owned(),
+                    highlight_id: None,
+                    repeat: Some(4),
+                },
+            ],
+            &defined_styles,
+        );
+
+        let (next_start, fragment) = window.build_line_fragment(0, 0);
+        assert_eq!(next_start, 5);
+        assert_eq!(fragment.text, "a    ");
+        assert_eq!(fragment.window_left, 0);
+        assert_eq!(fragment.width, 5);
+        assert_eq!(fragment.style, defined_styles.get(&1).cloned());
+    }
+
+    fn cells(texts: &[&str]) -> Vec<GridLineCell> {
+        texts
+            .iter()
+            .map(|text| GridLineCell {
+                text: (*text).to_owned(),
+                highlight_id: None,
+                repeat: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn line_past_the_right_edge_is_clipped() {
+        let mut window = build_test_window((5, 2));
+        window.draw_grid_line(1, 3, cells(&["a", "b", "c", "d"]), &HashMap::new());
+        window.draw_grid_line(
+            0,
+            2,
+            vec![GridLineCell {
+                text: "x".to_owned(),
+                highlight_id: None,
+                repeat: Some(u64::MAX / 2),
+            }],
+            &HashMap::new(),
+        );
+
+        assert_eq!(window.get_line_text(0), Some("  xxx".to_owned()));
+        assert_eq!(window.get_line_text(1), Some("   ab".to_owned()));
+        assert_eq!(window.edge_indicator(1), Some(EdgeIndicator::Extends));
+        // Only a row outside of the grid counts towards a resync.
+        assert_eq!(window.out_of_bounds_draws, 0);
+    }
+
+    #[test]
+    fn huge_repeats_past_the_right_edge_dont_overflow() {
+        let mut window = build_test_window((5, 1));
+        let huge = |text: &str| GridLineCell {
+            text: text.to_owned(),
+            highlight_id: None,
+            repeat: Some(u64::MAX),
+        };
+        window.draw_grid_line(0, 1, vec![huge("x"), huge("y")], &HashMap::new());
+
+        assert_eq!(window.get_line_text(0), Some(" xxxx".to_owned()));
+        assert_eq!(window.row_display_width(0), u64::MAX);
+        assert!(!window.clears_line_end(0, 1, &[huge(" ")]));
+    }
+
+    #[test]
+    fn line_filling_the_whole_row_is_drawn_completely() {
+        let mut window = build_test_window((5, 1));
+        window.draw_grid_line(0, 0, cells(&["a", "b", "c", "d", "e"]), &HashMap::new());
+
+        assert_eq!(window.get_line_text(0), Some("abcde".to_owned()));
+        assert_eq!(window.row_display_width(0), 5);
+        assert_eq!(window.edge_indicator(0), None);
+    }
+
+    #[test]
+    fn repeated_out_of_bounds_draws_request_a_resync() {
+        let mut window = build_test_window((10, 2));
+        let out_of_bounds_line = || {
+            vec![GridLineCell {
+                text: "a".to_owned(),
+                highlight_id: None,
+                repeat: None,
+            }]
+        };
+
+        for _ in 0..DESYNC_DRAW_THRESHOLD - 1 {
+            window.draw_grid_line(5, 0, out_of_bounds_line(), &HashMap::new());
+            assert_eq!(window.take_resync_request(), None);
+        }
+
+        window.draw_grid_line(5, 0, out_of_bounds_line(), &HashMap::new());
+        assert_eq!(window.take_resync_request(), Some((10, 2)));
+        // The request is only made once until the grid desyncs again.
+        assert_eq!(window.take_resync_request(), None);
+    }
+
+    #[test]
+    fn in_bounds_draw_resets_the_desync_counter() {
+        let mut window = build_test_window((10, 2));
+        let line = || {
+            vec![GridLineCell {
+                text: "a".to_owned(),
+                highlight_id: None,
+                repeat: None,
+            }]
+        };
+
+        for _ in 0..DESYNC_DRAW_THRESHOLD - 1 {
+            window.draw_grid_line(5, 0, line(), &HashMap::new());
+        }
+        window.draw_grid_line(0, 0, line(), &HashMap::new());
+        window.draw_grid_line(5, 0, line(), &HashMap::new());
+
+        assert_eq!(window.take_resync_request(), None);
+    }
+
+    fn redrawn_rows(window: &mut Window) -> Vec<u64> {
+        window.redraw();
+        window
+            .draw_command_batcher
+            .drain()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::Window {
+                    command: WindowDrawCommand::DrawLine(line_fragments),
+                    ..
+                } => line_fragments.first().map(|fragment| fragment.window_top),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn grid_line_redraws_only_its_own_row_next_to_underlines() {
+        let mut window = build_test_window((10, 5));
+        let line = |highlight_id| {
+            vec![GridLineCell {
+                text: "a".to_owned(),
+                highlight_id: Some(highlight_id),
+                repeat: None,
+            }]
+        };
+        let mut defined_styles = underlined_styles();
+        defined_styles.insert(2, Arc::new(Style::new(Colors::new(None, None, None))));
+
+        window.draw_command_batcher.drain();
+
+        // An underlined line and a plain line right below it each send a single line.
+        window.draw_grid_line(1, 0, line(1), &defined_styles);
+        assert_eq!(window.grid.dirty_rows().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(redrawn_rows(&mut window), vec![1]);
+        window.draw_grid_line(2, 0, line(2), &defined_styles);
+        assert_eq!(window.grid.dirty_rows().collect::<Vec<_>>(), vec![2]);
+        assert_eq!(redrawn_rows(&mut window), vec![2]);
+    }
+
+    #[test]
+    fn typing_into_a_row_redraws_it_once_per_flush() {
+        let mut window = build_test_window((10, 5));
+        let key = |text: &str| {
+            vec![GridLineCell {
+                text: text.to_owned(),
+                highlight_id: None,
+                repeat: None,
+            }]
+        };
+        window.draw_command_batcher.drain();
+
+        for (column, text) in ["a", "b", "c"].into_iter().enumerate() {
+            window.draw_grid_line(3, column as u64, key(text), &HashMap::new());
+        }
+        // Nothing is sent until the redraw, which doesn't clear the window.
+        assert!(window.draw_command_batcher.drain().is_empty());
+        window.redraw();
+        let commands = window.draw_command_batcher.drain();
+        assert!(!commands.iter().any(|command| matches!(
+            command,
+            DrawCommand::Window {
+                command: WindowDrawCommand::Clear,
+                ..
+            }
+        )));
+        assert_eq!(commands.len(), 1);
+
+        // Redrawing again, like after a move, has nothing left to send.
+        assert!(redrawn_rows(&mut window).is_empty());
+    }
+
+    #[test]
+    fn scrolled_rows_are_drawn_where_they_end_up() {
+        let mut window = build_test_window((10, 5));
+        window.draw_command_batcher.drain();
+        window.draw_grid_line(
+            3,
+            0,
+            vec![GridLineCell {
+                text: "a".to_owned(),
+                highlight_id: None,
+                repeat: None,
+            }],
+            &HashMap::new(),
+        );
+
+        // The row left behind stays dirty as well until Neovim draws over it.
+        window.scroll_region(0, 5, 0, 10, 2, 0);
+        assert_eq!(redrawn_rows(&mut window), vec![1, 3]);
+        assert_eq!(window.grid.get_cell(0, 1).unwrap().0, "a");
+    }
+
+    fn sent_urls(window: &Window) -> Vec<Vec<UrlRange>> {
+        window
+            .draw_command_batcher
+            .drain()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::Window {
+                    command: WindowDrawCommand::Urls(urls),
+                    ..
+                } => Some(urls),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn urls_are_detected_in_changed_rows() {
+        let mut window = build_test_window((40, 3));
+        window.update_urls();
+        window.draw_command_batcher.drain();
+
+        window.draw_grid_line(
+            1,
+            2,
+            "go to http://a.b/c now"
+                .chars()
+                .map(|character| GridLineCell {
+                    text: character.to_string(),
+                    highlight_id: None,
+                    repeat: None,
+                })
+                .collect(),
+            &HashMap::new(),
+        );
+        window.update_urls();
+
+        let url = UrlRange {
+            row: 1,
+            start: 8,
+            end: 20,
+            url: "http://a.b/c".to_owned(),
+        };
+        assert_eq!(sent_urls(&window), vec![vec![url]]);
+
+        // Nothing changed, so nothing is rescanned or sent.
+        window.update_urls();
+        assert!(sent_urls(&window).is_empty());
+        assert!(window.url_dirty_rows.is_empty());
+    }
+
+    fn sent_edge_indicators(window: &Window) -> Vec<Vec<(u64, EdgeIndicator)>> {
+        window
+            .draw_command_batcher
+            .drain()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::Window {
+                    command: WindowDrawCommand::EdgeIndicators(edge_indicators),
+                    ..
+                } => Some(edge_indicators),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn scroll_percentage_is_sent_when_the_viewport_moves() {
+        let mut window = build_test_window((20, 5));
+        let settings = ScrollPercentageSettings {
+            enabled: true,
+            position: ScrollPercentagePosition::BottomRight,
+            ..ScrollPercentageSettings::default()
+        };
+        let sent_percentages = |window: &Window| -> Vec<Option<(String, u64, u64)>> {
+            window
+                .draw_command_batcher
+                .drain()
+                .into_iter()
+                .filter_map(|command| match command {
+                    DrawCommand::Window {
+                        command: WindowDrawCommand::ScrollPercentage(fragment),
+                        ..
+                    } => Some(fragment.map(|fragment| {
+                        (fragment.text, fragment.window_left, fragment.window_top)
+                    })),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        window.set_viewport_lines(30.0, 35.0, 30.0, Some(110.0));
+        window.update_scroll_percentage(&settings);
+        assert_eq!(
+            sent_percentages(&window),
+            vec![Some(("28%".to_owned(), 17, 4))]
+        );
+
+        // Nothing is sent again until it changes.
+        window.update_scroll_percentage(&settings);
+        assert!(sent_percentages(&window).is_empty());
+
+        window.set_viewport_lines(0.0, 5.0, 0.0, Some(110.0));
+        window.update_scroll_percentage(&settings);
+        assert_eq!(
+            sent_percentages(&window),
+            vec![Some(("Top".to_owned(), 17, 4))]
+        );
+
+        window.update_scroll_percentage(&ScrollPercentageSettings::default());
+        assert_eq!(sent_percentages(&window), vec![None]);
+    }
+
+    #[test]
+    fn rows_past_the_grid_width_get_an_extends_indicator() {
+        let mut window = build_test_window((4, 3));
+        window.draw_grid_line(
+            1,
+            0,
+            vec![GridLineCell {
+                text: "a".to_owned(),
+                highlight_id: None,
+                repeat: Some(6),
+            }],
+            &HashMap::new(),
+        );
+        window.update_edge_indicators();
+
+        assert_eq!(window.row_display_width(1), 6);
+        assert_eq!(
+            sent_edge_indicators(&window),
+            vec![vec![(1, EdgeIndicator::Extends)]]
+        );
+
+        // Redrawing the row from the first column replaces its width.
+        window.draw_grid_line(
+            1,
+            0,
+            vec![GridLineCell {
+                text: "b".to_owned(),
+                highlight_id: None,
+                repeat: Some(2),
+            }],
+            &HashMap::new(),
+        );
+        window.update_edge_indicators();
+        assert_eq!(sent_edge_indicators(&window), vec![vec![]]);
+    }
+
+    #[test]
+    fn wrapped_rows_get_a_wrap_indicator_and_scroll_with_the_grid() {
+        let mut window = build_test_window((4, 3));
+        window.set_row_wraps(2, true);
+        assert_eq!(window.edge_indicator(2), Some(EdgeIndicator::Wrap));
+
+        window.scroll_region(0, 3, 0, 4, 1, 0);
+        assert_eq!(window.edge_indicator(2), None);
+        assert_eq!(window.edge_indicator(1), Some(EdgeIndicator::Wrap));
+    }
+
+    fn build_numbered_window(grid_size: (u64, u64)) -> Window {
+        let mut window = build_test_window(grid_size);
+        for row in 0..grid_size.1 {
+            for column in 0..grid_size.0 {
+                *window.grid.get_cell_mut(column, row).unwrap() = (format!("{column},{row}"), None);
+            }
+        }
+        window
+    }
+
+    // Moves the grid data the way scrolls did before moving whole rows, one cell at a time.
+    fn scroll_cells_one_by_one(
+        grid: &mut CharacterGrid,
+        (top, bottom, left, right): (u64, u64, u64, u64),
+        rows: i64,
+    ) {
+        let y_iter: Vec<i64> = if rows > 0 {
+            ((top as i64 + rows)..bottom as i64).collect()
+        } else {
+            (top as i64..(bottom as i64 + rows)).rev().collect()
+        };
+        for y in y_iter {
+            let dest_y = y - rows;
+            if dest_y >= 0 && dest_y < grid.height as i64 {
+                for x in (left..right).rev() {
+                    if let Some(cell) = grid.get_cell(x, y as u64).cloned() {
+                        if let Some(dest_cell) = grid.get_cell_mut(x, dest_y as u64) {
+                            *dest_cell = cell;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Draws over the rows a scroll brought into view, like neovim does right after it.
+    fn draw_scrolled_in_rows(
+        grid: &mut CharacterGrid,
+        (top, bottom, left, right): (u64, u64, u64, u64),
+        rows: i64,
+    ) {
+        let bottom = bottom.min(grid.height);
+        let scrolled_in = if rows > 0 {
+            bottom.saturating_sub(rows as u64).max(top)..bottom
+        } else {
+            top..(top + rows.unsigned_abs()).min(bottom)
+        };
+        for row in scrolled_in {
+            for column in left..right {
+                if let Some(cell) = grid.get_cell_mut(column, row) {
+                    *cell = ("new".to_owned(), None);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scrolling_rows_in_blocks_matches_moving_every_cell() {
+        let grid_size = (12, 9);
+        let regions = [(0, 9, 0, 12), (2, 7, 0, 12), (1, 8, 3, 9), (0, 9, 5, 20)];
+        for region in regions {
+            for rows in [-9, -5, -3, -1, 1, 2, 4, 9] {
+                let (top, bottom, left, right) = region;
+                let mut window = build_numbered_window(grid_size);
+                window.scroll_region(top, bottom, left, right, rows, 0);
+                draw_scrolled_in_rows(&mut window.grid, region, rows);
+
+                let mut expected = build_numbered_window(grid_size).grid;
+                scroll_cells_one_by_one(&mut expected, region, rows);
+                draw_scrolled_in_rows(&mut expected, region, rows);
+
+                assert_eq!(
+                    window.grid.export(),
+                    expected.export(),
+                    "{region:?} by {rows}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn odd_column_scrolls_blank_the_halves_of_split_double_width_characters() {
+        let row = |window: &Window| -> Vec<String> {
+            (0..window.grid.width)
+                .map(|column| window.grid.get_cell(column, 0).unwrap().0.clone())
+                .collect()
+        };
+        let draw = |window: &mut Window, cells: &[&str]| {
+            window.draw_grid_line(
+                0,
+                0,
+                cells
+                    .iter()
+                    .map(|text| GridLineCell {
+                        text: text.to_string(),
+                        highlight_id: None,
+                        repeat: None,
+                    })
+                    .collect(),
+                &HashMap::new(),
+            );
+        };
+
+        // Moving columns 1..7 one to the left covers the right half of the character at the left
+        // edge, and leaves the right half of the last one moved behind at the right edge.
+        let mut window = build_test_window((8, 1));
+        draw(&mut window, &["日", "", "本", "", "a", "語", "", "b"]);
+        window.scroll_region(0, 1, 1, 7, 0, 1);
+        assert_eq!(row(&window), vec![" ", "本", "", "a", "語", "", " ", "b"]);
+
+        // Moving them right instead leaves the right half of the first one moved behind.
+        let mut window = build_test_window((8, 1));
+        draw(&mut window, &["日", "", "本", "", "a", "b", "c", "d"]);
+        window.scroll_region(0, 1, 1, 8, 0, -1);
+        assert_eq!(row(&window), vec!["日", "", " ", "本", "", "a", "b", "c"]);
+
+        // Nothing is left of a double width character that can't be drawn.
+        for column in 0..window.grid.width {
+            let text = window.grid.get_cell(column, 0).unwrap().0.clone();
+            if text.is_empty() {
+                assert!(column > 0);
+                assert!(is_double_width_cell(
+                    &window.grid.get_cell(column - 1, 0).unwrap().0
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn scrolling_a_large_grid_many_times_matches_moving_every_cell() {
+        let (width, height) = (400, 200);
+        let mut window = build_numbered_window((width, height));
+        let mut expected = build_numbered_window((width, height)).grid;
+        for rows in [3, -3, 1, 17, -40, 199, -2, 5] {
+            let region = (0, height, 0, width);
+            window.scroll_region(0, height, 0, width, rows, 0);
+            draw_scrolled_in_rows(&mut window.grid, region, rows);
+            scroll_cells_one_by_one(&mut expected, region, rows);
+            draw_scrolled_in_rows(&mut expected, region, rows);
+        }
+
+        assert_eq!(window.grid.export(), expected.export());
+    }
+
+    #[test]
+    fn variation_selectors_stay_in_the_cell_of_their_base_character() {
+        let mut window = build_test_window((4, 1));
+        let cell = |text: &str| GridLineCell {
+            text: text.to_owned(),
+            highlight_id: None,
+            repeat: None,
+        };
+
+        window.draw_grid_line(
+            0,
+            0,
+            vec![
+                cell("\u{2764}\u{FE0F}"),
+                cell("\u{263A}\u{FE0E}"),
+                cell("a"),
+            ],
+            &HashMap::new(),
+        );
+
+        assert_eq!(
+            window.grid.get_cell(0, 0),
+            Some(&("\u{2764}\u{FE0F}".to_owned(), None))
+        );
+        assert_eq!(
+            window.grid.get_cell(1, 0),
+            Some(&("\u{263A}\u{FE0E}".to_owned(), None))
+        );
+        assert_eq!(window.grid.get_cell(2, 0), Some(&("a".to_owned(), None)));
+        assert_eq!(window.row_display_width(0), 3);
+    }
+
+    #[test]
+    fn visual_block_over_lines_of_different_lengths_is_one_rectangle() {
+        SETTINGS.set(&VisualBlockSettings::default());
+        let mut window = build_test_window((10, 4));
+        let cells = |texts: &[&str]| {
+            texts
+                .iter()
+                .map(|text| GridLineCell {
+                    text: text.to_string(),
+                    highlight_id: None,
+                    repeat: None,
+                })
+                .collect::<Vec<_>>()
+        };
+        window.draw_grid_line(0, 0, cells(&["a", "b"]), &HashMap::new());
+        window.draw_grid_line(
+            1,
+            0,
+            cells(&["a", "b", "c", "d", "e", "f", "g", "h"]),
+            &HashMap::new(),
+        );
+        // The block edges cut through both halves of the double width characters on this row.
+        window.draw_grid_line(2, 0, cells(&["a", "日", "", "本", ""]), &HashMap::new());
+        window.draw_command_batcher.drain();
+
+        window.set_visual_block(Some(&VisualBlock {
+            top: 0,
+            left: 2,
+            bottom: 3,
+            right: 4,
+        }));
+
+        let blocks: Vec<_> = window
+            .draw_command_batcher
+            .drain()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::Window {
+                    command: WindowDrawCommand::VisualBlock(block),
+                    ..
+                } => Some(block.map(|(block, _)| block)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            blocks,
+            vec![Some(VisualBlock {
+                top: 0,
+                left: 1,
+                bottom: 3,
+                right: 5,
+            })]
+        );
+
+        // Blocks reaching past the grid, like ones extended with $, are cut at its edges.
+        let block = window.visual_block_rect(&VisualBlock {
+            top: 1,
+            left: 6,
+            bottom: 9,
+            right: 20,
+        });
+        assert_eq!(
+            block,
+            VisualBlock {
+                top: 1,
+                left: 6,
+                bottom: 4,
+                right: 10,
+            }
+        );
+    }
+
+    #[test]
+    fn the_last_visual_block_is_kept_for_copying_after_the_selection_ends() {
+        let mut window = build_test_window((4, 2));
+        assert_eq!(window.visual_block_text(), None);
+        window.draw_grid_line(0, 0, cells(&["a", "b", "c"]), &HashMap::new());
+        window.draw_grid_line(1, 0, cells(&["d", "e"]), &HashMap::new());
+
+        window.set_visual_block(Some(&VisualBlock {
+            top: 0,
+            left: 1,
+            bottom: 2,
+            right: 4,
+        }));
+        window.set_visual_block(None);
+        assert_eq!(window.visual_block_text(), Some("bc\ne".to_owned()));
+    }
+
+    #[test]
+    fn indentation_split_by_a_style_change_gets_one_continuous_guide() {
+        let mut window = build_test_window((12, 3));
+        let defined_styles = underlined_styles();
+        let cell = |text: &str, highlight_id, repeat| GridLineCell {
+            text: text.to_owned(),
+            highlight_id: Some(highlight_id),
+            repeat,
+        };
+        window.draw_grid_line(
+            0,
+            0,
+            vec![cell(" ", 0, Some(4)), cell("a", 0, None)],
+            &defined_styles,
+        );
+        // Like the cursorline over a tab, the indentation of this row changes style half way.
+        window.draw_grid_line(
+            1,
+            0,
+            vec![
+                cell(" ", 0, Some(2)),
+                cell(" ", 1, Some(6)),
+                cell("b", 1, None),
+            ],
+            &defined_styles,
+        );
+        window.draw_grid_line(
+            2,
+            0,
+            vec![cell(" ", 0, Some(4)), cell("c", 0, None)],
+            &defined_styles,
+        );
+
+        assert_eq!(window.fragment_at(0, 1).unwrap().width, 2);
+        assert_eq!(
+            indent_guides::indent_guides(&window.indent_widths(), 4),
+            vec![
+                IndentGuide {
+                    column: 0,
+                    top: 0,
+                    bottom: 3,
+                },
+                IndentGuide {
+                    column: 4,
+                    top: 1,
+                    bottom: 2,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn restoring_a_snapshot_brings_back_the_cells_and_their_styles() {
+        let mut window = Window::new(
+            3,
+            WindowType::Editor,
+            Some(AnchorInfo {
+                anchor_grid_id: 1,
+                anchor_type: WindowAnchor::SouthEast,
+                anchor_left: 4.0,
+                anchor_top: 2.0,
+                sort_order: 5,
+            }),
+            (1.0, 2.0),
+            (6, 2),
+            Rc::new(DrawCommandBatcher::new()),
+            GridLimits::default(),
+        );
+        let defined_styles = underlined_styles();
+        window.draw_grid_line(
+            0,
+            0,
+            ["a", "b", "日", ""]
+                .into_iter()
+                .map(|text| GridLineCell {
+                    text: text.to_owned(),
+                    highlight_id: Some(1),
+                    repeat: None,
+                })
+                .collect(),
+            &defined_styles,
+        );
+        window.draw_grid_line(
+            1,
+            2,
+            vec![GridLineCell {
+                text: "c".to_owned(),
+                highlight_id: None,
+                repeat: Some(2),
+            }],
+            &defined_styles,
+        );
+
+        let snapshot = window.snapshot();
+        assert_eq!(snapshot.styles.len(), 1);
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let restored = Window::restore(
+            serde_json::from_str(&json).unwrap(),
+            Rc::new(DrawCommandBatcher::new()),
+            GridLimits::default(),
+        );
+
+        assert_eq!(restored.grid_id, 3);
+        assert_eq!(restored.grid_position, (1.0, 2.0));
+        assert_eq!(restored.anchor_info.as_ref().unwrap().sort_order, 5);
+        assert_eq!(restored.export_grid(), window.export_grid());
+        for row in 0..2 {
+            assert_eq!(restored.grid.row(row), window.grid.row(row));
+        }
+        assert_eq!(restored.row_display_widths, window.row_display_widths);
+    }
+
+    #[test]
+    fn cells_matching_finds_the_cells_with_the_foreground() {
+        let mut window = build_test_window((4, 2));
+        let red = Color4f::new(1.0, 0.0, 0.0, 1.0);
+        let mut defined_styles = HashMap::new();
+        defined_styles.insert(1, Arc::new(Style::new(Colors::new(Some(red), None, None))));
+        defined_styles.insert(
+            2,
+            Arc::new(Style::new(Colors::new(
+                Some(Color4f::new(0.0, 0.0, 1.0, 1.0)),
+                None,
+                None,
+            ))),
+        );
+        for (row, column, highlight_id) in [(0, 1, 1), (0, 2, 2), (1, 0, 2), (1, 3, 1)] {
+            window.draw_grid_line(
+                row,
+                column,
+                vec![GridLineCell {
+                    text: "x".to_owned(),
+                    highlight_id: Some(highlight_id),
+                    repeat: None,
+                }],
+                &defined_styles,
+            );
+        }
+
+        assert_eq!(
+            window.cells_matching(|style| style.colors.foreground == Some(red)),
+            vec![(1, 0), (3, 1)]
+        );
+        assert_eq!(
+            window
+                .cells_matching(|style| style.colors.foreground.is_none())
+                .len(),
+            4
+        );
+    }
+
+    #[test]
+    fn replace_styles_only_swaps_the_highlights_drawn_into_the_grid() {
+        let mut window = build_test_window((2, 1));
+        let defined_styles = underlined_styles();
+        window.draw_grid_line(
+            0,
+            0,
+            vec![GridLineCell {
+                text: "x".to_owned(),
+                highlight_id: Some(1),
+                repeat: None,
+            }],
+            &defined_styles,
+        );
+
+        let new_style = Arc::new(Style::new(Colors::new(None, None, None)));
+        let mut redefined = HashMap::new();
+        redefined.insert(2, (defined_styles[&1].clone(), new_style.clone()));
+        assert!(!window.replace_styles(&redefined));
+
+        redefined.insert(1, (defined_styles[&1].clone(), new_style.clone()));
+        assert!(window.replace_styles(&redefined));
+        let (_, style) = window.grid.get_cell(0, 0).unwrap();
+        assert!(Arc::ptr_eq(style.as_ref().unwrap(), &new_style));
+    }
+
+    #[test]
+    fn tokenize_line_reads_the_row_from_the_grid() {
+        let mut window = build_test_window((6, 1));
+        window.draw_grid_line(
+            0,
+            0,
+            "ab 12"
+                .chars()
+                .map(|character| GridLineCell {
+                    text: character.to_string(),
+                    highlight_id: None,
+                    repeat: None,
+                })
+                .collect(),
+            &HashMap::new(),
+        );
+
+        let classifier = TokenClassifier::default();
+        let kinds: Vec<_> = window
+            .tokenize_line(0, &classifier)
+            .unwrap()
+            .into_iter()
+            .map(|token| (token.kind, token.start, token.end))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![(TokenKind::Identifier, 0, 2), (TokenKind::Number, 3, 5)]
+        );
+        assert_eq!(window.tokenize_line(1, &classifier), None);
+    }
 }
@@ -0,0 +1,124 @@
+use log::error;
+use nvim_rs::Value;
+use skia_safe::{Color4f, Rect};
+
+use crate::{editor::parse_color, settings::*};
+
+/// How the line between two windows is drawn.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SeparatorStyle {
+    Solid,
+    Double,
+}
+
+impl ParseFromValue for SeparatorStyle {
+    fn parse_from_value(&mut self, value: Value) {
+        if value.is_str() {
+            *self = match value.as_str().unwrap() {
+                "solid" => SeparatorStyle::Solid,
+                "double" => SeparatorStyle::Double,
+                value => {
+                    error!("Expected solid or double, but received {:?}", value);
+                    return;
+                }
+            };
+        } else {
+            error!(
+                "Expected a separator style string, but received {:?}",
+                value
+            );
+        }
+    }
+}
+
+impl From<SeparatorStyle> for Value {
+    fn from(style: SeparatorStyle) -> Self {
+        match style {
+            SeparatorStyle::Solid => Value::from("solid"),
+            SeparatorStyle::Double => Value::from("double"),
+        }
+    }
+}
+
+#[derive(SettingGroup, Clone)]
+#[setting_prefix = "window_separator"]
+pub struct WindowSeparatorSettings {
+    pub enabled: bool,
+    // Width of the line in pixels, each of the two lines for the double style.
+    pub thickness: f32,
+    // Empty uses the default foreground color.
+    pub color: String,
+    pub style: SeparatorStyle,
+}
+
+impl Default for WindowSeparatorSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            thickness: 1.0,
+            color: "".to_string(),
+            style: SeparatorStyle::Solid,
+        }
+    }
+}
+
+impl WindowSeparatorSettings {
+    pub fn color(&self, default_foreground: Option<Color4f>) -> Option<Color4f> {
+        parse_color(&self.color).or(default_foreground)
+    }
+}
+
+/// The line between two windows next to each other, through the center of the column between
+/// them or along the top of the lower one. The position and length are in cells of the screen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowSeparator {
+    pub left: f64,
+    pub top: f64,
+    pub length: f64,
+    pub vertical: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowSeparators {
+    pub separators: Vec<WindowSeparator>,
+    pub color: Color4f,
+    pub thickness: f32,
+    pub style: SeparatorStyle,
+}
+
+// Splits are at most one cell apart, which neovim fills with the separator column or the status
+// line.
+const MAX_SEPARATOR_GAP: f32 = 1.0;
+
+/// Finds the separators between the given window rectangles, in cells of the screen.
+pub fn find_window_separators(windows: &[Rect]) -> Vec<WindowSeparator> {
+    let mut separators = Vec::new();
+    for a in windows {
+        for b in windows {
+            let column_gap = b.left - a.right;
+            let top = a.top.max(b.top);
+            let bottom = a.bottom.min(b.bottom);
+            if (0.0..=MAX_SEPARATOR_GAP).contains(&column_gap) && bottom > top {
+                separators.push(WindowSeparator {
+                    left: (a.right + column_gap / 2.0) as f64,
+                    top: top as f64,
+                    length: (bottom - top) as f64,
+                    vertical: true,
+                });
+            }
+
+            let row_gap = b.top - a.bottom;
+            let left = a.left.max(b.left);
+            let right = a.right.min(b.right);
+            if (0.0..=MAX_SEPARATOR_GAP).contains(&row_gap) && right > left {
+                separators.push(WindowSeparator {
+                    left: left as f64,
+                    top: b.top as f64,
+                    length: (right - left) as f64,
+                    vertical: false,
+                });
+            }
+        }
+    }
+    separators
+}
@@ -42,7 +42,14 @@ use log::trace;
 use backtrace::Backtrace;
 use bridge::start_bridge;
 use cmd_line::CmdLineSettings;
-use editor::start_editor;
+use editor::{
+    start_editor, BlameSettings, BreadcrumbSettings, CollapsedFloatSettings, CommandLineSettings,
+    CrosshairSettings, CursorContrastSettings, CursorShadowSettings, CursorViewSettings,
+    DiagnosticSettings, IndentGuideSettings, LigatureSettings, LineNumberSettings,
+    MessageLogSettings, ModeRippleSettings, PeekPreviewSettings, PendingKeysSettings,
+    PopupMenuSettings, RecordingIndicatorSettings, RenderMetricsSettings, ScrollOverviewSettings,
+    ScrollPercentageSettings, SpotlightSettings, VisualBlockSettings, WindowSeparatorSettings,
+};
 use renderer::{cursor_renderer::CursorSettings, RendererSettings};
 use settings::SETTINGS;
 use std::fs::{File, OpenOptions};
@@ -169,7 +176,31 @@ fn protected_main() {
     WindowSettings::register();
     RendererSettings::register();
     CursorSettings::register();
+    CursorShadowSettings::register();
+    CursorContrastSettings::register();
+    SpotlightSettings::register();
+    PeekPreviewSettings::register();
+    ModeRippleSettings::register();
+    CollapsedFloatSettings::register();
+    LigatureSettings::register();
+    RecordingIndicatorSettings::register();
+    CursorViewSettings::register();
     KeyboardSettings::register();
+    PopupMenuSettings::register();
+    LineNumberSettings::register();
+    VisualBlockSettings::register();
+    CommandLineSettings::register();
+    IndentGuideSettings::register();
+    PendingKeysSettings::register();
+    ScrollOverviewSettings::register();
+    BreadcrumbSettings::register();
+    DiagnosticSettings::register();
+    BlameSettings::register();
+    RenderMetricsSettings::register();
+    ScrollPercentageSettings::register();
+    MessageLogSettings::register();
+    WindowSeparatorSettings::register();
+    CrosshairSettings::register();
 
     start_bridge();
     start_editor();
@@ -0,0 +1,51 @@
+use skia_safe::{Canvas, Paint, Rect};
+
+use crate::{
+    editor::Crosshair,
+    renderer::{GridRenderer, WindowPadding},
+};
+
+pub fn draw_crosshair(
+    grid_renderer: &GridRenderer,
+    canvas: &mut Canvas,
+    crosshair: &Crosshair,
+    padding: &WindowPadding,
+) {
+    let font_dimensions = grid_renderer.font_dimensions;
+    let content_origin = padding.content_origin(font_dimensions);
+    let cell_width = font_dimensions.width as f32;
+    let cell_height = font_dimensions.height as f32;
+    let left = (crosshair.left as f32 + content_origin.x) * cell_width;
+    let top = (crosshair.top as f32 + content_origin.y) * cell_height;
+    let thickness = crosshair.thickness.max(0.0);
+    let paint = Paint::new(crosshair.color, None);
+
+    // The lines cross in the middle of the cursor cell, and the vertical one leaves out the part
+    // the horizontal one already covers so that it isn't drawn twice when translucent.
+    let center_x = left + (crosshair.column as f32 + 0.5) * cell_width;
+    let center_y = top + (crosshair.row as f32 + 0.5) * cell_height;
+    let width = crosshair.width as f32 * cell_width;
+    let height = crosshair.height as f32 * cell_height;
+    canvas.draw_rect(
+        Rect::from_xywh(left, center_y - thickness / 2.0, width, thickness),
+        &paint,
+    );
+    canvas.draw_rect(
+        Rect::from_ltrb(
+            center_x - thickness / 2.0,
+            top,
+            center_x + thickness / 2.0,
+            center_y - thickness / 2.0,
+        ),
+        &paint,
+    );
+    canvas.draw_rect(
+        Rect::from_ltrb(
+            center_x - thickness / 2.0,
+            center_y + thickness / 2.0,
+            center_x + thickness / 2.0,
+            top + height,
+        ),
+        &paint,
+    );
+}
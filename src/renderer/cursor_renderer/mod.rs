@@ -3,12 +3,12 @@ mod cursor_vfx;
 
 use std::collections::HashMap;
 
-use skia_safe::{op, Canvas, Paint, Path, Point};
+use skia_safe::{op, BlurStyle, Canvas, MaskFilter, Paint, Path, Point};
 use winit::event::{Event, WindowEvent};
 
 use crate::{
     bridge::EditorMode,
-    editor::{Cursor, CursorShape},
+    editor::{Cursor, CursorShape, CursorTravel},
     profiling::tracy_zone,
     redraw_scheduler::REDRAW_SCHEDULER,
     renderer::animation_utils::*,
@@ -31,7 +31,11 @@ pub struct CursorSettings {
     distance_length_adjust: bool,
     animate_in_insert_mode: bool,
     animate_command_line: bool,
+    // Jumps to the new grid when off, instead of moving across the screen to it.
+    animate_grid_switch: bool,
     trail_size: f32,
+    // Number of fading copies of the cursor drawn along its path while it moves, 0 disables them.
+    smear_length: u64,
     unfocused_outline_width: f32,
 
     vfx_mode: cursor_vfx::VfxMode,
@@ -51,7 +55,9 @@ impl Default for CursorSettings {
             distance_length_adjust: true,
             animate_in_insert_mode: true,
             animate_command_line: true,
+            animate_grid_switch: true,
             trail_size: 0.7,
+            smear_length: 0,
             unfocused_outline_width: 1.0 / 8.0,
             vfx_mode: cursor_vfx::VfxMode::Disabled,
             vfx_opacity: 200.0,
@@ -86,6 +92,15 @@ impl Corner {
         }
     }
 
+    // Puts the corner around the given cursor center, for the next animation to start from there.
+    fn move_to(&mut self, center: Point, font_dimensions: Point) {
+        self.current_position = (
+            center.x + self.relative_position.x * font_dimensions.x,
+            center.y + self.relative_position.y * font_dimensions.y,
+        )
+            .into();
+    }
+
     pub fn update(
         &mut self,
         settings: &CursorSettings,
@@ -170,16 +185,56 @@ impl Corner {
     }
 }
 
+/// The top left corner of the cursor cell on the screen, in pixels. It only depends on the cell
+/// and not on how the text around it is shaped, so the cursor stays on its cell in the middle of a
+/// ligature too. The window is positioned in cells of the font size, but its own cells are those
+/// of its zoom.
+fn cursor_cell_origin(
+    (cursor_grid_x, cursor_grid_y): (u64, u64),
+    window_position: Point,
+    scroll_offset: f32,
+    window_height: u64,
+    (font_width, font_height): (u64, u64),
+    (cell_width, cell_height): (u64, u64),
+) -> Point {
+    // Prevent the cursor from targeting a position outside its current window. Since only the
+    // vertical direction is effected by scrolling, we only have to clamp the vertical grid
+    // position.
+    let row = (cursor_grid_y as f32 - scroll_offset)
+        .max(0.0)
+        .min(window_height as f32 - 1.0);
+
+    (
+        window_position.x * font_width as f32 + cursor_grid_x as f32 * cell_width as f32,
+        window_position.y * font_height as f32 + row * cell_height as f32,
+    )
+        .into()
+}
+
+/// Evenly spaced points strictly between the two positions, ordered from `from` to `to`.
+pub fn smear_samples(from: Point, to: Point, length: u64) -> Vec<Point> {
+    (1..=length)
+        .map(|index| {
+            let t = index as f32 / (length + 1) as f32;
+            Point::new(from.x + (to.x - from.x) * t, from.y + (to.y - from.y) * t)
+        })
+        .collect()
+}
+
 pub struct CursorRenderer {
     pub corners: Vec<Corner>,
     cursor: Cursor,
     destination: Point,
+    // Center of the cursor when it started moving towards the current destination.
+    smear_start: Point,
     blink_status: BlinkStatus,
     previous_cursor_shape: Option<CursorShape>,
     previous_editor_mode: EditorMode,
     cursor_vfx: Option<Box<dyn cursor_vfx::CursorVfx>>,
     previous_vfx_mode: cursor_vfx::VfxMode,
     window_has_focus: bool,
+    // Where the cursor moved to another grid from and to since the last frame.
+    travel: Option<CursorTravel>,
 }
 
 impl CursorRenderer {
@@ -188,12 +243,14 @@ impl CursorRenderer {
             corners: vec![Corner::new(); 4],
             cursor: Cursor::new(),
             destination: (0.0, 0.0).into(),
+            smear_start: (0.0, 0.0).into(),
             blink_status: BlinkStatus::new(),
             previous_cursor_shape: None,
             previous_editor_mode: EditorMode::Normal,
             cursor_vfx: None,
             previous_vfx_mode: cursor_vfx::VfxMode::Disabled,
             window_has_focus: true,
+            travel: None,
         };
         renderer.set_cursor_shape(&CursorShape::Block, DEFAULT_CELL_PERCENTAGE);
         renderer
@@ -209,7 +266,21 @@ impl CursorRenderer {
         }
     }
 
+    /// The grid the cursor is in.
+    pub fn grid_id(&self) -> u64 {
+        self.cursor.parent_window_id
+    }
+
     pub fn update_cursor(&mut self, new_cursor: Cursor) {
+        // Kept until the next frame moves the cursor, which may only come after the next update.
+        // The first grid it left is where the animation starts from.
+        self.travel = match (self.travel, new_cursor.travel) {
+            (Some(travel), Some(new_travel)) => Some(CursorTravel {
+                start: travel.start,
+                end: new_travel.end,
+            }),
+            (travel, new_travel) => new_travel.or(travel),
+        };
         self.cursor = new_cursor;
     }
 
@@ -251,18 +322,14 @@ impl CursorRenderer {
         let (cursor_grid_x, cursor_grid_y) = self.cursor.grid_position;
 
         if let Some(window) = windows.get(&self.cursor.parent_window_id) {
-            let grid_x = cursor_grid_x as f32 + window.grid_current_position.x;
-            let mut grid_y = cursor_grid_y as f32 + window.grid_current_position.y
-                - (window.current_scroll - window.current_surface.vertical_position);
-
-            // Prevent the cursor from targeting a position outside its current window. Since only
-            // the vertical direction is effected by scrolling, we only have to clamp the vertical
-            // grid position.
-            grid_y = grid_y
-                .max(window.grid_current_position.y)
-                .min(window.grid_current_position.y + window.grid_size.height as f32 - 1.0);
-
-            self.destination = (grid_x * font_width as f32, grid_y * font_height as f32).into();
+            self.destination = cursor_cell_origin(
+                self.cursor.grid_position,
+                window.grid_current_position,
+                window.current_scroll - window.current_surface.vertical_position,
+                window.grid_size.height,
+                (font_width, font_height),
+                window.cell_size((font_width, font_height).into()).into(),
+            );
         } else {
             self.destination = (
                 (cursor_grid_x * font_width) as f32,
@@ -294,8 +361,12 @@ impl CursorRenderer {
         let character = self.cursor.grid_cell.0.clone();
 
         let mut cursor_width = grid_renderer.font_dimensions.width;
-        if self.cursor.double_width && self.cursor.shape == CursorShape::Block {
-            cursor_width *= 2;
+        if self.cursor.shape == CursorShape::Block {
+            if let Some(tab_width) = self.cursor.tab_width {
+                cursor_width *= tab_width;
+            } else if self.cursor.double_width {
+                cursor_width *= 2;
+            }
         }
 
         let cursor_dimensions: Point = (
@@ -327,10 +398,27 @@ impl CursorRenderer {
 
         let mut animating = false;
 
+        if self.corners[0].previous_destination != center_destination {
+            self.smear_start = self.current_center();
+        }
+
+        // A cursor moving into another grid travels across the screen from the cell it left,
+        // wherever the corners were still animating towards.
+        let travel = self.travel.take();
+        if let Some(travel) = travel.filter(|_| settings.animate_grid_switch) {
+            let (start, _) = travel.to_pixels(grid_renderer.font_dimensions);
+            let start_center = start + cursor_dimensions * 0.5;
+            for corner in self.corners.iter_mut() {
+                corner.move_to(start_center, cursor_dimensions);
+            }
+            self.smear_start = start_center;
+        }
+
         if !center_destination.is_zero() {
             for corner in self.corners.iter_mut() {
                 let immediate_movement = !settings.animate_in_insert_mode && in_insert_mode
-                    || !settings.animate_command_line && !changed_to_from_cmdline;
+                    || !settings.animate_command_line && !changed_to_from_cmdline
+                    || !settings.animate_grid_switch && travel.is_some();
 
                 let corner_animating = corner.update(
                     &settings,
@@ -360,12 +448,34 @@ impl CursorRenderer {
         if !(self.cursor.enabled && render) {
             return;
         }
-        // Draw Background
+
+        // Draw Shadow
+        if let Some(shadow) = &self.cursor.shadow {
+            let mut shadow_paint = Paint::new(shadow.color, None);
+            shadow_paint.set_anti_alias(settings.antialiasing);
+            shadow_paint.set_mask_filter(MaskFilter::blur(
+                BlurStyle::Normal,
+                // Skia's blur extends about three sigmas out from the shape.
+                shadow.radius / 3.0,
+                None,
+            ));
+            self.draw_rectangle(canvas, &shadow_paint);
+        }
+
         let background_color = self
             .cursor
             .background(&grid_renderer.default_style.colors)
             .to_color()
             .with_a(self.cursor.alpha());
+
+        // Draw Smear
+        if animating && settings.smear_length > 0 {
+            let mut smear_paint = paint.clone();
+            smear_paint.set_color(background_color);
+            self.draw_smear(canvas, &smear_paint, settings.smear_length);
+        }
+
+        // Draw Background
         paint.set_color(background_color);
 
         let path = if self.window_has_focus || self.cursor.shape != CursorShape::Block {
@@ -409,6 +519,40 @@ impl CursorRenderer {
         }
     }
 
+    fn current_center(&self) -> Point {
+        let sum = self
+            .corners
+            .iter()
+            .fold(Point::new(0.0, 0.0), |sum, corner| {
+                sum + corner.current_position
+            });
+        Point::new(
+            sum.x / self.corners.len() as f32,
+            sum.y / self.corners.len() as f32,
+        )
+    }
+
+    fn draw_smear(&self, canvas: &mut Canvas, paint: &Paint, length: u64) {
+        let current_center = self.current_center();
+        let samples = smear_samples(self.smear_start, current_center, length);
+        let opacity = paint.alpha_f();
+
+        for (index, sample) in samples.iter().enumerate() {
+            let offset = *sample - current_center;
+            let mut path = Path::new();
+            path.move_to(self.corners[0].current_position + offset);
+            path.line_to(self.corners[1].current_position + offset);
+            path.line_to(self.corners[2].current_position + offset);
+            path.line_to(self.corners[3].current_position + offset);
+            path.close();
+
+            // The copies fade out towards where the cursor came from.
+            let mut sample_paint = paint.clone();
+            sample_paint.set_alpha_f(opacity * (index + 1) as f32 / (length + 1) as f32);
+            canvas.draw_path(&path, &sample_paint);
+        }
+    }
+
     fn draw_rectangle(&self, canvas: &mut Canvas, paint: &Paint) -> Path {
         // The cursor is made up of four points, so I create a path with each of the four
         // corners.
@@ -463,3 +607,35 @@ impl CursorRenderer {
         self.destination
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smear_samples_are_evenly_spaced_between_the_positions() {
+        let samples = smear_samples(Point::new(0.0, 0.0), Point::new(10.0, 5.0), 4);
+
+        let expected = [(2.0, 1.0), (4.0, 2.0), (6.0, 3.0), (8.0, 4.0)];
+        assert_eq!(samples.len(), expected.len());
+        for (sample, (x, y)) in samples.iter().zip(expected) {
+            assert!((sample.x - x).abs() < 0.001 && (sample.y - y).abs() < 0.001);
+        }
+        assert!(smear_samples(Point::new(0.0, 0.0), Point::new(10.0, 5.0), 0).is_empty());
+    }
+
+    #[test]
+    fn cursor_inside_a_ligature_is_placed_on_its_cell() {
+        // The `>` of a `=>` drawn as one glyph from column 4 of a window at cell (10, 2).
+        let origin = cursor_cell_origin((5, 3), Point::new(10.0, 2.0), 0.0, 20, (8, 16), (8, 16));
+        assert_eq!(origin, Point::new(120.0, 80.0));
+
+        // Animating a scroll moves the cursor along with its row.
+        let origin = cursor_cell_origin((5, 3), Point::new(10.0, 2.0), -0.5, 20, (8, 16), (8, 16));
+        assert_eq!(origin, Point::new(120.0, 88.0));
+
+        // The cells of a zoomed window are bigger, the window is still placed in the font's.
+        let origin = cursor_cell_origin((5, 3), Point::new(10.0, 2.0), 0.0, 20, (8, 16), (16, 32));
+        assert_eq!(origin, Point::new(160.0, 128.0));
+    }
+}
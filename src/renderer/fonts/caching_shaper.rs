@@ -16,6 +16,7 @@ use swash::{
 };
 use unicode_segmentation::UnicodeSegmentation;
 
+use crate::editor::cell_width;
 use crate::profiling::tracy_zone;
 use crate::renderer::fonts::{font_loader::*, font_options::*};
 
@@ -24,10 +25,13 @@ struct ShapeKey {
     pub text: String,
     pub bold: bool,
     pub italic: bool,
+    pub font_fallback: Arc<Vec<String>>,
 }
 
 pub struct CachingShaper {
     options: FontOptions,
+    // Fonts of the window being drawn, tried after the guifont ones.
+    font_fallback: Arc<Vec<String>>,
     font_loader: FontLoader,
     blob_cache: LruCache<ShapeKey, Vec<TextBlob>>,
     shape_context: ShapeContext,
@@ -42,6 +46,7 @@ impl CachingShaper {
         let font_size = options.size * scale_factor;
         let mut shaper = CachingShaper {
             options,
+            font_fallback: Arc::default(),
             font_loader: FontLoader::new(font_size),
             blob_cache: LruCache::new(10000),
             shape_context: ShapeContext::new(),
@@ -69,6 +74,16 @@ impl CachingShaper {
             })
     }
 
+    /// A shaper for the same font at the size scaled by the zoom, the line spacing included.
+    pub fn zoomed(&self, zoom: f32) -> CachingShaper {
+        let mut shaper = CachingShaper::new(self.scale_factor * zoom);
+        shaper.options = self.options.clone();
+        shaper.font_fallback = self.font_fallback.clone();
+        shaper.linespace = (self.linespace as f32 * zoom).round() as i64;
+        shaper.reset_font_loader();
+        shaper
+    }
+
     pub fn current_size(&self) -> f32 {
         self.options.size * self.scale_factor * self.fudge_factor
     }
@@ -100,6 +115,11 @@ impl CachingShaper {
         }
     }
 
+    /// Sets the fonts glyphs missing from the guifont are looked up in next, until it's set again.
+    pub fn set_font_fallback(&mut self, font_fallback: Arc<Vec<String>>) {
+        self.font_fallback = font_fallback;
+    }
+
     pub fn update_linespace(&mut self, linespace: i64) {
         debug!("Updating linespace: {}", linespace);
 
@@ -192,6 +212,13 @@ impl CachingShaper {
         self.metrics().underline_offset as u64
     }
 
+    /// The height above the baseline to strike text through at, half way up the capital letters,
+    /// along with the thickness of the line.
+    pub fn strikethrough_position(&mut self) -> (f32, f32) {
+        let metrics = self.metrics();
+        (metrics.cap_height / 2., metrics.stroke_size.max(1.))
+    }
+
     pub fn y_adjustment(&mut self) -> u64 {
         let metrics = self.metrics();
         (metrics.ascent + metrics.leading + self.linespace as f32 / 2.).ceil() as u64
@@ -205,26 +232,28 @@ impl CachingShaper {
     ) -> Vec<(Vec<CharCluster>, Arc<FontPair>)> {
         let mut cluster = CharCluster::new();
 
-        // Enumerate the characters storing the glyph index in the user data so that we can position
-        // glyphs according to Neovim's grid rules
+        // Enumerate the characters storing the column of their cell in the user data so that we can
+        // position glyphs according to Neovim's grid rules. Double width characters take up two
+        // columns, as fragments continue past them.
         let mut character_index = 0;
+        let mut column = 0;
         let mut parser = Parser::new(
             Script::Latin,
-            text.graphemes(true)
-                .enumerate()
-                .flat_map(|(glyph_index, unicode_segment)| {
-                    unicode_segment.chars().map(move |character| {
-                        let token = Token {
-                            ch: character,
-                            offset: character_index as u32,
-                            len: character.len_utf8() as u8,
-                            info: character.into(),
-                            data: glyph_index as u32,
-                        };
-                        character_index += 1;
-                        token
-                    })
-                }),
+            text.graphemes(true).flat_map(|unicode_segment| {
+                let glyph_column = column;
+                column += cell_width(unicode_segment);
+                unicode_segment.chars().map(move |character| {
+                    let token = Token {
+                        ch: character,
+                        offset: character_index as u32,
+                        len: character.len_utf8() as u8,
+                        info: character.into(),
+                        data: glyph_column as u32,
+                    };
+                    character_index += 1;
+                    token
+                })
+            }),
         );
 
         let mut results = Vec::new();
@@ -242,6 +271,15 @@ impl CachingShaper {
                 edging: self.options.edging.clone(),
             }));
 
+            // Add the fallback fonts of the window
+            font_fallback_keys.extend(self.font_fallback.iter().map(|font_name| FontKey {
+                italic: self.options.italic || italic,
+                bold: self.options.bold || bold,
+                family_name: Some(font_name.clone()),
+                hinting: self.options.hinting.clone(),
+                edging: self.options.edging.clone(),
+            }));
+
             // Add default font
             font_fallback_keys.push(FontKey {
                 italic: self.options.italic || italic,
@@ -394,7 +432,7 @@ impl CachingShaper {
 
     pub fn shape_cached(&mut self, text: String, bold: bool, italic: bool) -> &Vec<TextBlob> {
         tracy_zone!("shape_cached");
-        let key = ShapeKey::new(text.clone(), bold, italic);
+        let key = ShapeKey::new(text.clone(), bold, italic, self.font_fallback.clone());
 
         if !self.blob_cache.contains(&key) {
             let blobs = self.shape(text, bold, italic);
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+
+use log::error;
+use nvim_rs::Value;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{editor::cell_width, settings::*};
+
+/// What a substituted character is drawn as.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum GlyphSubstitution {
+    // Shaped from the font like any other text. It should take as many cells as the original.
+    Glyph(String),
+    // A box filling the cells of the original in the foreground color.
+    Box,
+}
+
+/// Characters which are drawn differently than the font would, for working around fonts with
+/// broken glyphs. Only the rendering changes, the grid keeps the original characters.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct GlyphSubstitutions(HashMap<String, GlyphSubstitution>);
+
+// Keys are either the character itself or its code point written like `U+2502`.
+fn parse_character(key: &str) -> Option<String> {
+    match key.strip_prefix("U+").or_else(|| key.strip_prefix("u+")) {
+        Some(code_point) => u32::from_str_radix(code_point, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .map(String::from),
+        None if key.graphemes(true).count() == 1 => Some(key.to_owned()),
+        None => None,
+    }
+}
+
+impl ParseFromValue for GlyphSubstitutions {
+    fn parse_from_value(&mut self, value: Value) {
+        // Neovim sends an empty table as an array.
+        if value.as_array().map(|array| array.is_empty()) == Some(true) {
+            *self = GlyphSubstitutions::default();
+            return;
+        }
+        let entries = match value {
+            Value::Map(entries) => entries,
+            value => {
+                error!(
+                    "Expected a glyph substitution table, but received {:?}",
+                    value
+                );
+                return;
+            }
+        };
+
+        let mut substitutions = HashMap::new();
+        for (key, replacement) in entries {
+            let character = key.as_str().and_then(parse_character);
+            let substitution = match replacement.as_str() {
+                Some("box") => Some(GlyphSubstitution::Box),
+                Some(glyph) if !glyph.is_empty() => {
+                    Some(GlyphSubstitution::Glyph(glyph.to_owned()))
+                }
+                _ => None,
+            };
+            match (character, substitution) {
+                (Some(character), Some(substitution)) => {
+                    substitutions.insert(character, substitution);
+                }
+                _ => error!(
+                    "Expected a character mapped to a glyph or \"box\", but received {:?} = {:?}",
+                    key, replacement
+                ),
+            }
+        }
+        *self = GlyphSubstitutions(substitutions);
+    }
+}
+
+impl From<GlyphSubstitutions> for Value {
+    fn from(substitutions: GlyphSubstitutions) -> Self {
+        let mut entries: Vec<_> = substitutions.0.into_iter().collect();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Value::Map(
+            entries
+                .into_iter()
+                .map(|(character, substitution)| {
+                    let replacement = match substitution {
+                        GlyphSubstitution::Glyph(glyph) => Value::from(glyph),
+                        GlyphSubstitution::Box => Value::from("box"),
+                    };
+                    (Value::from(character), replacement)
+                })
+                .collect(),
+        )
+    }
+}
+
+impl GlyphSubstitutions {
+    /// Applies the table to the text of a line fragment, returning the text to shape along with
+    /// the columns and widths of the cells to draw boxes over. Boxed characters are replaced with
+    /// spaces, so the rest of the fragment stays aligned to the grid.
+    pub fn substitute(&self, text: String) -> (String, Vec<(u64, u64)>) {
+        if self.0.is_empty() {
+            return (text, Vec::new());
+        }
+
+        let mut substituted = String::with_capacity(text.len());
+        let mut boxes = Vec::new();
+        let mut column = 0;
+        for grapheme in text.graphemes(true) {
+            let width = cell_width(grapheme);
+            match self.0.get(grapheme) {
+                Some(GlyphSubstitution::Glyph(glyph)) => substituted.push_str(glyph),
+                Some(GlyphSubstitution::Box) => {
+                    substituted.push_str(&" ".repeat(width as usize));
+                    boxes.push((column, width));
+                }
+                None => substituted.push_str(grapheme),
+            }
+            column += width;
+        }
+        (substituted, boxes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substituted_characters_are_replaced_and_others_pass_through() {
+        let mut substitutions = GlyphSubstitutions::default();
+        substitutions.parse_from_value(Value::Map(vec![
+            (Value::from("U+2502"), Value::from("|")),
+            (Value::from("█"), Value::from("box")),
+        ]));
+
+        assert_eq!(
+            substitutions.substitute("a│日█b".to_owned()),
+            ("a|日 b".to_owned(), vec![(4, 1)])
+        );
+        assert_eq!(
+            substitutions.substitute("plain".to_owned()),
+            ("plain".to_owned(), Vec::new())
+        );
+    }
+}
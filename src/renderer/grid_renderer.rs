@@ -10,7 +10,10 @@ use crate::{
     dimensions::Dimensions,
     editor::{Colors, Style, UnderlineStyle},
     profiling::tracy_zone,
-    renderer::{CachingShaper, RendererSettings},
+    renderer::{
+        rendered_window::{bleed_background_region, underline_shift},
+        CachingShaper, RendererSettings,
+    },
     settings::*,
     window::WindowSettings,
 };
@@ -49,6 +52,22 @@ impl GridRenderer {
         }
     }
 
+    /// A renderer for the same font and colors at the size scaled by the zoom, which zoomed
+    /// windows are drawn with so that their text is rastered at that size.
+    pub fn zoomed(&self, zoom: f32) -> GridRenderer {
+        let mut grid_renderer = GridRenderer {
+            shaper: self.shaper.zoomed(zoom),
+            paint: self.paint.clone(),
+            default_style: self.default_style.clone(),
+            em_size: self.em_size,
+            font_dimensions: self.font_dimensions,
+            scale_factor: self.scale_factor * zoom as f64,
+            is_ready: self.is_ready,
+        };
+        grid_renderer.update_font_dimensions();
+        grid_renderer
+    }
+
     pub fn font_names(&self) -> Vec<String> {
         self.shaper.font_names()
     }
@@ -107,10 +126,15 @@ impl GridRenderer {
         tracy_zone!("draw_background");
         self.paint.set_blend_mode(BlendMode::Src);
 
-        let region = self.compute_text_region(grid_position, cell_width);
+        let settings = SETTINGS.get::<RendererSettings>();
+        // Only the fill bleeds, the glyphs stay where the grid puts them.
+        let region = bleed_background_region(
+            self.compute_text_region(grid_position, cell_width),
+            settings.background_bleed,
+        );
         let style = style.as_ref().unwrap_or(&self.default_style);
 
-        if SETTINGS.get::<RendererSettings>().debug_renderer {
+        if settings.debug_renderer {
             let random_hsv: HSV = (rand::random::<f32>() * 360.0, 0.3, 0.3).into();
             let random_color = random_hsv.to_color(255);
             self.paint.set_color(random_color);
@@ -120,8 +144,8 @@ impl GridRenderer {
         }
 
         if is_floating {
-            self.paint
-                .set_alpha((255.0 * ((100 - style.blend) as f32 / 100.0)) as u8);
+            let alpha = self.paint.alpha() as f32 * style.background_opacity();
+            self.paint.set_alpha(alpha as u8);
         } else if (SETTINGS.get::<WindowSettings>().transparency - 1.0).abs() > f32::EPSILON
             // Only make background color transparent
             && self.paint.color() == self.get_default_background()
@@ -131,6 +155,25 @@ impl GridRenderer {
         canvas.draw_rect(region, &self.paint);
     }
 
+    /// Whether cells of the style are filled with the default background.
+    pub fn is_default_background(&self, style: &Option<Arc<Style>>) -> bool {
+        let style = style.as_ref().unwrap_or(&self.default_style);
+        style.background(&self.default_style.colors).to_color() == self.get_default_background()
+    }
+
+    /// Makes the cells transparent, for a window background to show through them.
+    pub fn clear_background(
+        &mut self,
+        canvas: &mut Canvas,
+        grid_position: (u64, u64),
+        cell_width: u64,
+    ) {
+        self.paint.set_blend_mode(BlendMode::Clear);
+        let region = self.compute_text_region(grid_position, cell_width);
+        canvas.draw_rect(region, &self.paint);
+        self.paint.set_blend_mode(BlendMode::Src);
+    }
+
     pub fn draw_foreground(
         &mut self,
         canvas: &mut Canvas,
@@ -138,12 +181,29 @@ impl GridRenderer {
         grid_position: (u64, u64),
         cell_width: u64,
         style: &Option<Arc<Style>>,
+    ) {
+        self.draw_highlighted_foreground(canvas, text, grid_position, cell_width, style, &[]);
+    }
+
+    /// Draws the text like `draw_foreground`, but with the glyphs from each of the given columns
+    /// on in the foreground of another style. The text is still shaped as a whole, so ligatures
+    /// can span the highlights.
+    pub fn draw_highlighted_foreground(
+        &mut self,
+        canvas: &mut Canvas,
+        text: String,
+        grid_position: (u64, u64),
+        cell_width: u64,
+        style: &Option<Arc<Style>>,
+        foreground_styles: &[(u64, Option<Arc<Style>>)],
     ) {
         tracy_zone!("draw_foreground");
         let (x, y) = grid_position * self.font_dimensions;
         let width = cell_width * self.font_dimensions.width;
 
         let style = style.as_ref().unwrap_or(&self.default_style);
+        let settings = SETTINGS.get::<RendererSettings>();
+        let (text, boxes) = settings.glyph_substitutions.substitute(text);
 
         // We don't want to clip text in the x position, only the y so we add a buffer of 1
         // character on either side of the region so that we clip vertically but not horizontally.
@@ -162,7 +222,15 @@ impl GridRenderer {
                 (y - line_position + self.font_dimensions.height) as f32,
             );
 
-            self.draw_underline(canvas, style, underline_style, p1.into(), p2.into())
+            let cell_bottom = (y + self.font_dimensions.height) as f32;
+            self.draw_underline(
+                canvas,
+                style,
+                underline_style,
+                p1.into(),
+                p2.into(),
+                cell_bottom,
+            )
         }
 
         canvas.save();
@@ -170,32 +238,82 @@ impl GridRenderer {
 
         let y_adjustment = self.shaper.y_adjustment();
 
-        if SETTINGS.get::<RendererSettings>().debug_renderer {
+        let foreground = if settings.debug_renderer {
             let random_hsv: HSV = (rand::random::<f32>() * 360.0, 1.0, 1.0).into();
-            let random_color = random_hsv.to_color(255);
-            self.paint.set_color(random_color);
+            random_hsv.to_color(255)
         } else {
-            self.paint
-                .set_color(style.foreground(&self.default_style.colors).to_color());
-        }
+            style.foreground(&self.default_style.colors).to_color()
+        };
         self.paint.set_anti_alias(false);
 
-        for blob in self
-            .shaper
-            .shape_cached(text, style.bold, style.italic)
-            .iter()
-        {
-            canvas.draw_text_blob(blob, (x as f32, (y + y_adjustment) as f32), &self.paint);
+        // Each highlight only shows the glyphs in its own columns, while the outer edges keep the
+        // buffer of the whole region.
+        let mut ranges = vec![(0, foreground)];
+        if !settings.debug_renderer {
+            ranges.extend(foreground_styles.iter().map(|(column, range_style)| {
+                let range_style = range_style.as_ref().unwrap_or(&self.default_style);
+                (
+                    *column,
+                    range_style
+                        .foreground(&self.default_style.colors)
+                        .to_color(),
+                )
+            }));
+        }
+        let font_width = self.font_dimensions.width;
+        for (index, (column, color)) in ranges.iter().enumerate() {
+            let left = match index {
+                0 => region.left,
+                _ => (x + column * font_width) as f32,
+            };
+            let right = match ranges.get(index + 1) {
+                Some((end, _)) => (x + end * font_width) as f32,
+                None => region.right,
+            };
+            canvas.save();
+            canvas.clip_rect(
+                Rect::from_ltrb(left, region.top, right, region.bottom),
+                None,
+                Some(false),
+            );
+            self.paint.set_color(*color);
+
+            // Fragments made only of blank cells (e.g. underlined trailing spaces) have no glyphs
+            // to draw, but still need their underline and strikethrough decorations.
+            if !text.trim().is_empty() {
+                for blob in self
+                    .shaper
+                    .shape_cached(text.clone(), style.bold, style.italic)
+                    .iter()
+                {
+                    canvas.draw_text_blob(blob, (x as f32, (y + y_adjustment) as f32), &self.paint);
+                }
+            }
+
+            for (column, box_width) in boxes.iter() {
+                let box_region = Rect::from_xywh(
+                    (x + column * font_width) as f32,
+                    y as f32,
+                    (box_width * font_width) as f32,
+                    self.font_dimensions.height as f32,
+                );
+                canvas.draw_rect(box_region, &self.paint);
+            }
+            canvas.restore();
         }
+        self.paint.set_color(foreground);
 
+        // Drawn in the text color on top of any underline, and placed with the metrics of the
+        // current font so it follows guifont changes.
         if style.strikethrough {
-            let line_position = region.center_y();
-            self.paint
-                .set_color(style.special(&self.default_style.colors).to_color());
+            let (height, stroke_width) = self.shaper.strikethrough_position();
+            let line_position = (y + y_adjustment) as f32 - height;
+            let mut strikethrough_paint = self.paint.clone();
+            strikethrough_paint.set_stroke_width(stroke_width);
             canvas.draw_line(
                 (x as f32, line_position),
                 ((x + width) as f32, line_position),
-                &self.paint,
+                &strikethrough_paint,
             );
         }
 
@@ -209,6 +327,7 @@ impl GridRenderer {
         underline_style: UnderlineStyle,
         p1: Point,
         p2: Point,
+        cell_bottom: f32,
     ) {
         canvas.save();
 
@@ -226,6 +345,16 @@ impl GridRenderer {
             self.shaper.current_size() / 10.
         };
 
+        // How far below the given line the decoration reaches, the curl is drawn a bit higher but
+        // swings down by a stroke width.
+        let reach = match underline_style {
+            UnderlineStyle::UnderCurl => 2.5 * stroke_width - 3.,
+            _ => stroke_width / 2.,
+        };
+        let shift = underline_shift(p1.y, reach, cell_bottom);
+        let p1 = Point::new(p1.x, p1.y + shift);
+        let p2 = Point::new(p2.x, p2.y + shift);
+
         underline_paint
             .set_color(style.special(&self.default_style.colors).to_color())
             .set_stroke_width(stroke_width);
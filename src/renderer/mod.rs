@@ -1,44 +1,65 @@
 pub mod animation_utils;
+mod crosshair;
 pub mod cursor_renderer;
 pub mod fonts;
+mod glyph_substitutions;
 pub mod grid_renderer;
+mod mode_ripple;
 mod opengl;
+mod pending_keys;
+mod popup_menu;
 pub mod profiler;
+mod recording_indicator;
 mod rendered_window;
+mod scroll_overview;
+mod spotlight;
+mod window_separators;
+mod window_timings;
 
 use std::{
     cmp::Ordering,
     collections::{hash_map::Entry, HashMap},
     sync::Arc,
+    time::Instant,
 };
 
 use log::error;
-use skia_safe::{Canvas, Point};
+use skia_safe::{Canvas, Color, Color4f, Paint, PaintStyle, Point, Rect};
 use tokio::sync::mpsc::UnboundedReceiver;
 use winit::event::Event;
 
 use crate::{
     bridge::EditorMode,
-    editor::{Cursor, Style},
+    editor::{
+        parse_color, Crosshair, Cursor, ModeRipple, PendingKeys, PopupMenu, RecordingIndicator,
+        ScrollOverview, Spotlight, Style, WindowSeparators, WindowType,
+    },
     event_aggregator::EVENT_AGGREGATOR,
     profiling::tracy_zone,
+    redraw_scheduler::REDRAW_SCHEDULER,
     settings::*,
     WindowSettings,
 };
 
 use cursor_renderer::CursorRenderer;
 pub use fonts::caching_shaper::CachingShaper;
+use glyph_substitutions::GlyphSubstitutions;
 pub use grid_renderer::GridRenderer;
+use mode_ripple::ModeRippleAnimation;
 pub use rendered_window::{
-    LineFragment, RenderedWindow, WindowDrawCommand, WindowDrawDetails, WindowPadding,
+    LineFragment, OverlayOrder, RenderedWindow, WindowDrawCommand, WindowDrawDetails, WindowPadding,
 };
 
+pub use window_timings::WindowTimings;
+
 pub use opengl::{build_context, build_window, Context as WindowedContext};
 
 #[derive(SettingGroup, Clone)]
 pub struct RendererSettings {
     position_animation_length: f32,
     scroll_animation_length: f32,
+    // Jumps every window to where it scrolled when off.
+    smooth_scroll: bool,
     floating_opacity: f32,
     floating_blur: bool,
     floating_blur_amount_x: f32,
@@ -46,6 +67,22 @@ pub struct RendererSettings {
     debug_renderer: bool,
     profiler: bool,
     underline_automatic_scaling: bool,
+    wrap_indicator: String,
+    extends_indicator: String,
+    dim_overlay_opacity: f32,
+    focus_ring_width: f32,
+    focus_ring_color: String,
+    // Pixels of the line between the message grid and the windows above it.
+    message_separator_width: f32,
+    message_separator_color: String,
+    glyph_substitutions: GlyphSubstitutions,
+    collapse_blank_lines: bool,
+    // How many pixels the background of each line fragment reaches into its neighbors.
+    background_bleed: f32,
+    // Pixels between the rounded edge of a window and its clipped content.
+    corner_padding: f32,
+    // The overlays on top of each window, from the bottom up.
+    overlay_order: OverlayOrder,
 }
 
 impl Default for RendererSettings {
@@ -53,6 +90,7 @@ impl Default for RendererSettings {
         Self {
             position_animation_length: 0.15,
             scroll_animation_length: 0.3,
+            smooth_scroll: true,
             floating_opacity: 0.7,
             floating_blur: true,
             floating_blur_amount_x: 2.0,
@@ -60,6 +98,18 @@ impl Default for RendererSettings {
             debug_renderer: false,
             profiler: false,
             underline_automatic_scaling: false,
+            wrap_indicator: "".to_string(),
+            extends_indicator: "".to_string(),
+            dim_overlay_opacity: 0.5,
+            focus_ring_width: 0.0,
+            focus_ring_color: "".to_string(),
+            message_separator_width: 0.0,
+            message_separator_color: "".to_string(),
+            glyph_substitutions: GlyphSubstitutions::default(),
+            collapse_blank_lines: false,
+            background_bleed: 0.0,
+            corner_padding: 0.0,
+            overlay_order: OverlayOrder::default(),
         }
     }
 }
@@ -76,15 +126,39 @@ pub enum DrawCommand {
     LineSpaceChanged(i64),
     DefaultStyleChanged(Style),
     ModeChanged(EditorMode),
+    PopupMenuChanged(Option<PopupMenu>),
+    // The floating order the dim overlay is drawn below, floats with a lower order get dimmed too.
+    DimOverlayChanged(Option<u64>),
+    PendingKeysChanged(Option<PendingKeys>),
+    // The grid of the window drawn with the focus ring.
+    FocusChanged(Option<u64>),
+    ScrollOverviewChanged(Option<ScrollOverview>),
+    SpotlightChanged(Option<Spotlight>),
+    WindowSeparatorsChanged(Option<WindowSeparators>),
+    CrosshairChanged(Option<Crosshair>),
+    RecordingIndicatorChanged(Option<RecordingIndicator>),
+    // Starts a ripple over the screen, replacing one still fading away.
+    ModeRipple(ModeRipple),
 }
 
 pub struct Renderer {
     cursor_renderer: CursorRenderer,
     pub grid_renderer: GridRenderer,
     current_mode: EditorMode,
+    popup_menu: Option<PopupMenu>,
+    dim_overlay: Option<u64>,
+    pending_keys: Option<PendingKeys>,
+    recording_indicator: Option<RecordingIndicator>,
+    focused_grid: Option<u64>,
+    scroll_overview: Option<ScrollOverview>,
+    spotlight: Option<Spotlight>,
+    window_separators: Option<WindowSeparators>,
+    crosshair: Option<Crosshair>,
+    mode_ripple: Option<ModeRippleAnimation>,
 
     rendered_windows: HashMap<u64, RenderedWindow>,
     pub window_regions: Vec<WindowDrawDetails>,
+    pub window_timings: WindowTimings,
 
     pub batched_draw_command_receiver: UnboundedReceiver<Vec<DrawCommand>>,
     profiler: profiler::Profiler,
@@ -109,19 +183,25 @@ impl Renderer {
         let batched_draw_command_receiver = EVENT_AGGREGATOR.register_event::<Vec<DrawCommand>>();
         let profiler = profiler::Profiler::new(12.0);
 
-        let window_padding = WindowPadding {
-            top: window_settings.padding_top,
-            left: window_settings.padding_left,
-            right: window_settings.padding_right,
-            bottom: window_settings.padding_bottom,
-        };
+        let window_padding = WindowPadding::from_settings(&window_settings);
 
         Renderer {
             rendered_windows,
             cursor_renderer,
             grid_renderer,
             current_mode,
+            popup_menu: None,
+            dim_overlay: None,
+            pending_keys: None,
+            recording_indicator: None,
+            focused_grid: None,
+            scroll_overview: None,
+            spotlight: None,
+            window_separators: None,
+            crosshair: None,
+            mode_ripple: None,
             window_regions,
+            window_timings: WindowTimings::default(),
             batched_draw_command_receiver,
             profiler,
             os_scale_factor,
@@ -138,6 +218,23 @@ impl Renderer {
         self.grid_renderer.font_names()
     }
 
+    /// Returns the url detected under the given grid position of a window, if any.
+    pub fn url_at(&self, grid_id: u64, (column, row): (u32, u32)) -> Option<String> {
+        self.rendered_windows
+            .get(&grid_id)
+            .and_then(|window| window.url_at(column as u64, row as u64))
+            .map(str::to_owned)
+    }
+
+    /// Builds the surfaces of every window again on the canvas of a new graphics context, the
+    /// old ones belong to the one that was lost and can't be drawn to or read from anymore. The
+    /// editor draws their contents again afterwards.
+    pub fn handle_context_lost(&mut self, root_canvas: &mut Canvas) {
+        for window in self.rendered_windows.values_mut() {
+            window.rebuild_surface(root_canvas, &self.grid_renderer);
+        }
+    }
+
     /// Draws frame
     ///
     /// # Returns
@@ -172,6 +269,7 @@ impl Renderer {
             self.user_scale_factor = user_scale_factor;
             self.grid_renderer
                 .handle_scale_factor_update(self.os_scale_factor * self.user_scale_factor);
+            self.update_zoomed_renderers();
             font_changed = true;
         }
 
@@ -199,6 +297,7 @@ impl Renderer {
         };
 
         let settings = SETTINGS.get::<RendererSettings>();
+        let mut dim_overlay = self.dim_overlay;
         self.window_regions = windows
             .into_iter()
             .map(|window| {
@@ -206,22 +305,121 @@ impl Renderer {
                     window.padding = self.window_padding;
                 }
 
-                window.draw(
+                match (dim_overlay, window.floating_order) {
+                    (Some(overlay_order), Some(floating_order))
+                        if floating_order >= overlay_order =>
+                    {
+                        draw_dim_overlay(root_canvas, settings.dim_overlay_opacity);
+                        dim_overlay = None;
+                    }
+                    _ => {}
+                }
+
+                let start = Instant::now();
+                let details = window.draw(
                     root_canvas,
                     &settings,
                     default_background.with_a((255.0 * transparency) as u8),
                     font_dimensions,
                     dt,
-                )
+                );
+                window.draw_overlays(root_canvas, &mut self.grid_renderer, &settings);
+                if window.window_type == WindowType::Message {
+                    draw_message_separator(root_canvas, details.region, &settings);
+                }
+                if self.focused_grid == Some(window.id) {
+                    draw_focus_ring(root_canvas, details.region, &settings);
+                }
+                self.window_timings.add(window.id, start.elapsed());
+                details
             })
             .collect();
+        // The windows are drawn in one pass, so the separators end up over floats crossing them.
+        if let Some(window_separators) = &self.window_separators {
+            window_separators::draw_window_separators(
+                &self.grid_renderer,
+                root_canvas,
+                window_separators,
+                &self.window_padding,
+            );
+        }
+        if let Some(crosshair) = &self.crosshair {
+            crosshair::draw_crosshair(
+                &self.grid_renderer,
+                root_canvas,
+                crosshair,
+                &self.window_padding,
+            );
+        }
+        // The ripple is only drawn over the frame, the windows underneath stay as they are.
+        if let Some(mode_ripple) = &mut self.mode_ripple {
+            if !mode_ripple.draw(&self.grid_renderer, root_canvas, &self.window_padding, dt) {
+                self.mode_ripple = None;
+            }
+        }
+        // Nothing floats above the overlay, so it still covers everything.
+        if dim_overlay.is_some() {
+            draw_dim_overlay(root_canvas, settings.dim_overlay_opacity);
+        }
+        self.window_timings.finish_frame();
+
+        if let Some(spotlight) = &self.spotlight {
+            spotlight::draw_spotlight(
+                &self.grid_renderer,
+                root_canvas,
+                spotlight,
+                &self.window_padding,
+            );
+        }
+
+        if let Some(scroll_overview) = &self.scroll_overview {
+            scroll_overview::draw_scroll_overview(
+                &self.grid_renderer,
+                root_canvas,
+                scroll_overview,
+                &self.window_padding,
+            );
+        }
+
+        if let Some(popup_menu) = &self.popup_menu {
+            popup_menu::draw_popup_menu(
+                &mut self.grid_renderer,
+                root_canvas,
+                popup_menu,
+                &self.window_padding,
+            );
+        }
+
+        if let Some(pending_keys) = &self.pending_keys {
+            pending_keys::draw_pending_keys(
+                &mut self.grid_renderer,
+                root_canvas,
+                pending_keys,
+                &self.window_padding,
+            );
+        }
+
+        if let Some(recording_indicator) = &self.recording_indicator {
+            recording_indicator::draw_recording_indicator(
+                &mut self.grid_renderer,
+                root_canvas,
+                recording_indicator,
+                &self.window_padding,
+            );
+        }
 
         let windows = &self.rendered_windows;
         self.cursor_renderer
             .update_cursor_destination(font_dimensions.into(), windows);
 
+        // The cursor is drawn at the size of the text of a zoomed window it's in.
+        let cursor_grid_renderer = self
+            .rendered_windows
+            .get_mut(&self.cursor_renderer.grid_id())
+            .and_then(RenderedWindow::zoomed_renderer_mut)
+            .unwrap_or(&mut self.grid_renderer);
         self.cursor_renderer
-            .draw(&mut self.grid_renderer, &self.current_mode, root_canvas, dt);
+            .draw(cursor_grid_renderer, &self.current_mode, root_canvas, dt);
 
         self.profiler.draw(root_canvas, dt);
 
@@ -234,6 +432,14 @@ impl Renderer {
         self.os_scale_factor = os_scale_factor;
         self.grid_renderer
             .handle_scale_factor_update(self.os_scale_factor * self.user_scale_factor);
+        self.update_zoomed_renderers();
+    }
+
+    // Zoomed windows draw with a font scaled from the main one, so they follow its changes.
+    fn update_zoomed_renderers(&mut self) {
+        for window in self.rendered_windows.values_mut() {
+            window.update_zoomed_renderer(&self.grid_renderer);
+        }
     }
 
     fn handle_draw_command(&mut self, root_canvas: &mut Canvas, draw_command: DrawCommand) {
@@ -243,18 +449,22 @@ impl Renderer {
                 command: WindowDrawCommand::Close,
             } => {
                 self.rendered_windows.remove(&grid_id);
+                self.window_timings.remove(grid_id);
             }
             DrawCommand::Window { grid_id, command } => {
                 match self.rendered_windows.entry(grid_id) {
                     Entry::Occupied(mut occupied_entry) => {
                         let rendered_window = occupied_entry.get_mut();
+                        let start = Instant::now();
                         rendered_window
                             .handle_window_draw_command(&mut self.grid_renderer, command);
+                        self.window_timings.add(grid_id, start.elapsed());
                     }
                     Entry::Vacant(vacant_entry) => {
                         if let WindowDrawCommand::Position {
                             grid_position: (grid_left, grid_top),
                             grid_size: (width, height),
+                            window_type,
                             ..
                         } = command
                         {
@@ -262,6 +472,7 @@ impl Renderer {
                                 root_canvas,
                                 &self.grid_renderer,
                                 grid_id,
+                                window_type,
                                 (grid_left as f32, grid_top as f32).into(),
                                 (width, height).into(),
                                 self.window_padding,
@@ -278,16 +489,60 @@ impl Renderer {
             }
             DrawCommand::FontChanged(new_font) => {
                 self.grid_renderer.update_font(&new_font);
+                self.update_zoomed_renderers();
             }
             DrawCommand::LineSpaceChanged(new_linespace) => {
                 self.grid_renderer.update_linespace(new_linespace);
+                self.update_zoomed_renderers();
             }
             DrawCommand::DefaultStyleChanged(new_style) => {
                 self.grid_renderer.default_style = Arc::new(new_style);
+                for window in self.rendered_windows.values_mut() {
+                    window.set_default_style(&self.grid_renderer.default_style);
+                }
             }
             DrawCommand::ModeChanged(new_mode) => {
                 self.current_mode = new_mode;
             }
+            DrawCommand::PopupMenuChanged(popup_menu) => {
+                self.popup_menu = popup_menu;
+            }
+            DrawCommand::DimOverlayChanged(dim_overlay) => {
+                self.dim_overlay = dim_overlay;
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            DrawCommand::SpotlightChanged(spotlight) => {
+                self.spotlight = spotlight;
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            DrawCommand::CrosshairChanged(crosshair) => {
+                self.crosshair = crosshair;
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            DrawCommand::ModeRipple(ripple) => {
+                self.mode_ripple = Some(ModeRippleAnimation::new(ripple));
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            DrawCommand::WindowSeparatorsChanged(window_separators) => {
+                self.window_separators = window_separators;
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            DrawCommand::ScrollOverviewChanged(scroll_overview) => {
+                self.scroll_overview = scroll_overview;
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            DrawCommand::FocusChanged(focused_grid) => {
+                self.focused_grid = focused_grid;
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            DrawCommand::PendingKeysChanged(pending_keys) => {
+                self.pending_keys = pending_keys;
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            DrawCommand::RecordingIndicatorChanged(recording_indicator) => {
+                self.recording_indicator = recording_indicator;
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
             _ => {}
         }
     }
@@ -323,3 +578,45 @@ fn floating_sort(window_a: &&mut RenderedWindow, window_b: &&mut RenderedWindow)
     }
     ord
 }
+
+// Drawn inside the window's region, so that it still shows for windows at the edges of the screen
+// and doesn't cover the cells of its neighbours.
+fn draw_focus_ring(root_canvas: &mut Canvas, region: Rect, settings: &RendererSettings) {
+    if settings.focus_ring_width <= 0.0 {
+        return;
+    }
+
+    let color = parse_color(&settings.focus_ring_color).unwrap_or(Color4f::new(1.0, 1.0, 1.0, 0.5));
+    let mut paint = Paint::new(color, None);
+    paint.set_style(PaintStyle::Stroke);
+    paint.set_stroke_width(settings.focus_ring_width);
+    let inset = settings.focus_ring_width / 2.0;
+    root_canvas.draw_rect(region.with_inset((inset, inset)), &paint);
+}
+
+// Drawn along the top of the message grid, over its first row like the focus ring.
+fn draw_message_separator(root_canvas: &mut Canvas, region: Rect, settings: &RendererSettings) {
+    if settings.message_separator_width <= 0.0 {
+        return;
+    }
+
+    let color =
+        parse_color(&settings.message_separator_color).unwrap_or(Color4f::new(0.5, 0.5, 0.5, 0.5));
+    let paint = Paint::new(color, None);
+    let height = settings.message_separator_width.min(region.height());
+    root_canvas.draw_rect(
+        Rect::from_xywh(region.left, region.top, region.width(), height),
+        &paint,
+    );
+}
+
+fn draw_dim_overlay(root_canvas: &mut Canvas, opacity: f32) {
+    let mut paint = Paint::default();
+    paint.set_color(Color::from_argb(
+        (255.0 * opacity.clamp(0.0, 1.0)) as u8,
+        0,
+        0,
+        0,
+    ));
+    root_canvas.draw_paint(&paint);
+}
@@ -0,0 +1,78 @@
+use skia_safe::{Canvas, Paint, PaintStyle, Point, Rect};
+
+use crate::{
+    editor::ModeRipple,
+    redraw_scheduler::REDRAW_SCHEDULER,
+    renderer::{animation_utils::ease_out_cubic, GridRenderer, WindowPadding},
+};
+
+// Seconds the ripple takes to spread and fade away.
+const MODE_RIPPLE_LENGTH: f32 = 0.4;
+
+/// A ripple along with how long it has been shown, which is dropped once it faded away.
+pub struct ModeRippleAnimation {
+    ripple: ModeRipple,
+    elapsed: f32,
+}
+
+impl ModeRippleAnimation {
+    pub fn new(ripple: ModeRipple) -> Self {
+        Self {
+            ripple,
+            elapsed: 0.0,
+        }
+    }
+
+    /// Draws the next frame over whatever was drawn before, returning false once it's done.
+    pub fn draw(
+        &mut self,
+        grid_renderer: &GridRenderer,
+        canvas: &mut Canvas,
+        padding: &WindowPadding,
+        dt: f32,
+    ) -> bool {
+        self.elapsed += dt;
+        let t = self.elapsed / MODE_RIPPLE_LENGTH;
+        if t >= 1.0 {
+            return false;
+        }
+
+        let progress = ease_out_cubic(t);
+        let mut color = self.ripple.color;
+        color.a *= 1.0 - progress;
+        let mut paint = Paint::new(color, None);
+
+        let size = canvas.base_layer_size();
+        let screen = Rect::from_iwh(size.width, size.height);
+        match self.ripple.center {
+            Some((column, row)) => {
+                let font_dimensions = grid_renderer.font_dimensions;
+                let content_origin = padding.content_origin(font_dimensions);
+                let center = Point::new(
+                    (column as f32 + content_origin.x) * font_dimensions.width as f32,
+                    (row as f32 + content_origin.y) * font_dimensions.height as f32,
+                );
+                // The ring reaches the farthest corner of the screen by the end.
+                let max_radius = [
+                    (screen.left, screen.top),
+                    (screen.right, screen.top),
+                    (screen.left, screen.bottom),
+                    (screen.right, screen.bottom),
+                ]
+                .into_iter()
+                .map(|corner| Point::distance(center, Point::from(corner)))
+                .fold(0.0, f32::max);
+
+                paint.set_style(PaintStyle::Stroke);
+                paint.set_stroke_width(font_dimensions.height as f32);
+                canvas.draw_circle(center, max_radius * progress, &paint);
+            }
+            None => {
+                canvas.draw_rect(screen, &paint);
+            }
+        }
+
+        REDRAW_SCHEDULER.queue_next_frame();
+        true
+    }
+}
@@ -0,0 +1,40 @@
+use std::sync::Arc;
+
+use skia_safe::Canvas;
+
+use crate::{
+    editor::{PendingKeys, Style},
+    renderer::{GridRenderer, WindowPadding},
+};
+
+pub fn draw_pending_keys(
+    grid_renderer: &mut GridRenderer,
+    canvas: &mut Canvas,
+    pending_keys: &PendingKeys,
+    padding: &WindowPadding,
+) {
+    // Reversed so that it stands out from the text behind it whatever the colorscheme is.
+    let mut style = Style::new(grid_renderer.default_style.colors.clone());
+    style.reverse = true;
+    let style = Some(Arc::new(style));
+
+    let font_dimensions = grid_renderer.font_dimensions;
+    let content_origin = padding.content_origin(font_dimensions);
+    let (grid_left, grid_top) = pending_keys.grid_position;
+    let width = pending_keys.width();
+
+    canvas.save();
+    canvas.translate((
+        (grid_left as f32 + content_origin.x) * font_dimensions.width as f32,
+        (grid_top as f32 + content_origin.y) * font_dimensions.height as f32,
+    ));
+    grid_renderer.draw_background(canvas, (0, 0), width, &style, false);
+    grid_renderer.draw_foreground(
+        canvas,
+        format!(" {}", pending_keys.keys),
+        (0, 0),
+        width,
+        &style,
+    );
+    canvas.restore();
+}
@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use skia_safe::Canvas;
+
+use crate::{
+    editor::{parse_color, PopupMenu, PopupMenuSettings, Style},
+    renderer::{GridRenderer, WindowPadding},
+    settings::SETTINGS,
+};
+
+pub fn draw_popup_menu(
+    grid_renderer: &mut GridRenderer,
+    canvas: &mut Canvas,
+    popup_menu: &PopupMenu,
+    padding: &WindowPadding,
+) {
+    let settings = SETTINGS.get::<PopupMenuSettings>();
+
+    let mut item_style = Style::new(grid_renderer.default_style.colors.clone());
+    if let Some(background) = parse_color(&settings.background_color) {
+        item_style.colors.background = Some(background);
+    }
+
+    // Without an explicit color the selection is shown in reverse video.
+    let mut selected_style = item_style.clone();
+    match parse_color(&settings.selected_background_color) {
+        Some(background) => selected_style.colors.background = Some(background),
+        None => selected_style.reverse = true,
+    }
+
+    let item_style = Some(Arc::new(item_style));
+    let selected_style = Some(Arc::new(selected_style));
+
+    let font_dimensions = grid_renderer.font_dimensions;
+    let content_origin = padding.content_origin(font_dimensions);
+    let (grid_left, grid_top) = popup_menu.grid_position;
+    let width = popup_menu.width();
+
+    canvas.save();
+    canvas.translate((
+        (grid_left as f32 + content_origin.x) * font_dimensions.width as f32,
+        (grid_top as f32 + content_origin.y) * font_dimensions.height as f32,
+    ));
+
+    for (row, item) in popup_menu.visible_items().iter().enumerate() {
+        let index = popup_menu.scroll_offset + row;
+        let style = if popup_menu.selected == Some(index) {
+            &selected_style
+        } else {
+            &item_style
+        };
+
+        let grid_position = (0, row as u64);
+        grid_renderer.draw_background(canvas, grid_position, width, style, false);
+        grid_renderer.draw_foreground(
+            canvas,
+            format!(" {}", item.label()),
+            grid_position,
+            width,
+            style,
+        );
+    }
+
+    canvas.restore();
+}
@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use skia_safe::{Canvas, Paint};
+
+use crate::{
+    editor::{RecordingIndicator, Style},
+    renderer::{GridRenderer, WindowPadding},
+};
+
+pub fn draw_recording_indicator(
+    grid_renderer: &mut GridRenderer,
+    canvas: &mut Canvas,
+    recording_indicator: &RecordingIndicator,
+    padding: &WindowPadding,
+) {
+    // Reversed like the pending keys, so that it stands out whatever the colorscheme is.
+    let mut style = Style::new(grid_renderer.default_style.colors.clone());
+    style.reverse = true;
+    let style = Some(Arc::new(style));
+
+    let font_dimensions = grid_renderer.font_dimensions;
+    let content_origin = padding.content_origin(font_dimensions);
+    let (grid_left, grid_top) = recording_indicator.grid_position;
+    let width = recording_indicator.width();
+    let cell_width = font_dimensions.width as f32;
+    let cell_height = font_dimensions.height as f32;
+
+    canvas.save();
+    canvas.translate((
+        (grid_left as f32 + content_origin.x) * cell_width,
+        (grid_top as f32 + content_origin.y) * cell_height,
+    ));
+    grid_renderer.draw_background(canvas, (0, 0), width, &style, false);
+    grid_renderer.draw_foreground(
+        canvas,
+        format!("   {}", recording_indicator.register),
+        (0, 0),
+        width,
+        &style,
+    );
+
+    // The dot fills the second cell, as much of it as fits in its width.
+    let mut paint = Paint::new(recording_indicator.color, None);
+    paint.set_anti_alias(true);
+    let radius = cell_width.min(cell_height) * 0.4;
+    canvas.draw_circle((cell_width * 1.5, cell_height * 0.5), radius, &paint);
+    canvas.restore();
+}
@@ -1,31 +1,49 @@
-use std::{collections::VecDeque, sync::Arc};
+use std::{
+    collections::VecDeque,
+    sync::{
+        mpsc::{channel, Receiver, TryRecvError},
+        Arc,
+    },
+    thread,
+};
 
 use skia_safe::{
     canvas::{SaveLayerRec, SrcRectConstraint},
+    color_filters,
     gpu::{Budgeted, SurfaceOrigin},
     image_filters::blur,
-    BlendMode, Canvas, Color, Image, ImageInfo, Paint, Point, Rect, SamplingOptions, Surface,
-    SurfaceProps, SurfacePropsFlags,
+    BlendMode, Canvas, Color, Color4f, Data, Image, ImageInfo, Paint, Point, RRect, Rect,
+    SamplingOptions, Shader, Surface, SurfaceProps, SurfacePropsFlags, TileMode,
 };
 
 use crate::{
     dimensions::Dimensions,
-    editor::Style,
+    editor::{
+        BackgroundImage, BackgroundImageFit, BackgroundKind, EdgeIndicator, IndentGuide, Style,
+        UrlRange, VisualBlock, WindowType,
+    },
     profiling::tracy_zone,
     redraw_scheduler::REDRAW_SCHEDULER,
     renderer::{animation_utils::*, GridRenderer, RendererSettings},
+    settings::{ParseFromValue, SETTINGS},
+    window::WindowSettings,
 };
+use log::error;
+use nvim_rs::Value;
 use winit::dpi::PhysicalSize;
 
 use super::opengl::clamp_render_buffer_size;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct LineFragment {
     pub text: String,
     pub window_left: u64,
     pub window_top: u64,
     pub width: u64,
     pub style: Option<Arc<Style>>,
+    // The columns from which the glyphs are drawn in the foreground of another style, for text
+    // kept together across highlights so that its ligatures are shaped.
+    pub foreground_styles: Vec<(u64, Option<Arc<Style>>)>,
 }
 
 #[derive(Clone, Debug)]
@@ -34,6 +52,15 @@ pub enum WindowDrawCommand {
         grid_position: (f64, f64),
         grid_size: (u64, u64),
         floating_order: Option<u64>,
+        zoom: f32,
+        // Message grids are drawn apart from the editor grids above them.
+        window_type: WindowType,
+        // Rounds the pixel position of the window, including the ones it's animated through.
+        snap_to_pixels: bool,
+    },
+    // Restacks a window without moving or redrawing it.
+    Reorder {
+        floating_order: Option<u64>,
     },
     DrawLine(Vec<LineFragment>),
     Scroll {
@@ -45,12 +72,43 @@ pub enum WindowDrawCommand {
         cols: i64,
     },
     Clear,
+    // Clears the cells of the rows `top..bottom` and columns `left..right`.
+    ClearRegion {
+        top: u64,
+        bottom: u64,
+        left: u64,
+        right: u64,
+    },
     Show,
     Hide,
     Close,
     Viewport {
+        // How many lines fit, jumps further than that only animate the last of them.
+        line_height: f64,
         scroll_delta: f64,
+        // Jumps straight to the new position when false.
+        animated: bool,
     },
+    Brightness(f32),
+    // How much of what's under the window shows through all of it, from 0 to 100.
+    Blend(u8),
+    Mirrored(bool),
+    CornerRadius(f32),
+    Breadcrumb(Option<LineFragment>),
+    // The fonts missing glyphs are looked up in before the system fallback, in order.
+    FontFallback(Arc<Vec<String>>),
+    ScrollPercentage(Option<LineFragment>),
+    Diagnostics(Vec<LineFragment>),
+    Blame(Vec<LineFragment>),
+    // Drawn beneath the cells with the default background, which are left transparent over it.
+    Background {
+        kind: Option<BackgroundKind>,
+    },
+    Urls(Vec<UrlRange>),
+    LineNumbers(Vec<LineFragment>),
+    EdgeIndicators(Vec<(u64, EdgeIndicator)>),
+    VisualBlock(Option<(VisualBlock, Color4f)>),
+    IndentGuides(Vec<IndentGuide>, Color4f),
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -61,6 +119,205 @@ pub struct WindowPadding {
     pub bottom: u32,
 }
 
+impl WindowPadding {
+    pub fn from_settings(window_settings: &WindowSettings) -> WindowPadding {
+        // The margin is applied evenly on every side on top of the individual paddings.
+        let margin = window_settings.margin;
+        WindowPadding {
+            top: window_settings.padding_top + margin,
+            left: window_settings.padding_left + margin,
+            right: window_settings.padding_right + margin,
+            bottom: window_settings.padding_bottom + margin,
+        }
+    }
+
+    /// Offset of the grid content from the OS window origin, in grid cells.
+    pub fn content_origin(&self, font_dimensions: Dimensions) -> Point {
+        Point::new(
+            self.left as f32 / font_dimensions.width as f32,
+            self.top as f32 / font_dimensions.height as f32,
+        )
+    }
+}
+
+/// The decorations drawn on top of a window's grid.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WindowOverlay {
+    LineNumbers,
+    EdgeIndicators,
+    IndentGuides,
+    VisualBlock,
+    Breadcrumb,
+    Diagnostics,
+    Blame,
+    ScrollPercentage,
+}
+
+impl WindowOverlay {
+    const ALL: [WindowOverlay; 8] = [
+        WindowOverlay::LineNumbers,
+        WindowOverlay::EdgeIndicators,
+        WindowOverlay::IndentGuides,
+        WindowOverlay::VisualBlock,
+        WindowOverlay::Breadcrumb,
+        WindowOverlay::Diagnostics,
+        WindowOverlay::Blame,
+        WindowOverlay::ScrollPercentage,
+    ];
+
+    fn from_name(name: &str) -> Option<WindowOverlay> {
+        match name {
+            "line_numbers" => Some(WindowOverlay::LineNumbers),
+            "edge_indicators" => Some(WindowOverlay::EdgeIndicators),
+            "indent_guides" => Some(WindowOverlay::IndentGuides),
+            "visual_block" => Some(WindowOverlay::VisualBlock),
+            "breadcrumb" => Some(WindowOverlay::Breadcrumb),
+            "diagnostics" => Some(WindowOverlay::Diagnostics),
+            "blame" => Some(WindowOverlay::Blame),
+            "scroll_percentage" => Some(WindowOverlay::ScrollPercentage),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            WindowOverlay::LineNumbers => "line_numbers",
+            WindowOverlay::EdgeIndicators => "edge_indicators",
+            WindowOverlay::IndentGuides => "indent_guides",
+            WindowOverlay::VisualBlock => "visual_block",
+            WindowOverlay::Breadcrumb => "breadcrumb",
+            WindowOverlay::Diagnostics => "diagnostics",
+            WindowOverlay::Blame => "blame",
+            WindowOverlay::ScrollPercentage => "scroll_percentage",
+        }
+    }
+}
+
+/// The order the overlays of every window are drawn in, from the bottom up. Parsed once when
+/// `neovide_overlay_order` is set, from its comma separated overlay names.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OverlayOrder(Vec<WindowOverlay>);
+
+impl Default for OverlayOrder {
+    fn default() -> Self {
+        OverlayOrder(WindowOverlay::ALL.to_vec())
+    }
+}
+
+impl OverlayOrder {
+    pub fn overlays(&self) -> &[WindowOverlay] {
+        &self.0
+    }
+}
+
+impl ParseFromValue for OverlayOrder {
+    fn parse_from_value(&mut self, value: Value) {
+        match value.as_str() {
+            Some(order) => *self = OverlayOrder(overlay_order(order)),
+            None => error!(
+                "Setting expected a comma separated list of overlays, but received {:?}",
+                value
+            ),
+        }
+    }
+}
+
+impl From<OverlayOrder> for Value {
+    fn from(order: OverlayOrder) -> Self {
+        let names: Vec<_> = order.0.iter().map(|overlay| overlay.name()).collect();
+        Value::from(names.join(","))
+    }
+}
+
+// Overlays which aren't named are drawn first, below the named ones, in their default order.
+fn overlay_order(order: &str) -> Vec<WindowOverlay> {
+    let mut named = Vec::new();
+    for name in order
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        match WindowOverlay::from_name(name) {
+            Some(overlay) if !named.contains(&overlay) => named.push(overlay),
+            Some(_) => {}
+            None => error!("Unknown overlay {:?} in the overlay order", name),
+        }
+    }
+
+    let mut overlays: Vec<_> = WindowOverlay::ALL
+        .into_iter()
+        .filter(|overlay| !named.contains(overlay))
+        .collect();
+    overlays.extend(named);
+    overlays
+}
+
+/// Drops the styles of a line made only of whitespace, so that it's drawn with the default
+/// background instead of fills like the cursorline or colorcolumn. Returns whether the line was
+/// blank.
+pub fn collapse_blank_line(line_fragments: &mut [LineFragment]) -> bool {
+    let blank = line_fragments
+        .iter()
+        .all(|fragment| fragment.text.trim().is_empty());
+    if blank {
+        for fragment in line_fragments.iter_mut() {
+            fragment.style = None;
+        }
+    }
+    blank
+}
+
+/// Widens the background of a line fragment by `bleed` pixels on both sides, so that neighboring
+/// fragments overlap instead of leaving seams where their edges get rounded apart. Only the width
+/// grows, a taller rect would reach into lines which are redrawn independently.
+pub fn bleed_background_region(region: Rect, bleed: f32) -> Rect {
+    let bleed = bleed.max(0.0);
+    Rect::new(
+        region.left - bleed,
+        region.top,
+        region.right + bleed,
+        region.bottom,
+    )
+}
+
+/// How far an underline drawn at `line_y`, and reaching `reach` pixels below it, has to be moved up
+/// to stay inside its cell. The line below paints its background over anything past the cell's
+/// bottom, so thick lines and curls are lifted instead of being cut off.
+pub fn underline_shift(line_y: f32, reach: f32, cell_bottom: f32) -> f32 {
+    (cell_bottom - (line_y + reach)).min(0.0)
+}
+
+/// The area the content of a window with rounded corners is clipped to, as the rect and the radius
+/// of its corners. The content sits `padding` pixels inside the window's edge, with its corners
+/// rounded to follow the window's. Windows without rounded corners aren't clipped any further.
+pub fn rounded_content_clip(region: Rect, corner_radius: f32, padding: f32) -> Option<(Rect, f32)> {
+    if corner_radius <= 0.0 {
+        return None;
+    }
+    let padding = padding.max(0.0);
+    let rect = Rect::new(
+        region.left + padding,
+        region.top + padding,
+        (region.right - padding).max(region.left + padding),
+        (region.bottom - padding).max(region.top + padding),
+    );
+    let radius = (corner_radius - padding)
+        .min(rect.width() / 2.0)
+        .min(rect.height() / 2.0)
+        .max(0.0);
+    Some((rect, radius))
+}
+
+/// The horizontal scale and offset which flip a window drawn in the given region around its
+/// center, so that x ends up at `offset + scale * x`. Unmirrored windows keep the identity.
+pub fn mirror_transform(region: Rect, mirrored: bool) -> (f32, f32) {
+    if mirrored {
+        (-1.0, region.left + region.right)
+    } else {
+        (1.0, 0.0)
+    }
+}
+
 fn build_window_surface(parent_canvas: &mut Canvas, pixel_size: PhysicalSize<u32>) -> Surface {
     let pixel_size = clamp_render_buffer_size(pixel_size);
     let mut context = parent_canvas.recording_context().unwrap();
@@ -102,6 +359,129 @@ fn build_window_surface_with_grid_size(
     surface
 }
 
+// Decoded right away rather than lazily when it's first drawn, since this runs apart from the
+// render thread, see `WindowBackground::load_apart`.
+fn load_background_image(background_image: BackgroundImage) -> Option<(BackgroundImage, Image)> {
+    let image = std::fs::read(&background_image.path)
+        .ok()
+        .and_then(|bytes| Image::from_encoded(Data::new_copy(&bytes)))
+        .and_then(|image| image.to_raster_image(None));
+
+    match image {
+        Some(image) => Some((background_image, image)),
+        None => {
+            error!(
+                "Could not load the background image {}",
+                background_image.path.display()
+            );
+            None
+        }
+    }
+}
+
+// A background with its image already loaded, so that it's only read once.
+enum WindowBackground {
+    Color(Color4f),
+    Gradient {
+        from: Color4f,
+        to: Color4f,
+        horizontal: bool,
+    },
+    Image(BackgroundImage, Image),
+}
+
+impl WindowBackground {
+    fn load(kind: BackgroundKind) -> Option<WindowBackground> {
+        match kind {
+            BackgroundKind::Color(color) => Some(WindowBackground::Color(color)),
+            BackgroundKind::Gradient {
+                from,
+                to,
+                horizontal,
+            } => Some(WindowBackground::Gradient {
+                from,
+                to,
+                horizontal,
+            }),
+            BackgroundKind::Image(background_image) => load_background_image(background_image)
+                .map(|(background_image, image)| WindowBackground::Image(background_image, image)),
+        }
+    }
+
+    // Images are read and decoded on their own thread so that the frames don't wait on the disk.
+    // The next frame is queued once it's done.
+    fn load_apart(kind: BackgroundKind) -> Receiver<Option<WindowBackground>> {
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            if sender.send(WindowBackground::load(kind)).is_ok() {
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+        });
+        receiver
+    }
+
+    // Drawn under what's already in the region, so it only shows where that is transparent.
+    fn draw_beneath(&self, canvas: &mut Canvas, region: Rect) {
+        let mut paint = Paint::default();
+        paint.set_blend_mode(BlendMode::DstOver);
+        match self {
+            WindowBackground::Color(color) => {
+                paint.set_color(color.to_color());
+                canvas.draw_rect(region, &paint);
+            }
+            WindowBackground::Gradient {
+                from,
+                to,
+                horizontal,
+            } => {
+                let end = if *horizontal {
+                    Point::new(region.right, region.top)
+                } else {
+                    Point::new(region.left, region.bottom)
+                };
+                paint.set_shader(Shader::linear_gradient(
+                    (Point::new(region.left, region.top), end),
+                    &[from.to_color(), to.to_color()][..],
+                    None,
+                    TileMode::Clamp,
+                    None,
+                    None,
+                ));
+                canvas.draw_rect(region, &paint);
+            }
+            WindowBackground::Image(background_image, image) => {
+                paint.set_alpha_f(background_image.opacity);
+                canvas.draw_image_rect_with_sampling_options(
+                    image,
+                    None,
+                    background_image_rect(image, region, background_image.fit),
+                    SamplingOptions::default(),
+                    &paint,
+                );
+            }
+        }
+    }
+}
+
+// Where the image is drawn to fit it into the window region. Cover and contain keep the aspect
+// ratio and center the image, the window clip takes care of the cropped parts.
+fn background_image_rect(image: &Image, region: Rect, fit: BackgroundImageFit) -> Rect {
+    let (width, height) = (image.width() as f32, image.height() as f32);
+    let scale = match fit {
+        BackgroundImageFit::Stretch => return region,
+        BackgroundImageFit::Cover => (region.width() / width).max(region.height() / height),
+        BackgroundImageFit::Contain => (region.width() / width).min(region.height() / height),
+    };
+
+    let (width, height) = (width * scale, height * scale);
+    Rect::from_xywh(
+        region.center_x() - width / 2.0,
+        region.center_y() - height / 2.0,
+        width,
+        height,
+    )
+}
+
 pub struct LocatedSnapshot {
     image: Image,
     vertical_position: f32,
@@ -141,6 +521,7 @@ pub struct RenderedWindow {
     pub current_surface: LocatedSurface,
 
     pub id: u64,
+    pub window_type: WindowType,
     pub hidden: bool,
     pub floating_order: Option<u64>,
 
@@ -157,6 +538,28 @@ pub struct RenderedWindow {
     scroll_t: f32,
 
     pub padding: WindowPadding,
+    zoom: f32,
+    // Draws the window at the zoomed font size, none without a zoom.
+    zoomed_renderer: Option<GridRenderer>,
+    brightness: f32,
+    blend: u8,
+    snap_to_pixels: bool,
+    mirrored: bool,
+    corner_radius: f32,
+    background: Option<WindowBackground>,
+    // The background being loaded, the previous one is still drawn until it's done.
+    loading_background: Option<Receiver<Option<WindowBackground>>>,
+    urls: Vec<UrlRange>,
+    line_numbers: Vec<LineFragment>,
+    breadcrumb: Option<LineFragment>,
+    font_fallback: Arc<Vec<String>>,
+    scroll_percentage: Option<LineFragment>,
+    diagnostics: Vec<LineFragment>,
+    blame: Vec<LineFragment>,
+    edge_indicators: Vec<(u64, EdgeIndicator)>,
+    visual_block: Option<(VisualBlock, Color4f)>,
+    indent_guides: Vec<IndentGuide>,
+    indent_guide_color: Color4f,
 }
 
 #[derive(Clone, Debug)]
@@ -171,6 +574,7 @@ impl RenderedWindow {
         parent_canvas: &mut Canvas,
         grid_renderer: &GridRenderer,
         id: u64,
+        window_type: WindowType,
         grid_position: Point,
         grid_size: Dimensions,
         padding: WindowPadding,
@@ -181,6 +585,7 @@ impl RenderedWindow {
             snapshots: VecDeque::new(),
             current_surface,
             id,
+            window_type,
             hidden: false,
             floating_order: None,
 
@@ -196,23 +601,120 @@ impl RenderedWindow {
             scroll_destination: 0.0,
             scroll_t: 2.0, // 2.0 is out of the 0.0 to 1.0 range and stops animation.
             padding,
+            zoom: 1.0,
+            zoomed_renderer: None,
+            brightness: 1.0,
+            blend: 0,
+            snap_to_pixels: false,
+            mirrored: false,
+            corner_radius: 0.0,
+            background: None,
+            loading_background: None,
+            urls: Vec::new(),
+            line_numbers: Vec::new(),
+            breadcrumb: None,
+            font_fallback: Arc::default(),
+            scroll_percentage: None,
+            diagnostics: Vec::new(),
+            blame: Vec::new(),
+            edge_indicators: Vec::new(),
+            visual_block: None,
+            indent_guides: Vec::new(),
+            indent_guide_color: Color4f::new(0.0, 0.0, 0.0, 0.0),
+        }
+    }
+
+    // Zoomed windows are drawn in the cells of the zoomed font, their position is still in those
+    // of the main one.
+    pub fn cell_size(&self, font_dimensions: Dimensions) -> Dimensions {
+        match &self.zoomed_renderer {
+            Some(zoomed_renderer) => zoomed_renderer.font_dimensions,
+            None => font_dimensions,
+        }
+    }
+
+    /// The renderer the window is drawn with when it's zoomed.
+    pub fn zoomed_renderer_mut(&mut self) -> Option<&mut GridRenderer> {
+        self.zoomed_renderer.as_mut()
+    }
+
+    // The cells change size with the zoom, so the surface is built again at the new one and the
+    // editor draws the window into it again.
+    fn set_zoom(&mut self, zoom: f32, grid_renderer: &GridRenderer) {
+        self.zoom = zoom;
+        self.zoomed_renderer =
+            ((zoom - 1.0).abs() > f32::EPSILON).then(|| grid_renderer.zoomed(zoom));
+        self.current_surface.surface = build_window_surface_with_grid_size(
+            self.current_surface.surface.canvas(),
+            self.zoomed_renderer.as_ref().unwrap_or(grid_renderer),
+            self.grid_size,
+        );
+        self.snapshots.clear();
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    /// Builds the zoomed renderer again after the font or the scale factor of the main one
+    /// changed.
+    pub fn update_zoomed_renderer(&mut self, grid_renderer: &GridRenderer) {
+        if self.zoomed_renderer.is_some() {
+            self.set_zoom(self.zoom, grid_renderer);
+        }
+    }
+
+    pub fn set_default_style(&mut self, default_style: &Arc<Style>) {
+        if let Some(zoomed_renderer) = &mut self.zoomed_renderer {
+            zoomed_renderer.default_style = default_style.clone();
         }
     }
 
     pub fn pixel_region(&self, font_dimensions: Dimensions) -> Rect {
-        let current_pixel_position = Point::new(
+        let mut current_pixel_position = Point::new(
             self.grid_current_position.x * font_dimensions.width as f32,
             self.grid_current_position.y * font_dimensions.height as f32,
         );
+        if self.snap_to_pixels {
+            current_pixel_position.x = current_pixel_position.x.round();
+            current_pixel_position.y = current_pixel_position.y.round();
+        }
 
-        let image_size: (i32, i32) = (self.grid_size * font_dimensions).into();
+        let image_size: (i32, i32) = (self.grid_size * self.cell_size(font_dimensions)).into();
 
         Rect::from_point_and_size(current_pixel_position, image_size)
     }
 
+    pub fn url_at(&self, column: u64, row: u64) -> Option<&str> {
+        self.urls
+            .iter()
+            .find(|url| url.contains(column, row))
+            .map(|url| url.url.as_str())
+    }
+
+    /// Replaces the surface with an empty one built on the canvas at the same scroll position,
+    /// and drops the snapshots of the old one.
+    pub fn rebuild_surface(&mut self, root_canvas: &mut Canvas, grid_renderer: &GridRenderer) {
+        self.current_surface = LocatedSurface::new(
+            root_canvas,
+            self.zoomed_renderer.as_ref().unwrap_or(grid_renderer),
+            self.grid_size,
+            self.current_surface.vertical_position,
+        );
+        self.snapshots.clear();
+    }
+
     pub fn update(&mut self, settings: &RendererSettings, dt: f32) -> bool {
         let mut animating = false;
 
+        if let Some(loading_background) = &self.loading_background {
+            match loading_background.try_recv() {
+                Ok(background) => {
+                    self.background = background;
+                    self.loading_background = None;
+                }
+                Err(TryRecvError::Disconnected) => self.loading_background = None,
+                Err(TryRecvError::Empty) => {}
+            }
+        }
+
         {
             if 1.0 - self.position_t < std::f32::EPSILON {
                 // We are at destination, move t out of 0-1 range to stop the animation.
@@ -232,6 +734,10 @@ impl RenderedWindow {
         }
 
         {
+            // A scroll still animating finishes right away when this gets turned off.
+            if !settings.smooth_scroll && self.scroll_t <= 1.0 {
+                self.scroll_t = 1.0;
+            }
             if 1.0 - self.scroll_t < std::f32::EPSILON {
                 // We are at destination, move t out of 0-1 range to stop the animation.
                 self.scroll_t = 2.0;
@@ -268,6 +774,8 @@ impl RenderedWindow {
 
         root_canvas.save();
         root_canvas.clip_rect(pixel_region, None, Some(false));
+        self.apply_content_clip(root_canvas, pixel_region, settings);
+        self.apply_mirror_transform(root_canvas, pixel_region);
 
         if self.floating_order.is_none() {
             root_canvas.clear(default_background);
@@ -297,8 +805,23 @@ impl RenderedWindow {
         paint.set_blend_mode(BlendMode::Src);
         paint.set_anti_alias(false);
 
-        // Save layer so that setting the blend mode doesn't effect the blur.
-        root_canvas.save_layer(&SaveLayerRec::default());
+        if (self.brightness - 1.0).abs() > f32::EPSILON {
+            let brightness = self.brightness;
+            #[rustfmt::skip]
+            let brightness_matrix = [
+                brightness, 0.0, 0.0, 0.0, 0.0,
+                0.0, brightness, 0.0, 0.0, 0.0,
+                0.0, 0.0, brightness, 0.0, 0.0,
+                0.0, 0.0, 0.0, 1.0, 0.0,
+            ];
+            paint.set_color_filter(color_filters::matrix_row_major(&brightness_matrix));
+        }
+
+        // Save layer so that setting the blend mode doesn't effect the blur. A blended window is
+        // drawn in there as usual and only the whole layer is made translucent.
+        let mut layer_paint = Paint::default();
+        layer_paint.set_alpha_f(1.0 - self.blend.min(100) as f32 / 100.0);
+        root_canvas.save_layer(&SaveLayerRec::default().paint(&layer_paint));
         let mut a = 255;
         if self.floating_order.is_some() {
             a = (settings.floating_opacity.min(1.0).max(0.0) * 255.0) as u8;
@@ -309,7 +832,7 @@ impl RenderedWindow {
 
         paint.set_color(Color::from_argb(255, 255, 255, 255));
 
-        let font_height = font_dimensions.height;
+        let font_height = self.cell_size(font_dimensions).height;
 
         // Draw scrolling snapshots.
         for snapshot in self.snapshots.iter_mut().rev() {
@@ -335,6 +858,16 @@ impl RenderedWindow {
             &paint,
         );
 
+        // The cells with the default background were left transparent in the surfaces, so the
+        // background goes under them along with the default background in case it's translucent.
+        if let Some(background) = &self.background {
+            background.draw_beneath(root_canvas, pixel_region);
+            let mut default_paint = Paint::default();
+            default_paint.set_blend_mode(BlendMode::DstOver);
+            default_paint.set_color(default_background.with_a(a));
+            root_canvas.draw_rect(pixel_region, &default_paint);
+        }
+
         root_canvas.restore();
 
         if self.floating_order.is_some() {
@@ -350,30 +883,250 @@ impl RenderedWindow {
         }
     }
 
+    // Keeps glyphs in the cells at the corners from spilling over the rounded edge.
+    fn apply_content_clip(
+        &self,
+        root_canvas: &mut Canvas,
+        pixel_region: Rect,
+        settings: &RendererSettings,
+    ) {
+        if let Some((rect, radius)) =
+            rounded_content_clip(pixel_region, self.corner_radius, settings.corner_padding)
+        {
+            root_canvas.clip_rrect(RRect::new_rect_xy(rect, radius, radius), None, Some(true));
+        }
+    }
+
+    // Only the drawing is flipped, the grid and the cursor position stay as neovim sent them.
+    fn apply_mirror_transform(&self, root_canvas: &mut Canvas, pixel_region: Rect) {
+        if self.mirrored {
+            let (scale, offset) = mirror_transform(pixel_region, true);
+            root_canvas.translate((offset, 0.0));
+            root_canvas.scale((scale, 1.0));
+        }
+    }
+
+    // The line numbers and edge indicators are drawn straight onto the root canvas on top of the
+    // window, since the surface gets the cells underneath redrawn by neovim whenever a line is.
+    pub fn draw_overlays(
+        &mut self,
+        root_canvas: &mut Canvas,
+        grid_renderer: &mut GridRenderer,
+        settings: &RendererSettings,
+    ) {
+        let pixel_region = self.pixel_region(grid_renderer.font_dimensions);
+        let mut zoomed_renderer = self.zoomed_renderer.take();
+        self.draw_overlays_with(
+            root_canvas,
+            zoomed_renderer.as_mut().unwrap_or(grid_renderer),
+            pixel_region,
+            settings,
+        );
+        self.zoomed_renderer = zoomed_renderer;
+    }
+
+    // Draws the overlays in the cells of the renderer the window is drawn with.
+    fn draw_overlays_with(
+        &self,
+        root_canvas: &mut Canvas,
+        grid_renderer: &mut GridRenderer,
+        pixel_region: Rect,
+        settings: &RendererSettings,
+    ) {
+        let edge_indicators: Vec<_> = self
+            .edge_indicators
+            .iter()
+            .filter_map(|(row, indicator)| {
+                let glyph = match indicator {
+                    EdgeIndicator::Wrap => &settings.wrap_indicator,
+                    EdgeIndicator::Extends => &settings.extends_indicator,
+                };
+                (!glyph.is_empty()).then_some((*row, glyph))
+            })
+            .collect();
+
+        if (self.line_numbers.is_empty()
+            && edge_indicators.is_empty()
+            && self.visual_block.is_none()
+            && self.indent_guides.is_empty()
+            && self.breadcrumb.is_none()
+            && self.scroll_percentage.is_none()
+            && self.diagnostics.is_empty()
+            && self.blame.is_empty())
+            || self.hidden
+        {
+            return;
+        }
+
+        root_canvas.save();
+        root_canvas.clip_rect(pixel_region, None, Some(false));
+        self.apply_content_clip(root_canvas, pixel_region, settings);
+        self.apply_mirror_transform(root_canvas, pixel_region);
+        root_canvas.translate((pixel_region.left, pixel_region.top));
+
+        let font_dimensions = grid_renderer.font_dimensions;
+        let is_floating = self.floating_order.is_some();
+        for overlay in settings.overlay_order.overlays() {
+            match overlay {
+                WindowOverlay::LineNumbers => {
+                    for fragment in &self.line_numbers {
+                        let grid_position = (fragment.window_left, fragment.window_top);
+                        grid_renderer.draw_background(
+                            root_canvas,
+                            grid_position,
+                            fragment.width,
+                            &fragment.style,
+                            is_floating,
+                        );
+                        grid_renderer.draw_foreground(
+                            root_canvas,
+                            fragment.text.clone(),
+                            grid_position,
+                            fragment.width,
+                            &fragment.style,
+                        );
+                    }
+                }
+                WindowOverlay::EdgeIndicators => {
+                    let last_column = self.grid_size.width.saturating_sub(1);
+                    for (row, glyph) in &edge_indicators {
+                        let grid_position = (last_column, *row);
+                        grid_renderer.draw_background(
+                            root_canvas,
+                            grid_position,
+                            1,
+                            &None,
+                            is_floating,
+                        );
+                        grid_renderer.draw_foreground(
+                            root_canvas,
+                            glyph.to_string(),
+                            grid_position,
+                            1,
+                            &None,
+                        );
+                    }
+                }
+                WindowOverlay::IndentGuides => {
+                    // Every guide is one line over all of its rows, however the text underneath
+                    // is split up.
+                    let mut guide_paint = Paint::new(self.indent_guide_color, None);
+                    guide_paint.set_stroke_width(1.0);
+                    for guide in &self.indent_guides {
+                        let x = (guide.column * font_dimensions.width) as f32 + 0.5;
+                        root_canvas.draw_line(
+                            (x, (guide.top * font_dimensions.height) as f32),
+                            (x, (guide.bottom * font_dimensions.height) as f32),
+                            &guide_paint,
+                        );
+                    }
+                }
+                WindowOverlay::VisualBlock => {
+                    if let Some((block, color)) = &self.visual_block {
+                        let rect = Rect::from_xywh(
+                            (block.left * font_dimensions.width) as f32,
+                            (block.top * font_dimensions.height) as f32,
+                            ((block.right - block.left) * font_dimensions.width) as f32,
+                            ((block.bottom - block.top) * font_dimensions.height) as f32,
+                        );
+                        root_canvas.draw_rect(rect, &Paint::new(*color, None));
+                    }
+                }
+                WindowOverlay::Breadcrumb | WindowOverlay::ScrollPercentage => {
+                    let fragment = match overlay {
+                        WindowOverlay::Breadcrumb => &self.breadcrumb,
+                        _ => &self.scroll_percentage,
+                    };
+                    if let Some(fragment) = fragment {
+                        let grid_position = (fragment.window_left, fragment.window_top);
+                        grid_renderer.draw_background(
+                            root_canvas,
+                            grid_position,
+                            fragment.width,
+                            &fragment.style,
+                            is_floating,
+                        );
+                        grid_renderer.draw_foreground(
+                            root_canvas,
+                            fragment.text.clone(),
+                            grid_position,
+                            fragment.width,
+                            &fragment.style,
+                        );
+                    }
+                }
+                WindowOverlay::Diagnostics | WindowOverlay::Blame => {
+                    let fragments = match overlay {
+                        WindowOverlay::Diagnostics => &self.diagnostics,
+                        _ => &self.blame,
+                    };
+                    // Only the text, so that the row's background shows through.
+                    for fragment in fragments {
+                        grid_renderer.draw_foreground(
+                            root_canvas,
+                            fragment.text.clone(),
+                            (fragment.window_left, fragment.window_top),
+                            fragment.width,
+                            &fragment.style,
+                        );
+                    }
+                }
+            }
+        }
+
+        root_canvas.restore();
+    }
+
     pub fn handle_window_draw_command(
         &mut self,
         grid_renderer: &mut GridRenderer,
         draw_command: WindowDrawCommand,
+    ) {
+        if let WindowDrawCommand::Position { zoom, .. } = &draw_command {
+            if (self.zoom - zoom).abs() > f32::EPSILON {
+                self.set_zoom(*zoom, grid_renderer);
+            }
+        }
+
+        // Zoomed windows are drawn with their own renderer, only their position is laid out in
+        // the cells of the main one.
+        let font_dimensions = grid_renderer.font_dimensions;
+        let mut zoomed_renderer = self.zoomed_renderer.take();
+        self.handle_drawn_window_draw_command(
+            zoomed_renderer.as_mut().unwrap_or(grid_renderer),
+            font_dimensions,
+            draw_command,
+        );
+        self.zoomed_renderer = zoomed_renderer;
+    }
+
+    fn handle_drawn_window_draw_command(
+        &mut self,
+        grid_renderer: &mut GridRenderer,
+        font_dimensions: Dimensions,
+        draw_command: WindowDrawCommand,
     ) {
         match draw_command {
             WindowDrawCommand::Position {
                 grid_position: (grid_left, grid_top),
                 grid_size,
                 floating_order,
+                zoom: _,
+                window_type,
+                snap_to_pixels,
             } => {
                 tracy_zone!("position_cmd", 0);
-                let Dimensions {
-                    width: font_width,
-                    height: font_height,
-                } = grid_renderer.font_dimensions;
-
-                let top_offset = self.padding.top as f32 / font_height as f32;
-                let left_offset = self.padding.left as f32 / font_width as f32;
+                self.window_type = window_type;
+                self.snap_to_pixels = snap_to_pixels;
+                let content_origin = self.padding.content_origin(font_dimensions);
 
                 let grid_left = grid_left.max(0.0);
                 let grid_top = grid_top.max(0.0);
-                let new_destination: Point =
-                    (grid_left as f32 + left_offset, grid_top as f32 + top_offset).into();
+                let new_destination: Point = (
+                    grid_left as f32 + content_origin.x,
+                    grid_top as f32 + content_origin.y,
+                )
+                    .into();
                 let new_grid_size: Dimensions = grid_size.into();
 
                 if self.grid_destination != new_destination {
@@ -418,8 +1171,18 @@ impl RenderedWindow {
                     self.grid_destination = new_destination;
                 }
             }
-            WindowDrawCommand::DrawLine(line_fragments) => {
+            WindowDrawCommand::Reorder { floating_order } => {
+                self.floating_order = floating_order;
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            WindowDrawCommand::DrawLine(mut line_fragments) => {
                 tracy_zone!("draw_line_cmd", 0);
+                // Floats keep their fills, as they have their own background.
+                if self.floating_order.is_none()
+                    && SETTINGS.get::<RendererSettings>().collapse_blank_lines
+                {
+                    collapse_blank_line(&mut line_fragments);
+                }
                 let canvas = self.current_surface.surface.canvas();
 
                 canvas.save();
@@ -439,8 +1202,14 @@ impl RenderedWindow {
                         style,
                         self.floating_order.is_some(),
                     );
+                    if self.background.is_some() && grid_renderer.is_default_background(style) {
+                        grid_renderer.clear_background(canvas, grid_position, *width);
+                    }
                 }
 
+                grid_renderer
+                    .shaper
+                    .set_font_fallback(self.font_fallback.clone());
                 for line_fragment in line_fragments.into_iter() {
                     let LineFragment {
                         text,
@@ -448,10 +1217,19 @@ impl RenderedWindow {
                         window_top,
                         width,
                         style,
+                        foreground_styles,
                     } = line_fragment;
                     let grid_position = (window_left, window_top);
-                    grid_renderer.draw_foreground(canvas, text, grid_position, width, &style);
+                    grid_renderer.draw_highlighted_foreground(
+                        canvas,
+                        text,
+                        grid_position,
+                        width,
+                        &style,
+                        &foreground_styles,
+                    );
                 }
+                grid_renderer.shaper.set_font_fallback(Arc::default());
                 canvas.restore();
             }
             WindowDrawCommand::Scroll {
@@ -505,6 +1283,30 @@ impl RenderedWindow {
 
                 self.snapshots.clear();
             }
+            WindowDrawCommand::ClearRegion {
+                top,
+                bottom,
+                left,
+                right,
+            } => {
+                tracy_zone!("clear_region_cmd", 0);
+                let canvas = self.current_surface.surface.canvas();
+                canvas.save();
+                for row in top..bottom {
+                    if self.background.is_some() {
+                        grid_renderer.clear_background(canvas, (left, row), right - left);
+                    } else {
+                        grid_renderer.draw_background(
+                            canvas,
+                            (left, row),
+                            right - left,
+                            &None,
+                            self.floating_order.is_some(),
+                        );
+                    }
+                }
+                canvas.restore();
+            }
             WindowDrawCommand::Show => {
                 tracy_zone!("show_cmd", 0);
                 if self.hidden {
@@ -518,9 +1320,20 @@ impl RenderedWindow {
                 tracy_zone!("hide_cmd", 0);
                 self.hidden = true;
             }
-            WindowDrawCommand::Viewport { scroll_delta, .. } => {
+            WindowDrawCommand::Viewport {
+                line_height,
+                scroll_delta,
+                animated,
+            } => {
                 tracy_zone!("viewport_cmd", 0);
-                if scroll_delta.abs() > f64::EPSILON {
+                if !animated {
+                    self.current_surface.vertical_position += scroll_delta as f32;
+                    self.start_scroll = self.current_surface.vertical_position;
+                    self.scroll_destination = self.current_surface.vertical_position;
+                    self.current_scroll = self.current_surface.vertical_position;
+                    self.scroll_t = 2.0;
+                    self.snapshots.clear();
+                } else if scroll_delta.abs() > f64::EPSILON {
                     let new_snapshot = self.current_surface.snapshot();
                     self.snapshots.push_back(new_snapshot);
 
@@ -530,13 +1343,236 @@ impl RenderedWindow {
 
                     self.current_surface.vertical_position += scroll_delta as f32;
 
-                    // Set new target viewport position and initialize animation timer.
-                    self.start_scroll = self.current_scroll;
+                    // Set new target viewport position and initialize animation timer. Jumps
+                    // further than a screen only animate the last one of it.
+                    let line_height = line_height.max(1.0) as f32;
                     self.scroll_destination = self.current_surface.vertical_position;
+                    self.start_scroll = self.current_scroll.clamp(
+                        self.scroll_destination - line_height,
+                        self.scroll_destination + line_height,
+                    );
                     self.scroll_t = 0.0;
                 }
             }
+            WindowDrawCommand::Brightness(brightness) => {
+                self.brightness = brightness;
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            WindowDrawCommand::Blend(blend) => {
+                self.blend = blend;
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            WindowDrawCommand::Mirrored(mirrored) => {
+                self.mirrored = mirrored;
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            WindowDrawCommand::CornerRadius(corner_radius) => {
+                self.corner_radius = corner_radius;
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            WindowDrawCommand::Breadcrumb(breadcrumb) => {
+                self.breadcrumb = breadcrumb;
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            WindowDrawCommand::FontFallback(font_fallback) => {
+                self.font_fallback = font_fallback;
+            }
+            WindowDrawCommand::ScrollPercentage(scroll_percentage) => {
+                self.scroll_percentage = scroll_percentage;
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            WindowDrawCommand::Diagnostics(diagnostics) => {
+                self.diagnostics = diagnostics;
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            WindowDrawCommand::Blame(blame) => {
+                self.blame = blame;
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            WindowDrawCommand::Background { kind } => match kind {
+                Some(kind @ BackgroundKind::Image(_)) => {
+                    self.loading_background = Some(WindowBackground::load_apart(kind));
+                }
+                kind => {
+                    self.loading_background = None;
+                    self.background = kind.and_then(WindowBackground::load);
+                    REDRAW_SCHEDULER.queue_next_frame();
+                }
+            },
+            WindowDrawCommand::Urls(urls) => {
+                self.urls = urls;
+            }
+            WindowDrawCommand::LineNumbers(line_numbers) => {
+                self.line_numbers = line_numbers;
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            WindowDrawCommand::EdgeIndicators(edge_indicators) => {
+                self.edge_indicators = edge_indicators;
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            WindowDrawCommand::IndentGuides(indent_guides, color) => {
+                self.indent_guides = indent_guides;
+                self.indent_guide_color = color;
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            WindowDrawCommand::VisualBlock(visual_block) => {
+                self.visual_block = visual_block;
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
             _ => {}
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cmd_line::CmdLineSettings, editor::Colors, settings::SETTINGS};
+
+    #[test]
+    fn margin_is_reflected_in_content_origin() {
+        SETTINGS.set::<CmdLineSettings>(&CmdLineSettings::default());
+        let window_settings = WindowSettings {
+            padding_left: 5,
+            margin: 10,
+            ..Default::default()
+        };
+
+        let padding = WindowPadding::from_settings(&window_settings);
+        assert_eq!(
+            padding,
+            WindowPadding {
+                top: 10,
+                left: 15,
+                right: 10,
+                bottom: 10,
+            }
+        );
+
+        let font_dimensions = Dimensions {
+            width: 10,
+            height: 20,
+        };
+        assert_eq!(
+            padding.content_origin(font_dimensions),
+            Point::new(1.5, 0.5)
+        );
+    }
+
+    #[test]
+    fn overlays_are_drawn_in_the_configured_order() {
+        assert_eq!(
+            overlay_order("visual_block, line_numbers"),
+            vec![
+                WindowOverlay::EdgeIndicators,
+                WindowOverlay::IndentGuides,
+                WindowOverlay::Breadcrumb,
+                WindowOverlay::Diagnostics,
+                WindowOverlay::Blame,
+                WindowOverlay::ScrollPercentage,
+                WindowOverlay::VisualBlock,
+                WindowOverlay::LineNumbers,
+            ]
+        );
+        assert_eq!(overlay_order(""), WindowOverlay::ALL.to_vec());
+    }
+
+    #[test]
+    fn overlay_order_is_parsed_once_from_the_setting() {
+        let mut order = OverlayOrder::default();
+        order.parse_from_value(Value::from("blame,diagnostics"));
+        assert_eq!(
+            order.overlays().last().copied(),
+            Some(WindowOverlay::Diagnostics)
+        );
+
+        // Values which aren't a string leave the order as it was.
+        order.parse_from_value(Value::from(3));
+        assert_eq!(
+            order.overlays().last().copied(),
+            Some(WindowOverlay::Diagnostics)
+        );
+
+        order.parse_from_value(Value::from(OverlayOrder::default()));
+        assert_eq!(order, RendererSettings::default().overlay_order);
+    }
+
+    #[test]
+    fn only_whitespace_lines_are_collapsed() {
+        let style = Some(Arc::new(Style::new(Colors::new(None, None, None))));
+        let fragment = |text: &str| LineFragment {
+            text: text.to_owned(),
+            window_left: 0,
+            window_top: 0,
+            width: text.len() as u64,
+            style: style.clone(),
+            foreground_styles: Vec::new(),
+        };
+
+        let mut blank = vec![fragment("   "), fragment(" ")];
+        assert!(collapse_blank_line(&mut blank));
+        assert!(blank.iter().all(|fragment| fragment.style.is_none()));
+
+        let mut text = vec![fragment("  "), fragment("a ")];
+        assert!(!collapse_blank_line(&mut text));
+        assert!(text.iter().all(|fragment| fragment.style == style));
+    }
+
+    #[test]
+    fn background_bleed_widens_the_fragment_region() {
+        let region = Rect::from_xywh(16.0, 20.0, 24.0, 20.0);
+        assert_eq!(bleed_background_region(region, 0.0), region);
+        assert_eq!(
+            bleed_background_region(region, 1.5),
+            Rect::new(14.5, 20.0, 41.5, 40.0)
+        );
+        // Negative values are treated as no bleed rather than shrinking the fragment.
+        assert_eq!(bleed_background_region(region, -1.0), region);
+    }
+
+    #[test]
+    fn corner_cells_are_clipped_by_the_rounded_content_area() {
+        let region = Rect::from_xywh(100.0, 50.0, 80.0, 40.0);
+        assert_eq!(rounded_content_clip(region, 0.0, 2.0), None);
+
+        let (rect, radius) = rounded_content_clip(region, 10.0, 2.0).unwrap();
+        assert_eq!(rect, Rect::new(102.0, 52.0, 178.0, 88.0));
+        assert_eq!(radius, 8.0);
+
+        // The top left corner of the first cell is outside of the rounded corner, while its
+        // middle stays visible.
+        let is_inside =
+            |x: f32, y: f32| (x - (rect.left + radius)).hypot(y - (rect.top + radius)) <= radius;
+        assert!(!is_inside(102.0, 52.0));
+        assert!(is_inside(106.0, 62.0));
+
+        // The radius never gets larger than what fits into the content.
+        assert_eq!(rounded_content_clip(region, 100.0, 0.0).unwrap().1, 20.0);
+    }
+
+    #[test]
+    fn underlines_are_lifted_into_their_cell() {
+        // A thin line which fits isn't moved.
+        assert_eq!(underline_shift(36.0, 0.5, 40.0), 0.0);
+        // A thick line right at the bottom is lifted by what hangs over.
+        assert_eq!(underline_shift(39.0, 2.0, 40.0), -1.0);
+        // A curl swinging below the cell is lifted as a whole.
+        let (line_y, reach) = (38.0, 2.5 * 2.0 - 3.0);
+        let shift = underline_shift(line_y, reach, 40.0);
+        assert_eq!(line_y + shift + reach, 40.0);
+    }
+
+    #[test]
+    fn mirror_transform_flips_the_window_in_place() {
+        let region = Rect::from_xywh(100.0, 10.0, 50.0, 20.0);
+        let flip = |x: f32, mirrored| {
+            let (scale, offset) = mirror_transform(region, mirrored);
+            offset + scale * x
+        };
+
+        assert_eq!(flip(100.0, true), 150.0);
+        assert_eq!(flip(150.0, true), 100.0);
+        assert_eq!(flip(110.0, true), 140.0);
+        assert_eq!(flip(110.0, false), 110.0);
+    }
+}
@@ -0,0 +1,41 @@
+use skia_safe::{Canvas, Paint, Rect};
+
+use crate::{
+    editor::ScrollOverview,
+    renderer::{GridRenderer, WindowPadding},
+};
+
+// Keeps the thumb of a segment visible even for very long buffers.
+const MIN_THUMB_WIDTH: f32 = 2.0;
+
+pub fn draw_scroll_overview(
+    grid_renderer: &GridRenderer,
+    canvas: &mut Canvas,
+    scroll_overview: &ScrollOverview,
+    padding: &WindowPadding,
+) {
+    let font_dimensions = grid_renderer.font_dimensions;
+    let content_origin = padding.content_origin(font_dimensions);
+    let top = content_origin.y * font_dimensions.height as f32;
+    let track_paint = Paint::new(scroll_overview.color, None);
+    let thumb_paint = Paint::new(scroll_overview.thumb_color, None);
+
+    // The bar runs along the top of the screen, over the tabline when there is one, with every
+    // segment lined up with the split below it.
+    for segment in &scroll_overview.segments {
+        let left = (segment.left as f32 + content_origin.x) * font_dimensions.width as f32;
+        let width = (segment.width * font_dimensions.width) as f32;
+        canvas.draw_rect(
+            Rect::from_xywh(left, top, width, scroll_overview.height),
+            &track_paint,
+        );
+
+        let thumb_left = left + width * segment.thumb_start as f32;
+        let thumb_width =
+            (width * (segment.thumb_end - segment.thumb_start) as f32).max(MIN_THUMB_WIDTH);
+        canvas.draw_rect(
+            Rect::from_xywh(thumb_left, top, thumb_width, scroll_overview.height),
+            &thumb_paint,
+        );
+    }
+}
@@ -0,0 +1,28 @@
+use skia_safe::{Canvas, ClipOp, Paint, Rect};
+
+use crate::{
+    editor::Spotlight,
+    renderer::{GridRenderer, WindowPadding},
+};
+
+pub fn draw_spotlight(
+    grid_renderer: &GridRenderer,
+    canvas: &mut Canvas,
+    spotlight: &Spotlight,
+    padding: &WindowPadding,
+) {
+    let font_dimensions = grid_renderer.font_dimensions;
+    let content_origin = padding.content_origin(font_dimensions);
+    let cutout = Rect::from_xywh(
+        (spotlight.left as f32 + content_origin.x) * font_dimensions.width as f32,
+        (spotlight.top as f32 + content_origin.y) * font_dimensions.height as f32,
+        (spotlight.width * font_dimensions.width) as f32,
+        (spotlight.height * font_dimensions.height) as f32,
+    );
+
+    // Everything but the cutout is covered, including the padding around the grid.
+    canvas.save();
+    canvas.clip_rect(cutout, ClipOp::Difference, Some(false));
+    canvas.draw_paint(&Paint::new(spotlight.color, None));
+    canvas.restore();
+}
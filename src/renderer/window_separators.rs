@@ -0,0 +1,49 @@
+use skia_safe::{Canvas, Paint, Rect};
+
+use crate::{
+    editor::{SeparatorStyle, WindowSeparators},
+    renderer::{GridRenderer, WindowPadding},
+};
+
+pub fn draw_window_separators(
+    grid_renderer: &GridRenderer,
+    canvas: &mut Canvas,
+    window_separators: &WindowSeparators,
+    padding: &WindowPadding,
+) {
+    let font_dimensions = grid_renderer.font_dimensions;
+    let content_origin = padding.content_origin(font_dimensions);
+    let cell_width = font_dimensions.width as f32;
+    let cell_height = font_dimensions.height as f32;
+    let thickness = window_separators.thickness.max(0.0);
+    let paint = Paint::new(window_separators.color, None);
+
+    // The double style has two lines, as far apart as they are thick, around the same center.
+    let offsets: &[f32] = match window_separators.style {
+        SeparatorStyle::Solid => &[0.0],
+        SeparatorStyle::Double => &[-thickness, thickness],
+    };
+
+    for separator in &window_separators.separators {
+        let left = (separator.left as f32 + content_origin.x) * cell_width;
+        let top = (separator.top as f32 + content_origin.y) * cell_height;
+        for offset in offsets {
+            let rect = if separator.vertical {
+                Rect::from_xywh(
+                    left + offset - thickness / 2.0,
+                    top,
+                    thickness,
+                    separator.length as f32 * cell_height,
+                )
+            } else {
+                Rect::from_xywh(
+                    left,
+                    top + offset - thickness / 2.0,
+                    separator.length as f32 * cell_width,
+                    thickness,
+                )
+            };
+            canvas.draw_rect(rect, &paint);
+        }
+    }
+}
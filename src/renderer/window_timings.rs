@@ -0,0 +1,106 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+const WINDOW_TIMINGS_COUNT: usize = 120;
+
+/// Time spent drawing each window in the last frames, to help finding the window responsible
+/// for a slow frame.
+#[derive(Default)]
+pub struct WindowTimings {
+    current_frame: HashMap<u64, Duration>,
+    frames: HashMap<u64, VecDeque<Duration>>,
+}
+
+impl WindowTimings {
+    pub fn add(&mut self, grid_id: u64, duration: Duration) {
+        *self.current_frame.entry(grid_id).or_default() += duration;
+    }
+
+    pub fn finish_frame(&mut self) {
+        for (grid_id, duration) in self.current_frame.drain() {
+            let frames = self
+                .frames
+                .entry(grid_id)
+                .or_insert_with(|| VecDeque::with_capacity(WINDOW_TIMINGS_COUNT));
+            if frames.len() == WINDOW_TIMINGS_COUNT {
+                frames.pop_front();
+            }
+            frames.push_back(duration);
+        }
+    }
+
+    pub fn remove(&mut self, grid_id: u64) {
+        self.current_frame.remove(&grid_id);
+        self.frames.remove(&grid_id);
+    }
+
+    pub fn frames(&self, grid_id: u64) -> Option<&VecDeque<Duration>> {
+        self.frames.get(&grid_id)
+    }
+
+    /// One line per window, the most expensive window first.
+    pub fn report(&self) -> Vec<String> {
+        let mut windows: Vec<_> = self
+            .frames
+            .iter()
+            .filter(|(_, frames)| !frames.is_empty())
+            .map(|(grid_id, frames)| {
+                let total: Duration = frames.iter().sum();
+                let average = total / frames.len() as u32;
+                let max = frames.iter().max().copied().unwrap_or_default();
+                (*grid_id, average, max, frames.len())
+            })
+            .collect();
+        windows.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        windows
+            .into_iter()
+            .map(|(grid_id, average, max, count)| {
+                format!(
+                    "grid {grid_id}: {:.3}ms average, {:.3}ms max over {count} frames",
+                    average.as_secs_f64() * 1000.0,
+                    max.as_secs_f64() * 1000.0,
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    #[test]
+    fn drawing_a_window_records_a_timing() {
+        let mut window_timings = WindowTimings::default();
+
+        let start = Instant::now();
+        while start.elapsed().is_zero() {}
+        window_timings.add(2, start.elapsed());
+        window_timings.finish_frame();
+
+        let frames = window_timings.frames(2).expect("Timing should be recorded");
+        assert_eq!(frames.len(), 1);
+        assert!(!frames[0].is_zero());
+        assert!(window_timings.frames(1).is_none());
+        assert!(window_timings.report()[0].starts_with("grid 2: "));
+    }
+
+    #[test]
+    fn timings_are_kept_in_a_ring_buffer() {
+        let mut window_timings = WindowTimings::default();
+
+        for frame in 0..WINDOW_TIMINGS_COUNT + 5 {
+            window_timings.add(1, Duration::from_micros(frame as u64));
+            window_timings.finish_frame();
+        }
+
+        let frames = window_timings.frames(1).unwrap();
+        assert_eq!(frames.len(), WINDOW_TIMINGS_COUNT);
+        assert_eq!(frames[0], Duration::from_micros(5));
+    }
+}
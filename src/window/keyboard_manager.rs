@@ -1,5 +1,6 @@
 use crate::{
     bridge::{SerialCommand, UiCommand},
+    editor::cell_width,
     event_aggregator::EVENT_AGGREGATOR,
 };
 #[cfg(target_os = "macos")]
@@ -71,6 +72,11 @@ impl KeyboardManager {
         }
     }
 
+    /// The number of cells the text being composed takes up, so the IME area can cover all of it.
+    pub fn ime_preedit_cell_width(&self) -> u64 {
+        cell_width(&self.ime_preedit.0)
+    }
+
     fn handle_numpad_numkey<'a>(
         is_numlock_enabled: bool,
         numlock_str: &'a str,
@@ -179,6 +185,17 @@ impl KeyboardManager {
         }
     }
 
+    /// Whether the modifier used for opening urls with the mouse is held, which is Cmd on macOS
+    /// and Ctrl everywhere else.
+    pub fn url_modifier_pressed(&self) -> bool {
+        let state = self.modifiers.state();
+        if cfg!(target_os = "macos") {
+            state.super_key()
+        } else {
+            state.control_key()
+        }
+    }
+
     pub fn format_modifier_string(&self, text: &str, is_special: bool) -> String {
         // Shift should always be sent together with special keys (Enter, Space, F keys and so on).
         // And as a special case togeter with CTRL and standard a-z characters.
@@ -1,9 +1,13 @@
 use std::{
     cmp::Ordering,
     collections::HashMap,
+    process::Command,
+    thread,
     time::{Duration, Instant},
 };
 
+use log::error;
+
 use skia_safe::Rect;
 use winit::{
     dpi::PhysicalPosition,
@@ -50,6 +54,32 @@ fn to_grid_coords(
     )
 }
 
+// The program and arguments that open a url in the default browser. None of them go through a
+// shell, which would run whatever follows a `&` or a `^` in the url as another command.
+fn url_opener(url: &str) -> (&'static str, Vec<String>) {
+    if cfg!(target_os = "windows") {
+        (
+            "rundll32",
+            vec!["url.dll,FileProtocolHandler".to_owned(), url.to_owned()],
+        )
+    } else if cfg!(target_os = "macos") {
+        ("open", vec![url.to_owned()])
+    } else {
+        ("xdg-open", vec![url.to_owned()])
+    }
+}
+
+fn open_url(url: &str) {
+    let (program, arguments) = url_opener(url);
+    match Command::new(program).args(arguments).spawn() {
+        // Waited for apart so the opener doesn't stay around as a zombie once it exits.
+        Ok(mut child) => {
+            thread::spawn(move || child.wait());
+        }
+        Err(error) => error!("Could not open url {}: {}", url, error),
+    }
+}
+
 fn mouse_button_to_button_text(mouse_button: &MouseButton) -> Option<String> {
     match mouse_button {
         MouseButton::Left => Some("left".to_owned()),
@@ -81,6 +111,8 @@ pub struct MouseManager {
     touch_position: HashMap<(DeviceId, u64), TouchTrace>,
 
     window_details_under_mouse: Option<WindowDrawDetails>,
+    // Set when a press opened a url, so that the matching release isn't sent to neovim either.
+    opened_url: bool,
 
     mouse_hidden: bool,
     pub enabled: bool,
@@ -97,6 +129,7 @@ impl MouseManager {
             scroll_position: PhysicalPosition::new(0.0, 0.0),
             touch_position: HashMap::new(),
             window_details_under_mouse: None,
+            opened_url: false,
             mouse_hidden: false,
             enabled: true,
         }
@@ -245,6 +278,38 @@ impl MouseManager {
         }
     }
 
+    // Opens the url under the mouse on a modified left click. Returns whether the event was
+    // consumed and shouldn't be forwarded to neovim.
+    fn handle_url_click(
+        &mut self,
+        mouse_button: &MouseButton,
+        down: bool,
+        keyboard_manager: &KeyboardManager,
+        renderer: &Renderer,
+    ) -> bool {
+        if !self.enabled || mouse_button != &MouseButton::Left {
+            return false;
+        }
+
+        if !down {
+            return std::mem::take(&mut self.opened_url);
+        }
+
+        if !keyboard_manager.url_modifier_pressed() {
+            return false;
+        }
+
+        let url = self
+            .window_details_under_mouse
+            .as_ref()
+            .and_then(|details| renderer.url_at(details.id, self.relative_position.into()));
+        if let Some(url) = url {
+            open_url(&url);
+            self.opened_url = true;
+        }
+        self.opened_url
+    }
+
     fn handle_line_scroll(&mut self, x: f32, y: f32, keyboard_manager: &KeyboardManager) {
         if !self.enabled {
             return;
@@ -485,11 +550,12 @@ impl MouseManager {
             Event::WindowEvent {
                 event: WindowEvent::MouseInput { button, state, .. },
                 ..
-            } => self.handle_pointer_transition(
-                button,
-                state == &ElementState::Pressed,
-                keyboard_manager,
-            ),
+            } => {
+                let down = state == &ElementState::Pressed;
+                if !self.handle_url_click(button, down, keyboard_manager, renderer) {
+                    self.handle_pointer_transition(button, down, keyboard_manager);
+                }
+            }
             Event::WindowEvent {
                 event:
                     WindowEvent::KeyboardInput {
@@ -509,3 +575,16 @@ impl MouseManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url_with_a_query_string_is_passed_whole_without_a_shell() {
+        let url = "https://example.com/?a=1&b=2";
+        let (program, arguments) = url_opener(url);
+        assert!(!matches!(program, "cmd" | "sh"));
+        assert_eq!(arguments.last().map(String::as_str), Some(url));
+    }
+}
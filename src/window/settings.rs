@@ -5,6 +5,8 @@ pub struct WindowSettings {
     pub refresh_rate: u64,
     pub refresh_rate_idle: u64,
     pub idle: bool,
+    pub repaint_interval: f32,
+    pub throttle_live_resize: bool,
     pub transparency: f32,
     pub scale_factor: f32,
     pub fullscreen: bool,
@@ -20,6 +22,7 @@ pub struct WindowSettings {
     pub padding_left: u32,
     pub padding_right: u32,
     pub padding_bottom: u32,
+    pub margin: u32,
     pub theme: String,
 }
 
@@ -33,6 +36,8 @@ impl Default for WindowSettings {
             refresh_rate: 60,
             refresh_rate_idle: 5,
             idle: SETTINGS.get::<CmdLineSettings>().idle,
+            repaint_interval: 0.0,
+            throttle_live_resize: false,
             remember_window_size: true,
             remember_window_position: true,
             hide_mouse_when_typing: false,
@@ -44,6 +49,7 @@ impl Default for WindowSettings {
             padding_left: 0,
             padding_right: 0,
             padding_bottom: 0,
+            margin: 0,
             theme: "".to_string(),
         }
     }
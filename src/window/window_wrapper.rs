@@ -17,8 +17,10 @@ use crate::{
     CmdLineSettings,
 };
 
-use glutin::config::Config;
-use log::trace;
+use std::time::{Duration, Instant};
+
+use glutin::{config::Config, error::ErrorKind};
+use log::{trace, warn};
 use tokio::sync::mpsc::UnboundedReceiver;
 use winit::{
     dpi::{PhysicalPosition, PhysicalSize, Position},
@@ -28,6 +30,8 @@ use winit::{
 
 const MIN_WINDOW_WIDTH: u64 = 20;
 const MIN_WINDOW_HEIGHT: u64 = 6;
+// How long the size has to stay the same before a live resize counts as over.
+const LIVE_RESIZE_SETTLE_TIME: Duration = Duration::from_millis(100);
 
 pub struct WinitWindowWrapper {
     pub windowed_context: WindowedContext,
@@ -44,6 +48,9 @@ pub struct WinitWindowWrapper {
     maximized_at_startup: bool,
     window_command_receiver: UnboundedReceiver<WindowCommand>,
     ime_enabled: bool,
+    ime_cell_width: u64,
+    last_repaint: Instant,
+    last_live_resize: Option<Instant>,
 }
 
 pub fn set_background(background: &str) {
@@ -137,6 +144,9 @@ impl WinitWindowWrapper {
             saved_grid_size: None,
             window_command_receiver,
             ime_enabled,
+            last_repaint: Instant::now(),
+            last_live_resize: None,
+            ime_cell_width: 1,
         };
 
         window_wrapper.set_ime(ime_enabled);
@@ -184,6 +194,7 @@ impl WinitWindowWrapper {
                     self.mouse_manager.enabled = mouse_enabled
                 }
                 WindowCommand::ListAvailableFonts => self.send_font_names(),
+                WindowCommand::ListWindowTimings => self.send_window_timings(),
                 WindowCommand::FocusWindow => {
                     self.windowed_context.window().focus_window();
                 }
@@ -203,6 +214,13 @@ impl WinitWindowWrapper {
         )));
     }
 
+    pub fn send_window_timings(&self) {
+        let report = self.renderer.window_timings.report();
+        EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::DisplayWindowTimings(
+            report,
+        )));
+    }
+
     pub fn handle_quit(&mut self) {
         if SETTINGS.get::<CmdLineSettings>().server.is_none() {
             EVENT_AGGREGATOR.send(UiCommand::Parallel(ParallelCommand::Quit));
@@ -291,12 +309,7 @@ impl WinitWindowWrapper {
         let window = self.windowed_context.window();
 
         let window_settings = SETTINGS.get::<WindowSettings>();
-        let window_padding = WindowPadding {
-            top: window_settings.padding_top,
-            left: window_settings.padding_left,
-            right: window_settings.padding_right,
-            bottom: window_settings.padding_bottom,
-        };
+        let window_padding = WindowPadding::from_settings(&window_settings);
 
         let padding_changed = window_padding != self.renderer.window_padding;
         if padding_changed {
@@ -312,6 +325,21 @@ impl WinitWindowWrapper {
             self.skia_renderer.resize(&self.windowed_context);
         }
 
+        if let Some(last_live_resize) = self.last_live_resize {
+            if last_live_resize.elapsed() >= LIVE_RESIZE_SETTLE_TIME {
+                self.last_live_resize = None;
+                EVENT_AGGREGATOR.send(EditorCommand::LiveResizeEnded);
+            }
+        }
+
+        // Periodically repaint everything from the grids, in case a frame went stale without the
+        // context loss being reported.
+        let repaint_interval = window_settings.repaint_interval;
+        if repaint_interval > 0.0 && self.last_repaint.elapsed().as_secs_f32() >= repaint_interval {
+            self.last_repaint = Instant::now();
+            EVENT_AGGREGATOR.send(EditorCommand::RedrawScreen);
+        }
+
         if REDRAW_SCHEDULER.should_draw() || !SETTINGS.get::<WindowSettings>().idle {
             let prev_cursor_position = self.renderer.get_cursor_position();
             self.font_changed_last_frame =
@@ -322,20 +350,34 @@ impl WinitWindowWrapper {
             }
             {
                 tracy_gpu_zone!("swap buffers");
-                self.windowed_context.swap_buffers().unwrap();
+                if let Err(error) = self.windowed_context.swap_buffers() {
+                    if error.error_kind() != ErrorKind::ContextLost {
+                        panic!("Could not swap buffers: {error}");
+                    }
+                    self.handle_context_lost();
+                }
             }
             emit_frame_mark();
             tracy_gpu_collect();
             let current_cursor_position = self.renderer.get_cursor_position();
-            if current_cursor_position != prev_cursor_position {
+            let ime_cell_width = self.keyboard_manager.ime_preedit_cell_width().max(1);
+            if current_cursor_position != prev_cursor_position
+                || ime_cell_width != self.ime_cell_width
+            {
+                self.ime_cell_width = ime_cell_width;
                 let font_dimensions = self.renderer.grid_renderer.font_dimensions;
                 let position = PhysicalPosition::new(
                     current_cursor_position.x.round() as i32,
                     current_cursor_position.y.round() as i32 + font_dimensions.height as i32,
                 );
+                // Reserve the cells the preedit covers, so the candidate window doesn't overlap
+                // the text being composed.
                 self.windowed_context.window().set_ime_cursor_area(
                     Position::Physical(position),
-                    PhysicalSize::new(100, font_dimensions.height as u32),
+                    PhysicalSize::new(
+                        (ime_cell_width * font_dimensions.width) as u32,
+                        font_dimensions.height as u32,
+                    ),
                 );
             }
         }
@@ -417,6 +459,25 @@ impl WinitWindowWrapper {
             width: grid_size.width,
             height: grid_size.height,
         }));
+
+        // Every size the window goes through while it's dragged is a step of a live resize, the
+        // editor then draws what neovim sent for a step all at once.
+        if SETTINGS.get::<WindowSettings>().throttle_live_resize {
+            self.last_live_resize = Some(Instant::now());
+            EVENT_AGGREGATOR.send(EditorCommand::LiveResizeStep);
+        }
+    }
+
+    // Happens after the GPU was reset, for example when resuming from sleep. The skia context and
+    // the surfaces of the windows are rebuilt, then the editor repaints every window from its grid.
+    fn handle_context_lost(&mut self) {
+        warn!("Graphics context lost, rebuilding the renderer");
+        self.skia_renderer = SkiaRenderer::new(&self.windowed_context);
+        self.renderer
+            .handle_context_lost(self.skia_renderer.canvas());
+        self.last_repaint = Instant::now();
+        EVENT_AGGREGATOR.send(EditorCommand::GraphicsContextLost);
+        REDRAW_SCHEDULER.queue_next_frame();
     }
 
     fn handle_scale_factor_update(&mut self, scale_factor: f64) {